@@ -10,29 +10,56 @@ use fibonacci_lib::{fibonacci, PublicValuesStruct};
 use curve25519_dalek::ristretto::{RistrettoPoint, CompressedRistretto};
 use curve25519_dalek::scalar::Scalar;
 use doprf::active_security::ActiveSecurityKey;
-use doprf::prf::Query;
+use doprf::prf::{compute_query_modifier, Query};
 
 pub fn main() {
-    let hashed_concat_quries_bytes = sp1_zkvm::io::read::<[u8; 32]>();
-    let hashed_concat_quries = Scalar::from_canonical_bytes(hashed_concat_quries_bytes).expect("Invalid scalar bytes");
+    // A panic here would abort proving without saying why, so invalid inputs
+    // are reported to the host with a sentinel instead (see
+    // doprf::prove::ProofError::InvalidChecksumInput)
+    let query = checksum_query().unwrap_or_else(|_| Query::invalid_input_sentinel());
+    // Commit the compressed hash to the zkVM for public verification.
+    sp1_zkvm::io::commit::<Query>(&query);
+}
+
+/// Read the inputs and compute the checksum query, or say which input is invalid.
+fn checksum_query() -> Result<Query, &'static str> {
+    // Hash the queries here rather than trusting a precomputed modifier, with
+    // the same function as the host (see doprf::prf::compute_query_modifier)
+    let queries = sp1_zkvm::io::read::<Vec<Query>>();
+    let hashed_concat_quries = compute_query_modifier(&queries);
     
     let active_security_key = sp1_zkvm::io::read::<ActiveSecurityKey>();
     
     let sum_bytes = sp1_zkvm::io::read::<[u8; 32]>();
-    let sum_compressed = CompressedRistretto::from_slice(&sum_bytes).expect("Invalid compressed point");
-    let sum = sum_compressed.decompress().expect("Invalid compressed point");
+    let sum = CompressedRistretto::from_slice(&sum_bytes)
+        .ok()
+        .and_then(|sum_compressed| sum_compressed.decompress())
+        .ok_or("invalid compressed point for the verification-weighted sum")?;
 
     let verification_factor_0_bytes = sp1_zkvm::io::read::<[u8; 32]>();
-    let verification_factor_0 = Scalar::from_canonical_bytes(verification_factor_0_bytes).expect("Invalid scalar bytes");
+    let verification_factor_0 = canonical_scalar(verification_factor_0_bytes)
+        .ok_or("invalid scalar bytes for the checksum's verification factor")?;
 
     let blinding_factor_bytes = sp1_zkvm::io::read::<[u8; 32]>();
-    let blinding_factor = Scalar::from_canonical_bytes(blinding_factor_bytes).expect("Invalid scalar bytes");
+    let blinding_factor = canonical_scalar(blinding_factor_bytes)
+        .ok_or("invalid scalar bytes for the checksum's blinding factor")?;
+
+    // The configured bound on verification factors, 2^SecurityParameter (see doprf::prf)
+    let verification_factor_max = sp1_zkvm::io::read::<u32>();
+    let (low, high) = verification_factor_0_bytes.split_at(4);
+    if !(high.iter().all(|&b| b == 0)
+        && u32::from_le_bytes(low.try_into().unwrap()) <= verification_factor_max)
+    {
+        return Err("verification factor exceeds the security parameter's bound");
+    }
 
     let randomized_target = active_security_key.randomized_target(hashed_concat_quries);
     let checksum = randomized_target.get_checksum_point_for_validation(&sum);
     let x_0 = checksum * verification_factor_0.invert();
 
-    let query = Query::from_rp(x_0 * blinding_factor);
-    // Commit the compressed hash to the zkVM for public verification.
-    sp1_zkvm::io::commit::<Query>(&query);
+    Ok(Query::from_rp(x_0 * blinding_factor))
+}
+
+fn canonical_scalar(bytes: [u8; 32]) -> Option<Scalar> {
+    Scalar::from_canonical_bytes(bytes).into()
 }
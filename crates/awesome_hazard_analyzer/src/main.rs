@@ -19,7 +19,7 @@ use flate2::Compression;
 use itertools::Itertools;
 use rayon::prelude::*;
 use serde::Serialize;
-use shared_types::hash::{HashSpec, HashTypeDescriptor};
+use shared_types::hash::{HashSpec, HashTypeDescriptor, Windowing};
 use time::format_description::well_known::Iso8601;
 use tracing::{info, warn};
 
@@ -82,6 +82,29 @@ pub struct Opts {
 
     #[clap(long, default_value_t = NonZeroUsize::MIN, help = "Max expansions per window")]
     pub expansions_limit: NonZeroUsize,
+
+    #[clap(
+        long,
+        requires = "minimizer_w",
+        help = "Only generate minimizer windows, ordered by this prefix length (must match the HDB)"
+    )]
+    pub minimizer_k: Option<usize>,
+
+    #[clap(
+        long,
+        requires = "minimizer_k",
+        help = "Number of consecutive windows to pick each minimizer from (must match the HDB)"
+    )]
+    pub minimizer_w: Option<usize>,
+}
+
+impl Opts {
+    fn windowing(&self) -> Windowing {
+        match (self.minimizer_k, self.minimizer_w) {
+            (Some(k), Some(w)) => Windowing::Minimizer { k, w },
+            _ => Windowing::All,
+        }
+    }
 }
 
 pub struct AhaCheckerConfiguration {
@@ -91,6 +114,7 @@ pub struct AhaCheckerConfiguration {
     pub generate_runt_windows: bool,
     pub generate_aa_windows: bool,
     pub max_expansions_per_window: NonZeroUsize,
+    pub windowing: Windowing,
 }
 
 impl Default for AhaCheckerConfiguration {
@@ -102,6 +126,7 @@ impl Default for AhaCheckerConfiguration {
             generate_runt_windows: true,
             generate_aa_windows: true,
             max_expansions_per_window: NonZeroUsize::MIN,
+            windowing: Windowing::All,
         }
     }
 }
@@ -327,6 +352,7 @@ fn check_one_record(
     let hash_spec = HashSpec {
         max_expansions_per_window: config.max_expansions_per_window,
         htdv,
+        windowing: config.windowing,
     };
 
     let windows_object =
@@ -619,6 +645,7 @@ fn run(opts: &Opts) -> anyhow::Result<()> {
                         generate_runt_windows: !opts.no_runts,
                         generate_aa_windows: !opts.no_aa,
                         max_expansions_per_window: opts.expansions_limit,
+                        windowing: opts.windowing(),
                     },
                 )
             })
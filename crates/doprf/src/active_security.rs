@@ -88,9 +88,8 @@ pub fn commitments_from_secret_and_keyshares<'a>(
     keyholders_required: NonZeroU32,
 ) -> Result<Vec<Commitment>, InvalidSecretAndKeyshareInput> {
     let keyholders_required = keyholders_required.get() as usize;
-    let mut secrets = Vec::new();
-    secrets.push(*secret);
-    secrets.extend(keyshares);
+    let mut secrets = vec![secret.clone()];
+    secrets.extend(keyshares.into_iter().cloned());
 
     // Check we have enough information to calculate and verify active security key
     if secrets.len() < keyholders_required {
@@ -226,7 +225,7 @@ impl Display for InvalidSecretAndKeyshareInput {
 }
 
 /// Randomised target is a modification of the established target
-#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub struct SerializableRandomizedTarget {
     pub random_modifier: [u8; 32],
     target: [u8; 32],
@@ -234,24 +233,39 @@ pub struct SerializableRandomizedTarget {
 }
 
 impl SerializableRandomizedTarget {
-    pub fn to_randomized_target(&self) -> RandomizedTarget {
-        let random_modifier = Scalar::from_canonical_bytes(self.random_modifier);
-        if random_modifier.is_none().into() {
-            panic!("Invalid scalar! {:?}", self.random_modifier);
+    /// Check that the random modifier is canonical and all points decompress,
+    /// without building the [`RandomizedTarget`].
+    pub fn validate(&self) -> Result<(), DecodeError> {
+        if Option::<Scalar>::from(Scalar::from_canonical_bytes(self.random_modifier)).is_none() {
+            return Err(DecodeError::InvalidScalar);
         }
-        RandomizedTarget {
-            random_modifier: random_modifier.unwrap(),            // JUMP
-            target: Target(CompressedRistretto::from_slice(&self.target).expect("couldn't get bytes").decompress().expect("couldn't decompress")),
-            commitments: self.commitments
-            .iter()
-            .map(|c| {
-                CompressedRistretto::from_slice(c)
-                    .expect("couldn't get bytes")
-                    .decompress()
-                    .expect("couldn't decompress")
-            })
-            .collect(),
+        for point in std::iter::once(&self.target).chain(&self.commitments) {
+            if CompressedRistretto(*point).decompress().is_none() {
+                return Err(DecodeError::InvalidRistrettoPoint);
+            }
         }
+        Ok(())
+    }
+
+    /// Decode the [`RandomizedTarget`], failing rather than panicking if the
+    /// random modifier isn't canonical or a point doesn't decompress.
+    pub fn to_randomized_target(&self) -> Result<RandomizedTarget, DecodeError> {
+        let random_modifier = Option::from(Scalar::from_canonical_bytes(self.random_modifier))
+            .ok_or(DecodeError::InvalidScalar)?;
+        let decompress = |point: &[u8; 32]| {
+            CompressedRistretto(*point)
+                .decompress()
+                .ok_or(DecodeError::InvalidRistrettoPoint)
+        };
+        Ok(RandomizedTarget {
+            random_modifier,
+            target: Target(decompress(&self.target)?),
+            commitments: self
+                .commitments
+                .iter()
+                .map(decompress)
+                .collect::<Result<_, _>>()?,
+        })
     }
 }
 
@@ -281,15 +295,55 @@ impl RandomizedTarget {
         self.target.0 == *verifier
     }
 
+    /// The difference `expected - actual` between the target and the verifier,
+    /// which is the identity exactly when the responses validate.
+    pub fn discrepancy(&self, verifier: &RistrettoPoint) -> RistrettoPoint {
+        self.target.0 - verifier
+    }
+
     pub fn is_keyserver_response_valid(
         &self,
         keyservers: &KeyserverIdSet,
         keyserver_id: &KeyserverId,
         sum: &RistrettoPoint,
     ) -> bool {
+        self.expected_contribution_from_commitments(keyservers, keyserver_id) == *sum
+    }
+
+    /// The difference `expected - actual` between what keyserver
+    /// `keyserver_id` should contribute and its contribution `sum`, which is
+    /// the identity exactly when the keyserver's responses are valid.
+    pub fn keyserver_discrepancy(
+        &self,
+        keyservers: &KeyserverIdSet,
+        keyserver_id: &KeyserverId,
+        sum: &RistrettoPoint,
+    ) -> RistrettoPoint {
+        self.expected_contribution_from_commitments(keyservers, keyserver_id) - sum
+    }
+
+    fn expected_contribution_from_commitments(
+        &self,
+        keyservers: &KeyserverIdSet,
+        keyserver_id: &KeyserverId,
+    ) -> RistrettoPoint {
         let coeff = keyservers.langrange_coefficient_for_id(keyserver_id);
         let verifier = evaluate_lagrange_polynomial(&self.commitments, keyserver_id.into());
-        self.random_modifier * coeff * verifier == *sum
+        self.random_modifier * coeff * verifier
+    }
+
+    /// The sum a correct keyserver holding `share` would contribute, to
+    /// compare against its observed contribution when auditing offline with
+    /// the reconstructed key.
+    #[cfg(feature = "centralized_keygen")]
+    pub fn expected_keyserver_contribution(
+        &self,
+        keyservers: &KeyserverIdSet,
+        keyserver_id: &KeyserverId,
+        share: &KeyShare,
+    ) -> RistrettoPoint {
+        let coeff = keyservers.langrange_coefficient_for_id(keyserver_id);
+        self.random_modifier * coeff * share.multiply_by_base()
     }
 }
 
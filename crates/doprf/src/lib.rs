@@ -6,5 +6,7 @@ pub mod party;
 #[macro_use]
 pub mod prf;
 pub mod active_security;
+#[cfg(feature = "sp1")]
+pub mod prove;
 pub mod shims;
 pub mod tagged;
@@ -143,6 +143,10 @@ impl KeyserverIdSet {
         self.sorted_keyserver_ids.iter()
     }
 
+    pub fn contains(&self, id: &KeyserverId) -> bool {
+        self.sorted_keyserver_ids.binary_search(id).is_ok()
+    }
+
     pub fn len(&self) -> usize {
         self.sorted_keyserver_ids.len()
     }
@@ -1,18 +1,9 @@
 // Copyright 2021-2024 SecureDNA Stiftung (SecureDNA Foundation) <licensing@securedna.org>
 // SPDX-License-Identifier: MIT OR Apache-2.0
 
-// added packages for file write
-use serde_json;
-use std::fs::{self, File};
-use std::io::{self, Read, BufWriter};
-use std::path::Path;
-
-// added packages for SP1 'Script'
-use std::env;
-use clap::Parser;
-use sp1_sdk::{include_elf, utils, ProverClient, SP1Stdin, SP1ProofWithPublicValues, SP1VerifyingKey};
-
 use std::collections::BTreeMap;
+#[cfg(any(feature = "centralized_keygen", test))]
+use std::collections::BTreeSet;
 use std::error::Error;
 use std::fmt;
 use std::num::NonZeroU32;
@@ -20,25 +11,101 @@ use std::str::FromStr;
 
 use curve25519_dalek::ristretto::{CompressedRistretto, RistrettoPoint};
 use curve25519_dalek::scalar::Scalar;
-use curve25519_dalek::traits::Identity;
+use curve25519_dalek::traits::{Identity, VartimeMultiscalarMul};
 use hex::FromHexError;
 use rand::{rngs::OsRng, Rng};
-#[cfg(any(feature = "centralized_keygen", test))]
 use rand::{CryptoRng, RngCore};
 use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
-use sha3::Sha3_512;
-
-use crate::active_security::{ActiveSecurityKey, RandomizedTarget, SerializableRandomizedTarget};
+use sha3::digest::consts::U64;
+use sha3::{Digest, Sha3_512};
+use subtle::{Choice, ConstantTimeEq};
+use zeroize::Zeroizing;
+
+use crate::active_security::{
+    ActiveSecurityKey, Commitment, RandomizedTarget, SerializableRandomizedTarget,
+};
 #[cfg(any(feature = "centralized_keygen", test))]
-use crate::lagrange::evaluate_lagrange_polynomial;
+use crate::lagrange::{evaluate_lagrange_polynomial, lagrange_coefficients_at_zero};
 use crate::party::{KeyserverId, KeyserverIdSet};
 use crate::tagged::{HashTag, TaggedHash};
 
 /// The probability that a malicious party could evade active security is 2^(-SECURITY_PARAMETER).
 /// Values of 4N+2 for N=0,1,... will maximise security vs speed.
 /// A value of 18 entails a 2.5% performance reduction.
+///
+/// This is the default [`SecurityParameter`].
 pub const SECURITY_PARAMETER: u32 = 18;
 
+/// A security parameter `n`, so that a malicious party evades active security
+/// with probability 2^(-n). Verification factors are drawn from `0..=2^n`, so
+/// larger values are stronger but cost more to prove.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SecurityParameter(u32);
+
+impl SecurityParameter {
+    /// Errors if 2^`n` doesn't fit in a `u32`.
+    pub fn new(n: u32) -> Result<Self, InvalidSecurityParameter> {
+        if n < u32::BITS {
+            Ok(Self(n))
+        } else {
+            Err(InvalidSecurityParameter(n))
+        }
+    }
+
+    pub fn get(self) -> u32 {
+        self.0
+    }
+
+    /// The largest verification factor, 2^n.
+    pub fn verification_factor_max(self) -> u32 {
+        1 << self.0
+    }
+}
+
+impl Default for SecurityParameter {
+    fn default() -> Self {
+        Self(SECURITY_PARAMETER)
+    }
+}
+
+/// A [`SecurityParameter`] too large for 2^n to fit in a `u32`.
+#[derive(Debug, Clone)]
+pub struct InvalidSecurityParameter(u32);
+
+impl fmt::Display for InvalidSecurityParameter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let n = self.0;
+        write!(
+            f,
+            "security parameter {n} is too large for 2^{n} to fit in a u32"
+        )
+    }
+}
+
+impl Error for InvalidSecurityParameter {}
+
+/// A digest that sequences can be hashed to the curve with.
+///
+/// Functions that hash sequences to the curve take this as a type parameter
+/// rather than choosing at runtime, so a deployment fixed to one algorithm pays
+/// no branch for it.
+pub trait HashToCurve: Digest<OutputSize = U64> + Default {}
+
+impl<D: Digest<OutputSize = U64> + Default> HashToCurve for D {}
+
+/// The digest sequences are hashed to the curve with, unless another is chosen.
+/// SP1 guests proving queries must use the same one.
+pub type DefaultHashToCurve = Sha3_512;
+
+/// Hash `bytes` to the curve with the digest `D`.
+pub fn hash_to_curve<D: HashToCurve>(bytes: &[u8]) -> RistrettoPoint {
+    RistrettoPoint::hash_from_bytes::<D>(bytes)
+}
+
+/// A blinded hash of a window, sent to keyservers.
+///
+/// The derived `PartialEq` compares in variable time, and is kept for tests.
+/// Production code should compare queries with [`ConstantTimeEq::ct_eq`].
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Query(CompressedRistretto);
 
@@ -52,6 +119,28 @@ impl Query {
     pub fn sentinel() -> Self {
         Query(CompressedRistretto::default())
     }
+
+    /// Committed by the checksum program instead of the checksum query when
+    /// it's given invalid inputs. Unlike [`Query::sentinel`] and every real
+    /// query, it isn't a valid point.
+    pub fn invalid_input_sentinel() -> Self {
+        Query(CompressedRistretto([0xff; 32]))
+    }
+
+    /// Hash `seq` to the curve and blind it with `blinding`.
+    ///
+    /// This is the query a [`QueryStateSet`] sends to keyservers for `seq`, without
+    /// the active security checksum or proofs. Intended for tooling and testing.
+    pub fn for_sequence(seq: &[u8], blinding: Scalar) -> Self {
+        Self::from_rp(hash_to_curve::<DefaultHashToCurve>(seq) * blinding)
+    }
+
+    /// Like [`Query::for_sequence`], but with a random blinding factor, which is
+    /// returned alongside the query so the response can be unblinded.
+    pub fn for_sequence_with_random_blinding(seq: &[u8]) -> (Self, Scalar) {
+        let blinding = Scalar::random(&mut OsRng);
+        (Self::for_sequence(seq, blinding), blinding)
+    }
 }
 
 /// Response from a keyholder: (H(x)^r)^{f(i)*c_i}
@@ -61,20 +150,72 @@ pub struct HashPart(CompressedRistretto);
 #[derive(Debug, Clone, Copy)]
 pub struct CompletedHashValue(CompressedRistretto);
 
-#[derive(Debug, Clone, Copy)]
-pub struct KeyShare(Scalar);
+/// A keyholder's share of the secret key, wiped from memory when dropped.
+///
+/// Its `Debug` output is redacted, and it has no `Display`, so that it can't end
+/// up in logs by accident. Use [`KeyShare::reveal_hex`] to write it out.
+#[derive(Clone)]
+pub struct KeyShare(Zeroizing<Scalar>);
 
 impl KeyShare {
+    /// The secret scalar, hex-encoded as [`KeyShare::from_str`] parses it.
+    pub fn reveal_hex(&self) -> String {
+        hex::encode(self.0.as_bytes())
+    }
+
+    /// The share of the sum of the secrets `self` and `other` are shares of,
+    /// if both are for the same keyholder. Adding a share from
+    /// [`generate_zero_shares`] refreshes a share without changing the secret.
+    pub fn add(&self, other: &KeyShare) -> KeyShare {
+        KeyShare::from(*self.0 + *other.0)
+    }
+
     pub fn apply(&self, q: Query) -> HashPart {
-        HashPart::from_rp(q.to_rp() * self.0)
+        HashPart::from_rp(q.to_rp() * &*self.0)
     }
 
     pub fn apply_query_and_lagrange_coefficient(&self, q: Query, c: &Scalar) -> HashPart {
-        HashPart::from_rp(c * self.0 * q.to_rp())
+        HashPart::from_rp(c * &*self.0 * q.to_rp())
+    }
+
+    /// [`Self::apply_query_and_lagrange_coefficient`] for each of `queries`, in
+    /// order, so the parts line up with the queries they answer. Runs across
+    /// threads with the `parallel` feature.
+    pub fn apply_batch(&self, queries: &[Query], c: &Scalar) -> Vec<HashPart> {
+        let apply = |q: &Query| self.apply_query_and_lagrange_coefficient(*q, c);
+        #[cfg(feature = "parallel")]
+        {
+            use rayon::prelude::*;
+
+            queries.par_iter().map(apply).collect()
+        }
+        #[cfg(not(feature = "parallel"))]
+        queries.iter().map(apply).collect()
+    }
+
+    /// Like [`Self::apply_query_and_lagrange_coefficient`], but also prove that the
+    /// part was computed with the share behind [`Commitment::from_keyshare`].
+    pub fn apply_and_prove(&self, q: Query, c: &Scalar) -> (HashPart, KeyserverApplyProof) {
+        let base = c * q.to_rp();
+        let part = &*self.0 * base;
+
+        let nonce = Scalar::random(&mut OsRng);
+        let challenge = KeyserverApplyProof::challenge(
+            &self.multiply_by_base(),
+            &base,
+            &part,
+            &RistrettoPoint::mul_base(&nonce),
+            &(nonce * base),
+        );
+        let proof = KeyserverApplyProof {
+            challenge,
+            response: nonce - challenge * &*self.0,
+        };
+        (HashPart::from_rp(part), proof)
     }
 
     pub fn multiply_by_rp(&self, point: RistrettoPoint) -> RistrettoPoint {
-        self.0 * point
+        &*self.0 * point
     }
 
     /// Multiplication by the Ristretto base point
@@ -83,37 +224,103 @@ impl KeyShare {
     }
 }
 
+/// Proof that a [`HashPart`] is `c * share * q` for the share behind a
+/// [`Commitment`], without revealing the share.
+///
+/// This is a Chaum-Pedersen (DLEQ) proof that the part has the same discrete log
+/// with respect to `c * q` as the commitment has with respect to the base point.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyserverApplyProof {
+    challenge: Scalar,
+    response: Scalar,
+}
+
+impl KeyserverApplyProof {
+    const DOMAIN_SEPARATOR: &'static [u8] = b"securedna keyserver apply proof v1";
+
+    fn challenge(
+        commitment: &RistrettoPoint,
+        base: &RistrettoPoint,
+        part: &RistrettoPoint,
+        nonce_commitment: &RistrettoPoint,
+        nonce_part: &RistrettoPoint,
+    ) -> Scalar {
+        let mut hasher = Sha3_512::new();
+        hasher.update(Self::DOMAIN_SEPARATOR);
+        for point in [commitment, base, part, nonce_commitment, nonce_part] {
+            hasher.update(point.compress().as_bytes());
+        }
+        Scalar::from_hash(hasher)
+    }
+
+    /// Check that `part` is the result of applying the share committed to by
+    /// `commitment` to `q` with Lagrange coefficient `c`.
+    pub fn verify(&self, q: Query, c: &Scalar, part: HashPart, commitment: Commitment) -> bool {
+        let base = c * q.to_rp();
+        let commitment = commitment.to_rp();
+        let Ok(part) = part.try_to_rp() else {
+            return false;
+        };
+        let nonce_commitment =
+            RistrettoPoint::mul_base(&self.response) + self.challenge * commitment;
+        let nonce_part = self.response * base + self.challenge * part;
+        Self::challenge(&commitment, &base, &part, &nonce_commitment, &nonce_part) == self.challenge
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct QueryState {
     required_keyholders: usize,
-    blinding_factor: Scalar,
-    verification_factor: Scalar,
+    pub(crate) blinding_factor: Scalar,
+    pub(crate) verification_factor: Scalar,
     query: Query,
     responses: Vec<(KeyserverId, HashPart)>,
 }
 
 impl QueryState {
-    pub fn new(bytes: &[u8], required_keyholders: usize) -> Self {
-        let point = RistrettoPoint::hash_from_bytes::<Sha3_512>(bytes);
+    pub fn new(bytes: &[u8], required_keyholders: usize) -> Result<Self, ZeroQuorumError> {
+        Self::new_with_digest::<DefaultHashToCurve>(bytes, required_keyholders)
+    }
+
+    /// Like [`QueryState::new`], but hashing `bytes` to the curve with `D`.
+    pub fn new_with_digest<D: HashToCurve>(
+        bytes: &[u8],
+        required_keyholders: usize,
+    ) -> Result<Self, ZeroQuorumError> {
+        let point = hash_to_curve::<D>(bytes);
 
         Self::from_rp(point, required_keyholders, Scalar::ONE)
     }
 
+    /// Errors if `required_keyholders` is zero, since the hash would then be
+    /// "reconstructed" from no responses at all.
     pub fn from_rp(
         point: RistrettoPoint,
         required_keyholders: usize,
         verification_factor: Scalar
-    ) -> Self {
-        let mut rng = OsRng;
-        let blinding_factor = Scalar::random(&mut rng);
+    ) -> Result<Self, ZeroQuorumError> {
+        Self::from_rp_with_rng(point, required_keyholders, verification_factor, &mut OsRng)
+    }
+
+    /// Like [`QueryState::from_rp`], but drawing the blinding factor from `rng`.
+    pub fn from_rp_with_rng(
+        point: RistrettoPoint,
+        required_keyholders: usize,
+        verification_factor: Scalar,
+        rng: &mut (impl RngCore + CryptoRng),
+    ) -> Result<Self, ZeroQuorumError> {
+        if required_keyholders == 0 {
+            return Err(ZeroQuorumError);
+        }
+        let blinding_factor = Scalar::random(rng);
     
-        QueryState {
+        Ok(QueryState {
             required_keyholders,
             blinding_factor,
             verification_factor,
             query: Query::from_rp(point * blinding_factor),
             responses: vec![],
-        }
+        })
     }
 
     pub fn query(&self) -> &Query {
@@ -128,46 +335,61 @@ impl QueryState {
         self.responses.push((i, p));
     }
 
+    /// Like [`QueryState::incorporate_response`], but keeps responses sorted by
+    /// keyserver ID rather than in arrival order.
+    fn incorporate_response_in_order(&mut self, i: KeyserverId, p: HashPart) {
+        let index = self.responses.partition_point(|(id, _)| *id < i);
+        self.responses.insert(index, (i, p));
+    }
+
+    /// Whether keyserver `id`'s response has been incorporated.
+    fn has_response_from(&self, id: KeyserverId) -> bool {
+        self.responses.iter().any(|(i, _)| *i == id)
+    }
+
     /// Whether enough responses have been incorporated to reconstruct hash
     pub fn has_hash(&self) -> bool {
         self.responses.len() >= self.required_keyholders
     }
 
     /// Attempts to reconstruct the hash from incorporated responses.
-    /// Returns `None` if not enough responses have been incorporated.
-    fn calculate_hash_value(&self) -> Option<RistrettoPoint> {
-        if self.has_hash() {
-            let inverted_modification = self.blinding_factor.invert();
-            let sum_hash_parts = self
-                .responses
-                .iter()
-                .map(|(_, hash_part)| hash_part.to_rp())
-                .sum::<RistrettoPoint>();
-            Some(inverted_modification * sum_hash_parts)
-        } else {
-            None
+    /// Returns `None` if not enough responses have been incorporated, and
+    /// [`QueryError::InvalidResponse`] if a response isn't a valid point.
+    fn calculate_hash_value(&self) -> Result<Option<RistrettoPoint>, QueryError> {
+        if !self.has_hash() {
+            return Ok(None);
         }
+        let inverted_modification = self.blinding_factor.invert();
+        let mut sum_hash_parts = RistrettoPoint::identity();
+        for (id, hash_part) in &self.responses {
+            sum_hash_parts += hash_part
+                .try_to_rp()
+                .map_err(|_| QueryError::InvalidResponse(*id))?;
+        }
+        Ok(Some(inverted_modification * sum_hash_parts))
     }
 
     /// Attempts to reconstruct the hash from incorporated responses.
     /// Returns `None` if not enough responses have been incorporated.
-    pub fn get_hash_value(&self) -> Option<CompletedHashValue> {
-        self.calculate_hash_value().map(CompletedHashValue::from_rp)
+    pub fn get_hash_value(&self) -> Result<Option<CompletedHashValue>, QueryError> {
+        Ok(self
+            .calculate_hash_value()?
+            .map(CompletedHashValue::from_rp))
     }
 
     /// The first value is the reconstructed hash, the second is used to verify that keyservers have
     /// correctly evaluated the DOPRF.
     pub fn get_hash_value_and_verification_value(
         &self,
-    ) -> Option<(CompletedHashValue, RistrettoPoint)> {
-        self.calculate_hash_value().map(|result| {
+    ) -> Result<Option<(CompletedHashValue, RistrettoPoint)>, QueryError> {
+        Ok(self.calculate_hash_value()?.map(|result| {
             let verifier = RistrettoPoint::vartime_double_scalar_mul_basepoint(
                 &self.verification_factor,
                 &result,
                 &Scalar::ZERO,
             );
             (CompletedHashValue::from_rp(result), verifier)
-        })
+        }))
     }
 
     /// Convert this `QueryState` into a `SerializableQueryState`.
@@ -186,6 +408,12 @@ impl QueryState {
     }
 }
 
+/// Decode a scalar written by [`Scalar::to_bytes`], which is always canonical,
+/// so that corrupted bytes are an error rather than silently reduced.
+fn canonical_scalar(bytes: [u8; 32]) -> Result<Scalar, DecodeError> {
+    Option::from(Scalar::from_canonical_bytes(bytes)).ok_or(DecodeError::InvalidScalar)
+}
+
 /// A version of QueryState suitable for serialization.
 #[derive(Serialize, Deserialize)]
 pub struct SerializableQueryState {
@@ -196,11 +424,35 @@ pub struct SerializableQueryState {
     responses: Vec<(KeyserverId, [u8; 32])>,
 }
 
+impl SerializableQueryState {
+    fn validate(&self) -> Result<(), DecodeError> {
+        if self.required_keyholders == 0 {
+            return Err(ZeroQuorumError.into());
+        }
+        for scalar in [self.blinding_factor, self.verification_factor] {
+            canonical_scalar(scalar)?;
+        }
+        Query::try_from(self.query.as_bytes())?;
+        for (_, part) in &self.responses {
+            HashPart::try_from(part)?;
+        }
+        Ok(())
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum QueryError {
     WrongSizeResponse,
+    /// The keyserver already responded to this query set.
+    DuplicateResponse(KeyserverId),
     MissingKeyserverResponse,
-    ValidationFailed(Vec<KeyserverId>),
+    /// The keyserver's response held bytes that aren't a valid point.
+    InvalidResponse(KeyserverId),
+    ValidationFailed {
+        keyservers: Vec<KeyserverId>,
+        /// `expected - actual` for the active security target, for debugging.
+        discrepancy: RistrettoPoint,
+    },
 }
 
 impl Error for QueryError {}
@@ -211,29 +463,32 @@ impl fmt::Display for QueryError {
             QueryError::WrongSizeResponse => {
                 write!(f, "Query response has the wrong size")
             }
-            QueryError::ValidationFailed(keyservers) => {
+            QueryError::DuplicateResponse(keyserver) => {
+                write!(f, "Keyserver {keyserver} responded more than once")
+            }
+            QueryError::ValidationFailed {
+                keyservers,
+                discrepancy,
+            } => {
                 write!(
                     f,
-                    "Query response did not validate. Responsible keyservers: {:?}",
-                    keyservers
+                    "Query response did not validate. Responsible keyservers: {:?}, discrepancy: {}",
+                    keyservers,
+                    hex::encode(discrepancy.compress().as_bytes())
                 )
             }
             QueryError::MissingKeyserverResponse => {
                 write!(f, "Missing keyserver response")
             }
+            QueryError::InvalidResponse(keyserver) => {
+                write!(f, "Keyserver {keyserver} responded with an invalid point")
+            }
         }
     }
 }
 
-/// An input to the aggregation program.
-///
-/// Consists of a proof and a verification key.
-// #[cfg(feature = "sp1")]
-#[derive(Serialize, Deserialize, Clone)]
-pub struct VerificationInput {
-    pub proof: SP1ProofWithPublicValues,
-    pub vk: SP1VerifyingKey,
-}
+#[cfg(feature = "sp1")]
+pub use crate::prove::VerificationInput;
 
 // Added this struct for serialization of QueryStateSet
 #[derive(Serialize, Deserialize)]
@@ -243,69 +498,202 @@ pub struct SerializableQueryStateSet {
 }
 
 impl SerializableQueryStateSet {
+    /// Check that all scalars are canonical, all points decompress and every
+    /// query state requires at least one keyholder, without reconstructing the set. Returns the first error found.
+    pub fn validate(&self) -> Result<(), DecodeError> {
+        for (_, sqs) in &self.querystates {
+            sqs.validate()?;
+        }
+        self.randomized_target.validate()
+    }
+
     /// Converts this serializable set back into a `QueryStateSet`.
-    pub fn to_query_state_set(&self) -> QueryStateSet {
-        QueryStateSet {
+    ///
+    /// Errors (see [`Self::validate`]) if a scalar isn't canonical or a point
+    /// doesn't decompress, which means the set was corrupted. Sets can come
+    /// from untrusted captures, so nothing here or in using the set panics.
+    pub fn to_query_state_set(&self) -> Result<QueryStateSet, DecodeError> {
+        self.validate()?;
+        let checksum_index = self.querystates.len().checked_sub(1);
+        Ok(QueryStateSet {
             querystates: self.querystates
                 .iter()
-                .map(|(tag, sqs)| {
+                .enumerate()
+                .map(|(i, (tag, sqs))| {
                     let query_state = QueryState {
                         required_keyholders: sqs.required_keyholders,
-                        blinding_factor: Scalar::from_bytes_mod_order(sqs.blinding_factor),
-                        verification_factor: Scalar::from_bytes_mod_order(sqs.verification_factor),
+                        blinding_factor: canonical_scalar(sqs.blinding_factor)?,
+                        verification_factor: canonical_scalar(sqs.verification_factor)?,
                         query: sqs.query.clone(),
                         responses: sqs.responses
                             .iter()
                             .map(|(k, part)| (*k, HashPart(CompressedRistretto::from_slice(part).expect("couldn't read bytes"))))
                             .collect(),
                     };
-                    (Some(HashTag::from_bytes(*tag)), query_state)
+                    // The checksum's tag was written as the default, so restore its `None`.
+                    let tag = (Some(i) != checksum_index).then(|| HashTag::from_bytes(*tag));
+                    Ok((tag, query_state))
                 })
-                .collect(),
-            randomized_target: self.randomized_target.to_randomized_target(),
-        }
+                .collect::<Result<_, DecodeError>>()?,
+            randomized_target: self.randomized_target.to_randomized_target()?,
+            parallel_threshold: None,
+            canonical_response_order: false,
+            #[cfg(feature = "shadow_validation")]
+            shadow_target: None,
+        })
+    }
+}
+
+/// Result of [`QueryStateSet::get_hash_values_deadline`].
+#[derive(Debug, Clone)]
+pub enum HashValues {
+    Complete(Vec<TaggedHash>),
+    /// The deadline passed after `processed` of `total` querystates.
+    Pending {
+        processed: usize,
+        total: usize,
+    },
+}
+
+/// Derives the [`RandomizedTarget`] for a query set incrementally, for callers
+/// that produce queries one at a time rather than through [`QueryStateSet::build`].
+///
+/// The random modifier is the hash of all (non-checksum) queries concatenated in
+/// order, so pushing the same queries yields the same target as `build`.
+#[derive(Clone, Default)]
+pub struct RandomizedTargetBuilder {
+    concat_queries: Sha3_512,
+}
+
+impl RandomizedTargetBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push_query(&mut self, query: &Query) {
+        self.concat_queries.update(query.0.as_bytes());
+    }
+
+    /// The random modifier for the queries pushed so far.
+    pub fn random_modifier(self) -> Scalar {
+        Scalar::from_hash(self.concat_queries)
+    }
+
+    /// Finalize the target once all queries are known.
+    pub fn finalize(self, active_security_key: &ActiveSecurityKey) -> RandomizedTarget {
+        active_security_key.randomized_target(self.random_modifier())
+    }
+}
+
+/// The random modifier for `queries`: the hash of their concatenation, as
+/// [`RandomizedTargetBuilder`] computes it. The checksum proof's guest calls
+/// this too, so the host and guest can't drift apart.
+pub fn compute_query_modifier(queries: &[Query]) -> Scalar {
+    let mut builder = RandomizedTargetBuilder::new();
+    for query in queries {
+        builder.push_query(query);
     }
+    builder.random_modifier()
 }
 
+/// Batches smaller than this are hashed serially unless configured otherwise:
+/// below it, handing the work to other threads costs more than it saves. See
+/// [`QueryStateSet::set_parallel_threshold`].
+pub const DEFAULT_PARALLEL_THRESHOLD: usize = 1024;
+
 #[derive(Debug, Clone, Default)]
 pub struct QueryStateSet {
     querystates: Vec<(Option<HashTag>, QueryState)>,
     pub randomized_target: RandomizedTarget,
+    /// See [`QueryStateSet::set_parallel_threshold`]; `None` uses
+    /// [`DEFAULT_PARALLEL_THRESHOLD`].
+    parallel_threshold: Option<usize>,
+    /// See [`QueryStateSet::set_canonical_response_order`].
+    canonical_response_order: bool,
+    /// Experimental target validated alongside `randomized_target`, for A/B
+    /// testing changes to active security. See [`QueryStateSet::set_shadow_target`].
+    #[cfg(feature = "shadow_validation")]
+    shadow_target: Option<RandomizedTarget>,
 }
 
 impl QueryStateSet {
-    #[cfg(feature = "sp1")]
-    pub fn from_iter(
+    /// Blind and hash each window in `iter`, with verification factors bounded
+    /// by `security_parameter`, then append the checksum query for
+    /// `active_security_key`.
+    ///
+    /// Nothing is proven here; see [`crate::prove::prove_querystate`].
+    pub fn build(
+        iter: impl IntoIterator<Item = (HashTag, impl AsRef<[u8]>)>,
+        required_keyholders: usize,
+        active_security_key: ActiveSecurityKey,
+        security_parameter: SecurityParameter,
+    ) -> Result<Self, ZeroQuorumError> {
+        Self::build_with_rng(
+            iter,
+            required_keyholders,
+            active_security_key,
+            security_parameter,
+            &mut OsRng,
+        )
+    }
+
+    /// Like [`QueryStateSet::build`], but drawing blinding and verification
+    /// factors from `rng`, e.g. a seeded one to reproduce a set in tests.
+    pub fn build_with_rng(
+        iter: impl IntoIterator<Item = (HashTag, impl AsRef<[u8]>)>,
+        required_keyholders: usize,
+        active_security_key: ActiveSecurityKey,
+        security_parameter: SecurityParameter,
+        rng: &mut (impl RngCore + CryptoRng),
+    ) -> Result<Self, ZeroQuorumError> {
+        Self::build_with_digest_and_rng::<DefaultHashToCurve>(
+            iter,
+            required_keyholders,
+            active_security_key,
+            security_parameter,
+            rng,
+        )
+    }
+
+    /// Like [`QueryStateSet::build`], but hashing sequences to the curve
+    /// with `D`. The hash proof's guest must be built to hash with `D` too.
+    pub fn build_with_digest<D: HashToCurve>(
         iter: impl IntoIterator<Item = (HashTag, impl AsRef<[u8]>)>,
         required_keyholders: usize,
         active_security_key: ActiveSecurityKey,
-    ) -> (Self, Vec<VerificationInput>) {
-        // The ELF we want to execute inside the zkVM, ensure it's up to date (auto-build disabled)
-        const HASH_ELF: &[u8] = include_bytes!("../../../hash_proof/elf/riscv32im-succinct-zkvm-elf");
-        const CHECKSUM_ELF: &[u8] = include_bytes!("../../../checksum_proof/elf/riscv32im-succinct-zkvm-elf");
+        security_parameter: SecurityParameter,
+    ) -> Result<Self, ZeroQuorumError> {
+        Self::build_with_digest_and_rng::<D>(
+            iter,
+            required_keyholders,
+            active_security_key,
+            security_parameter,
+            &mut OsRng,
+        )
+    }
 
-        // The input stream that the program will read from using `sp1_zkvm::io::read`. Note that the
-        // types of the elements in the input stream must match the types being read in the program.
-        let mut hash_stdin = SP1Stdin::new();
-        let mut checksum_stdin = SP1Stdin::new();
+    /// [`QueryStateSet::build_with_digest`] and
+    /// [`QueryStateSet::build_with_rng`] combined.
+    pub fn build_with_digest_and_rng<D: HashToCurve>(
+        iter: impl IntoIterator<Item = (HashTag, impl AsRef<[u8]>)>,
+        required_keyholders: usize,
+        active_security_key: ActiveSecurityKey,
+        security_parameter: SecurityParameter,
+        rng: &mut (impl RngCore + CryptoRng),
+    ) -> Result<Self, ZeroQuorumError> {
+        if required_keyholders == 0 {
+            return Err(ZeroQuorumError);
+        }
 
         let iter = iter.into_iter();
-        let estimated_size = iter.size_hint().0 + 1;
-        let mut querystates = Vec::with_capacity(estimated_size);
+        let mut querystates = Vec::with_capacity(iter.size_hint().0 + 1);
         let mut sum = RistrettoPoint::identity();
+        let mut target_builder = RandomizedTargetBuilder::new();
 
-        let verification_factor_max = 2u32.pow(SECURITY_PARAMETER);
-        let mut rng = OsRng;
-
-        // Concatenate all queries forrandom random_modifier
-        let mut concat_queries = Vec::new();
+        let verification_factor_max = security_parameter.verification_factor_max();
 
         for (tag, b) in iter {
-            let byte_vec = b.as_ref().to_vec();
-            // Write these bytes to the input stream
-            hash_stdin.write(&byte_vec);
-
-            let point = RistrettoPoint::hash_from_bytes::<Sha3_512>(b.as_ref());
+            let point = hash_to_curve::<D>(b.as_ref());
             let verification_factor = Scalar::from(rng.gen_range(0u32..=verification_factor_max));
 
             // We need variable time scalar * point multiplication; this is the fastest option provided by curve25519-dalek
@@ -314,170 +702,48 @@ impl QueryStateSet {
                 &point,
                 &Scalar::ZERO,
             );
-            
-            let state = QueryState::from_rp(point, required_keyholders, verification_factor);
-            
-            // Retrieve the random blinding factor generated in from_rp, convert to 
-            // a serializable type and write it
-            hash_stdin.write(&state.blinding_factor.as_bytes());
-
-            // Concatenate all queries
-            concat_queries.extend_from_slice(state.query.0.as_bytes());
 
+            let state =
+                QueryState::from_rp_with_rng(point, required_keyholders, verification_factor, rng)?;
+            target_builder.push_query(&state.query);
             querystates.push((Some(tag), state));
         }
 
-        // After writing all byte arrays, write a sentinel value
-        let sentinel: Vec<u8> = Vec::new();
-        hash_stdin.write(&sentinel);
-
-        // Create a `ProverClient` method
-        let client = ProverClient::new();
-
-        // DEBUGGING SECTION START
-        // Execute the hash_proof program using the `ProverClient.execute` method,
-        let (mut hash_public_values, execution_report) = client.execute(HASH_ELF, hash_stdin.clone()).run().unwrap();
-        println!(
-            "Hash program executed with {} cycles",
-            execution_report.total_instruction_count() + execution_report.total_syscall_count()
-        );
-
-        // Read the proof hashes from the output stream until sentinel value is reached
-        let mut proof_quries = Vec::with_capacity(estimated_size);
-        let sentinel = Query::sentinel();
-
-        loop {
-            let read_val = hash_public_values.read::<Query>();
-            if read_val == sentinel {
-                break;
-            }
-            proof_quries.push(read_val)
-        }
-
-        // Extract only queries from querystates states
-        let local_queries: Vec<Query> = querystates.iter().map(|(_, state)| state.query.clone()).collect();
-
-        // Confirm matching outputs, for debugging
-        if proof_quries == local_queries {
-            println!("Hash proof: Hashes match.");
-        } else {
-            println!("Hash proof: Hashes do not match.");
-        }   
-
-        // // Determine if the program is using the precompiles
-        // for (code, count) in &(*execution_report.syscall_counts) {
-        //     println!("Syscall {code:?} was called {count} times");
-        // }
-
-        // Read in previously generated proof to avoid unecessary computation
-        let (hash_pk, hash_vk) = client.setup(HASH_ELF);
-        let deserialized_hash_proof =
-            SP1ProofWithPublicValues::load("/client/output/hash_proof-with-pis.bin").expect("loading proof failed");
-        let input_1 = VerificationInput {
-                proof: deserialized_hash_proof,
-                vk: hash_vk.clone(),
-        };
-        // DEBUGGING SECTION END
-
-        // // PROOF GENERATION SECTION START
-        // //Generate the proof for the given program and input
-        // let (hash_pk, hash_vk) = client.setup(HASH_ELF);
-
-        // // Note that we use the "compressed" proof type, which is necessary for aggregation in SP1
-        // let mut hash_proof = client.prove(&hash_pk, hash_stdin).compressed().run().expect("proving failed");
-        // println!("Generated hash_proof");
-
-        // // Verify proof and public values
-        // client.verify(&hash_proof, &hash_vk).expect("verification failed");
-
-        // // Save the proof to file
-        // hash_proof.save("/client/output/hash_proof-with-pis.bin").expect("saving proof failed");
-
-        // // Create a VerificationInputs struct for the hash_proof
-        // let input_1 = VerificationInput {
-        //     proof: hash_proof,
-        //     vk: hash_vk.clone(),
-        // };
-        // // PROOF GENERATION SECTION END
-
-        // Hash the concatenated queries (to be used as random_modifier)
-        let hashed_concat_quries = Scalar::hash_from_bytes::<Sha3_512>(&concat_queries);
-
-        // write needed values to the input stream of checksum proof
-        checksum_stdin.write(&hashed_concat_quries.as_bytes());
-        checksum_stdin.write(&active_security_key);
-        checksum_stdin.write(&sum.compress().as_bytes());
-
-        // Added a parameter in randomized_target to accept a precomuted random_modifier
-        let randomized_target = active_security_key.randomized_target(hashed_concat_quries);
+        let randomized_target = target_builder.finalize(&active_security_key);
 
         let checksum = randomized_target.get_checksum_point_for_validation(&sum);
         let verification_factor_0 = Scalar::from(rng.gen_range(0u32..=verification_factor_max));
         let x_0 = checksum * verification_factor_0.invert();
-        let local_checksum_state = QueryState::from_rp(x_0, required_keyholders, verification_factor_0);
-
-        // write the remaining values to the input stream
-        checksum_stdin.write(&verification_factor_0.as_bytes());
-        checksum_stdin.write(&local_checksum_state.blinding_factor.as_bytes());
-
-        // DEBUGGING SECTION START
-        // Execute the checksum_proof program using the `ProverClient.execute` method,
-        let (mut checksum_public_values, execution_report) = client.execute(CHECKSUM_ELF, checksum_stdin.clone()).run().unwrap();
-        println!(
-            "Checksum program executed with {} cycles",
-            execution_report.total_instruction_count() + execution_report.total_syscall_count()
-        );
-
-        // Read the checksum_query that is generated in the program
-        let proof_checksum_query = checksum_public_values.read::<Query>();
-
-        // Confirm this output maches the query generated locally
-        if local_checksum_state.query.clone() == proof_checksum_query {
-            println!("Checksum proof: Checksums match.");
-        } else {
-            println!("Checksum proof: Checksums do not match.");
-        }
-
-        // Read in previously generated proof to avoid unecessary computation
-        let (checksum_pk, checksum_vk) = client.setup(CHECKSUM_ELF);
-        let deserialized_checksum_proof =
-            SP1ProofWithPublicValues::load("/client/output/checksum-proof-with-pis.bin").expect("loading proof failed");
-        let input_2 = VerificationInput {
-            proof: deserialized_checksum_proof,
-            vk: checksum_vk.clone(),
-        };
-        // DEBUGGING SECTION END
-
-        // Note: required secureDNA line, after check to not be consumed, DO NOT ALTER
-        querystates.push((None, local_checksum_state));
-
-        // // PROOF GENERATION SECTION START
-        // // Generate the proof for the given program and input
-        // let (checksum_pk, checksum_vk) = client.setup(CHECKSUM_ELF);
-        // let mut checksum_proof = client.prove(&checksum_pk, checksum_stdin).compressed().run().expect("proving failed");
-        // println!("Generated checksum_proof");
-
-        // // Verify proof and public values
-        // client.verify(&checksum_proof, &checksum_vk).expect("verification failed");
+        let checksum_state =
+            QueryState::from_rp_with_rng(x_0, required_keyholders, verification_factor_0, rng)?;
+        querystates.push((None, checksum_state));
 
-        // // Save the proof to file
-        // checksum_proof.save("/client/output/checksum-proof-with-pis.bin").expect("saving proof failed");
-
-        // // Create a VerificationInputs struct for the checksum_proof
-        // let input_2 = VerificationInput {
-        //     proof: checksum_proof,
-        //     vk: checksum_vk.clone(),
-        // };
-        // // PROOF GENERATION SECTION END
-
-        // Create the a vector of VerificationInputs
-        let inputs = vec![input_1, input_2];
-
-        (Self {
+        Ok(Self {
             querystates,
             randomized_target,
-        }, 
-        inputs)
+            parallel_threshold: None,
+            canonical_response_order: false,
+            #[cfg(feature = "shadow_validation")]
+            shadow_target: None,
+        })
+    }
+
+    /// The querystates, windows first and the checksum last, for proving the
+    /// set was built honestly.
+    pub(crate) fn querystates(&self) -> &[(Option<HashTag>, QueryState)] {
+        &self.querystates
+    }
+
+    /// The sum of the windows' unblinded points, weighted by their
+    /// verification factors, which the checksum was derived from.
+    pub(crate) fn verification_weighted_sum(&self) -> RistrettoPoint {
+        self.querystates
+            .iter()
+            .filter(|(tag, _)| tag.is_some())
+            .map(|(_, qs)| {
+                qs.query.to_rp() * (qs.verification_factor * qs.blinding_factor.invert())
+            })
+            .sum()
     }
 
     pub fn len(&self) -> usize {
@@ -492,6 +758,49 @@ impl QueryStateSet {
         self.querystates.iter().map(|qs| qs.1.query())
     }
 
+    /// The tag of each querystate, in the same order as [`Self::queries`].
+    ///
+    /// The checksum querystate is always included, as the final `None`, so that
+    /// the tags line up index-for-index with the queries sent to keyservers.
+    pub fn tags(&self) -> impl Iterator<Item = Option<HashTag>> + '_ {
+        self.querystates.iter().map(|qs| qs.0)
+    }
+
+    /// How many querystates each keyserver that has responded contributed to.
+    ///
+    /// A complete response covers all [`Self::len`] of them, so a lower count
+    /// shows which keyserver under-delivered.
+    pub fn response_counts(&self) -> BTreeMap<KeyserverId, usize> {
+        let mut counts = BTreeMap::new();
+        for (_, qs) in &self.querystates {
+            for (id, _) in &qs.responses {
+                *counts.entry(*id).or_insert(0) += 1;
+            }
+        }
+        counts
+    }
+
+    /// The keyservers in `expected` that haven't responded to every querystate,
+    /// e.g. to say who is to blame for [`QueryError::MissingKeyserverResponse`].
+    pub fn missing_keyservers(&self, expected: &KeyserverIdSet) -> Vec<KeyserverId> {
+        let counts = self.response_counts();
+        expected
+            .iter()
+            .filter(|id| counts.get(*id).copied().unwrap_or(0) < self.len())
+            .copied()
+            .collect()
+    }
+
+    /// Incorporate keyserver `id`'s response, which must contain one part per
+    /// query, in the same order as [`Self::queries`].
+    ///
+    /// Only the structure of the response is checked here: its length, that
+    /// each part is a valid point, and that `id` hasn't already responded. A
+    /// well-formed response to the wrong
+    /// queries (e.g. a stale or cached one) is caught by the active security
+    /// checksum, either for `id` alone with
+    /// [`Self::validate_keyserver_contribution`], or in [`Self::get_hash_values`],
+    /// which blames the keyservers whose contribution doesn't validate.
     pub fn incorporate_response(
         &mut self,
         id: KeyserverId,
@@ -500,68 +809,305 @@ impl QueryStateSet {
         if parts.len() != self.len() {
             return Err(QueryError::WrongSizeResponse);
         }
+        self.incorporate_partial_response(id, 0, parts)
+    }
 
-        for (i, &part) in parts.iter().enumerate() {
-            self.querystates[i].1.incorporate_response(id, part);
+    /// Like [`Self::incorporate_response`], but for a chunk of keyserver `id`'s
+    /// response: `parts` answer the queries from `start_index` on. Chunks can
+    /// arrive in any order, so responses can be streamed or sharded; each
+    /// querystate only counts the keyservers that have answered it, so
+    /// [`Self::all_have_hash`] waits for every chunk.
+    ///
+    /// Fails with [`QueryError::WrongSizeResponse`] if the chunk runs past the
+    /// last query, and [`QueryError::DuplicateResponse`] if `id` has already
+    /// answered any of its queries. Parts are only decompressed when the hashes
+    /// are reconstructed, which fails with [`QueryError::InvalidResponse`] for
+    /// one that isn't a valid point.
+    pub fn incorporate_partial_response(
+        &mut self,
+        id: KeyserverId,
+        start_index: usize,
+        parts: &[HashPart],
+    ) -> Result<(), QueryError> {
+        let chunk = start_index
+            .checked_add(parts.len())
+            .filter(|&end| end <= self.len())
+            .map(|end| start_index..end)
+            .ok_or(QueryError::WrongSizeResponse)?;
+        let already_responded = self.querystates[chunk.clone()]
+            .iter()
+            .any(|(_, qs)| qs.has_response_from(id));
+        if already_responded {
+            return Err(QueryError::DuplicateResponse(id));
+        }
+        for (i, &part) in chunk.zip(parts) {
+            let qs = &mut self.querystates[i].1;
+            if self.canonical_response_order {
+                qs.incorporate_response_in_order(id, part);
+            } else {
+                qs.incorporate_response(id, part);
+            }
         }
 
         Ok(())
     }
 
+    /// Incorporate responses and compute hashes across threads for batches of at
+    /// least `threshold` querystates, and serially for smaller ones. Only has an
+    /// effect with the `parallel` feature.
+    pub fn set_parallel_threshold(&mut self, threshold: usize) {
+        self.parallel_threshold = Some(threshold);
+    }
+
+    /// Keep each query's responses sorted by [`KeyserverId`] instead of in the
+    /// order they were incorporated. The hashes don't depend on the order, but
+    /// [`Self::to_serializable_set`] then does not depend on arrival order either,
+    /// which makes captured sets reproducible.
+    pub fn set_canonical_response_order(&mut self, canonical: bool) {
+        self.canonical_response_order = canonical;
+    }
+
+    /// `items.iter().map(f)`, collected, but across threads if there are at
+    /// least as many items as the parallel threshold.
+    fn map_batch<T: Sync, R: Send>(
+        &self,
+        items: &[T],
+        f: impl Fn(&T) -> R + Sync + Send,
+    ) -> Vec<R> {
+        #[cfg(feature = "parallel")]
+        {
+            use rayon::prelude::*;
+
+            let threshold = self
+                .parallel_threshold
+                .unwrap_or(DEFAULT_PARALLEL_THRESHOLD);
+            if items.len() >= threshold {
+                return items.par_iter().map(f).collect();
+            }
+        }
+        items.iter().map(f).collect()
+    }
+
+    /// Whether every querystate has enough responses to reconstruct its hash.
+    /// Partial responses only count towards the querystates they answered.
     pub fn all_have_hash(&self) -> bool {
         self.querystates.iter().all(|qs| qs.1.has_hash())
     }
 
+    /// Also validate keyserver responses against `shadow_target` in
+    /// [`Self::get_hash_values`], logging whether it agrees with
+    /// `randomized_target`. The shadow target never affects the result.
+    #[cfg(feature = "shadow_validation")]
+    pub fn set_shadow_target(&mut self, shadow_target: RandomizedTarget) {
+        self.shadow_target = Some(shadow_target);
+    }
+
+    #[cfg(feature = "shadow_validation")]
+    fn shadow_validate(&self, verifier: &RistrettoPoint, primary_valid: bool) {
+        let Some(shadow_target) = &self.shadow_target else {
+            return;
+        };
+        let shadow_valid = shadow_target.validate_responses(verifier);
+        if shadow_valid == primary_valid {
+            tracing::debug!("shadow active security validation agrees (valid: {primary_valid})");
+        } else {
+            tracing::warn!(
+                "shadow active security validation disagrees: \
+                 primary valid: {primary_valid}, shadow valid: {shadow_valid}"
+            );
+        }
+    }
+
     /// This function will also most likely block for a long time, `async` callers should `spawn_blocking`
+    ///
+    /// The set isn't consumed: the checksum hash is only dropped from the returned
+    /// hashes, so calling this again gives the same result.
+    ///
+    /// If the active security checksum doesn't validate, returns
+    /// [`QueryError::ValidationFailed`] with the keyservers whose contributions are invalid,
+    /// and the difference between the expected and actual verifier.
     pub fn get_hash_values(&self) -> Result<Vec<TaggedHash>, QueryError> {
         if !self.all_have_hash() {
             return Err(QueryError::MissingKeyserverResponse);
         }
-        let (mut hashes, verifier) = self
+        let points: Vec<RistrettoPoint> = self
+            .map_batch(&self.querystates, |(_, qs)| {
+                Ok(qs
+                    .calculate_hash_value()?
+                    .expect("all_have_hash is true but get_hash_value is None!"))
+            })
+            .into_iter()
+            .collect::<Result<_, QueryError>>()?;
+        let verifier = self.batched_verifier(&points);
+        let hashes = self
             .querystates
             .iter()
-            .map(|(tag, qs)| {
-                let (hash, verification) = qs
-                    .get_hash_value_and_verification_value()
-                    .expect("all_have_hash is true but get_hash_value is None!");
-                let tag = (*tag).unwrap_or_default();
-                (TaggedHash { tag, hash }, verification)
+            .zip(&points)
+            .map(|((tag, _), point)| TaggedHash {
+                tag: (*tag).unwrap_or_default(),
+                hash: CompletedHashValue::from_rp(*point),
             })
-            .fold(
-                (Vec::new(), RistrettoPoint::identity()),
-                |(mut acc_hashes, acc_verifier), (hash, verifier)| {
-                    acc_hashes.push(hash);
-                    (acc_hashes, acc_verifier + verifier)
-                },
-            );
+            .collect();
+        self.validate_hash_values(hashes, verifier)
+    }
+
+    /// Sum of each hash point times its querystate's verification factor, as a
+    /// single multiscalar multiplication: much faster than summing the products.
+    /// curve25519-dalek runs this on its AVX2 backend where the CPU supports it,
+    /// falling back to the serial backend elsewhere, with identical results.
+    fn batched_verifier(&self, points: &[RistrettoPoint]) -> RistrettoPoint {
+        RistrettoPoint::vartime_multiscalar_mul(
+            self.querystates
+                .iter()
+                .map(|(_, qs)| qs.verification_factor),
+            points,
+        )
+    }
+
+    /// Like [`Self::get_hash_values`], but gives up once `deadline` has passed,
+    /// returning [`HashValues::Pending`] so the caller can reschedule.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn get_hash_values_deadline(
+        &self,
+        deadline: std::time::Instant,
+    ) -> Result<HashValues, QueryError> {
+        if !self.all_have_hash() {
+            return Err(QueryError::MissingKeyserverResponse);
+        }
+        let total = self.querystates.len();
+        let mut hashes = Vec::with_capacity(total);
+        let mut verifier = RistrettoPoint::identity();
+        for (processed, querystate) in self.querystates.iter().enumerate() {
+            if std::time::Instant::now() >= deadline {
+                return Ok(HashValues::Pending { processed, total });
+            }
+            let (hash, verification) = Self::hash_and_verification_value(querystate)?;
+            hashes.push(hash);
+            verifier += verification;
+        }
+        self.validate_hash_values(hashes, verifier)
+            .map(HashValues::Complete)
+    }
+
+    fn hash_and_verification_value(
+        (tag, qs): &(Option<HashTag>, QueryState),
+    ) -> Result<(TaggedHash, RistrettoPoint), QueryError> {
+        let (hash, verification) = qs
+            .get_hash_value_and_verification_value()?
+            .expect("all_have_hash is true but get_hash_value is None!");
+        let tag = (*tag).unwrap_or_default();
+        Ok((TaggedHash { tag, hash }, verification))
+    }
 
-        if self.randomized_target.validate_responses(&verifier) {
+    /// Check the summed verification values against the target, and strip the checksum hash.
+    fn validate_hash_values(
+        &self,
+        mut hashes: Vec<TaggedHash>,
+        verifier: RistrettoPoint,
+    ) -> Result<Vec<TaggedHash>, QueryError> {
+        let valid = self.randomized_target.validate_responses(&verifier);
+        #[cfg(feature = "shadow_validation")]
+        self.shadow_validate(&verifier, valid);
+
+        if valid {
             hashes.pop();
             Ok(hashes)
         } else {
             let keyservers_responsible = self.find_keyservers_with_invalid_contribution();
-            Err(QueryError::ValidationFailed(keyservers_responsible))
+            Err(QueryError::ValidationFailed {
+                keyservers: keyservers_responsible,
+                discrepancy: self.randomized_target.discrepancy(&verifier),
+            })
         }
     }
 
-    fn find_keyservers_with_invalid_contribution(&self) -> Vec<KeyserverId> {
-        let mut individual_sums: BTreeMap<KeyserverId, RistrettoPoint> = BTreeMap::new();
+    /// Check keyserver `id`'s contribution on its own, as soon as its response
+    /// has been incorporated, rather than waiting for the rest of `quorum` and
+    /// [`Self::get_hash_values`]. `quorum` is the set of keyservers the
+    /// queries were sent to, which determines each one's Lagrange coefficient.
+    ///
+    /// Fails with [`QueryError::ValidationFailed`] blaming only `id` if its
+    /// contribution is invalid, [`QueryError::InvalidResponse`] if one of its
+    /// parts isn't a valid point, or [`QueryError::MissingKeyserverResponse`]
+    /// if it hasn't responded.
+    pub fn validate_keyserver_contribution(
+        &self,
+        quorum: &KeyserverIdSet,
+        id: KeyserverId,
+    ) -> Result<(), QueryError> {
+        let mut sum = RistrettoPoint::identity();
         for (_, qs) in &self.querystates {
-            let modification = qs.blinding_factor.invert() * qs.verification_factor;
-            for (id, hash) in &qs.responses {
-                let sum = individual_sums
-                    .entry(*id)
-                    .or_insert(RistrettoPoint::identity());
-                *sum += hash.to_rp() * modification;
-            }
+            let (_, hash) = qs
+                .responses
+                .iter()
+                .find(|(i, _)| *i == id)
+                .ok_or(QueryError::MissingKeyserverResponse)?;
+            let point = hash
+                .try_to_rp()
+                .map_err(|_| QueryError::InvalidResponse(id))?;
+            sum += point * (qs.blinding_factor.invert() * qs.verification_factor);
         }
-        let keyservers = KeyserverIdSet::from_iter(individual_sums.keys().cloned());
-        individual_sums
+        if self
+            .randomized_target
+            .is_keyserver_response_valid(quorum, &id, &sum)
+        {
+            Ok(())
+        } else {
+            Err(QueryError::ValidationFailed {
+                keyservers: vec![id],
+                discrepancy: self
+                    .randomized_target
+                    .keyserver_discrepancy(quorum, &id, &sum),
+            })
+        }
+    }
+
+    /// Each keyserver's contribution to the verifier: the sum of its
+    /// responses, unblinded and weighted like the verification values.
+    ///
+    /// A correct keyserver's contribution is
+    /// [`RandomizedTarget::expected_keyserver_contribution`] for its share.
+    /// Keyservers with a part that isn't a valid point are left out.
+    pub fn observed_keyserver_contributions(&self) -> BTreeMap<KeyserverId, RistrettoPoint> {
+        self.individual_sums()
+            .into_iter()
+            .filter_map(|(id, sum)| Some((id, sum?)))
+            .collect()
+    }
+
+    /// Each responding keyserver's contribution, or `None` if one of its parts
+    /// isn't a valid point.
+    fn individual_sums(&self) -> BTreeMap<KeyserverId, Option<RistrettoPoint>> {
+        let mut individual_sums: BTreeMap<KeyserverId, Option<RistrettoPoint>> = BTreeMap::new();
+        for (_, qs) in &self.querystates {
+            let modification = qs.blinding_factor.invert() * qs.verification_factor;
+            for (id, hash) in &qs.responses {
+                let sum = individual_sums
+                    .entry(*id)
+                    .or_insert(Some(RistrettoPoint::identity()));
+                *sum = sum
+                    .zip(hash.try_to_rp().ok())
+                    .map(|(sum, point)| sum + point * modification);
+            }
+        }
+        individual_sums
+    }
+
+    /// The keyservers whose [`Self::observed_keyserver_contributions`] don't match
+    /// what their share of the active security key predicts.
+    ///
+    /// Only meaningful once every keyserver's responses have been incorporated.
+    /// A keyserver with a part that isn't a valid point is always included.
+    pub fn find_keyservers_with_invalid_contribution(&self) -> Vec<KeyserverId> {
+        let individual_sums = self.individual_sums();
+        let keyservers = KeyserverIdSet::from_iter(individual_sums.keys().cloned());
+        individual_sums
             .into_iter()
             .filter(|(id, sum)| {
-                !self
-                    .randomized_target
-                    .is_keyserver_response_valid(&keyservers, id, sum)
+                !sum.is_some_and(|sum| {
+                    self.randomized_target
+                        .is_keyserver_response_valid(&keyservers, id, &sum)
+                })
             })
             .map(|(id, _)| id)
             .collect()
@@ -591,26 +1137,94 @@ pub fn generate_keyshares(
     if num_keyholders < required_keyholders {
         return Err(UnreachableQuorumError {
             required_keyholders,
-            num_keyholders,
+            num_keyholders: num_keyholders.get(),
         });
     }
-    let mut control_points = vec![secret_key.0];
+    // Allocated up front so that growing it doesn't leave unwiped copies behind.
+    let mut control_points = Zeroizing::new(Vec::with_capacity(required_keyholders.get() as usize));
+    control_points.push(*secret_key.0);
     control_points.extend((1..required_keyholders.get()).map(|_| Scalar::random(rng)));
     let keyshares = (1..=num_keyholders.get())
         .map(|x| {
             let cached = control_points.get(x as usize).copied();
-            let lagrange_curve_at_x =
-                cached.unwrap_or_else(|| evaluate_lagrange_polynomial(&control_points, x));
-            KeyShare(lagrange_curve_at_x)
+            let lagrange_curve_at_x = cached
+                .unwrap_or_else(|| evaluate_lagrange_polynomial(control_points.as_slice(), x));
+            KeyShare::from(lagrange_curve_at_x)
         })
         .collect();
     Ok(keyshares)
 }
 
+/// Generates keyshares of zero, for refreshing existing keyshares with
+/// [`KeyShare::add`] without changing the secret they reconstruct.
+///
+/// Every keyholder must add its zero share, and the shares' commitments in the
+/// active security key change with them.
+#[cfg(any(feature = "centralized_keygen", test))]
+pub fn generate_zero_shares(
+    required_keyholders: NonZeroU32,
+    num_keyholders: NonZeroU32,
+    rng: &mut (impl RngCore + CryptoRng),
+) -> Result<Vec<KeyShare>, UnreachableQuorumError> {
+    let zero = KeyShare::from(Scalar::ZERO);
+    generate_keyshares(&zero, required_keyholders, num_keyholders, rng)
+}
+
+/// Like [`generate_keyshares`], but with randomness from a ChaCha20 stream
+/// seeded with `seed`, for reproducible test vectors. The output is the same on
+/// every platform: each of the `required_keyholders - 1` random control points
+/// is the next 64 bytes of the stream, read as a little-endian integer mod ℓ.
+///
+/// Never use this for real keys.
+#[cfg(feature = "centralized_keygen")]
+pub fn generate_keyshares_from_seed(
+    secret_key: &KeyShare,
+    required_keyholders: NonZeroU32,
+    num_keyholders: NonZeroU32,
+    seed: [u8; 32],
+) -> Result<Vec<KeyShare>, UnreachableQuorumError> {
+    use rand_chacha::rand_core::SeedableRng;
+
+    let mut rng = rand_chacha::ChaCha20Rng::from_seed(seed);
+    generate_keyshares(secret_key, required_keyholders, num_keyholders, &mut rng)
+}
+
+/// Reconstructs the secret key split by [`generate_keyshares`] from at least
+/// `required_keyholders` of its keyshares, each paired with the id of the
+/// keyholder it was generated for (the `i`th keyshare is for id `i + 1`).
+///
+/// For key ceremonies and disaster recovery; the reconstructed secret should
+/// never exist anywhere else.
+#[cfg(any(feature = "centralized_keygen", test))]
+pub fn reconstruct_secret(
+    shares: &[(KeyserverId, KeyShare)],
+    required_keyholders: NonZeroU32,
+) -> Result<KeyShare, ReconstructSecretError> {
+    let mut ids = BTreeSet::new();
+    if let Some((id, _)) = shares.iter().find(|(id, _)| !ids.insert(*id)) {
+        return Err(ReconstructSecretError::DuplicateKeyholder(*id));
+    }
+    let num_keyholders = u32::try_from(shares.len()).unwrap_or(u32::MAX);
+    if num_keyholders < required_keyholders.get() {
+        return Err(UnreachableQuorumError {
+            required_keyholders,
+            num_keyholders,
+        }
+        .into());
+    }
+
+    let x_coords: Vec<Scalar> = shares.iter().map(|(id, _)| id.to_scalar()).collect();
+    let secret = lagrange_coefficients_at_zero(&x_coords)
+        .zip(shares)
+        .map(|(coefficient, (_, share))| coefficient * &*share.0)
+        .sum::<Scalar>();
+    Ok(KeyShare::from(secret))
+}
+
 #[derive(Debug, Clone)]
 pub struct UnreachableQuorumError {
     required_keyholders: NonZeroU32,
-    num_keyholders: NonZeroU32,
+    num_keyholders: u32,
 }
 
 impl fmt::Display for UnreachableQuorumError {
@@ -623,11 +1237,58 @@ impl fmt::Display for UnreachableQuorumError {
 
 impl Error for UnreachableQuorumError {}
 
+/// The keyshares given to [`reconstruct_secret`] can't reconstruct a secret.
+#[derive(Debug, Clone)]
+pub enum ReconstructSecretError {
+    UnreachableQuorum(UnreachableQuorumError),
+    /// More than one keyshare was given for this keyholder.
+    DuplicateKeyholder(KeyserverId),
+}
+
+impl fmt::Display for ReconstructSecretError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnreachableQuorum(e) => e.fmt(f),
+            Self::DuplicateKeyholder(id) => write!(f, "keyholder {id} was given more than once"),
+        }
+    }
+}
+
+impl Error for ReconstructSecretError {}
+
+impl From<UnreachableQuorumError> for ReconstructSecretError {
+    fn from(e: UnreachableQuorumError) -> Self {
+        Self::UnreachableQuorum(e)
+    }
+}
+
+/// A query was constructed with a quorum of zero keyholders.
+#[derive(Debug, Clone)]
+pub struct ZeroQuorumError;
+
+impl fmt::Display for ZeroQuorumError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "A quorum requires at least one keyholder")
+    }
+}
+
+impl Error for ZeroQuorumError {}
+
 #[derive(Debug, Clone)]
 pub enum DecodeError {
     HexError(FromHexError),
     InvalidRistrettoPoint,
     InvalidScalar,
+    /// A [`HashTag`] had reserved bits set.
+    InvalidHashTag,
+    /// A query state required zero keyholders.
+    ZeroQuorum(ZeroQuorumError),
+}
+
+impl From<ZeroQuorumError> for DecodeError {
+    fn from(value: ZeroQuorumError) -> Self {
+        Self::ZeroQuorum(value)
+    }
 }
 
 impl fmt::Display for DecodeError {
@@ -636,6 +1297,8 @@ impl fmt::Display for DecodeError {
             Self::HexError(fhe) => format!("Could not decode hexadecimal: {}", fhe),
             Self::InvalidRistrettoPoint => "Value was not a valid Ristretto point".to_string(),
             Self::InvalidScalar => "Value was not a valid Ristretto Scalar".to_string(),
+            Self::InvalidHashTag => "Hash tag had reserved bits set".to_string(),
+            Self::ZeroQuorum(zqe) => zqe.to_string(),
         };
         write!(f, "{}", s)
     }
@@ -645,13 +1308,13 @@ impl Error for DecodeError {}
 
 impl From<Scalar> for KeyShare {
     fn from(value: Scalar) -> Self {
-        Self(value)
+        Self(Zeroizing::new(value))
     }
 }
 
-impl fmt::Display for KeyShare {
+impl fmt::Debug for KeyShare {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", hex::encode(self.0.as_bytes()))
+        write!(f, "KeyShare(<redacted>)")
     }
 }
 
@@ -663,21 +1326,40 @@ impl FromStr for KeyShare {
             .map_err(DecodeError::HexError)?
             .try_into()
             .map_err(|_| DecodeError::InvalidScalar)?;
-        if let Some(scalar) = Scalar::from_canonical_bytes(bytes).into() {
-            Ok(KeyShare(scalar))
+        if let Some(scalar) = Option::<Scalar>::from(Scalar::from_canonical_bytes(bytes)) {
+            Ok(KeyShare::from(scalar))
         } else {
             Err(DecodeError::InvalidScalar)
         }
     }
 }
 
+/// Hex for human-readable formats like JSON, and the scalar's 32 bytes for
+/// binary ones like bincode, as curve25519-dalek does for [`Scalar`].
+impl Serialize for KeyShare {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.serialize_str(&self.reveal_hex())
+        } else {
+            self.0.as_bytes().serialize(serializer)
+        }
+    }
+}
+
 impl<'de> Deserialize<'de> for KeyShare {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
         D: Deserializer<'de>,
     {
-        let s = String::deserialize(deserializer)?;
-        FromStr::from_str(&s).map_err(de::Error::custom)
+        if deserializer.is_human_readable() {
+            let s = String::deserialize(deserializer)?;
+            FromStr::from_str(&s).map_err(de::Error::custom)
+        } else {
+            let bytes = Zeroizing::new(<[u8; 32]>::deserialize(deserializer)?);
+            canonical_scalar(*bytes)
+                .map(KeyShare::from)
+                .map_err(de::Error::custom)
+        }
     }
 }
 
@@ -687,12 +1369,6 @@ impl Query {
     }
 }
 
-impl HashPart {
-    fn to_rp(self) -> RistrettoPoint {
-        self.0.decompress().unwrap() // already verified to be valid via try_from_buf
-    }
-}
-
 //Todo: why not in blanket impl?
 impl CompletedHashValue {
     pub fn to_rp(self) -> RistrettoPoint {
@@ -711,6 +1387,14 @@ macro_rules! impls_for_ristretto_point {
                 $type_(point.compress())
             }
 
+            /// Decompress this point, erroring rather than panicking if it was
+            /// built from bytes that were never validated.
+            pub fn try_to_rp(self) -> Result<RistrettoPoint, DecodeError> {
+                self.0
+                    .decompress()
+                    .ok_or(DecodeError::InvalidRistrettoPoint)
+            }
+
             fn try_from_buf(bytes: &[u8; 32]) -> Result<Self, DecodeError> {
                 let compressed_rp = CompressedRistretto::from_slice(bytes)
                     .map_err(|_| DecodeError::InvalidRistrettoPoint)?;
@@ -804,9 +1488,24 @@ impls_for_ristretto_point!(Query);
 impls_for_ristretto_point!(HashPart);
 impls_for_ristretto_point!(CompletedHashValue);
 
+// Which of two points differs, and where, can depend on secrets, so these are
+// compared in constant time.
+macro_rules! impl_ct_eq {
+    ($($type_:ident),*) => {$(
+        impl ConstantTimeEq for $type_ {
+            fn ct_eq(&self, other: &Self) -> Choice {
+                self.0.ct_eq(&other.0)
+            }
+        }
+    )*};
+}
+
+impl_ct_eq!(Query, HashPart, CompletedHashValue);
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use curve25519_dalek::constants::RISTRETTO_BASEPOINT_POINT;
     use curve25519_dalek::scalar::Scalar;
     use itertools::Itertools;
     use quickcheck::{quickcheck, Arbitrary, Gen};
@@ -939,14 +1638,27 @@ mod tests {
         windows: impl IntoIterator<Item = impl AsRef<[u8]>>,
         target: ActiveSecurityKey,
     ) -> Result<Vec<CompletedHashValue>, QueryError> {
-        let mut querystates = QueryStateSet::from_iter(
+        query_via_keyshares(keyshares, windows, target)
+            .get_hash_values()
+            .map(|v| v.iter().map(|x| x.hash).collect())
+    }
+
+    /// Build a querystate set for `windows` with every chosen keyserver's response incorporated.
+    fn query_via_keyshares(
+        keyshares: &KeyShares,
+        windows: impl IntoIterator<Item = impl AsRef<[u8]>>,
+        target: ActiveSecurityKey,
+    ) -> QueryStateSet {
+        let mut querystates = QueryStateSet::build(
             windows
                 .into_iter()
                 .enumerate()
                 .map(|(i, x)| (HashTag::new(i == 0, 0, i), x)),
             keyshares.chosen_keyservers.len(),
             target,
-        );
+            SecurityParameter::default(),
+        )
+        .unwrap();
         let keyserver_ids: KeyserverIdSet = keyshares
             .chosen_keyservers
             .iter()
@@ -954,65 +1666,241 @@ mod tests {
             .collect();
         for (ks_id, key) in keyshares.chosen_keyservers_and_shares() {
             let coeff = keyserver_ids.langrange_coefficient_for_id(&ks_id);
-            let hashparts: Vec<_> = querystates
-                .queries()
-                .map(|q| key.apply_query_and_lagrange_coefficient(*q, &coeff))
-                .collect();
+            let queries: Vec<_> = querystates.queries().copied().collect();
+            let hashparts = key.apply_batch(&queries, &coeff);
             querystates.incorporate_response(ks_id, &hashparts).unwrap();
         }
         querystates
-            .get_hash_values()
-            .map(|v| v.iter().map(|x| x.hash).collect())
     }
 
-    // Finds a message for which distributed key hashing doesn't match single-key hashing
-    fn find_message_with_mismatching_hashes<'a>(
-        keys: KeyShares,
-        messages: impl IntoIterator<Item = &'a [u8]> + Clone,
-        target: ActiveSecurityKey,
-    ) -> Option<&'a [u8]> {
-        let distributed_hashes = hash_via_keyshares(&keys, messages.clone(), target).unwrap();
-        for (message, distributed_hash) in messages.into_iter().zip(distributed_hashes) {
-            let query = Query::hash_from_bytes_for_tests_only(message);
-            let single_key_hash = keys.secret.apply(query);
-            if <[u8; 32]>::from(distributed_hash) != <[u8; 32]>::from(single_key_hash) {
-                return Some(message);
-            }
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn small_batches_hash_serially_and_large_ones_in_parallel() {
+        let keys = KeyShares::random(&mut OsRng);
+        let keyholders_required = NonZeroU32::new(keys.chosen_keyservers.len() as u32).unwrap();
+        let target = ActiveSecurityKey::from_secret_and_keyshares(
+            &keys.secret,
+            &keys.shares,
+            keyholders_required,
+        )
+        .unwrap();
+        let windows: Vec<_> = (0..8).map(|i| format!("window {i}")).collect();
+        let mut set = query_via_keyshares(&keys, &windows, target);
+
+        // Work run on rayon's pool has a thread index; work run serially on
+        // the test's own thread doesn't.
+        set.set_parallel_threshold(4);
+        let probe = |n: usize| set.map_batch(&vec![(); n], |_| rayon::current_thread_index());
+        assert!(probe(3).iter().all(Option::is_none));
+        assert!(probe(8).iter().all(Option::is_some));
+
+        // 9 querystates, counting the checksum.
+        let hashes = |set: &QueryStateSet| -> Vec<_> {
+            let hashes = set.get_hash_values().unwrap();
+            hashes
+                .into_iter()
+                .map(|h| (h.tag, <[u8; 32]>::from(h.hash)))
+                .collect()
+        };
+        set.set_parallel_threshold(10);
+        let serial = hashes(&set);
+        set.set_parallel_threshold(4);
+        assert_eq!(hashes(&set), serial);
+    }
+
+    #[test]
+    fn apply_batch_matches_applying_each_query_in_order() {
+        let share = KeyShare::from(Scalar::random(&mut OsRng));
+        let coefficient = Scalar::random(&mut OsRng);
+        let queries: Vec<_> = (0..2 * DEFAULT_PARALLEL_THRESHOLD)
+            .map(|i| Query::hash_from_bytes_for_tests_only(format!("window {i}").as_bytes()))
+            .collect();
+
+        let batch = share.apply_batch(&queries, &coefficient);
+        assert_eq!(batch.len(), queries.len());
+        for (query, part) in queries.iter().zip(&batch) {
+            let expected = share.apply_query_and_lagrange_coefficient(*query, &coefficient);
+            assert_eq!(part.as_bytes(), expected.as_bytes());
         }
-        None
     }
 
-    // Quick sanity check... Run with -- --ignored to do more comprehensive tests
     #[test]
-    fn test_batched_distributed_encryption_matches_single_key_encryption() {
+    fn tags_follow_input_order_and_end_with_checksum() {
+        let tags = [
+            HashTag::new(true, 0, 0),
+            HashTag::new(false, 0, 1),
+            HashTag::new(true, 1, 0),
+        ];
+        let mut querystates: Vec<_> = tags
+            .iter()
+            .map(|&tag| (Some(tag), QueryState::new(b"acgt", 1).unwrap()))
+            .collect();
+        querystates.push((None, QueryState::new(b"checksum", 1).unwrap()));
+        let set = QueryStateSet {
+            querystates,
+            ..Default::default()
+        };
+
+        let expected: Vec<_> = tags.into_iter().map(Some).chain([None]).collect();
+        assert_eq!(set.tags().collect::<Vec<_>>(), expected);
+        assert_eq!(set.tags().count(), set.queries().count());
+
+        let round_tripped = set.to_serializable_set().to_query_state_set().unwrap();
+        assert_eq!(round_tripped.tags().collect::<Vec<_>>(), expected);
+    }
+
+    #[test]
+    fn stale_keyserver_response_is_rejected_with_blame() {
         let keys = KeyShares::random(&mut OsRng);
         let keyholders_required = NonZeroU32::new(keys.chosen_keyservers.len() as u32).unwrap();
-
         let target = ActiveSecurityKey::from_secret_and_keyshares(
             &keys.secret,
             &keys.shares,
             keyholders_required,
         )
         .unwrap();
-        let messages = [
-            "foobar",
-            "The five boxing wizards jump quickly.",
-            "",
-            "acgtacgtacgt",
-            "xyzzy",
-        ]
-        .map(AsRef::<[u8]>::as_ref);
+
+        let windows = ["acgtacgtacgt", "xyzzy", "foobar"];
+        let tagged = || {
+            windows
+                .iter()
+                .enumerate()
+                .map(|(i, x)| (HashTag::new(i == 0, 0, i), x))
+        };
+        let security_parameter = SecurityParameter::default();
+        let mut querystates = QueryStateSet::build(
+            tagged(),
+            keys.chosen_keyservers.len(),
+            target.clone(),
+            security_parameter,
+        )
+        .unwrap();
+        // An earlier request for the same windows, with different blinding.
+        let stale = QueryStateSet::build(
+            tagged(),
+            keys.chosen_keyservers.len(),
+            target,
+            security_parameter,
+        )
+        .unwrap();
+
+        let keyserver_ids: KeyserverIdSet = keys
+            .chosen_keyservers
+            .iter()
+            .map(|index| KeyserverId::try_from(*index as u32 + 1).unwrap())
+            .collect();
+        let mut stale_keyserver = None;
+        for (ks_id, key) in keys.chosen_keyservers_and_shares() {
+            let coeff = keyserver_ids.langrange_coefficient_for_id(&ks_id);
+            // The first keyserver answers the earlier request's queries instead of ours.
+            let answered = if stale_keyserver.is_none() {
+                stale_keyserver = Some(ks_id);
+                &stale
+            } else {
+                &querystates
+            };
+            let hashparts: Vec<_> = answered
+                .queries()
+                .map(|q| key.apply_query_and_lagrange_coefficient(*q, &coeff))
+                .collect();
+            querystates.incorporate_response(ks_id, &hashparts).unwrap();
+
+            // Responding twice is rejected outright.
+            assert!(matches!(
+                querystates.incorporate_response(ks_id, &hashparts),
+                Err(QueryError::DuplicateResponse(id)) if id == ks_id
+            ));
+        }
+
+        match querystates.get_hash_values() {
+            Err(QueryError::ValidationFailed {
+                keyservers: blamed, ..
+            }) => {
+                assert_eq!(blamed, vec![stale_keyserver.unwrap()])
+            }
+            other => panic!("expected validation failure, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn query_modifier_hashes_the_concatenated_queries() {
+        let keys = KeyShares::random(&mut OsRng);
+        let target = ActiveSecurityKey::from_secret_and_keyshares(
+            &keys.secret,
+            &keys.shares,
+            NonZeroU32::new(keys.chosen_keyservers.len() as u32).unwrap(),
+        )
+        .unwrap();
+
+        let samples: [&[&str]; 3] = [&[], &["foobar"], &["acgtacgtacgt", "xyzzy", "foobar"]];
+        for windows in samples {
+            let queries: Vec<_> = windows
+                .iter()
+                .map(|w| Query::hash_from_bytes_for_tests_only(w.as_bytes()))
+                .collect();
+            let concat_queries: Vec<u8> = queries.iter().flat_map(|q| *q.as_bytes()).collect();
+            assert_eq!(
+                compute_query_modifier(&queries),
+                Scalar::hash_from_bytes::<Sha3_512>(&concat_queries)
+            );
+        }
+
+        // The host's modifier, which the checksum guest recomputes from the
+        // same (non-checksum) queries.
+        let windows = ["acgtacgtacgt", "xyzzy", "foobar"];
+        let querystates = QueryStateSet::build(
+            windows
+                .iter()
+                .enumerate()
+                .map(|(i, x)| (HashTag::new(i == 0, 0, i), x)),
+            keys.chosen_keyservers.len(),
+            target,
+            SecurityParameter::default(),
+        )
+        .unwrap();
+        let queries: Vec<_> = querystates.queries().take(windows.len()).copied().collect();
         assert_eq!(
-            find_message_with_mismatching_hashes(keys, messages, target),
-            None
+            compute_query_modifier(&queries),
+            querystates.randomized_target.random_modifier
         );
     }
 
     #[test]
-    fn corrupted_keyservers_cause_query_error_and_are_correctly_identified() {
-        let mut keys = KeyShares::random(&mut OsRng);
-        let keyholders_required = NonZeroU32::new(keys.chosen_keyservers.len() as u32).unwrap();
+    fn checksum_is_derived_from_the_verification_weighted_sum() {
+        let target = ActiveSecurityKey::from_commitments([
+            crate::active_security::Commitment::hash_from_bytes_for_tests_only(&[1]),
+        ]);
+        let windows = ["acgtacgtacgt", "xyzzy", "foobar"];
+        let querystates = QueryStateSet::build(
+            windows
+                .iter()
+                .enumerate()
+                .map(|(i, x)| (HashTag::new(i == 0, 0, i), x)),
+            1,
+            target,
+            SecurityParameter::default(),
+        )
+        .unwrap();
+
+        // What the checksum proof is given to rederive the checksum query from.
+        let sum = querystates.verification_weighted_sum();
+        let Some((None, checksum)) = querystates.querystates().last() else {
+            panic!("expected the checksum last");
+        };
+        let checksum_point = querystates
+            .randomized_target
+            .get_checksum_point_for_validation(&sum);
+        let x_0 = checksum_point * checksum.verification_factor.invert();
+        assert_eq!(
+            checksum.query,
+            Query::from_rp(x_0 * checksum.blinding_factor)
+        );
+    }
 
+    #[test]
+    fn incremental_randomized_target_matches_build() {
+        let keys = KeyShares::random(&mut OsRng);
+        let keyholders_required = NonZeroU32::new(keys.chosen_keyservers.len() as u32).unwrap();
         let target = ActiveSecurityKey::from_secret_and_keyshares(
             &keys.secret,
             &keys.shares,
@@ -1020,32 +1908,749 @@ mod tests {
         )
         .unwrap();
 
-        let corrupted_ks = keys.corrupt_random_subset_of_chosen_keyservers().unwrap();
-
-        let messages = [
-            "foobar",
-            "The five boxing wizards jump quickly.",
-            "",
-            "acgtacgtacgt",
-            "xyzzy",
-        ]
-        .map(AsRef::<[u8]>::as_ref);
+        let windows = ["acgtacgtacgt", "xyzzy", "foobar"];
+        let querystates = QueryStateSet::build(
+            windows
+                .iter()
+                .enumerate()
+                .map(|(i, x)| (HashTag::new(i == 0, 0, i), x)),
+            keys.chosen_keyservers.len(),
+            target.clone(),
+            SecurityParameter::default(),
+        )
+        .unwrap();
 
-        let result = hash_via_keyshares(&keys, messages, target);
+        let mut builder = RandomizedTargetBuilder::new();
+        // The last query is the checksum, which depends on the target.
+        for query in querystates.queries().take(windows.len()) {
+            builder.push_query(query);
+        }
+        let incremental = builder.finalize(&target);
 
-        assert!(
-            matches!(
-            result, Err(QueryError::ValidationFailed(ref responsible_ks)) if responsible_ks == &corrupted_ks),
-            "Should have found corrupted ks {:?}, found {:?}",
-            corrupted_ks,
-            result
+        assert_eq!(
+            incremental.to_serializable_randomized_target(),
+            querystates
+                .randomized_target
+                .to_serializable_randomized_target()
         );
     }
 
-    #[cfg(feature = "centralized_keygen")]
     #[test]
-    fn generate_keyshares_requires_enough_keyholders_for_quorum() {
-        let rng = &mut OsRng;
+    fn get_hash_values_deadline_returns_pending_when_out_of_time() {
+        let keys = KeyShares::random(&mut OsRng);
+        let keyholders_required = NonZeroU32::new(keys.chosen_keyservers.len() as u32).unwrap();
+        let target = ActiveSecurityKey::from_secret_and_keyshares(
+            &keys.secret,
+            &keys.shares,
+            keyholders_required,
+        )
+        .unwrap();
+        let windows = ["acgtacgtacgt", "xyzzy", "foobar"];
+        let querystates = query_via_keyshares(&keys, windows, target);
+
+        let now = std::time::Instant::now();
+        match querystates.get_hash_values_deadline(now).unwrap() {
+            HashValues::Pending { processed, total } => {
+                assert!(processed < total);
+                assert_eq!(total, windows.len() + 1); // plus the checksum
+            }
+            HashValues::Complete(_) => panic!("expected pending"),
+        }
+
+        let deadline = now + std::time::Duration::from_secs(3600);
+        let HashValues::Complete(hashes) = querystates.get_hash_values_deadline(deadline).unwrap()
+        else {
+            panic!("expected complete");
+        };
+        assert_eq!(hashes, querystates.get_hash_values().unwrap());
+    }
+
+    #[test]
+    fn batched_verifier_matches_per_querystate_verifiers() {
+        let keys = KeyShares::random(&mut OsRng);
+        let keyholders_required = NonZeroU32::new(keys.chosen_keyservers.len() as u32).unwrap();
+        let target = ActiveSecurityKey::from_secret_and_keyshares(
+            &keys.secret,
+            &keys.shares,
+            keyholders_required,
+        )
+        .unwrap();
+        let windows: Vec<_> = (0..64u32).map(|i| i.to_le_bytes()).collect();
+        let querystates = query_via_keyshares(&keys, &windows, target);
+
+        let (points, per_querystate): (Vec<_>, Vec<_>) = querystates
+            .querystates
+            .iter()
+            .map(|(_, qs)| {
+                let point = qs.calculate_hash_value().unwrap().unwrap();
+                let (_, verification) =
+                    qs.get_hash_value_and_verification_value().unwrap().unwrap();
+                (point, verification)
+            })
+            .unzip();
+        let summed: RistrettoPoint = per_querystate.into_iter().sum();
+        let batched = querystates.batched_verifier(&points);
+        assert_eq!(batched, summed);
+    }
+
+    #[test]
+    fn serializable_set_validation_catches_corruption() {
+        let keys = KeyShares::random(&mut OsRng);
+        let target = ActiveSecurityKey::from_secret_and_keyshares(
+            &keys.secret,
+            &keys.shares,
+            NonZeroU32::new(keys.chosen_keyservers.len() as u32).unwrap(),
+        )
+        .unwrap();
+        let valid = || {
+            query_via_keyshares(&keys, ["foobar", "xyzzy"], target.clone()).to_serializable_set()
+        };
+        assert!(valid().validate().is_ok());
+
+        // Not canonical as a scalar, nor a valid point encoding.
+        let junk = [0xff; 32];
+
+        let mut set = valid();
+        set.querystates[0].1.blinding_factor = junk;
+        assert!(matches!(set.validate(), Err(DecodeError::InvalidScalar)));
+
+        let mut set = valid();
+        set.querystates[1].1.verification_factor = junk;
+        assert!(matches!(set.validate(), Err(DecodeError::InvalidScalar)));
+
+        let mut set = valid();
+        set.querystates[2].1.responses[0].1 = junk;
+        assert!(matches!(
+            set.validate(),
+            Err(DecodeError::InvalidRistrettoPoint)
+        ));
+
+        let mut set = valid();
+        set.randomized_target.random_modifier = junk;
+        assert!(matches!(set.validate(), Err(DecodeError::InvalidScalar)));
+    }
+
+    #[test]
+    fn invalid_response_points_are_errors() {
+        let keys = KeyShares::random(&mut OsRng);
+        let target = ActiveSecurityKey::from_secret_and_keyshares(
+            &keys.secret,
+            &keys.shares,
+            NonZeroU32::new(keys.chosen_keyservers.len() as u32).unwrap(),
+        )
+        .unwrap();
+        let set = query_via_keyshares(&keys, ["foobar", "xyzzy"], target);
+        assert!(set.get_hash_values().is_ok());
+
+        // Not a valid point encoding.
+        let junk = [0xff; 32];
+
+        // Parts are only decompressed once the hashes are reconstructed...
+        let quorum: KeyserverIdSet = keys
+            .chosen_keyservers
+            .iter()
+            .map(|index| KeyserverId::try_from(*index as u32 + 1).unwrap())
+            .collect();
+        let id = *quorum.iter().next().unwrap();
+        let mut corrupted = set.clone();
+        for (_, qs) in &mut corrupted.querystates {
+            qs.responses.retain(|(i, _)| *i != id);
+        }
+        let mut parts: Vec<_> = set.queries().map(|q| HashPart(q.0)).collect();
+        parts[1] = HashPart(CompressedRistretto(junk));
+        corrupted.incorporate_response(id, &parts).unwrap();
+        assert!(matches!(
+            corrupted.get_hash_values(),
+            Err(QueryError::InvalidResponse(blamed)) if blamed == id
+        ));
+        assert!(matches!(
+            corrupted.querystates[1].1.get_hash_value(),
+            Err(QueryError::InvalidResponse(blamed)) if blamed == id
+        ));
+        assert!(matches!(
+            corrupted.validate_keyserver_contribution(&quorum, id),
+            Err(QueryError::InvalidResponse(blamed)) if blamed == id
+        ));
+        assert!(corrupted
+            .find_keyservers_with_invalid_contribution()
+            .contains(&id));
+
+        // ...and a deserialized set's are checked up front.
+        let mut serialized = set.to_serializable_set();
+        serialized.querystates[0].1.responses[0].1 = junk;
+        assert!(matches!(
+            serialized.to_query_state_set(),
+            Err(DecodeError::InvalidRistrettoPoint)
+        ));
+    }
+
+    #[test]
+    fn non_canonical_factors_fail_to_deserialize() {
+        let keys = KeyShares::random(&mut OsRng);
+        let target = ActiveSecurityKey::from_secret_and_keyshares(
+            &keys.secret,
+            &keys.shares,
+            NonZeroU32::new(keys.chosen_keyservers.len() as u32).unwrap(),
+        )
+        .unwrap();
+        let set = query_via_keyshares(&keys, ["foobar", "xyzzy"], target);
+        let valid = || set.to_serializable_set();
+
+        let round_tripped = valid().to_query_state_set().unwrap();
+        assert_eq!(
+            round_tripped.queries().collect::<Vec<_>>(),
+            set.queries().collect::<Vec<_>>()
+        );
+
+        // Reduces mod the group order (whose top byte is 0x10), so was never
+        // written by `Scalar::to_bytes`.
+        let mut non_canonical = [0xff; 32];
+        non_canonical[31] = 0x7f;
+
+        let mut corrupted = valid();
+        corrupted.querystates[0].1.blinding_factor = non_canonical;
+        assert!(matches!(
+            corrupted.to_query_state_set(),
+            Err(DecodeError::InvalidScalar)
+        ));
+
+        let mut corrupted = valid();
+        corrupted.querystates[1].1.verification_factor = non_canonical;
+        assert!(matches!(
+            corrupted.to_query_state_set(),
+            Err(DecodeError::InvalidScalar)
+        ));
+
+        let mut corrupted = valid();
+        corrupted.querystates[0].1.required_keyholders = 0;
+        assert!(matches!(
+            corrupted.validate(),
+            Err(DecodeError::ZeroQuorum(_))
+        ));
+    }
+
+    #[test]
+    fn zero_quorum_is_rejected() {
+        assert!(QueryState::new(b"acgt", 0).is_err());
+
+        let target = ActiveSecurityKey::from_commitments([
+            crate::active_security::Commitment::hash_from_bytes_for_tests_only(&[1]),
+        ]);
+        let windows = [(HashTag::new(true, 0, 0), "acgt")];
+        assert!(QueryStateSet::build(windows, 0, target, SecurityParameter::default()).is_err());
+    }
+
+    #[test]
+    fn security_parameter_must_fit_verification_factors_in_u32() {
+        let default = SecurityParameter::default();
+        assert_eq!(default.get(), SECURITY_PARAMETER);
+        assert_eq!(default.verification_factor_max(), 1 << 18);
+        let largest = SecurityParameter::new(31).unwrap();
+        assert_eq!(largest.verification_factor_max(), 1 << 31);
+        assert!(SecurityParameter::new(32).is_err());
+        assert!(SecurityParameter::new(u32::MAX).is_err());
+    }
+
+    #[test]
+    fn invalid_input_sentinel_is_never_a_query() {
+        let sentinel = Query::invalid_input_sentinel();
+        assert!(sentinel.try_to_rp().is_err());
+        assert!(!bool::from(sentinel.ct_eq(&Query::sentinel())));
+    }
+
+    #[test]
+    fn query_for_sequence_matches_querystate_set() {
+        let seq = b"acgtacgtacgtacgtacgtacgtacgtacgtacgtacgtac";
+        let state = QueryState::new(seq, 1).unwrap();
+        let blinding = state.blinding_factor;
+        let set = QueryStateSet {
+            querystates: vec![(Some(HashTag::new(true, 0, 0)), state)],
+            ..Default::default()
+        };
+
+        let expected = set.queries().next().unwrap();
+        assert_eq!(Query::for_sequence(seq, blinding), *expected);
+
+        let (query, blinding) = Query::for_sequence_with_random_blinding(seq);
+        assert_eq!(query, Query::for_sequence(seq, blinding));
+    }
+
+    #[test]
+    fn sha3_512_digest_gives_current_queries() {
+        let seq = b"acgtacgtacgtacgtacgtacgtacgtacgtacgtacgtac";
+        let state = QueryState::new_with_digest::<Sha3_512>(seq, 1).unwrap();
+        let point = RistrettoPoint::hash_from_bytes::<Sha3_512>(seq);
+        assert_eq!(
+            *state.query(),
+            Query::from_rp(point * state.blinding_factor)
+        );
+        assert_eq!(
+            *state.query(),
+            Query::for_sequence(seq, state.blinding_factor)
+        );
+    }
+
+    #[test]
+    fn seeded_rng_gives_reproducible_query_states() {
+        use rand::{rngs::StdRng, SeedableRng};
+
+        let point = RistrettoPoint::hash_from_bytes::<Sha3_512>(b"acgtacgtac");
+        let verification_factor = Scalar::from(7u32);
+        let state = |seed| {
+            QueryState::from_rp_with_rng(
+                point,
+                1,
+                verification_factor,
+                &mut StdRng::seed_from_u64(seed),
+            )
+            .unwrap()
+        };
+
+        let (a, b, c) = (state(1), state(1), state(2));
+        assert_eq!(a.blinding_factor, b.blinding_factor);
+        assert_eq!(*a.query(), *b.query());
+        assert_ne!(a.blinding_factor, c.blinding_factor);
+        assert_ne!(*a.query(), *c.query());
+    }
+
+    // Finds a message for which distributed key hashing doesn't match single-key hashing
+    fn find_message_with_mismatching_hashes<'a>(
+        keys: KeyShares,
+        messages: impl IntoIterator<Item = &'a [u8]> + Clone,
+        target: ActiveSecurityKey,
+    ) -> Option<&'a [u8]> {
+        let distributed_hashes = hash_via_keyshares(&keys, messages.clone(), target).unwrap();
+        for (message, distributed_hash) in messages.into_iter().zip(distributed_hashes) {
+            let query = Query::hash_from_bytes_for_tests_only(message);
+            let single_key_hash = keys.secret.apply(query);
+            if <[u8; 32]>::from(distributed_hash) != <[u8; 32]>::from(single_key_hash) {
+                return Some(message);
+            }
+        }
+        None
+    }
+
+    // Quick sanity check... Run with -- --ignored to do more comprehensive tests
+    #[test]
+    fn test_batched_distributed_encryption_matches_single_key_encryption() {
+        let keys = KeyShares::random(&mut OsRng);
+        let keyholders_required = NonZeroU32::new(keys.chosen_keyservers.len() as u32).unwrap();
+
+        let target = ActiveSecurityKey::from_secret_and_keyshares(
+            &keys.secret,
+            &keys.shares,
+            keyholders_required,
+        )
+        .unwrap();
+        let messages = [
+            "foobar",
+            "The five boxing wizards jump quickly.",
+            "",
+            "acgtacgtacgt",
+            "xyzzy",
+        ]
+        .map(AsRef::<[u8]>::as_ref);
+        assert_eq!(
+            find_message_with_mismatching_hashes(keys, messages, target),
+            None
+        );
+    }
+
+    #[test]
+    fn corrupted_keyservers_cause_query_error_and_are_correctly_identified() {
+        let mut keys = KeyShares::random(&mut OsRng);
+        let keyholders_required = NonZeroU32::new(keys.chosen_keyservers.len() as u32).unwrap();
+
+        let target = ActiveSecurityKey::from_secret_and_keyshares(
+            &keys.secret,
+            &keys.shares,
+            keyholders_required,
+        )
+        .unwrap();
+
+        let corrupted_ks = keys.corrupt_random_subset_of_chosen_keyservers().unwrap();
+
+        let messages = [
+            "foobar",
+            "The five boxing wizards jump quickly.",
+            "",
+            "acgtacgtacgt",
+            "xyzzy",
+        ]
+        .map(AsRef::<[u8]>::as_ref);
+
+        let result = hash_via_keyshares(&keys, messages, target);
+
+        assert!(
+            matches!(
+            result, Err(QueryError::ValidationFailed { keyservers: ref responsible_ks, .. }) if responsible_ks == &corrupted_ks),
+            "Should have found corrupted ks {:?}, found {:?}",
+            corrupted_ks,
+            result
+        );
+    }
+
+    #[test]
+    fn single_corrupted_keyserver_yields_reproducible_discrepancy() {
+        let mut keys = KeyShares::random(&mut OsRng);
+        let keyholders_required = NonZeroU32::new(keys.chosen_keyservers.len() as u32).unwrap();
+
+        let target = ActiveSecurityKey::from_secret_and_keyshares(
+            &keys.secret,
+            &keys.shares,
+            keyholders_required,
+        )
+        .unwrap();
+
+        // Off by one: the corrupted keyserver's contribution to every hash is
+        // its lagrange coefficient times the hashed point too much.
+        let corrupted_index = keys.chosen_keyservers[0];
+        *keys.shares[corrupted_index].0 += Scalar::ONE;
+        let corrupted_ks = KeyserverId::try_from(corrupted_index as u32 + 1).unwrap();
+        let keyserver_ids: KeyserverIdSet = keys
+            .chosen_keyservers_and_shares()
+            .map(|(id, _)| id)
+            .collect();
+        let coeff = keyserver_ids.langrange_coefficient_for_id(&corrupted_ks);
+
+        let querystates = query_via_keyshares(&keys, ["foobar", "xyzzy"], target);
+        // The verification-weighted hashed points sum to `random_modifier * G`.
+        let expected_discrepancy =
+            -(coeff * querystates.randomized_target.random_modifier * RISTRETTO_BASEPOINT_POINT);
+
+        for _ in 0..2 {
+            match querystates.get_hash_values() {
+                Err(QueryError::ValidationFailed {
+                    keyservers,
+                    discrepancy,
+                }) => {
+                    assert_eq!(keyservers, vec![corrupted_ks]);
+                    assert_eq!(discrepancy, expected_discrepancy);
+                }
+                other => panic!("expected validation failure, got {other:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn canonical_response_order_ignores_arrival_order() {
+        let keys = KeyShares::random(&mut OsRng);
+        let keyholders_required = NonZeroU32::new(keys.chosen_keyservers.len() as u32).unwrap();
+        let target = ActiveSecurityKey::from_secret_and_keyshares(
+            &keys.secret,
+            &keys.shares,
+            keyholders_required,
+        )
+        .unwrap();
+        let mut querystates = QueryStateSet::build(
+            [
+                (HashTag::new(true, 0, 0), "foobar"),
+                (HashTag::new(false, 0, 1), "xyzzy"),
+            ],
+            keys.chosen_keyservers.len(),
+            target,
+            SecurityParameter::default(),
+        )
+        .unwrap();
+        querystates.set_canonical_response_order(true);
+        let quorum: KeyserverIdSet = keys
+            .chosen_keyservers_and_shares()
+            .map(|(id, _)| id)
+            .collect();
+        let queries: Vec<_> = querystates.queries().copied().collect();
+        let responses: Vec<_> = keys
+            .chosen_keyservers_and_shares()
+            .map(|(id, key)| {
+                let coeff = quorum.langrange_coefficient_for_id(&id);
+                (id, key.apply_batch(&queries, &coeff))
+            })
+            .collect();
+
+        let serialized_after = |arrivals: &[(KeyserverId, Vec<HashPart>)]| {
+            let mut run = querystates.clone();
+            for (id, parts) in arrivals {
+                run.incorporate_response(*id, parts).unwrap();
+            }
+            assert!(run.get_hash_values().is_ok());
+            serde_json::to_string(&run.to_serializable_set()).unwrap()
+        };
+
+        let mut reversed = responses.clone();
+        reversed.reverse();
+        assert_eq!(serialized_after(&responses), serialized_after(&reversed));
+    }
+
+    #[test]
+    fn out_of_order_partial_responses_match_full_responses() {
+        let keys = KeyShares::random(&mut OsRng);
+        let keyholders_required = NonZeroU32::new(keys.chosen_keyservers.len() as u32).unwrap();
+        let target = ActiveSecurityKey::from_secret_and_keyshares(
+            &keys.secret,
+            &keys.shares,
+            keyholders_required,
+        )
+        .unwrap();
+        let querystates = QueryStateSet::build(
+            ["foobar", "xyzzy", "plugh", "frobozz", "zorkmid"]
+                .into_iter()
+                .enumerate()
+                .map(|(i, window)| (HashTag::new(true, 0, i), window)),
+            keys.chosen_keyservers.len(),
+            target,
+            SecurityParameter::default(),
+        )
+        .unwrap();
+        let quorum: KeyserverIdSet = keys
+            .chosen_keyservers_and_shares()
+            .map(|(id, _)| id)
+            .collect();
+        let queries: Vec<_> = querystates.queries().copied().collect();
+        let responses: Vec<_> = keys
+            .chosen_keyservers_and_shares()
+            .map(|(id, key)| {
+                let coeff = quorum.langrange_coefficient_for_id(&id);
+                (id, key.apply_batch(&queries, &coeff))
+            })
+            .collect();
+
+        let mut batched = querystates.clone();
+        for (id, parts) in &responses {
+            batched.incorporate_response(*id, parts).unwrap();
+        }
+
+        // Five windows and the checksum, in three chunks per keyserver.
+        let chunks = [4..6, 0..1, 1..4];
+        let mut streamed = querystates;
+        for chunk in chunks {
+            assert!(!streamed.all_have_hash());
+            for (id, parts) in &responses {
+                streamed
+                    .incorporate_partial_response(*id, chunk.start, &parts[chunk.clone()])
+                    .unwrap();
+            }
+        }
+        assert!(streamed.all_have_hash());
+        assert_eq!(
+            streamed.get_hash_values().unwrap(),
+            batched.get_hash_values().unwrap()
+        );
+
+        // Chunks can't overlap what a keyserver already answered, or run past
+        // the last query.
+        let (id, parts) = &responses[0];
+        assert!(matches!(
+            streamed.incorporate_partial_response(*id, 5, &parts[5..]),
+            Err(QueryError::DuplicateResponse(dup)) if dup == *id
+        ));
+        assert!(matches!(
+            streamed.incorporate_response(*id, parts),
+            Err(QueryError::DuplicateResponse(dup)) if dup == *id
+        ));
+        assert!(matches!(
+            streamed.incorporate_partial_response(*id, 5, &parts[4..]),
+            Err(QueryError::WrongSizeResponse)
+        ));
+    }
+
+    #[test]
+    fn getting_hash_values_again_gives_the_same_result() {
+        let keys = KeyShares::random(&mut OsRng);
+        let keyholders_required = NonZeroU32::new(keys.chosen_keyservers.len() as u32).unwrap();
+        let target = ActiveSecurityKey::from_secret_and_keyshares(
+            &keys.secret,
+            &keys.shares,
+            keyholders_required,
+        )
+        .unwrap();
+        let windows = ["foobar", "xyzzy", "plugh"];
+        let mut querystates = QueryStateSet::build(
+            windows
+                .into_iter()
+                .enumerate()
+                .map(|(i, window)| (HashTag::new(true, 0, i), window)),
+            keys.chosen_keyservers.len(),
+            target,
+            SecurityParameter::default(),
+        )
+        .unwrap();
+        let quorum: KeyserverIdSet = keys
+            .chosen_keyservers_and_shares()
+            .map(|(id, _)| id)
+            .collect();
+        let queries: Vec<_> = querystates.queries().copied().collect();
+        let responses: Vec<_> = keys
+            .chosen_keyservers_and_shares()
+            .map(|(id, key)| {
+                let coeff = quorum.langrange_coefficient_for_id(&id);
+                (id, key.apply_batch(&queries, &coeff))
+            })
+            .collect();
+        for (id, parts) in &responses {
+            querystates.incorporate_response(*id, parts).unwrap();
+        }
+
+        let first = querystates.get_hash_values().unwrap();
+        assert_eq!(first.len(), windows.len());
+        // The checksum querystate is still there...
+        assert_eq!(querystates.len(), windows.len() + 1);
+        // ...so the second call validates and strips it just like the first.
+        assert_eq!(querystates.get_hash_values().unwrap(), first);
+
+        // Responses can't be incorporated twice afterwards either.
+        let (id, parts) = &responses[0];
+        assert!(matches!(
+            querystates.incorporate_response(*id, parts),
+            Err(QueryError::DuplicateResponse(dup)) if dup == *id
+        ));
+        assert_eq!(querystates.get_hash_values().unwrap(), first);
+    }
+
+    #[test]
+    fn response_counts_show_which_keyservers_are_missing() {
+        let keys = KeyShares::random(&mut OsRng);
+        let keyholders_required = NonZeroU32::new(keys.chosen_keyservers.len() as u32).unwrap();
+        let target = ActiveSecurityKey::from_secret_and_keyshares(
+            &keys.secret,
+            &keys.shares,
+            keyholders_required,
+        )
+        .unwrap();
+        let mut querystates = QueryStateSet::build(
+            [
+                (HashTag::new(true, 0, 0), "foobar"),
+                (HashTag::new(false, 0, 1), "xyzzy"),
+            ],
+            keys.chosen_keyservers.len(),
+            target,
+            SecurityParameter::default(),
+        )
+        .unwrap();
+        let quorum: KeyserverIdSet = keys
+            .chosen_keyservers_and_shares()
+            .map(|(id, _)| id)
+            .collect();
+        let queries: Vec<_> = querystates.queries().copied().collect();
+
+        assert!(querystates.response_counts().is_empty());
+        assert_eq!(
+            querystates.missing_keyservers(&quorum),
+            quorum.iter().copied().collect::<Vec<_>>()
+        );
+
+        // Only the first keyserver responds.
+        let (first, key) = keys.chosen_keyservers_and_shares().next().unwrap();
+        let coeff = quorum.langrange_coefficient_for_id(&first);
+        querystates
+            .incorporate_response(first, &key.apply_batch(&queries, &coeff))
+            .unwrap();
+
+        // Two windows and the checksum.
+        assert_eq!(querystates.response_counts(), BTreeMap::from([(first, 3)]));
+        let missing = querystates.missing_keyservers(&quorum);
+        assert_eq!(missing.len(), quorum.len() - 1);
+        assert!(!missing.contains(&first));
+    }
+
+    #[test]
+    fn corrupted_keyserver_is_caught_on_its_own_response() {
+        let mut keys = KeyShares::random(&mut OsRng);
+        let keyholders_required = NonZeroU32::new(keys.chosen_keyservers.len() as u32).unwrap();
+        let target = ActiveSecurityKey::from_secret_and_keyshares(
+            &keys.secret,
+            &keys.shares,
+            keyholders_required,
+        )
+        .unwrap();
+
+        let corrupted_index = keys.chosen_keyservers[0];
+        keys.corrupt_keyservers_by_index(&[corrupted_index])
+            .unwrap();
+        let corrupted_ks = KeyserverId::try_from(corrupted_index as u32 + 1).unwrap();
+
+        let mut querystates = QueryStateSet::build(
+            [(HashTag::new(true, 0, 0), "foobar")],
+            keys.chosen_keyservers.len(),
+            target,
+            SecurityParameter::default(),
+        )
+        .unwrap();
+        let quorum: KeyserverIdSet = keys
+            .chosen_keyservers_and_shares()
+            .map(|(id, _)| id)
+            .collect();
+        let queries: Vec<_> = querystates.queries().copied().collect();
+
+        // The corrupted keyserver responds first, and is caught before anyone else responds.
+        assert!(matches!(
+            querystates.validate_keyserver_contribution(&quorum, corrupted_ks),
+            Err(QueryError::MissingKeyserverResponse)
+        ));
+        let (_, corrupted_key) = keys
+            .chosen_keyservers_and_shares()
+            .find(|(id, _)| *id == corrupted_ks)
+            .unwrap();
+        let coeff = quorum.langrange_coefficient_for_id(&corrupted_ks);
+        querystates
+            .incorporate_response(corrupted_ks, &corrupted_key.apply_batch(&queries, &coeff))
+            .unwrap();
+        match querystates.validate_keyserver_contribution(&quorum, corrupted_ks) {
+            Err(QueryError::ValidationFailed { keyservers, .. }) => {
+                assert_eq!(keyservers, vec![corrupted_ks]);
+            }
+            other => panic!("expected validation failure, got {other:?}"),
+        }
+
+        for (id, key) in keys.chosen_keyservers_and_shares() {
+            if id == corrupted_ks {
+                continue;
+            }
+            let coeff = quorum.langrange_coefficient_for_id(&id);
+            querystates
+                .incorporate_response(id, &key.apply_batch(&queries, &coeff))
+                .unwrap();
+            querystates
+                .validate_keyserver_contribution(&quorum, id)
+                .unwrap();
+        }
+    }
+
+    #[cfg(feature = "centralized_keygen")]
+    #[test]
+    fn observed_contributions_match_expected_only_for_correct_keyservers() {
+        let mut keys = KeyShares::random(&mut OsRng);
+        let keyholders_required = NonZeroU32::new(keys.chosen_keyservers.len() as u32).unwrap();
+        let target = ActiveSecurityKey::from_secret_and_keyshares(
+            &keys.secret,
+            &keys.shares,
+            keyholders_required,
+        )
+        .unwrap();
+
+        // The auditor has the real shares; the keyserver uses a corrupt one.
+        let real_shares = keys.shares.clone();
+        let corrupted_index = keys.chosen_keyservers[0];
+        keys.corrupt_keyservers_by_index(&[corrupted_index])
+            .unwrap();
+        let corrupted_ks = KeyserverId::try_from(corrupted_index as u32 + 1).unwrap();
+
+        let querystates = query_via_keyshares(&keys, ["foobar", "xyzzy"], target);
+        let observed = querystates.observed_keyserver_contributions();
+        let keyserver_ids: KeyserverIdSet = observed.keys().copied().collect();
+        assert_eq!(observed.len(), keys.chosen_keyservers.len());
+
+        for (id, sum) in &observed {
+            let share = &real_shares[u32::from(id) as usize - 1];
+            let expected = querystates
+                .randomized_target
+                .expected_keyserver_contribution(&keyserver_ids, id, share);
+            assert_eq!(*sum == expected, *id != corrupted_ks, "keyserver {id}");
+        }
+    }
+
+    #[cfg(feature = "centralized_keygen")]
+    #[test]
+    fn generate_keyshares_requires_enough_keyholders_for_quorum() {
+        let rng = &mut OsRng;
         let secret = Scalar::random(rng).into();
         let cases = [(2, 1), (3, 2), (3, 1), (4, 2), (5, 4), (5, 1), (10, 1)];
         for (required_keyholders, num_keyholders) in cases {
@@ -1060,8 +2665,227 @@ mod tests {
         }
     }
 
+    #[cfg(feature = "centralized_keygen")]
+    #[test]
+    fn seeded_keyshares_match_test_vector() {
+        let secret = KeyShare::from(Scalar::from(1234u64));
+        let shares = generate_keyshares_from_seed(
+            &secret,
+            NonZeroU32::new(3).unwrap(),
+            NonZeroU32::new(5).unwrap(),
+            *b"securedna keyshare test vector!!",
+        )
+        .unwrap();
+
+        let shares: Vec<_> = shares.iter().map(KeyShare::reveal_hex).collect();
+        assert_eq!(
+            shares,
+            [
+                "566ed8971ff67dfc3ac6b68d4411010c17f643dbb77b2137155db33e74a4a601",
+                "463cb7d76716216394b6af21d7c5efbc00b9a0e62564d2e647b0a2cb320b7f0e",
+                "c8c6b005a49ac4835f97fb75fa290ee9bc4816224ab9120f98f9cda63b348906",
+                "b6b5b0db08498d0e49a289d06b311aba4ba5a48d247be2af053935d08e1fc509",
+                "2335c1fc7bbe68ab7a3a628e4ce2341badce4b29b5a941c9906ed8472ccd3208",
+            ]
+        );
+    }
+
+    #[cfg(feature = "shadow_validation")]
+    #[tracing_test::traced_test]
+    #[test]
+    fn shadow_target_disagreement_is_logged_but_does_not_fail() {
+        let keys = KeyShares::random(&mut OsRng);
+        let keyholders_required = NonZeroU32::new(keys.chosen_keyservers.len() as u32).unwrap();
+        let target = ActiveSecurityKey::from_secret_and_keyshares(
+            &keys.secret,
+            &keys.shares,
+            keyholders_required,
+        )
+        .unwrap();
+
+        let mut querystates = query_via_keyshares(&keys, ["acgtacgtacgt", "xyzzy"], target);
+        // An unrelated target, standing in for an experimental scheme that disagrees.
+        querystates.set_shadow_target(RandomizedTarget::default());
+
+        let hashes = querystates.get_hash_values().unwrap();
+        assert_eq!(hashes.len(), 2);
+        assert!(logs_contain("shadow active security validation disagrees"));
+    }
+
+    #[test]
+    fn keyshares_survive_clone_and_reveal_hex() {
+        let scalar = Scalar::random(&mut OsRng);
+        let share = KeyShare::from(scalar);
+        let cloned = share.clone();
+        drop(share);
+
+        let parsed = KeyShare::from_str(&cloned.reveal_hex()).unwrap();
+        assert_eq!(cloned.reveal_hex(), hex::encode(scalar.as_bytes()));
+        let query = Query::hash_from_string("acgtacgtacgt");
+        assert_eq!(
+            parsed.apply(query).as_bytes(),
+            (query.to_rp() * scalar).compress().as_bytes()
+        );
+    }
+
+    #[test]
+    fn keyshares_are_redacted_unless_revealed() {
+        let share = KeyShare::from(Scalar::random(&mut OsRng));
+        let hex = share.reveal_hex();
+        assert_eq!(format!("{share:?}"), "KeyShare(<redacted>)");
+        assert!(!format!("{:?}", Some(&share)).contains(&hex));
+
+        let json = serde_json::to_string(&share).unwrap();
+        assert_eq!(json, format!("\"{hex}\""));
+        let parsed: KeyShare = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.reveal_hex(), hex);
+    }
+
+    #[test]
+    fn keyshares_serialize_as_bytes_for_binary_formats() {
+        let scalar = Scalar::random(&mut OsRng);
+        let share = KeyShare::from(scalar);
+
+        let bytes = bincode::serialize(&share).unwrap();
+        assert_eq!(bytes, scalar.as_bytes());
+        let parsed: KeyShare = bincode::deserialize(&bytes).unwrap();
+        assert_eq!(parsed.reveal_hex(), share.reveal_hex());
+
+        // Human-readable formats still get hex.
+        let json = serde_json::to_string(&share).unwrap();
+        assert_eq!(json, format!("\"{}\"", share.reveal_hex()));
+
+        let mut non_canonical = [0xff; 32];
+        non_canonical[31] = 0x7f;
+        assert!(bincode::deserialize::<KeyShare>(&non_canonical).is_err());
+    }
+
+    #[test]
+    fn keyserver_apply_proof_verifies_only_the_committed_apply() {
+        let share = KeyShare::from(Scalar::random(&mut OsRng));
+        let commitment = Commitment::from_keyshare(&share);
+        let coefficient = Scalar::random(&mut OsRng);
+        let query = Query::for_sequence(b"acgtacgtacgt", Scalar::random(&mut OsRng));
+
+        let (part, proof) = share.apply_and_prove(query, &coefficient);
+        assert_eq!(
+            part.as_bytes(),
+            share
+                .apply_query_and_lagrange_coefficient(query, &coefficient)
+                .as_bytes()
+        );
+        assert!(proof.verify(query, &coefficient, part, commitment));
+
+        // A part computed with some other share...
+        let other_share = KeyShare::from(Scalar::random(&mut OsRng));
+        let tampered = other_share.apply_query_and_lagrange_coefficient(query, &coefficient);
+        assert!(!proof.verify(query, &coefficient, tampered, commitment));
+        // ...or checked against the wrong commitment, query, or coefficient fails.
+        let other_commitment = Commitment::from_keyshare(&other_share);
+        assert!(!proof.verify(query, &coefficient, part, other_commitment));
+        let other_query = Query::for_sequence(b"xyzzy", Scalar::random(&mut OsRng));
+        assert!(!proof.verify(other_query, &coefficient, part, commitment));
+        assert!(!proof.verify(query, &(coefficient + Scalar::ONE), part, commitment));
+    }
+
+    #[test]
+    fn reconstruct_secret_needs_a_quorum_of_distinct_keyholders() {
+        let secret = KeyShare::from(Scalar::random(&mut OsRng));
+        let required = NonZeroU32::new(3).unwrap();
+        let shares: Vec<_> =
+            generate_keyshares(&secret, required, NonZeroU32::new(5).unwrap(), &mut OsRng)
+                .unwrap()
+                .into_iter()
+                .enumerate()
+                .map(|(i, share)| (KeyserverId::try_from(i as u32 + 1).unwrap(), share))
+                .collect();
+
+        let reconstructed = reconstruct_secret(&shares[2..], required).unwrap();
+        assert_eq!(reconstructed.reveal_hex(), secret.reveal_hex());
+
+        assert!(matches!(
+            reconstruct_secret(&shares[3..], required),
+            Err(ReconstructSecretError::UnreachableQuorum(_))
+        ));
+        assert!(matches!(
+            reconstruct_secret(&[], required),
+            Err(ReconstructSecretError::UnreachableQuorum(_))
+        ));
+
+        let duplicated = [shares[0].clone(), shares[1].clone(), shares[1].clone()];
+        assert!(matches!(
+            reconstruct_secret(&duplicated, required),
+            Err(ReconstructSecretError::DuplicateKeyholder(id)) if id == shares[1].0
+        ));
+    }
+
+    #[test]
+    fn refreshed_shares_reconstruct_the_same_secret() {
+        let secret = KeyShare::from(Scalar::random(&mut OsRng));
+        let required = NonZeroU32::new(3).unwrap();
+        let total = NonZeroU32::new(5).unwrap();
+        let with_ids = |shares: Vec<KeyShare>| -> Vec<_> {
+            shares
+                .into_iter()
+                .enumerate()
+                .map(|(i, share)| (KeyserverId::try_from(i as u32 + 1).unwrap(), share))
+                .collect()
+        };
+
+        let old = with_ids(generate_keyshares(&secret, required, total, &mut OsRng).unwrap());
+        let zero = generate_zero_shares(required, total, &mut OsRng).unwrap();
+        let refreshed: Vec<_> = old
+            .iter()
+            .zip(&zero)
+            .map(|((id, share), zero_share)| (*id, share.add(zero_share)))
+            .collect();
+
+        for ((_, old_share), (_, new_share)) in old.iter().zip(&refreshed) {
+            assert_ne!(old_share.reveal_hex(), new_share.reveal_hex());
+        }
+        // Any quorum of refreshed shares still reconstructs the secret.
+        for quorum in [&refreshed[..3], &refreshed[2..]] {
+            let reconstructed = reconstruct_secret(quorum, required).unwrap();
+            assert_eq!(reconstructed.reveal_hex(), secret.reveal_hex());
+        }
+        let before = reconstruct_secret(&old[1..4], required).unwrap();
+        let after = reconstruct_secret(&refreshed[1..4], required).unwrap();
+        assert_eq!(before.reveal_hex(), after.reveal_hex());
+    }
+
+    #[test]
+    fn ct_eq_agrees_with_byte_equality() {
+        let query = Query::hash_from_bytes_for_tests_only(b"a");
+        let other = Query::hash_from_bytes_for_tests_only(b"b");
+        assert!(bool::from(query.ct_eq(&query)));
+        assert!(!bool::from(query.ct_eq(&other)));
+        assert!(bool::from([query, other].ct_eq(&[query, other])));
+        assert!(!bool::from([query, other].ct_eq(&[query, query])));
+
+        let part = HashPart::hash_from_bytes_for_tests_only(b"a");
+        let other = HashPart::hash_from_bytes_for_tests_only(b"b");
+        assert!(bool::from(part.ct_eq(&part)));
+        assert!(!bool::from(part.ct_eq(&other)));
+
+        let hash = CompletedHashValue::hash_from_bytes_for_tests_only(b"a");
+        let other = CompletedHashValue::hash_from_bytes_for_tests_only(b"b");
+        assert!(bool::from(hash.ct_eq(&hash)));
+        assert!(!bool::from(hash.ct_eq(&other)));
+    }
+
     quickcheck! {
 
+        fn reconstruct_secret_inverts_generate_keyshares(keys: KeyShares) -> bool {
+            let required_keyholders =
+                NonZeroU32::new(keys.chosen_keyservers.len() as u32).unwrap();
+            let shares: Vec<_> = keys
+                .chosen_keyservers_and_shares()
+                .map(|(id, share)| (id, share.clone()))
+                .collect();
+            let reconstructed = reconstruct_secret(&shares, required_keyholders).unwrap();
+            reconstructed.reveal_hex() == keys.secret.reveal_hex()
+        }
+
         #[ignore]
         fn distributed_hashing_matches_single_key_hashing(keys: KeyShares, dna: Dna) -> bool {
             let dna: &[u8] = dna.0.as_ref();
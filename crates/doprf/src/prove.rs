@@ -0,0 +1,866 @@
+// Copyright 2021-2024 SecureDNA Stiftung (SecureDNA Foundation) <licensing@securedna.org>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Proving, with SP1, that a [`QueryStateSet`] was built honestly.
+//!
+//! The hash proof shows that each query is a blinded hash of its window, and
+//! the checksum proof that the checksum query was derived from those queries
+//! and the active security key. Building a set doesn't need either; see
+//! [`QueryStateSet::build`].
+
+use std::borrow::Cow;
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use once_cell::sync::OnceCell;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use sp1_sdk::action::Prove;
+use sp1_sdk::provers::ProverMode;
+use sp1_sdk::{
+    ExecutionReport, ProverClient, SP1Proof, SP1ProofWithPublicValues, SP1ProvingKey,
+    SP1PublicValues, SP1Stdin, SP1VerifyingKey, SP1_CIRCUIT_VERSION,
+};
+use subtle::ConstantTimeEq;
+
+use crate::active_security::ActiveSecurityKey;
+use crate::prf::{Query, QueryState, QueryStateSet, SecurityParameter};
+use crate::tagged::HashTag;
+
+const HASH_PROOF_FILE: &str = "hash_proof-with-pis.bin";
+const CHECKSUM_PROOF_FILE: &str = "checksum-proof-with-pis.bin";
+const VERIFICATION_PROOF_FILE: &str = "verification_proof-with-pis.bin";
+
+/// A zkVM program.
+#[derive(Debug, Clone)]
+pub enum Elf {
+    /// Built into this binary.
+    Bytes(&'static [u8]),
+    /// Read from this file whenever it's needed.
+    Path(PathBuf),
+}
+
+impl Elf {
+    pub fn load(&self) -> Result<Cow<'static, [u8]>, ProofError> {
+        match self {
+            Self::Bytes(bytes) => Ok(Cow::Borrowed(bytes)),
+            Self::Path(path) => {
+                std::fs::read(path)
+                    .map(Cow::Owned)
+                    .map_err(|error| ProofError::ReadElf {
+                        path: path.clone(),
+                        error,
+                    })
+            }
+        }
+    }
+}
+
+/// Where the prover finds its zkVM programs, and keeps its proofs.
+#[derive(Debug, Clone)]
+pub struct ProverPaths {
+    pub hash_elf: Elf,
+    pub checksum_elf: Elf,
+    pub verification_elf: Elf,
+    /// Previously generated proofs are loaded from here, instead of proving
+    /// again, to avoid unnecessary computation. Verification proofs are saved
+    /// here when they're generated, see [`Prover::load_or_prove`].
+    pub output_dir: PathBuf,
+}
+
+impl Default for ProverPaths {
+    /// The programs built into this binary (ensure they're up to date:
+    /// auto-build is disabled), and the client image's output directory.
+    fn default() -> Self {
+        Self {
+            hash_elf: Elf::Bytes(include_bytes!(
+                "../../../hash_proof/elf/riscv32im-succinct-zkvm-elf"
+            )),
+            checksum_elf: Elf::Bytes(include_bytes!(
+                "../../../checksum_proof/elf/riscv32im-succinct-zkvm-elf"
+            )),
+            verification_elf: Elf::Bytes(include_bytes!(
+                "../../../verification_proof/elf/riscv32im-succinct-zkvm-elf"
+            )),
+            output_dir: PathBuf::from("/client/output"),
+        }
+    }
+}
+
+/// One of the zkVM programs in [`ProverPaths`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Program {
+    Hash,
+    Checksum,
+    Verification,
+}
+
+impl ProverPaths {
+    pub fn elf(&self, program: Program) -> &Elf {
+        match program {
+            Program::Hash => &self.hash_elf,
+            Program::Checksum => &self.checksum_elf,
+            Program::Verification => &self.verification_elf,
+        }
+    }
+
+    pub fn hash_proof_path(&self) -> PathBuf {
+        self.output_dir.join(HASH_PROOF_FILE)
+    }
+
+    pub fn checksum_proof_path(&self) -> PathBuf {
+        self.output_dir.join(CHECKSUM_PROOF_FILE)
+    }
+
+    pub fn verification_proof_path(&self) -> PathBuf {
+        self.output_dir.join(VERIFICATION_PROOF_FILE)
+    }
+}
+
+/// Which prover to prove with.
+#[derive(Debug, Clone, Default)]
+pub enum ProverBackend {
+    /// Prove on this machine.
+    #[default]
+    Local,
+    /// Prove with the SP1 Prover Network.
+    Network { rpc_url: String, api_key: String },
+    /// Don't prove at all, but stand in dummy proofs (see
+    /// [`VerificationInput::mock`]), to test the rest of the pipeline.
+    Mock,
+}
+
+impl ProverBackend {
+    pub fn client(&self) -> ProverClient {
+        match self {
+            Self::Local => ProverClient::local(),
+            Self::Network { rpc_url, api_key } => ProverClient::builder()
+                .mode(ProverMode::Network)
+                .rpc_url(rpc_url.clone())
+                .private_key(api_key.clone())
+                .build(),
+            Self::Mock => ProverClient::mock(),
+        }
+    }
+
+    pub fn is_mock(&self) -> bool {
+        matches!(self, Self::Mock)
+    }
+}
+
+/// Which kind of proof the verification program's proof is, i.e. what the
+/// HDB is sent. The hash and checksum proofs are always compressed, since
+/// only compressed proofs can be aggregated.
+///
+/// The SNARK modes wrap the compressed proof once more, which costs proving
+/// time for a much smaller proof:
+/// - [`Self::Compressed`] is the cheapest to generate, but is over a megabyte
+///   and can only be verified with SP1 itself.
+/// - [`Self::Groth16`] is a few hundred bytes and the cheapest to verify
+///   on-chain, but wrapping takes minutes more, and relies on the trusted
+///   setup of SP1's Groth16 circuit.
+/// - [`Self::Plonk`] needs no circuit-specific trusted setup, at the cost of
+///   a larger proof (around a kilobyte), slower wrapping, and more gas to
+///   verify on-chain.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProofMode {
+    #[default]
+    Compressed,
+    Groth16,
+    Plonk,
+}
+
+impl ProofMode {
+    /// Have `prove` generate this kind of proof.
+    pub fn apply(self, prove: Prove<'_>) -> Prove<'_> {
+        match self {
+            Self::Compressed => prove.compressed(),
+            Self::Groth16 => prove.groth16(),
+            Self::Plonk => prove.plonk(),
+        }
+    }
+
+    /// The kind of `proof`, or `None` for an uncompressed core proof.
+    pub fn of(proof: &SP1Proof) -> Option<Self> {
+        match proof {
+            SP1Proof::Core(_) => None,
+            SP1Proof::Compressed(_) => Some(Self::Compressed),
+            SP1Proof::Groth16(_) => Some(Self::Groth16),
+            SP1Proof::Plonk(_) => Some(Self::Plonk),
+        }
+    }
+}
+
+impl fmt::Display for ProofMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Compressed => write!(f, "compressed"),
+            Self::Groth16 => write!(f, "groth16"),
+            Self::Plonk => write!(f, "plonk"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseProofModeError(String);
+
+impl fmt::Display for ParseProofModeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "unknown proof mode {:?}, expected compressed, groth16 or plonk",
+            self.0
+        )
+    }
+}
+
+impl Error for ParseProofModeError {}
+
+impl FromStr for ProofMode {
+    type Err = ParseProofModeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "compressed" => Ok(Self::Compressed),
+            "groth16" => Ok(Self::Groth16),
+            "plonk" => Ok(Self::Plonk),
+            _ => Err(ParseProofModeError(s.to_owned())),
+        }
+    }
+}
+
+/// Proves with the programs in [`ProverPaths`] on a [`ProverBackend`].
+///
+/// Creating a [`ProverClient`] and setting up a program's keys are both
+/// expensive, so each is done the first time it's needed and then reused.
+/// Share one `Prover` between screens rather than making one per screen.
+pub struct Prover {
+    paths: ProverPaths,
+    backend: ProverBackend,
+    proof_mode: ProofMode,
+    client: OnceCell<ProverClient>,
+    hash_keys: OnceCell<(SP1ProvingKey, SP1VerifyingKey)>,
+    checksum_keys: OnceCell<(SP1ProvingKey, SP1VerifyingKey)>,
+    verification_keys: OnceCell<(SP1ProvingKey, SP1VerifyingKey)>,
+}
+
+impl Prover {
+    pub fn new(paths: ProverPaths, backend: ProverBackend) -> Self {
+        Self {
+            paths,
+            backend,
+            proof_mode: ProofMode::default(),
+            client: OnceCell::new(),
+            hash_keys: OnceCell::new(),
+            checksum_keys: OnceCell::new(),
+            verification_keys: OnceCell::new(),
+        }
+    }
+
+    pub fn paths(&self) -> &ProverPaths {
+        &self.paths
+    }
+
+    pub fn backend(&self) -> &ProverBackend {
+        &self.backend
+    }
+
+    /// Generate verification proofs of this kind, rather than compressed.
+    pub fn with_proof_mode(mut self, proof_mode: ProofMode) -> Self {
+        self.proof_mode = proof_mode;
+        self
+    }
+
+    pub fn proof_mode(&self) -> ProofMode {
+        self.proof_mode
+    }
+
+    pub fn client(&self) -> &ProverClient {
+        self.client.get_or_init(|| self.backend.client())
+    }
+
+    /// The proving and verifying keys for `program`.
+    pub fn keys(&self, program: Program) -> Result<&(SP1ProvingKey, SP1VerifyingKey), ProofError> {
+        let keys = match program {
+            Program::Hash => &self.hash_keys,
+            Program::Checksum => &self.checksum_keys,
+            Program::Verification => &self.verification_keys,
+        };
+        keys.get_or_try_init(|| {
+            let elf = self.paths.elf(program).load()?;
+            Ok(self.client().setup(&elf))
+        })
+    }
+
+    /// A `mode` proof of running `program` on `stdin`: the one saved at
+    /// `path` if it's of that kind, or else a new one, which is verified and
+    /// saved there in its place.
+    pub fn load_or_prove(
+        &self,
+        program: Program,
+        stdin: SP1Stdin,
+        mode: ProofMode,
+        path: PathBuf,
+    ) -> Result<VerificationInput, ProofError> {
+        let (pk, vk) = self.keys(program)?;
+        if let Ok(proof) = load_proof(path.clone()) {
+            if ProofMode::of(&proof.proof) == Some(mode) {
+                return Ok(VerificationInput {
+                    proof,
+                    vk: vk.clone(),
+                });
+            }
+        }
+
+        let client = self.client();
+        let proof = mode
+            .apply(client.prove(pk, stdin))
+            .run()
+            .map_err(|e| ProofError::Execute(e.to_string()))?;
+        client
+            .verify(&proof, vk)
+            .map_err(|e| ProofError::Verify(e.to_string()))?;
+        proof
+            .save(&path)
+            .map_err(|e| ProofError::Serialize(e.to_string()))?;
+        Ok(VerificationInput {
+            proof,
+            vk: vk.clone(),
+        })
+    }
+}
+
+impl Default for Prover {
+    /// The default programs, proven locally.
+    fn default() -> Self {
+        Self::new(ProverPaths::default(), ProverBackend::default())
+    }
+}
+
+#[derive(Debug)]
+pub enum ProofError {
+    /// A zkVM program couldn't be read.
+    ReadElf { path: PathBuf, error: io::Error },
+    /// A zkVM program failed to run, or to be proven.
+    Execute(String),
+    /// A proof didn't verify, or can't be verified as needed.
+    Verify(String),
+    /// A program's public values couldn't be read, or a proof couldn't be saved.
+    Serialize(String),
+    /// A previously generated proof couldn't be loaded, e.g. because it's missing.
+    LoadProof { path: PathBuf, error: String },
+    /// The checksum program was given inputs it couldn't decode, or that are
+    /// out of bounds, so it committed [`Query::invalid_input_sentinel`].
+    InvalidChecksumInput,
+    /// The set to prove doesn't end with an untagged checksum query, as
+    /// [`QueryStateSet::build`] makes them, e.g. because it was rebuilt from a
+    /// [`crate::prf::SerializableQueryStateSet`].
+    MissingChecksum,
+    /// A program committed something other than what was computed on this
+    /// machine from the same inputs.
+    UnexpectedOutput(String),
+    /// The verification program was given verifying keys for a different
+    /// number of proofs than it was given public values for.
+    MismatchedInputs { vkeys: usize, public_values: usize },
+    /// The verification program's public values aren't what it would have
+    /// committed for the expected inputs.
+    CommitmentMismatch,
+    /// The verification program committed that it failed, and at which step.
+    GuestFailed(String),
+}
+
+impl ProofError {
+    /// Whether trying again might succeed: the prover may only be out of
+    /// resources, but missing files and bad proofs won't fix themselves.
+    pub fn is_retriable(&self) -> bool {
+        match self {
+            Self::ReadElf { .. } => false,
+            Self::Execute(_) => true,
+            Self::Verify(_) => false,
+            Self::Serialize(_) => false,
+            Self::LoadProof { .. } => false,
+            Self::InvalidChecksumInput => false,
+            Self::MissingChecksum => false,
+            Self::UnexpectedOutput(_) => false,
+            Self::MismatchedInputs { .. } => false,
+            Self::CommitmentMismatch => false,
+            Self::GuestFailed(_) => false,
+        }
+    }
+}
+
+impl fmt::Display for ProofError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ReadElf { path, error } => {
+                write!(f, "Could not read zkVM program {}: {error}", path.display())
+            }
+            Self::Execute(error) => write!(f, "zkVM program failed: {error}"),
+            Self::Verify(error) => write!(f, "Proof did not verify: {error}"),
+            Self::Serialize(error) => write!(f, "Could not serialize proof data: {error}"),
+            Self::LoadProof { path, error } => {
+                write!(f, "Could not load proof {}: {error}", path.display())
+            }
+            Self::InvalidChecksumInput => write!(f, "Checksum program was given invalid inputs"),
+            Self::MissingChecksum => write!(f, "Query states to prove have no checksum query"),
+            Self::UnexpectedOutput(error) => write!(f, "Unexpected zkVM program output: {error}"),
+            Self::MismatchedInputs {
+                vkeys,
+                public_values,
+            } => write!(
+                f,
+                "{vkeys} verification keys were given for {public_values} proofs' public values"
+            ),
+            Self::CommitmentMismatch => write!(
+                f,
+                "Public values don't match what the verification program would have committed"
+            ),
+            Self::GuestFailed(step) => write!(f, "Verification program failed: {step}"),
+        }
+    }
+}
+
+impl Error for ProofError {}
+
+/// Load a previously generated proof from `path`.
+pub fn load_proof(path: PathBuf) -> Result<SP1ProofWithPublicValues, ProofError> {
+    SP1ProofWithPublicValues::load(&path).map_err(|error| ProofError::LoadProof {
+        path,
+        error: error.to_string(),
+    })
+}
+
+/// Reads a program's public values in the order they were committed, like
+/// [`SP1PublicValues::read`], but fails rather than panicking on bad data.
+pub struct PublicValuesReader<'a>(&'a [u8]);
+
+impl<'a> PublicValuesReader<'a> {
+    pub fn new(public_values: &'a SP1PublicValues) -> Self {
+        Self(public_values.as_slice())
+    }
+
+    pub fn read<T: DeserializeOwned>(&mut self) -> Result<T, ProofError> {
+        bincode::deserialize_from(&mut self.0).map_err(|e| ProofError::Serialize(e.to_string()))
+    }
+}
+
+/// An input to the aggregation program.
+///
+/// Consists of a proof and a verification key.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct VerificationInput {
+    pub proof: SP1ProofWithPublicValues,
+    pub vk: SP1VerifyingKey,
+}
+
+impl VerificationInput {
+    /// A stand-in for a proof with verifying key `vk`, committing
+    /// `public_values`. It won't verify, or aggregate.
+    pub fn mock(vk: SP1VerifyingKey, public_values: SP1PublicValues) -> Self {
+        Self {
+            proof: SP1ProofWithPublicValues {
+                proof: SP1Proof::Core(vec![]),
+                stdin: SP1Stdin::new(),
+                public_values,
+                sp1_version: SP1_CIRCUIT_VERSION.to_string(),
+            },
+            vk,
+        }
+    }
+
+    /// Whether this is a stand-in from [`Self::mock`], rather than a proof.
+    pub fn is_mock(&self) -> bool {
+        matches!(&self.proof.proof, SP1Proof::Core(shards) if shards.is_empty())
+    }
+
+    /// The kind of proof this is; see [`ProofMode::of`].
+    pub fn proof_mode(&self) -> Option<ProofMode> {
+        ProofMode::of(&self.proof.proof)
+    }
+}
+
+/// How many cycles (instructions and syscalls) each zkVM program took to run
+/// for a screen, since proving cost grows with them. Programs that weren't
+/// run, e.g. by a mock prover, count zero.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExecutionStats {
+    pub hash_cycles: u64,
+    pub checksum_cycles: u64,
+    pub verification_cycles: u64,
+}
+
+impl ExecutionStats {
+    /// The cycles counted in an execution `report`.
+    pub fn cycles(report: &ExecutionReport) -> u64 {
+        report.total_instruction_count() + report.total_syscall_count()
+    }
+}
+
+/// Prove `querystates`, built by [`QueryStateSet::build`] from `windows` with
+/// `active_security_key` and `security_parameter`, with `prover`. Returns the
+/// hash proof's input, then the checksum proof's, along with the cycles the
+/// hash and checksum programs took.
+pub fn prove_querystate(
+    querystates: &QueryStateSet,
+    windows: impl IntoIterator<Item = impl AsRef<[u8]>>,
+    active_security_key: &ActiveSecurityKey,
+    security_parameter: SecurityParameter,
+    prover: &Prover,
+) -> Result<(Vec<VerificationInput>, ExecutionStats), ProofError> {
+    let (window_states, checksum_state) = match querystates.querystates() {
+        [window_states @ .., (None, checksum_state)] => (window_states, checksum_state),
+        _ => return Err(ProofError::MissingChecksum),
+    };
+
+    // The input stream that the program will read from using `sp1_zkvm::io::read`. Note that the
+    // types of the elements in the input stream must match the types being read in the program.
+    let mut hash_stdin = SP1Stdin::new();
+    let mut checksum_stdin = SP1Stdin::new();
+
+    let windows: Vec<_> = windows.into_iter().zip(window_states).collect();
+    // The program reads exactly this many windows; since windows can be empty,
+    // there's no in-band end marker
+    let count = windows.len() as u64;
+    hash_stdin.write(&count);
+    for (window, (_, state)) in windows {
+        // Write these bytes to the input stream
+        hash_stdin.write(&window.as_ref().to_vec());
+        // Followed by the window's blinding factor
+        hash_stdin.write(&state.blinding_factor.as_bytes());
+    }
+
+    if prover.backend().is_mock() {
+        let inputs = mock_inputs(prover, window_states, checksum_state)?;
+        return Ok((inputs, ExecutionStats::default()));
+    }
+
+    let paths = prover.paths();
+    let hash_elf = paths.hash_elf.load()?;
+    let checksum_elf = paths.checksum_elf.load()?;
+    let client = prover.client();
+
+    // DEBUGGING SECTION START
+    // Execute the hash_proof program using the `ProverClient.execute` method,
+    let (hash_public_values, execution_report) = client
+        .execute(&hash_elf, hash_stdin.clone())
+        .run()
+        .map_err(|e| ProofError::Execute(e.to_string()))?;
+    let hash_cycles = ExecutionStats::cycles(&execution_report);
+
+    // Read the proof hashes from the output stream until sentinel value is reached
+    let mut proof_queries = Vec::with_capacity(window_states.len());
+    let sentinel = Query::sentinel();
+    let mut hash_public_values = PublicValuesReader::new(&hash_public_values);
+    loop {
+        let read_val = hash_public_values.read::<Query>()?;
+        if read_val == sentinel {
+            break;
+        }
+        proof_queries.push(read_val)
+    }
+
+    let local_queries: Vec<Query> = window_states
+        .iter()
+        .map(|(_, state)| *state.query())
+        .collect();
+
+    // The program must have hashed exactly the queries built here
+    if proof_queries.len() as u64 != count {
+        return Err(ProofError::UnexpectedOutput(format!(
+            "hash program committed {} queries for {count} windows",
+            proof_queries.len()
+        )));
+    }
+    if !bool::from(proof_queries.as_slice().ct_eq(local_queries.as_slice())) {
+        return Err(ProofError::UnexpectedOutput(
+            "hash program's queries don't match the ones built".into(),
+        ));
+    }
+
+    let (_hash_pk, hash_vk) = prover.keys(Program::Hash)?;
+    let deserialized_hash_proof = load_proof(paths.hash_proof_path())?;
+    let input_1 = VerificationInput {
+        proof: deserialized_hash_proof,
+        vk: hash_vk.clone(),
+    };
+    // DEBUGGING SECTION END
+
+    // // PROOF GENERATION SECTION START
+    // // Generate the proof for the given program and input
+    // let (hash_pk, hash_vk) = prover.keys(Program::Hash)?;
+
+    // // Note that we use the "compressed" proof type, which is necessary for aggregation in SP1
+    // let hash_proof = client
+    //     .prove(hash_pk, hash_stdin)
+    //     .compressed()
+    //     .run()
+    //     .map_err(|e| ProofError::Execute(e.to_string()))?;
+
+    // // Verify proof and public values
+    // client
+    //     .verify(&hash_proof, hash_vk)
+    //     .map_err(|e| ProofError::Verify(e.to_string()))?;
+
+    // // Save the proof to file
+    // hash_proof
+    //     .save(paths.hash_proof_path())
+    //     .map_err(|e| ProofError::Serialize(e.to_string()))?;
+
+    // // Create a VerificationInputs struct for the hash_proof
+    // let input_1 = VerificationInput {
+    //     proof: hash_proof,
+    //     vk: hash_vk.clone(),
+    // };
+    // // PROOF GENERATION SECTION END
+
+    // write needed values to the input stream of checksum proof; the guest
+    // hashes the queries itself
+    checksum_stdin.write(&local_queries);
+    checksum_stdin.write(active_security_key);
+    checksum_stdin.write(
+        &querystates
+            .verification_weighted_sum()
+            .compress()
+            .as_bytes(),
+    );
+    checksum_stdin.write(&checksum_state.verification_factor.as_bytes());
+    checksum_stdin.write(&checksum_state.blinding_factor.as_bytes());
+    checksum_stdin.write(&security_parameter.verification_factor_max());
+
+    // DEBUGGING SECTION START
+    // Execute the checksum_proof program using the `ProverClient.execute` method,
+    let (checksum_public_values, execution_report) = client
+        .execute(&checksum_elf, checksum_stdin.clone())
+        .run()
+        .map_err(|e| ProofError::Execute(e.to_string()))?;
+    let checksum_cycles = ExecutionStats::cycles(&execution_report);
+
+    // Read the checksum_query that is generated in the program
+    let proof_checksum_query = PublicValuesReader::new(&checksum_public_values).read::<Query>()?;
+    if bool::from(proof_checksum_query.ct_eq(&Query::invalid_input_sentinel())) {
+        return Err(ProofError::InvalidChecksumInput);
+    }
+
+    // Confirm this output matches the query generated locally
+    if !bool::from(checksum_state.query().ct_eq(&proof_checksum_query)) {
+        return Err(ProofError::UnexpectedOutput(
+            "checksum program's query doesn't match the one built".into(),
+        ));
+    }
+
+    let (_checksum_pk, checksum_vk) = prover.keys(Program::Checksum)?;
+    let deserialized_checksum_proof = load_proof(paths.checksum_proof_path())?;
+    let input_2 = VerificationInput {
+        proof: deserialized_checksum_proof,
+        vk: checksum_vk.clone(),
+    };
+    // DEBUGGING SECTION END
+
+    // // PROOF GENERATION SECTION START
+    // // Generate the proof for the given program and input
+    // let (checksum_pk, checksum_vk) = prover.keys(Program::Checksum)?;
+    // let checksum_proof = client
+    //     .prove(checksum_pk, checksum_stdin)
+    //     .compressed()
+    //     .run()
+    //     .map_err(|e| ProofError::Execute(e.to_string()))?;
+
+    // // Verify proof and public values
+    // client
+    //     .verify(&checksum_proof, checksum_vk)
+    //     .map_err(|e| ProofError::Verify(e.to_string()))?;
+
+    // // Save the proof to file
+    // checksum_proof
+    //     .save(paths.checksum_proof_path())
+    //     .map_err(|e| ProofError::Serialize(e.to_string()))?;
+
+    // // Create a VerificationInputs struct for the checksum_proof
+    // let input_2 = VerificationInput {
+    //     proof: checksum_proof,
+    //     vk: checksum_vk.clone(),
+    // };
+    // // PROOF GENERATION SECTION END
+
+    let inputs = vec![input_1, input_2];
+    let stats = ExecutionStats {
+        hash_cycles,
+        checksum_cycles,
+        verification_cycles: 0,
+    };
+    Ok((inputs, stats))
+}
+
+/// Stand-ins for the proofs of `window_states` and `checksum_state`, committing
+/// what the hash and checksum programs would have.
+fn mock_inputs(
+    prover: &Prover,
+    window_states: &[(Option<HashTag>, QueryState)],
+    checksum_state: &QueryState,
+) -> Result<Vec<VerificationInput>, ProofError> {
+    let mut hash_public_values = SP1PublicValues::new();
+    for (_, state) in window_states {
+        hash_public_values.write(state.query());
+    }
+    hash_public_values.write(&Query::sentinel());
+    let (_hash_pk, hash_vk) = prover.keys(Program::Hash)?;
+
+    let mut checksum_public_values = SP1PublicValues::new();
+    checksum_public_values.write(checksum_state.query());
+    let (_checksum_pk, checksum_vk) = prover.keys(Program::Checksum)?;
+
+    Ok(vec![
+        VerificationInput::mock(hash_vk.clone(), hash_public_values),
+        VerificationInput::mock(checksum_vk.clone(), checksum_public_values),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::active_security::Commitment;
+
+    use super::*;
+
+    #[test]
+    fn missing_files_are_errors() {
+        let missing = std::env::temp_dir().join("doprf-prove-test-missing");
+        let paths = ProverPaths {
+            hash_elf: Elf::Path(missing.join("hash-elf")),
+            output_dir: missing,
+            ..Default::default()
+        };
+        assert!(matches!(
+            paths.hash_elf.load(),
+            Err(ProofError::ReadElf { .. })
+        ));
+        assert!(matches!(
+            load_proof(paths.hash_proof_path()),
+            Err(ProofError::LoadProof { .. })
+        ));
+        assert!(paths.checksum_elf.load().is_ok());
+    }
+
+    #[test]
+    fn mock_proofs_commit_what_the_programs_would() {
+        let key =
+            ActiveSecurityKey::from_commitments([Commitment::hash_from_bytes_for_tests_only(&[1])]);
+        let windows = ["acgtacgtacgt", "cgtacgtacgta"];
+        let querystates = QueryStateSet::build(
+            windows
+                .iter()
+                .enumerate()
+                .map(|(i, w)| (HashTag::new(i == 0, 0, i), w.as_bytes())),
+            1,
+            key.clone(),
+            SecurityParameter::default(),
+        )
+        .unwrap();
+        let (inputs, stats) = prove_querystate(
+            &querystates,
+            windows,
+            &key,
+            SecurityParameter::default(),
+            &Prover::new(ProverPaths::default(), ProverBackend::Mock),
+        )
+        .unwrap();
+        assert_eq!(inputs.len(), 2);
+        // Nothing was executed
+        assert_eq!(stats, ExecutionStats::default());
+
+        let queries: Vec<Query> = querystates.queries().copied().collect();
+        let mut hash_public_values = PublicValuesReader::new(&inputs[0].proof.public_values);
+        for query in &queries[..windows.len()] {
+            assert_eq!(hash_public_values.read::<Query>().unwrap(), *query);
+        }
+        let sentinel = hash_public_values.read::<Query>().unwrap();
+        assert_eq!(sentinel, Query::sentinel());
+        let mut checksum_public_values = PublicValuesReader::new(&inputs[1].proof.public_values);
+        let checksum_query = checksum_public_values.read::<Query>().unwrap();
+        assert_eq!(checksum_query, queries[windows.len()]);
+    }
+
+    #[test]
+    fn sets_without_a_checksum_are_errors() {
+        let key =
+            ActiveSecurityKey::from_commitments([Commitment::hash_from_bytes_for_tests_only(&[1])]);
+        let windows = ["acgtacgtacgt"];
+        let querystates = QueryStateSet::build(
+            [(HashTag::new(true, 0, 0), windows[0].as_bytes())],
+            1,
+            key.clone(),
+            SecurityParameter::default(),
+        )
+        .unwrap();
+        // Rebuilding tags every query state, including the checksum's.
+        let rebuilt = querystates
+            .to_serializable_set()
+            .to_query_state_set()
+            .unwrap();
+        let result = prove_querystate(
+            &rebuilt,
+            windows,
+            &key,
+            SecurityParameter::default(),
+            &Prover::new(ProverPaths::default(), ProverBackend::Mock),
+        );
+        assert!(matches!(result, Err(ProofError::MissingChecksum)));
+    }
+
+    #[test]
+    fn truncated_public_values_are_errors() {
+        let mut public_values = SP1PublicValues::new();
+        public_values.write(&true);
+        let mut reader = PublicValuesReader::new(&public_values);
+        assert!(reader.read::<bool>().unwrap());
+        let error = reader.read::<Query>().unwrap_err();
+        assert!(matches!(error, ProofError::Serialize(_)));
+        assert!(!error.is_retriable());
+    }
+
+    #[test]
+    fn mock_inputs_are_recognizable() {
+        let prover = Prover::new(ProverPaths::default(), ProverBackend::Mock);
+        let (_pk, vk) = prover.keys(Program::Verification).unwrap();
+        let input = VerificationInput::mock(vk.clone(), SP1PublicValues::new());
+        assert!(input.is_mock());
+        assert_eq!(input.proof_mode(), None);
+    }
+
+    #[test]
+    fn proof_modes_parse_as_displayed() {
+        for mode in [ProofMode::Compressed, ProofMode::Groth16, ProofMode::Plonk] {
+            assert_eq!(mode.to_string().parse::<ProofMode>(), Ok(mode));
+        }
+        assert!("core".parse::<ProofMode>().is_err());
+        assert_eq!(Prover::default().proof_mode(), ProofMode::Compressed);
+    }
+
+    #[test]
+    fn keys_are_set_up_once() {
+        let prover = Prover::new(ProverPaths::default(), ProverBackend::Mock);
+        let first = prover.keys(Program::Hash).unwrap();
+        let again = prover.keys(Program::Hash).unwrap();
+        assert!(std::ptr::eq(first, again));
+        assert!(std::ptr::eq(prover.client(), prover.client()));
+
+        // Programs that can't be read aren't cached, so they're retried.
+        let missing = std::env::temp_dir().join("doprf-prove-test-missing");
+        let prover = Prover::new(
+            ProverPaths {
+                hash_elf: Elf::Path(missing.join("hash-elf")),
+                ..Default::default()
+            },
+            ProverBackend::Mock,
+        );
+        for _ in 0..2 {
+            assert!(matches!(
+                prover.keys(Program::Hash),
+                Err(ProofError::ReadElf { .. })
+            ));
+        }
+    }
+}
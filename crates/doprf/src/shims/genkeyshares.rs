@@ -44,7 +44,7 @@ pub fn main<Out: Write, Err: Write>(
     .map_err(|err| std::io::Error::new(ErrorKind::InvalidInput, err))?;
 
     for keyshare in keyshares.iter() {
-        writeln!(stdout, "{}", keyshare)?;
+        writeln!(stdout, "{}", keyshare.reveal_hex())?;
     }
 
     Ok(())
@@ -100,7 +100,7 @@ mod tests {
         assert_eq!(shares.len(), NUM_KEYHOLDERS.get() as usize);
 
         let query_state =
-            QueryState::new(QUERY_STRING.as_bytes(), KEYHOLDERS_REQUIRED.get() as usize);
+            QueryState::new(QUERY_STRING.as_bytes(), KEYHOLDERS_REQUIRED.get() as usize).unwrap();
 
         let keyserver_subsets = vec![
             (0, 1, 2),
@@ -132,7 +132,10 @@ mod tests {
                 );
             }
             assert!(query_state.has_hash());
-            let completed_hash = query_state.get_hash_value().expect("Hash value");
+            let completed_hash = query_state
+                .get_hash_value()
+                .expect("valid responses")
+                .expect("Hash value");
             assert_eq!(hash_bytes, <[u8; 32]>::from(&completed_hash));
         }
     }
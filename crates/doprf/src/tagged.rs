@@ -1,7 +1,7 @@
 // Copyright 2021-2024 SecureDNA Stiftung (SecureDNA Foundation) <licensing@securedna.org>
 // SPDX-License-Identifier: MIT OR Apache-2.0
 
-use crate::prf::CompletedHashValue;
+use crate::prf::{CompletedHashValue, DecodeError};
 
 /// A 4-byte header prepended to each Ristretto hash in a tagged hash stream. It
 /// describes whether the hash starts a new record, its index in the record, and
@@ -54,6 +54,23 @@ impl HashTag {
         (u32::from_be_bytes(self.0) & 0xffffff) as usize
     }
 
+    /// Return the index of the record this hash belongs to, given the record
+    /// index of the previous hash in the stream (`None` for the first hash).
+    ///
+    /// Tags only mark where records start, so record indexes can only be
+    /// recovered by walking the stream in order.
+    pub fn record_index(&self, previous_record: Option<u64>) -> u64 {
+        previous_record
+            .map(|r| r + self.starts_new_record() as u64)
+            .unwrap_or_default()
+    }
+
+    /// Are any of the reserved bits set? Tags built with [`Self::new`] never
+    /// have them set.
+    pub fn has_reserved_bits(&self) -> bool {
+        self.0[0] & 0xe0 != 0
+    }
+
     pub fn as_bytes(&self) -> &[u8; 4] {
         &self.0
     }
@@ -82,15 +99,44 @@ pub struct TaggedHash {
 
 impl TaggedHash {
     pub const SIZE: usize = 36;
+
+    /// Does this hash mark the start of a new record?
+    pub fn is_record_start(&self) -> bool {
+        self.tag.starts_new_record()
+    }
+
+    /// See [`HashTag::record_index`].
+    pub fn record_index(&self, previous_record: Option<u64>) -> u64 {
+        self.tag.record_index(previous_record)
+    }
+}
+
+/// Equality is over the encoded form: the 4 tag bytes and the 32 bytes of the
+/// compressed hash. Since ristretto compression is canonical, this agrees with
+/// equality of the underlying points.
+impl PartialEq for TaggedHash {
+    fn eq(&self, other: &Self) -> bool {
+        self.tag == other.tag && self.hash.as_bytes() == other.hash.as_bytes()
+    }
+}
+
+impl Eq for TaggedHash {}
+
+/// Hashes the same encoded form that [`PartialEq`] compares.
+impl std::hash::Hash for TaggedHash {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.tag.hash(state);
+        self.hash.as_bytes().hash(state);
+    }
 }
 
 impl TryFrom<[u8; 36]> for TaggedHash {
-    type Error = ();
+    type Error = DecodeError;
 
     fn try_from(value: [u8; 36]) -> Result<Self, Self::Error> {
         let tag = HashTag(value[..4].try_into().unwrap());
         let hash: &[u8; 32] = value[4..].try_into().unwrap();
-        let hash: CompletedHashValue = hash.try_into().unwrap();
+        let hash = CompletedHashValue::try_from(hash)?;
         Ok(Self { tag, hash })
     }
 }
@@ -114,6 +160,66 @@ impl From<TaggedHash> for CompletedHashValue {
 mod tests {
     use super::*;
     use quickcheck::quickcheck;
+    use std::collections::HashSet;
+
+    #[test]
+    fn tagged_hash_set_membership() {
+        let hash = CompletedHashValue::hash_from_bytes_for_tests_only(b"acgt");
+        let other_hash = CompletedHashValue::hash_from_bytes_for_tests_only(b"tgca");
+        let tag = HashTag::new(true, 0, 0);
+        let other_tag = HashTag::new(false, 0, 1);
+
+        let mut set = HashSet::new();
+        assert!(set.insert(TaggedHash { tag, hash }));
+        // The same tag and hash, decoded separately, is a duplicate
+        let encoded = <[u8; 36]>::from(TaggedHash { tag, hash });
+        let roundtripped = TaggedHash::try_from(encoded).unwrap();
+        assert!(!set.insert(roundtripped));
+
+        // Differing in either the tag or the hash makes it distinct
+        assert!(!set.contains(&TaggedHash {
+            tag: other_tag,
+            hash
+        }));
+        assert!(!set.contains(&TaggedHash {
+            tag,
+            hash: other_hash
+        }));
+        assert!(set.insert(TaggedHash {
+            tag: other_tag,
+            hash
+        }));
+        assert_eq!(set.len(), 2);
+    }
+
+    #[test]
+    fn record_boundaries_follow_record_start_tags() {
+        let hash = CompletedHashValue::hash_from_bytes_for_tests_only(b"acgt");
+        // Three records: two windows, one window, then three windows.
+        let starts = [true, false, true, true, false, false];
+        let hashes: Vec<_> = starts
+            .iter()
+            .enumerate()
+            .map(|(i, &start)| TaggedHash {
+                tag: HashTag::new(start, 0, i),
+                hash,
+            })
+            .collect();
+
+        let mut previous_record = None;
+        let records: Vec<_> = hashes
+            .iter()
+            .map(|tagged| {
+                let record = tagged.record_index(previous_record);
+                previous_record = Some(record);
+                record
+            })
+            .collect();
+        assert_eq!(records, vec![0, 0, 1, 2, 2, 2]);
+
+        let record_starts: Vec<_> = hashes.iter().map(|h| h.is_record_start()).collect();
+        assert_eq!(record_starts, starts);
+    }
 
     quickcheck! {
         fn qc_hashtag_roundtrips(starts_new_record: bool, h: u8, i: usize) -> bool {
@@ -0,0 +1,357 @@
+// Copyright 2021-2024 SecureDNA Stiftung (SecureDNA Foundation) <licensing@securedna.org>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Checkpointing for long screens.
+//!
+//! Instead of screening every record in one DOPRF round, records are screened in
+//! batches of `every_records`. After each batch, the accumulated result and the
+//! position in the record/window stream are handed to a caller callback, which can
+//! persist them. Passing a persisted [`ScreenCheckpoint`] back in as `resume_from`
+//! skips the records it already covers, as long as it was taken for the same
+//! sequences.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::num::NonZeroUsize;
+use std::ops::Range;
+
+use quickdna::ToNucleotideLike;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use shared_types::hdb::HdbScreeningResult;
+use shared_types::synthesis_permission::Region;
+
+use crate::doprf_client::{process, DoprfConfig, DoprfOutput, DoprfTimings, NotScreened};
+use crate::error::DoprfError;
+
+/// Progress through a checkpointed screen.
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ScreenCheckpoint {
+    /// The [`input_digest`] of the sequences being screened. A checkpoint is
+    /// only resumed for the same sequences.
+    #[serde(default)]
+    pub input_digest: [u8; 32],
+    /// Number of leading records whose results are included in `partial`.
+    pub records_done: u64,
+    /// Number of windows hashed and screened for those records.
+    pub windows_done: u64,
+    /// Hashes sent to the keyservers for those records on top of
+    /// `windows_done`; see [`DoprfOutput::overhead_hashes`].
+    #[serde(default)]
+    pub overhead_hashes: u64,
+    /// Results for the first `records_done` records in each region, with
+    /// `record` indices relative to the full input.
+    pub partial: HashMap<Region, HdbScreeningResult>,
+    /// Records among the first `records_done` that were not screened.
+    #[serde(default)]
+    pub not_screened: Vec<NotScreened>,
+    /// The first HDB failure among those records, if the screen failed open;
+    /// see [`DoprfOutput::hdb_failure`].
+    #[serde(default)]
+    pub hdb_failure: Option<String>,
+}
+
+/// Like [`process`], but screens `every_records` records at a time, calling
+/// `on_checkpoint` after each batch.
+///
+/// If `resume_from` is given, the records it covers are skipped and its results
+/// are carried over into the output.
+pub async fn process_with_checkpoints<'a, NLike, SliceN>(
+    config: DoprfConfig<'a, SliceN>,
+    every_records: NonZeroUsize,
+    resume_from: Option<ScreenCheckpoint>,
+    on_checkpoint: impl FnMut(&ScreenCheckpoint),
+) -> Result<DoprfOutput, DoprfError>
+where
+    NLike: ToNucleotideLike + Copy + 'a,
+    SliceN: AsRef<[NLike]>,
+{
+    let config = &config;
+    run_checkpointed(
+        config.sequences.len(),
+        input_digest(config.sequences),
+        every_records,
+        resume_from,
+        |records| process(config.with_sequences(&config.sequences[records])),
+        on_checkpoint,
+    )
+    .await
+}
+
+/// A SHA-256 of `sequences`, record by record, binding a [`ScreenCheckpoint`]
+/// to the input it was taken for.
+pub fn input_digest<NLike, SliceN>(sequences: &[SliceN]) -> [u8; 32]
+where
+    NLike: ToNucleotideLike + Copy,
+    SliceN: AsRef<[NLike]>,
+{
+    let mut hasher = Sha256::new();
+    for sequence in sequences {
+        let sequence = sequence.as_ref();
+        hasher.update((sequence.len() as u64).to_le_bytes());
+        let ascii: Vec<u8> = sequence
+            .iter()
+            .map(|nuc| nuc.to_nucleotide_like().to_ascii())
+            .collect();
+        hasher.update(ascii);
+    }
+    hasher.finalize().into()
+}
+
+/// Drive `screen_batch` over `0..total_records` in batches, starting after
+/// `resume_from` (if any), and merge the per-batch outputs. `resume_from` must
+/// have been taken for input with the same `input_digest`.
+async fn run_checkpointed<F, Fut>(
+    total_records: usize,
+    input_digest: [u8; 32],
+    every_records: NonZeroUsize,
+    resume_from: Option<ScreenCheckpoint>,
+    mut screen_batch: F,
+    mut on_checkpoint: impl FnMut(&ScreenCheckpoint),
+) -> Result<DoprfOutput, DoprfError>
+where
+    F: FnMut(Range<usize>) -> Fut,
+    Fut: Future<Output = Result<DoprfOutput, DoprfError>>,
+{
+    let mut checkpoint = match resume_from {
+        Some(checkpoint) if checkpoint.input_digest != input_digest => {
+            return Err(DoprfError::CheckpointMismatch);
+        }
+        Some(checkpoint) => checkpoint,
+        None => ScreenCheckpoint {
+            input_digest,
+            ..Default::default()
+        },
+    };
+    let mut all_too_short = checkpoint.windows_done == 0;
+    // Only covers the batches screened by this call, not any resumed from.
+    let mut timings = DoprfTimings::default();
+
+    let mut start = usize::try_from(checkpoint.records_done)
+        .map_err(|_| DoprfError::InvalidRecord)?
+        .min(total_records);
+    while start < total_records {
+        let end = start.saturating_add(every_records.get()).min(total_records);
+        let output = screen_batch(start..end).await?;
+
+        let offset = start as u64;
+        for (region, response) in output.responses {
+            let partial = checkpoint.partial.entry(region).or_default();
+            append_batch(partial, response, offset);
+        }
+        checkpoint
+            .not_screened
+            .extend(output.not_screened.into_iter().map(|mut not_screened| {
+                not_screened.record += offset;
+                not_screened
+            }));
+        checkpoint.windows_done += output.n_hashes;
+        checkpoint.overhead_hashes += output.overhead_hashes;
+        checkpoint.records_done = end as u64;
+        all_too_short &= output.too_short;
+        if checkpoint.hdb_failure.is_none() {
+            checkpoint.hdb_failure = output.hdb_failure;
+        }
+        timings += output.timings;
+
+        on_checkpoint(&checkpoint);
+        start = end;
+    }
+
+    Ok(DoprfOutput {
+        n_hashes: checkpoint.windows_done,
+        overhead_hashes: checkpoint.overhead_hashes,
+        too_short: all_too_short,
+        responses: checkpoint.partial,
+        not_screened: checkpoint.not_screened,
+        timings,
+        hdb_failure: checkpoint.hdb_failure,
+    })
+}
+
+/// Append the `response` for a batch starting at record `offset` to `partial`.
+fn append_batch(partial: &mut HdbScreeningResult, response: HdbScreeningResult, offset: u64) {
+    let HdbScreeningResult {
+        results,
+        debug_hdb_responses,
+        provider_reference,
+        warnings,
+    } = response;
+    partial
+        .results
+        .extend(results.into_iter().map(|mut hazard| {
+            hazard.record += offset;
+            hazard
+        }));
+    if let Some(debug_hdb_responses) = debug_hdb_responses {
+        partial
+            .debug_hdb_responses
+            .get_or_insert_with(Vec::new)
+            .extend(debug_hdb_responses.into_iter().map(|mut response| {
+                response.record += offset;
+                response
+            }));
+    }
+    partial
+        .warnings
+        .extend(warnings.into_iter().map(|mut warning| {
+            warning.record += offset;
+            warning
+        }));
+    if provider_reference.is_some() {
+        partial.provider_reference = provider_reference;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::cell::RefCell;
+
+    use quickdna::{DnaSequence, Nucleotide};
+
+    use crate::doprf_client::CHECKSUM_HASHES;
+
+    use shared_types::hdb::{ConsolidatedHazardResult, HitRegion, Organism};
+    use shared_types::synthesis_permission::SynthesisPermission;
+
+    fn hit(record: u64) -> ConsolidatedHazardResult {
+        let organism = Organism {
+            name: "T. Checkpointitis".into(),
+            organism_type: pipeline_bridge::OrganismType::Virus,
+            ans: vec![],
+            tags: vec![],
+        };
+        ConsolidatedHazardResult {
+            record,
+            hit_regions: vec![HitRegion {
+                seq_range_start: 0,
+                seq_range_end: 42,
+            }],
+            synthesis_permission: SynthesisPermission::Denied,
+            most_likely_organism: organism.clone(),
+            organisms: vec![organism],
+            is_dna: true,
+            is_wild_type: None,
+            exempt: false,
+            window_offsets: vec![],
+        }
+    }
+
+    const DIGEST: [u8; 32] = [7; 32];
+
+    /// Screens each record as one window, reporting a hit on every record.
+    async fn fake_screen(records: Range<usize>) -> Result<DoprfOutput, DoprfError> {
+        Ok(DoprfOutput {
+            n_hashes: records.len() as u64,
+            overhead_hashes: CHECKSUM_HASHES,
+            too_short: false,
+            responses: HashMap::from([(
+                Region::All,
+                HdbScreeningResult {
+                    results: (0..records.len() as u64).map(hit).collect(),
+                    debug_hdb_responses: None,
+                    provider_reference: None,
+                    warnings: vec![],
+                },
+            )]),
+            not_screened: vec![],
+            timings: Default::default(),
+            hdb_failure: None,
+        })
+    }
+
+    #[tokio::test]
+    async fn interrupted_screen_resumes_from_checkpoint() {
+        let every = NonZeroUsize::new(2).unwrap();
+        let persisted = RefCell::new(None);
+        let screened = RefCell::new(vec![]);
+
+        // The second batch fails, after the first batch has been checkpointed.
+        let err = run_checkpointed(
+            5,
+            DIGEST,
+            every,
+            None,
+            |records| {
+                screened.borrow_mut().push(records.clone());
+                async move {
+                    if records.start == 0 {
+                        fake_screen(records).await
+                    } else {
+                        Err(DoprfError::SequencesTooBig)
+                    }
+                }
+            },
+            |checkpoint| {
+                *persisted.borrow_mut() = Some(serde_json::to_string(checkpoint).unwrap());
+            },
+        )
+        .await;
+        assert!(err.is_err());
+        assert_eq!(screened.take(), vec![0..2, 2..4]);
+
+        let checkpoint: ScreenCheckpoint =
+            serde_json::from_str(&persisted.take().unwrap()).unwrap();
+        assert_eq!(checkpoint.records_done, 2);
+        assert_eq!(checkpoint.windows_done, 2);
+
+        let output = run_checkpointed(
+            5,
+            DIGEST,
+            every,
+            Some(checkpoint),
+            |records| {
+                screened.borrow_mut().push(records.clone());
+                fake_screen(records)
+            },
+            |_| {},
+        )
+        .await
+        .unwrap();
+
+        // Already-checkpointed records aren't screened again...
+        assert_eq!(screened.take(), vec![2..4, 4..5]);
+        // ...but their results are still part of the output.
+        assert_eq!(output.n_hashes, 5);
+        // One checksum per batch, including the checkpointed one.
+        assert_eq!(output.overhead_hashes, 3 * CHECKSUM_HASHES);
+        assert!(!output.too_short);
+        let records: Vec<_> = output.responses[&Region::All]
+            .results
+            .iter()
+            .map(|r| r.record)
+            .collect();
+        assert_eq!(records, vec![0, 1, 2, 3, 4]);
+    }
+
+    #[tokio::test]
+    async fn checkpoints_only_resume_the_same_input() {
+        let checkpoint = ScreenCheckpoint {
+            input_digest: DIGEST,
+            records_done: 1,
+            ..Default::default()
+        };
+        let err = run_checkpointed(
+            5,
+            [8; 32],
+            NonZeroUsize::new(2).unwrap(),
+            Some(checkpoint),
+            |_| async { panic!("screened a batch for a mismatched checkpoint") },
+            |_| {},
+        )
+        .await
+        .unwrap_err();
+        assert!(matches!(err, DoprfError::CheckpointMismatch));
+    }
+
+    #[test]
+    fn input_digest_depends_on_record_boundaries() {
+        let dna = |s| DnaSequence::<Nucleotide>::parse(0, s).unwrap();
+        let joined = [dna("acgtacgt")];
+        let split = [dna("acgt"), dna("acgt")];
+        assert_eq!(input_digest(&joined), input_digest(&joined));
+        assert_ne!(input_digest(&joined), input_digest(&split));
+    }
+}
@@ -1,62 +1,196 @@
 // Copyright 2021-2024 SecureDNA Stiftung (SecureDNA Foundation) <licensing@securedna.org>
 // SPDX-License-Identifier: MIT OR Apache-2.0
 
-use std::sync::Arc;
-
-use crate::error::DoprfError;
-use crate::instant::get_now;
-use crate::operations::{incorporate_responses_and_hash, make_keyserver_querysets};
-use crate::scep_client::{ClientConfig, HdbClient, KeyserverSetClient};
+use std::collections::{HashMap, VecDeque};
+use std::num::NonZeroUsize;
+use std::ops::AddAssign;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use crate::error::{DoprfError, RetryLimit};
+use crate::instant::{get_now, Instant};
+use crate::operations::{
+    incorporate_responses_and_hash, incorporate_responses_and_hash_capturing,
+    make_keyserver_querysets,
+};
+use crate::progress::{NoProgress, ProgressSink};
+use crate::rate_limit::RateLimiter;
+use crate::recursive_proof::{read_recursive_hashes, recursive_commitment};
+use crate::retry_if::with_deadline;
+use crate::scep_client::{
+    ClientConfig, HdbClient, KeyserverApplyFn, KeyserverSetClient, RetryBudget,
+};
 use crate::server_selection::{ChosenSelectionSubset, SelectedKeyserver, ServerSelector};
 use crate::server_version_handler::LastServerVersionHandler;
-use crate::windows::Windows;
+use crate::windows::{Window, Windows};
 use certificates::{ExemptionTokenGroup, TokenBundle};
 use doprf::active_security::ActiveSecurityKey;
 use doprf::party::{KeyserverIdSet, KeyserverId};
-use doprf::prf::{Query, QueryStateSet, SerializableQueryStateSet, HashPart, VerificationInput};
+use doprf::prf::{
+    CompletedHashValue, HashPart, Query, QueryStateSet, SecurityParameter,
+    SerializableQueryStateSet, VerificationInput,
+};
+use doprf::prove::{
+    ExecutionStats, Program, ProofError, Prover, ProverBackend, PublicValuesReader,
+};
 use doprf::tagged::{HashTag, TaggedHash};
+use futures::future::join_all;
 use http_client::BaseApiClient;
 use packed_ristretto::{PackableRistretto, PackedRistrettos};
 use quickdna::ToNucleotideLike;
 use scep_client_helpers::ClientCerts;
+use serde::{Deserialize, Serialize};
 use shared_types::et::WithOtps;
 use shared_types::hash::HashSpec;
 use shared_types::hdb::HdbScreeningResult;
+use shared_types::requests::AttemptLog;
+use shared_types::requests::AttemptServers;
 use shared_types::requests::RequestContext;
 use shared_types::requests::RequestId;
 use shared_types::requests::SerializableRequestContext;
 use shared_types::synthesis_permission::Region;
-use tracing::{debug, info};
-use sp1_sdk::{
-    include_elf, HashableKey, ProverClient, SP1Proof, SP1ProofWithPublicValues, SP1Stdin,
-    SP1VerifyingKey,
-};
+use tracing::{debug, info, warn};
+use sp1_sdk::{include_elf, HashableKey, SP1Proof, SP1Stdin, SP1VerifyingKey};
 
 pub struct DoprfConfig<'a, S> {
     pub api_client: &'a BaseApiClient,
     pub server_selector: Arc<ServerSelector>,
     pub request_ctx: &'a RequestContext,
     pub certs: Arc<ClientCerts>,
-    pub region: Region,
+    /// The regions to screen under. Keyserver hashing is shared between them,
+    /// but each gets its own HDB session and its own entry in
+    /// [`DoprfOutput::responses`]. Regions are queried concurrently, and the
+    /// base pairs only count towards the client's daily limit once.
+    pub regions: Vec<Region>,
     /// Whether to request debug_info from the servers
     pub debug_info: bool,
     pub sequences: &'a [S],
     pub max_windows: u64,
     /// A freeform version hint for the caller, used for tracking client
     /// distribution (similar to User-Agent in HTTP)
-    pub version_hint: String,
+    pub version_hint: VersionHint,
     /// Exemption tokens.
     pub ets: Vec<WithOtps<TokenBundle<ExemptionTokenGroup>>>,
     pub server_version_handler: &'a LastServerVersionHandler,
+    /// How to report records that are too short to produce any windows.
+    pub zero_window_policy: ZeroWindowPolicy,
+    /// Whether to fail the screen if the HDB fails.
+    pub hdb_failure_policy: HdbFailurePolicy,
+    /// Whether to return a [`FailureCapture`](crate::failure_capture::FailureCapture)
+    /// with errors incorporating keyserver responses, so they can be reproduced offline.
+    pub capture_failures: bool,
+    /// Whether to keep the offsets of the windows that hit each hazard, as
+    /// reported by the HDB (in
+    /// [`ConsolidatedHazardResult::window_offsets`](shared_types::hdb::ConsolidatedHazardResult::window_offsets)).
+    /// These can make results for large hits much bigger, so they're dropped
+    /// unless debugging.
+    pub report_window_offsets: bool,
+    /// Paces requests to each keyserver. Share one limiter between screens
+    /// that should count towards the same per-keyserver limits.
+    pub keyserver_rate_limiter: Option<Arc<RateLimiter>>,
+    /// The longest response to accept from any keyserver. A keyserver that
+    /// sends more is cut off and marked bad.
+    pub max_keyserver_response_bytes: Option<u64>,
+    /// The most attempts (each with its own server selection) to make at this
+    /// request, counting every attempt in `request_ctx`, so including those
+    /// made by earlier calls that are being retried. Retries of requests to
+    /// servers within an attempt count too. Once reached, [`process`] returns
+    /// [`DoprfError::DeadlineExceeded`] rather than trying again.
+    pub max_total_attempts: Option<NonZeroUsize>,
+    /// When to give up on this request. Internal retries still in progress at
+    /// the deadline are abandoned, and [`process`] returns
+    /// [`DoprfError::DeadlineExceeded`].
+    pub overall_deadline: Option<Instant>,
+    /// Batches of at least this many hashes are hashed across threads, and
+    /// smaller ones serially. `None` uses [`doprf::prf::DEFAULT_PARALLEL_THRESHOLD`].
+    pub parallel_threshold: Option<usize>,
+    /// Incorporate keyserver responses sorted by keyserver ID rather than in
+    /// arrival order, so captured query sets are reproducible across runs.
+    pub canonical_response_order: bool,
+    /// How hard active security is to evade, against how costly the proofs
+    /// are. `None` uses [`doprf::prf::SECURITY_PARAMETER`].
+    pub security_parameter: Option<SecurityParameter>,
+    /// The zkVM programs to prove with, where proofs are kept, and which
+    /// prover to prove with, e.g. the SP1 Prover Network. Share one between
+    /// screens, so its client and keys are only set up once. The backend is
+    /// ignored with the `mock-prover` feature, which always uses
+    /// [`ProverBackend::Mock`].
+    pub prover: Arc<Prover>,
+    /// Answer keyserver queries with this instead of the keyservers, so tests
+    /// can screen against misbehaving keyservers. [`hash_only`] ignores it,
+    /// since it takes the hash spec from the keyservers' sessions.
+    pub keyserver_apply: Option<KeyserverApplyFn>,
+    /// Where to report progress through the screen, as it's made. `None`
+    /// discards it.
+    pub progress_sink: Option<Arc<dyn ProgressSink>>,
 }
 
 impl<'a, S> DoprfConfig<'a, S> {
-    fn client_config(&self) -> ClientConfig {
+    /// This config, but screening `sequences` instead, e.g. a batch of the
+    /// original sequences.
+    pub fn with_sequences<'b, T>(&self, sequences: &'b [T]) -> DoprfConfig<'b, T>
+    where
+        'a: 'b,
+    {
+        DoprfConfig {
+            api_client: self.api_client,
+            server_selector: self.server_selector.clone(),
+            request_ctx: self.request_ctx,
+            certs: self.certs.clone(),
+            regions: self.regions.clone(),
+            debug_info: self.debug_info,
+            sequences,
+            max_windows: self.max_windows,
+            version_hint: self.version_hint.clone(),
+            ets: self.ets.clone(),
+            server_version_handler: self.server_version_handler,
+            zero_window_policy: self.zero_window_policy,
+            hdb_failure_policy: self.hdb_failure_policy,
+            capture_failures: self.capture_failures,
+            report_window_offsets: self.report_window_offsets,
+            keyserver_rate_limiter: self.keyserver_rate_limiter.clone(),
+            max_keyserver_response_bytes: self.max_keyserver_response_bytes,
+            max_total_attempts: self.max_total_attempts,
+            overall_deadline: self.overall_deadline,
+            parallel_threshold: self.parallel_threshold,
+            canonical_response_order: self.canonical_response_order,
+            security_parameter: self.security_parameter,
+            prover: self.prover.clone(),
+            keyserver_apply: self.keyserver_apply.clone(),
+            progress_sink: self.progress_sink.clone(),
+        }
+    }
+
+    /// The config for connecting to servers, with the current version hint.
+    pub(crate) fn client_config(&self) -> ClientConfig {
         ClientConfig {
             api_client: self.api_client.clone(),
             certs: self.certs.clone(),
-            version_hint: self.version_hint.clone(),
+            version_hint: self.version_hint.get().to_string(),
             debug_info: self.debug_info,
+            keyserver_rate_limiter: self.keyserver_rate_limiter.clone(),
+            max_keyserver_response_bytes: self.max_keyserver_response_bytes,
+            keyserver_apply: self.keyserver_apply.clone(),
+            retry_budget: RetryBudget::new(
+                self.request_ctx.attempts.clone(),
+                self.max_total_attempts,
+            ),
+        }
+    }
+
+    /// Where to report progress, see `progress_sink`.
+    pub(crate) fn progress_sink(&self) -> &dyn ProgressSink {
+        self.progress_sink.as_deref().unwrap_or(&NoProgress)
+    }
+
+    /// The prover to actually prove with, see `prover`.
+    pub(crate) fn effective_prover(&self) -> Arc<Prover> {
+        if cfg!(feature = "mock-prover") && !self.prover.backend().is_mock() {
+            let paths = self.prover.paths().clone();
+            let prover = Prover::new(paths, ProverBackend::Mock);
+            Arc::new(prover.with_proof_mode(self.prover.proof_mode()))
+        } else {
+            self.prover.clone()
         }
     }
 
@@ -64,32 +198,225 @@ impl<'a, S> DoprfConfig<'a, S> {
     where
         S: AsRef<[N]>,
     {
-        self.sequences
-            .iter()
-            .map(|seq| seq.as_ref().len())
-            .try_fold(0u64, |total, len| total.checked_add(len.try_into().ok()?))
-            .ok_or(DoprfError::SequencesTooBig)
+        checked_total_len(self.sequences.iter().map(|seq| seq.as_ref().len() as u64))
+    }
+
+    /// The configured regions, without duplicates, in their original order.
+    fn unique_regions(&self) -> Result<Vec<Region>, DoprfError> {
+        let mut regions: Vec<Region> = Vec::with_capacity(self.regions.len());
+        for &region in &self.regions {
+            if !regions.contains(&region) {
+                regions.push(region);
+            }
+        }
+        if regions.is_empty() {
+            return Err(DoprfError::NoRegions);
+        }
+        Ok(regions)
+    }
+}
+
+/// A version hint that can be changed (e.g. during a canary rollout) without
+/// rebuilding the configs that share it.
+///
+/// Clones share the hint. Each screen reads it once, when it starts connecting
+/// to servers, so changes apply from the next screen on.
+#[derive(Clone, Debug)]
+pub struct VersionHint(Arc<RwLock<Arc<str>>>);
+
+impl VersionHint {
+    pub fn new(hint: impl Into<Arc<str>>) -> Self {
+        Self(Arc::new(RwLock::new(hint.into())))
+    }
+
+    pub fn get(&self) -> Arc<str> {
+        self.0.read().unwrap().clone()
+    }
+
+    pub fn set(&self, hint: impl Into<Arc<str>>) {
+        *self.0.write().unwrap() = hint.into();
+    }
+}
+
+impl From<&str> for VersionHint {
+    fn from(hint: &str) -> Self {
+        Self::new(hint)
+    }
+}
+
+impl From<String> for VersionHint {
+    fn from(hint: String) -> Self {
+        Self::new(hint)
+    }
+}
+
+/// Sum the given sequence lengths, reporting where the total overflowed if it does.
+fn checked_total_len(lens: impl Iterator<Item = u64>) -> Result<u64, DoprfError> {
+    let mut total = 0u64;
+    for (sequence_index, len) in lens.enumerate() {
+        total = total
+            .checked_add(len)
+            .ok_or(DoprfError::NucleotideCountOverflow {
+                total_before: total,
+                sequence_index,
+            })?;
+    }
+    Ok(total)
+}
+
+/// What to do with records that produce no windows (e.g. because they are
+/// shorter than every window in the hash spec).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ZeroWindowPolicy {
+    /// Leave them out of the output.
+    #[default]
+    Omit,
+    /// List them in [`DoprfOutput::not_screened`].
+    Report,
+}
+
+/// What to do when the HDB can't be reached or fails to screen the hashes.
+/// Keyserver failures, and running out of `overall_deadline`, always fail the
+/// screen.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum HdbFailurePolicy {
+    /// Return the HDB's error.
+    #[default]
+    FailClosed,
+    /// Return an output with no hazards for the regions the HDB didn't
+    /// screen, with the error in [`DoprfOutput::hdb_failure`].
+    FailOpen,
+}
+
+/// A record that was never sent to the HDB.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NotScreened {
+    /// Index of the record in the input sequences.
+    pub record: u64,
+    pub reason: NotScreenedReason,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NotScreenedReason {
+    /// The record is too short to produce any windows under the hash spec.
+    TooShort,
+}
+
+/// Build the `not_screened` list for the given zero-window `records` under `policy`.
+fn not_screened(
+    records: impl IntoIterator<Item = u64>,
+    policy: ZeroWindowPolicy,
+) -> Vec<NotScreened> {
+    match policy {
+        ZeroWindowPolicy::Omit => vec![],
+        ZeroWindowPolicy::Report => records
+            .into_iter()
+            .map(|record| NotScreened {
+                record,
+                reason: NotScreenedReason::TooShort,
+            })
+            .collect(),
     }
 }
 
+/// Hashes sent to the keyservers for each round of hashing on top of the
+/// windows themselves: the active security checksum.
+pub const CHECKSUM_HASHES: u64 = 1;
+
+/// The number of hashes sent to the keyservers to hash `windows` windows.
+fn keyserver_hash_count(windows: u64) -> Result<u64, DoprfError> {
+    windows
+        .checked_add(CHECKSUM_HASHES)
+        .ok_or(DoprfError::SequencesTooBig)
+}
+
 #[derive(Debug)]
 pub struct DoprfOutput {
-    /// The number of hashes sent to the HDB
+    /// The number of windows hashed and sent to the HDB. This is what's
+    /// screened, and what callers should bill or report on.
     pub n_hashes: u64,
+    /// Hashes sent to the keyservers on top of `n_hashes`: the active security
+    /// checksum for each round of hashing, plus any exemption token hashes.
+    /// The keyservers were sent `n_hashes + overhead_hashes` hashes in total.
+    pub overhead_hashes: u64,
     /// True iff all sequences are shorter than the minimum length demanded by the hash spec.
     pub too_short: bool,
-    /// The consolidation returned from the HDB
-    pub response: HdbScreeningResult,
+    /// The consolidation returned from the HDB, for each configured region
+    pub responses: HashMap<Region, HdbScreeningResult>,
+    /// Records that produced no windows, in input order. Always empty unless
+    /// the config's `zero_window_policy` is [`ZeroWindowPolicy::Report`].
+    pub not_screened: Vec<NotScreened>,
+    /// How long each phase of the screen took.
+    pub timings: DoprfTimings,
+    /// The HDB's error, if it failed and the config's `hdb_failure_policy` is
+    /// [`HdbFailurePolicy::FailOpen`]. Regions it didn't screen have empty
+    /// responses, so this must be checked before treating them as clean.
+    pub hdb_failure: Option<String>,
 }
 
 impl DoprfOutput {
-    fn too_short() -> DoprfOutput {
+    fn too_short(regions: &[Region], not_screened: Vec<NotScreened>) -> DoprfOutput {
         Self {
             n_hashes: 0,
+            overhead_hashes: 0,
             too_short: true,
-            response: HdbScreeningResult::default(),
+            responses: empty_responses(regions),
+            not_screened,
+            timings: DoprfTimings::default(),
+            hdb_failure: None,
         }
     }
+
+    /// The output when the HDB failed with `error` before any hashes were
+    /// sent to it, under [`HdbFailurePolicy::FailOpen`].
+    fn hdb_unavailable(regions: &[Region], error: &DoprfError) -> DoprfOutput {
+        Self {
+            n_hashes: 0,
+            overhead_hashes: 0,
+            too_short: false,
+            responses: empty_responses(regions),
+            not_screened: vec![],
+            timings: DoprfTimings::default(),
+            hdb_failure: Some(error.to_string()),
+        }
+    }
+}
+
+/// How long each phase of a screen took, for performance monitoring. Phases
+/// that run more than once, e.g. for exemption tokens or for each region, are
+/// summed. Time spent opening sessions with servers isn't counted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DoprfTimings {
+    /// Splitting sequences into windows.
+    pub windowing: Duration,
+    /// Waiting for the keyservers to hash the queries.
+    pub keyserver_query: Duration,
+    /// Blinding the queries and proving them, and proving the incorporation
+    /// of the keyservers' responses.
+    pub proof_gen: Duration,
+    /// Incorporating the keyservers' responses into hashes.
+    pub incorporation: Duration,
+    /// Waiting for the HDB to screen the hashes.
+    pub hdb_query: Duration,
+}
+
+impl AddAssign for DoprfTimings {
+    fn add_assign(&mut self, other: Self) {
+        self.windowing += other.windowing;
+        self.keyserver_query += other.keyserver_query;
+        self.proof_gen += other.proof_gen;
+        self.incorporation += other.incorporation;
+        self.hdb_query += other.hdb_query;
+    }
+}
+
+/// An empty screening result for each of `regions`.
+fn empty_responses(regions: &[Region]) -> HashMap<Region, HdbScreeningResult> {
+    regions
+        .iter()
+        .map(|&region| (region, HdbScreeningResult::default()))
+        .collect()
 }
 
 /// An internal struct representing the result of the windowing step of DOPRF.
@@ -98,10 +425,12 @@ struct DoprfWindows {
     /// Total window count.
     count: u64,
     /// Combined windows from the supplied sequences.
-    combined_windows: Vec<(HashTag, String)>,
+    combined_windows: Vec<(HashTag, Window)>,
     /// The indices of records that generated at least one window.
     /// These are used to fix up the indices returned by the HDB.
     non_empty_records: Vec<u64>,
+    /// The indices of records that generated no windows.
+    empty_records: Vec<u64>,
 }
 
 impl DoprfWindows {
@@ -130,24 +459,31 @@ impl DoprfWindows {
             return Err(DoprfError::SequencesTooBig);
         }
 
-        let non_empty_records: Vec<u64> = window_iters
-            .iter()
-            .enumerate()
-            .filter_map(|(record, iter)| (iter.size_hint().0 > 0).then_some(record as u64))
-            .collect();
+        let mut non_empty_records = vec![];
+        let mut empty_records = vec![];
+        for (record, iter) in window_iters.iter().enumerate() {
+            if iter.size_hint().0 > 0 {
+                non_empty_records.push(record as u64);
+            } else {
+                empty_records.push(record as u64);
+            }
+        }
 
-        let combined_windows: Vec<(HashTag, String)> = window_iters.into_iter().flatten().collect();
+        let combined_windows: Vec<(HashTag, Window)> = window_iters.into_iter().flatten().collect();
 
         Ok(DoprfWindows {
             count: n_windows,
             combined_windows,
             non_empty_records,
+            empty_records,
         })
     }
 }
 
 struct DoprfClient<'a, S> {
     config: DoprfConfig<'a, S>,
+    /// Read once, so a screen uses the same version hint throughout.
+    client_config: ClientConfig,
     nucleotide_total_count: u64,
     keyserver_id_set: KeyserverIdSet,
     keyservers: Vec<(SelectedKeyserver, Option<u64>)>,
@@ -156,13 +492,37 @@ struct DoprfClient<'a, S> {
     hdb_client: HdbClient,
 }
 
+/// Why [`DoprfClient::open`] failed, so HDB failures can be told apart for
+/// the config's `hdb_failure_policy`.
+enum OpenError {
+    Hdb(DoprfError),
+    Other(DoprfError),
+}
+
+impl From<DoprfError> for OpenError {
+    fn from(error: DoprfError) -> Self {
+        Self::Other(error)
+    }
+}
+
 impl<'a, S> DoprfClient<'a, S> {
     /// Given a DOPRF config, select keyservers and a hdbserver, and open
-    /// a connection to the HDB.
+    /// a connection to the HDB for `region`.
     async fn open(
         config: DoprfConfig<'a, S>,
         nucleotide_total_count: u64,
-    ) -> Result<Self, DoprfError> {
+        region: Region,
+    ) -> Result<Self, OpenError> {
+        if let Some(max_total_attempts) = config.max_total_attempts {
+            if config.request_ctx.attempts.total() >= max_total_attempts.get() {
+                return Err(DoprfError::deadline_exceeded(
+                    RetryLimit::MaxTotalAttempts(max_total_attempts),
+                    &config.request_ctx.attempts,
+                )
+                .into());
+            }
+        }
+
         // if either of these return an error, then a refresh is required by whoever holds the server selector
         // not our problem! they need to check DoprfError::SelectionRefreshRequired
         let ChosenSelectionSubset {
@@ -170,11 +530,21 @@ impl<'a, S> DoprfClient<'a, S> {
             active_security_key,
             keyservers,
             hdb,
-        } = config.server_selector.clone().choose().await?;
+        } = config
+            .server_selector
+            .clone()
+            .choose()
+            .await
+            .map_err(DoprfError::from)?;
 
         let keyserver_id_set: KeyserverIdSet =
             keyservers.iter().map(|ks| ks.id).collect::<Vec<_>>().into();
 
+        config.request_ctx.attempts.record(AttemptServers {
+            keyservers: keyservers.iter().map(|ks| ks.domain.clone()).collect(),
+            hdb: hdb.domain.clone(),
+        });
+
         info!(
             "{}: selected keyservers=[{}], hdb={}",
             config.request_ctx.id,
@@ -206,16 +576,18 @@ impl<'a, S> DoprfClient<'a, S> {
             .get_server_version(hdb.domain.clone())
             .await?;
 
+        let client_config = config.client_config();
         let hdb_client = HdbClient::open(
             hdb,
-            config.client_config(),
+            client_config.clone(),
             nucleotide_total_count,
             last_hdbserver_version,
             keyserver_id_set.clone(),
-            config.region,
+            region,
             !config.ets.is_empty(),
         )
-        .await?;
+        .await
+        .map_err(OpenError::Hdb)?;
 
         config
             .server_version_handler
@@ -224,6 +596,7 @@ impl<'a, S> DoprfClient<'a, S> {
 
         Ok(Self {
             config,
+            client_config,
             nucleotide_total_count,
             keyserver_id_set,
             keyservers,
@@ -250,7 +623,7 @@ impl<'a, S> DoprfClient<'a, S> {
     async fn connect_to_keyservers(&self) -> Result<KeyserverSetClient, DoprfError> {
         let keyserver_set_client = KeyserverSetClient::open(
             self.keyservers.clone(),
-            self.config.client_config(),
+            self.client_config.clone(),
             self.nucleotide_total_count,
             self.keyserver_id_set.clone(),
         )
@@ -279,7 +652,11 @@ impl<'a, S> DoprfClient<'a, S> {
         )
     }
 
-    async fn hash<R>(&self, windows: &DoprfWindows) -> Result<(PackedRistrettos<R>, VerificationInput), DoprfError>
+    async fn hash<R>(
+        &self,
+        windows: &DoprfWindows,
+        timings: &mut DoprfTimings,
+    ) -> Result<(PackedRistrettos<R>, VerificationInput, ExecutionStats), DoprfError>
     where
         R: From<TaggedHash> + PackableRistretto + 'static,
         <R as PackableRistretto>::Array: Send + 'static,
@@ -288,19 +665,26 @@ impl<'a, S> DoprfClient<'a, S> {
             return Err(DoprfError::SequencesTooBig);
         }
 
-        // add one for active security checksum
-        let hash_total_count = windows
-            .count
-            .checked_add(1)
-            .ok_or(DoprfError::SequencesTooBig)?;
+        let hash_total_count = keyserver_hash_count(windows.count)?;
 
         // added 'inputs' return value for recursive proof
-        let (querystate, inputs) = make_keyserver_querysets(
+        let prover = self.config.effective_prover();
+        let now = get_now();
+        let (mut querystate, inputs, mut execution_stats) = make_keyserver_querysets(
             self.config.request_ctx,
             &windows.combined_windows,
             self.keyserver_threshold as usize,
             &self.active_security_key,
-        );
+            self.config.security_parameter.unwrap_or_default(),
+            &prover,
+            self.config.progress_sink(),
+        )
+        .await?;
+        timings.proof_gen += now.elapsed();
+        if let Some(threshold) = self.config.parallel_threshold {
+            querystate.set_parallel_threshold(threshold);
+        }
+        querystate.set_canonical_response_order(self.config.canonical_response_order);
 
         let ks = self.connect_to_keyservers().await?;
 
@@ -310,13 +694,13 @@ impl<'a, S> DoprfClient<'a, S> {
         let keyserver_responses = ks.query(hash_total_count, &querystate_ristrettos).await?;
         let querying_duration = now.elapsed();
         debug!("Querying key servers done. Took: {:.2?}", querying_duration);
+        timings.keyserver_query += querying_duration;
 
-        const VERIFICATION_ELF: &[u8] = include_bytes!("../../../verification_proof/elf/riscv32im-succinct-zkvm-elf");
+        let now = get_now();
 
-        // Initialize the proving client.
-        let client = ProverClient::new();
-        // Setup the proving and verifying keys.
-        let (verification_pk, verification_vk) = client.setup(VERIFICATION_ELF);
+        // The proving client, and the proving and verifying keys.
+        let client = prover.client();
+        let (_verification_pk, verification_vk) = prover.keys(Program::Verification)?;
 
         let mut stdin = SP1Stdin::new();
 
@@ -329,12 +713,28 @@ impl<'a, S> DoprfClient<'a, S> {
             inputs.iter().map(|input| input.proof.public_values.to_vec()).collect::<Vec<_>>();
         stdin.write::<Vec<Vec<u8>>>(&public_values_write);
 
+        if prover.backend().is_mock() {
+            // Mock proofs can't be aggregated, so stand in for the verification
+            // proof with what the verification program would have committed
+            timings.proof_gen += now.elapsed();
+            let now = get_now();
+            let local_tagged_hash = self.incorporate(querystate, keyserver_responses).await?;
+            timings.incorporation += now.elapsed();
+            let committed = recursive_commitment(&vkeys, &public_values_write, &local_tagged_hash)?;
+            let hashes = to_packed_ristrettos(&local_tagged_hash);
+            let verification_input = VerificationInput::mock(verification_vk.clone(), committed);
+            return Ok((hashes, verification_input, execution_stats));
+        }
+
         // Write the proofs: recursive proof
         //
         // Note: this data will not directly read by the aggregation program, instead it will be
         // witnessed by the prover during the recursive aggregation process inside SP1 itself.
         for input in inputs {
-            let SP1Proof::Compressed(proof) = input.proof.proof else { panic!() };
+            let SP1Proof::Compressed(proof) = input.proof.proof else {
+                let error = ProofError::Verify("only compressed proofs can be aggregated".into());
+                return Err(error.into());
+            };
             stdin.write_proof(*proof, input.vk.vk);
         }
 
@@ -345,136 +745,654 @@ impl<'a, S> DoprfClient<'a, S> {
 
         // DEBUGGING SECTION START
         // Execute the verification_proof program using the `ProverClient.execute` method,
-        let (mut public_values, execution_report) = client.execute(VERIFICATION_ELF, stdin.clone()).run().unwrap();
-        println!(
-            "Verification program executed with {} cycles",
-            execution_report.total_instruction_count() + execution_report.total_syscall_count()
-        );
-
-        // Read the public values
-        let verified_status = public_values.read::<bool>();
-        println!("Verificationation Proof: Recursive proof return value --> {:?}", verified_status);
-        let proof_tagged_hash = public_values.read::<PackedRistrettos<TaggedHash>>();
+        let verification_elf = prover.paths().verification_elf.load()?;
+        let (public_values, execution_report) = client
+            .execute(&verification_elf, stdin.clone())
+            .run()
+            .map_err(|e| ProofError::Execute(e.to_string()))?;
+        execution_stats.verification_cycles = ExecutionStats::cycles(&execution_report);
+
+        // Read the public values. The guest only reports which step failed, so
+        // hold on to any error until incorporating locally has had a chance to
+        // say which keyservers are to blame.
+        let proof_tagged_hash = read_recursive_hashes(&mut PublicValuesReader::new(&public_values));
+        timings.proof_gen += now.elapsed();
 
-        let local_tagged_hash: PackedRistrettos<TaggedHash> = incorporate_responses_and_hash(self.config.request_ctx, querystate, keyserver_responses)
-            .await?;
+        let now = get_now();
+        let local_tagged_hash = self.incorporate(querystate, keyserver_responses).await?;
+        timings.incorporation += now.elapsed();
 
-        if proof_tagged_hash.encoded_items() == local_tagged_hash.encoded_items() {
-            println!("Verificationation Proof: Incorporated responses match.");
-        } else {
-            println!("Verificationation Proof: Incorporated responses do not match.EDIT");
-        }
-        
-        let verification_proof = 
-            SP1ProofWithPublicValues::load("/client/output/verification_proof-with-pis.bin").expect("loading proof failed");
-        let hdb_verification_input = VerificationInput {
-            proof: verification_proof,
-            vk: verification_vk.clone(),
-        };
+        let proof_tagged_hash = proof_tagged_hash?;
+        if proof_tagged_hash.encoded_items() != local_tagged_hash.encoded_items() {
+            return Err(ProofError::CommitmentMismatch.into());
+        }
         // DEBUGGING SECTION END
 
-        // // PROOF GENERATION
-        // // Generate the proof for the given program and input
-        // let (verification_pk, verification_vk) = client.setup(VERIFICATION_ELF);
-        // let mut verification_proof = client.prove(&verification_pk, stdin).run().unwrap();
-        // println!("generated proof");
-
-        // // Verify proof and public values
-        // client.verify(&verification_proof, &verification_vk).expect("verification failed");
+        // Like the hash and checksum proofs, a previously generated proof is
+        // reused, as long as it's of the configured kind; otherwise one is
+        // generated in that mode
+        let hdb_verification_input = prover.load_or_prove(
+            Program::Verification,
+            stdin,
+            prover.proof_mode(),
+            prover.paths().verification_proof_path(),
+        )?;
+        let hashes = to_packed_ristrettos(&local_tagged_hash);
+        Ok((hashes, hdb_verification_input, execution_stats))
+    }
 
-        // // Save the proof to file
-        // verification_proof.save("/client/output/verification_proof-with-pis.bin").expect("saving proof failed");
+    /// Incorporate `keyserver_responses` into `querystate` on this machine,
+    /// capturing any failure if configured to.
+    async fn incorporate(
+        &self,
+        querystate: QueryStateSet,
+        keyserver_responses: Vec<(KeyserverId, PackedRistrettos<HashPart>)>,
+    ) -> Result<PackedRistrettos<TaggedHash>, DoprfError> {
+        if self.config.capture_failures {
+            incorporate_responses_and_hash_capturing(
+                self.config.request_ctx,
+                querystate,
+                &self.keyserver_id_set,
+                keyserver_responses,
+                self.config.progress_sink(),
+            )
+            .await
+        } else {
+            incorporate_responses_and_hash(
+                self.config.request_ctx,
+                querystate,
+                &self.keyserver_id_set,
+                keyserver_responses,
+                self.config.progress_sink(),
+            )
+            .await
+        }
+    }
+}
 
-        // let local_tagged_hash: PackedRistrettos<TaggedHash> = incorporate_responses_and_hash(self.config.request_ctx, querystate, keyserver_responses)
-        //     .await?;
+fn to_packed_ristrettos<R>(hashes: &PackedRistrettos<TaggedHash>) -> PackedRistrettos<R>
+where
+    R: From<TaggedHash> + PackableRistretto,
+{
+    hashes
+        .iter_decoded()
+        .map(|item| R::from(item.unwrap()))
+        .collect()
+}
 
-        // let hdb_verification_input = VerificationInput {
-        //     proof: verification_proof,
-        //     vk: verification_vk.clone(),
-        // };
-        // // PROOF GENERATION SECTION END
-        let packed_ristrettos: PackedRistrettos<R> = local_tagged_hash
-            .iter_decoded()
-            .map(|item| R::from(item.unwrap()))
-            .collect();
+/// Hashes from [`prepare_hashes`], ready to be sent to the HDB with [`submit_to_hdb`].
+pub struct PreparedHashes {
+    pub hashes: PackedRistrettos<TaggedHash>,
+    hdb_verification_input: VerificationInput,
+    /// What running the zkVM programs for `hdb_verification_input` cost,
+    /// reported to the HDB alongside it.
+    execution_stats: ExecutionStats,
+    /// The exemption tokens and their hashes, if there are any.
+    exemptions: Option<(
+        Vec<WithOtps<TokenBundle<ExemptionTokenGroup>>>,
+        PackedRistrettos<CompletedHashValue>,
+    )>,
+    n_hashes: u64,
+    overhead_hashes: u64,
+    /// Records that generated at least one window, to fix up the HDB's records.
+    non_empty_records: Vec<u64>,
+    not_screened: Vec<NotScreened>,
+    /// The config's `report_window_offsets`.
+    report_window_offsets: bool,
+    /// Regions to screen under, starting with the one the HDB connection was
+    /// opened for.
+    regions: Vec<Region>,
+    /// The config's `hdb_failure_policy`, for failures querying the HDB.
+    hdb_failure_policy: HdbFailurePolicy,
+    /// The config's `overall_deadline`, which still applies to querying the HDB.
+    overall_deadline: Option<Instant>,
+    attempts: AttemptLog,
+    /// Timings so far, which querying the HDB adds to.
+    timings: DoprfTimings,
+}
 
-        Ok((packed_ristrettos, hdb_verification_input))
-    }
+pub enum Prepared {
+    /// Nothing needs to be sent to the HDB; this is the final output.
+    Finished(DoprfOutput),
+    /// The hashes, along with the open connection to the HDB they should be sent to.
+    Hashed(PreparedHashes, HdbClient),
 }
 
 /// Takes a slice of sequences, hashes them, sends them to the keyservers,
 /// then sends the results to the hdb, per the DOPRF protocol.
+///
+/// The hashes are computed once and screened under each of the config's regions,
+/// all at once (see [`submit_to_hdb`]).
 pub async fn process<'a, NLike, SliceN>(
     config: DoprfConfig<'a, SliceN>,
 ) -> Result<DoprfOutput, DoprfError>
+where
+    NLike: ToNucleotideLike + Copy + 'a,
+    SliceN: AsRef<[NLike]>,
+{
+    match prepare_hashes(config).await? {
+        Prepared::Finished(output) => Ok(output),
+        Prepared::Hashed(hashes, hdb_client) => submit_to_hdb(hashes, hdb_client).await,
+    }
+}
+
+/// The first half of [`process`]: opens a connection to the HDB, and hashes
+/// the sequences with the keyservers, but leaves querying the HDB to the
+/// caller (via [`submit_to_hdb`]).
+pub async fn prepare_hashes<'a, NLike, SliceN>(
+    config: DoprfConfig<'a, SliceN>,
+) -> Result<Prepared, DoprfError>
+where
+    NLike: ToNucleotideLike + Copy + 'a,
+    SliceN: AsRef<[NLike]>,
+{
+    let overall_deadline = config.overall_deadline;
+    let attempts = config.request_ctx.attempts.clone();
+    with_deadline(overall_deadline, &attempts, prepare_hashes_inner(config)).await
+}
+
+async fn prepare_hashes_inner<'a, NLike, SliceN>(
+    config: DoprfConfig<'a, SliceN>,
+) -> Result<Prepared, DoprfError>
 where
     NLike: ToNucleotideLike + Copy + 'a,
     SliceN: AsRef<[NLike]>,
 {
     let nucleotide_total_count = config.nucleotide_total_count()?;
+    let zero_window_policy = config.zero_window_policy;
+    let hdb_failure_policy = config.hdb_failure_policy;
+    let all_records = 0..config.sequences.len() as u64;
+    let regions = config.unique_regions()?;
 
     if nucleotide_total_count == 0 {
         info!("{}: all sequences were empty", config.request_ctx.id);
-        return Ok(DoprfOutput::too_short());
+        return Ok(Prepared::Finished(DoprfOutput::too_short(
+            &regions,
+            not_screened(all_records, zero_window_policy),
+        )));
     }
 
-    let client = DoprfClient::open(config, nucleotide_total_count).await?;
+    let client = match DoprfClient::open(config, nucleotide_total_count, regions[0]).await {
+        Ok(client) => client,
+        Err(OpenError::Hdb(error)) if hdb_failure_policy == HdbFailurePolicy::FailOpen => {
+            warn!("couldn't open a connection to the HDB, failing open: {error}");
+            return Ok(Prepared::Finished(DoprfOutput::hdb_unavailable(
+                &regions, &error,
+            )));
+        }
+        Err(OpenError::Hdb(error) | OpenError::Other(error)) => return Err(error),
+    };
 
     if client.sequences_too_short_for_hash_spec() {
-        return Ok(DoprfOutput::too_short());
+        return Ok(Prepared::Finished(DoprfOutput::too_short(
+            &regions,
+            not_screened(all_records, zero_window_policy),
+        )));
     }
 
+    let mut timings = DoprfTimings::default();
+    let now = get_now();
     let windows = client.window(client.config.sequences.iter())?;
+    timings.windowing += now.elapsed();
+    let not_screened = not_screened(windows.empty_records.iter().copied(), zero_window_policy);
 
     if windows.count == 0 {
         info!("{}: didn't generate any windows", client.id());
-        return Ok(DoprfOutput {
+        return Ok(Prepared::Finished(DoprfOutput {
             n_hashes: 0,
+            overhead_hashes: 0,
             too_short: false,
-            response: HdbScreeningResult::default(),
-        });
+            responses: empty_responses(&regions),
+            not_screened,
+            timings,
+            hdb_failure: None,
+        }));
     }
 
     info!("{}: generated {} windows", client.id(), windows.count);
-    let (hashes, hdb_verification_input) = client.hash(&windows).await?;
+    let (hashes, hdb_verification_input, execution_stats) =
+        client.hash::<TaggedHash>(&windows, &mut timings).await?;
 
-    let mut response = match &client.config.ets {
-        ets if !ets.is_empty() => {
-            let et_windows = client.window(ets.iter().flat_map(|w| w.et.token.dna_sequences()))?;
-            let (et_hashes, _) = client.hash(&et_windows).await?;
-            let now = get_now();
-            let response = client
-                .hdb_client
-                .query_with_ets(&hashes, ets, et_hashes)
-                .await?;
-            let hdb_duration = now.elapsed();
-            debug!("Querying HDB done. Took: {:.2?}", hdb_duration);
-            response
-        }
-        _ => {
-            let now = get_now();
-            let response = client.hdb_client.query(&hashes, hdb_verification_input).await?;
-            let hdb_duration = now.elapsed();
-            debug!("Querying HDB done. Took: {:.2?}", hdb_duration);
-            response
+    let mut overhead_hashes = CHECKSUM_HASHES;
+    let exemptions = if client.config.ets.is_empty() {
+        None
+    } else {
+        let now = get_now();
+        let et_windows = client.window(
+            client
+                .config
+                .ets
+                .iter()
+                .flat_map(|w| w.et.token.dna_sequences()),
+        )?;
+        timings.windowing += now.elapsed();
+        let (et_hashes, _, _) = client
+            .hash::<CompletedHashValue>(&et_windows, &mut timings)
+            .await?;
+        overhead_hashes = overhead_hashes
+            .checked_add(keyserver_hash_count(et_windows.count)?)
+            .ok_or(DoprfError::SequencesTooBig)?;
+        Some(et_hashes)
+    };
+
+    let DoprfClient {
+        config, hdb_client, ..
+    } = client;
+    Ok(Prepared::Hashed(
+        PreparedHashes {
+            hashes,
+            hdb_verification_input,
+            execution_stats,
+            exemptions: exemptions.map(|et_hashes| (config.ets, et_hashes)),
+            n_hashes: windows.count,
+            overhead_hashes,
+            non_empty_records: windows.non_empty_records,
+            not_screened,
+            report_window_offsets: config.report_window_offsets,
+            regions,
+            hdb_failure_policy,
+            overall_deadline: config.overall_deadline,
+            attempts: config.request_ctx.attempts.clone(),
+            timings,
+        },
+        hdb_client,
+    ))
+}
+
+/// The second half of [`process`]: sends hashes from [`prepare_hashes`] to
+/// the HDB, and returns its response for each region.
+///
+/// Each region after the first gets its own HDB session, with cookies of its
+/// own, and all regions are queried at once. Where the API client can't keep
+/// cookies apart (in a browser), the sessions share one, so each is opened
+/// once the previous region is done.
+pub async fn submit_to_hdb(
+    prepared: PreparedHashes,
+    hdb_client: HdbClient,
+) -> Result<DoprfOutput, DoprfError> {
+    let overall_deadline = prepared.overall_deadline;
+    let attempts = prepared.attempts.clone();
+    with_deadline(
+        overall_deadline,
+        &attempts,
+        submit_to_hdb_inner(prepared, hdb_client),
+    )
+    .await
+}
+
+/// Like [`submit_to_hdb`], but exempting hazards covered by `ets`, whose
+/// sequences the caller has already hashed into `et_hashes` (e.g. with
+/// [`hash_only`]). These replace any ETs `hashes` were prepared with.
+///
+/// This mirrors [`HdbClient::query_with_ets`], for callers that control the ET
+/// flow themselves rather than leaving it to [`process`]. If `hdb_client`'s
+/// session wasn't opened for ETs, because `hashes` were prepared without any,
+/// a new session is opened first.
+///
+/// The ET hashes aren't counted in [`DoprfOutput::overhead_hashes`], since the
+/// caller hashed them separately.
+pub async fn submit_to_hdb_with_ets(
+    mut hashes: PreparedHashes,
+    ets: Vec<WithOtps<TokenBundle<ExemptionTokenGroup>>>,
+    et_hashes: PackedRistrettos<CompletedHashValue>,
+    hdb_client: HdbClient,
+) -> Result<DoprfOutput, DoprfError> {
+    hashes.exemptions = Some((ets, et_hashes));
+    let overall_deadline = hashes.overall_deadline;
+    let attempts = hashes.attempts.clone();
+    with_deadline(overall_deadline, &attempts, async move {
+        let hdb_client = if hdb_client.with_exemption() {
+            hdb_client
+        } else {
+            match hdb_client
+                .reopen_with_exemption(hdb_client.region(), true)
+                .await
+            {
+                Ok(hdb_client) => hdb_client,
+                Err(error) => return hashes.fail(HashMap::new(), error),
+            }
+        };
+        submit_to_hdb_inner(hashes, hdb_client).await
+    })
+    .await
+}
+
+async fn submit_to_hdb_inner(
+    prepared: PreparedHashes,
+    hdb_client: HdbClient,
+) -> Result<DoprfOutput, DoprfError> {
+    let mut responses = HashMap::with_capacity(prepared.regions.len());
+    let mut queries = RegionQueries::new(prepared, hdb_client);
+    loop {
+        match queries.next().await {
+            Ok(Some((region, response))) => {
+                responses.insert(region, response);
+            }
+            Ok(None) => return Ok(queries.finish(responses)),
+            Err(error) => return queries.fail(responses, error),
+        }
+    }
+}
+
+impl PreparedHashes {
+    /// The output for these hashes, with the given `responses`.
+    fn finish(self, responses: HashMap<Region, HdbScreeningResult>) -> DoprfOutput {
+        DoprfOutput {
+            n_hashes: self.n_hashes,
+            overhead_hashes: self.overhead_hashes,
+            too_short: false,
+            responses,
+            not_screened: self.not_screened,
+            timings: self.timings,
+            hdb_failure: None,
+        }
+    }
+
+    /// Handle the HDB failing with `error` after responding with `responses`,
+    /// per the config's `hdb_failure_policy`. Failing open gives every region
+    /// without a response an empty one.
+    fn fail(
+        self,
+        mut responses: HashMap<Region, HdbScreeningResult>,
+        error: DoprfError,
+    ) -> Result<DoprfOutput, DoprfError> {
+        match self.hdb_failure_policy {
+            HdbFailurePolicy::FailClosed => Err(error),
+            HdbFailurePolicy::FailOpen => {
+                warn!("querying the HDB failed, failing open: {error}");
+                for &region in &self.regions {
+                    responses.entry(region).or_default();
+                }
+                Ok(DoprfOutput {
+                    hdb_failure: Some(error.to_string()),
+                    ..self.finish(responses)
+                })
+            }
+        }
+    }
+}
+
+/// Queries the HDB with prepared hashes under each region, as in
+/// [`submit_to_hdb`], handing back each region's response.
+pub(crate) struct RegionQueries {
+    prepared: PreparedHashes,
+    hdb_client: HdbClient,
+    other_regions: std::vec::IntoIter<Region>,
+    /// Responses to the regions queried at once, not yet handed back.
+    received: VecDeque<Result<(Region, HdbScreeningResult), DoprfError>>,
+    queried_first_region: bool,
+}
+
+impl RegionQueries {
+    pub(crate) fn new(prepared: PreparedHashes, hdb_client: HdbClient) -> Self {
+        let other_regions = prepared.regions[1..].to_vec().into_iter();
+        Self {
+            prepared,
+            hdb_client,
+            other_regions,
+            received: VecDeque::new(),
+            queried_first_region: false,
+        }
+    }
+
+    /// The next region's response, or `None` once every region is done.
+    ///
+    /// The first call queries every region at once, if the API client can keep
+    /// a session's cookies apart for each of them; the responses that arrive
+    /// are handed back before any error. Otherwise, each call queries one
+    /// region.
+    pub(crate) async fn next(
+        &mut self,
+    ) -> Result<Option<(Region, HdbScreeningResult)>, DoprfError> {
+        if let Some(received) = self.received.pop_front() {
+            return received.map(Some);
+        }
+        if self.queried_first_region {
+            let Some(region) = self.other_regions.next() else {
+                return Ok(None);
+            };
+            let api_client = self.hdb_client.api_client().clone();
+            self.hdb_client = self.hdb_client.open_for_region(api_client, region).await?;
+        } else if self.other_regions.len() > 0 {
+            if let Some(api_clients) = self.own_cookie_clients() {
+                self.queried_first_region = true;
+                self.query_all_at_once(api_clients).await;
+                return self.received.pop_front().transpose();
+            }
+        }
+        self.queried_first_region = true;
+
+        let now = get_now();
+        let response = query_region(&self.prepared, &self.hdb_client).await?;
+        self.prepared.timings.hdb_query += now.elapsed();
+        Ok(Some(response))
+    }
+
+    /// An API client with its own cookies for each region after the first, if
+    /// the API client can make them.
+    fn own_cookie_clients(&self) -> Option<Vec<BaseApiClient>> {
+        self.other_regions
+            .as_slice()
+            .iter()
+            .map(|_| self.hdb_client.api_client().with_own_cookies())
+            .collect()
+    }
+
+    /// Open a session for each region after the first, through `api_clients`,
+    /// then query every region at once, queueing the responses in `received`.
+    /// As when querying one region at a time, only the first error is kept.
+    async fn query_all_at_once(&mut self, api_clients: Vec<BaseApiClient>) {
+        let now = get_now();
+        let hdb_client = &self.hdb_client;
+        let opened = join_all(
+            self.other_regions
+                .by_ref()
+                .zip(api_clients)
+                .map(|(region, api_client)| hdb_client.open_for_region(api_client, region)),
+        )
+        .await;
+        let mut sessions = vec![];
+        let mut errors = vec![];
+        for session in opened {
+            match session {
+                Ok(session) => sessions.push(session),
+                Err(error) => errors.push(error),
+            }
+        }
+
+        let prepared = &self.prepared;
+        let responses = join_all(
+            std::iter::once(hdb_client)
+                .chain(&sessions)
+                .map(|session| query_region(prepared, session)),
+        )
+        .await;
+        for response in responses {
+            match response {
+                Ok(response) => self.received.push_back(Ok(response)),
+                Err(error) => errors.push(error),
+            }
+        }
+        self.received.extend(errors.into_iter().next().map(Err));
+        self.prepared.timings.hdb_query += now.elapsed();
+    }
+
+    /// The output for these hashes, with the given `responses`.
+    pub(crate) fn finish(self, responses: HashMap<Region, HdbScreeningResult>) -> DoprfOutput {
+        self.prepared.finish(responses)
+    }
+
+    /// Handle [`Self::next`] failing with `error`, per the config's
+    /// `hdb_failure_policy`, given the `responses` received so far.
+    pub(crate) fn fail(
+        self,
+        responses: HashMap<Region, HdbScreeningResult>,
+        error: DoprfError,
+    ) -> Result<DoprfOutput, DoprfError> {
+        self.prepared.fail(responses, error)
+    }
+}
+
+/// Query the HDB with `prepared` hashes through `hdb_client`'s session.
+async fn query_region(
+    prepared: &PreparedHashes,
+    hdb_client: &HdbClient,
+) -> Result<(Region, HdbScreeningResult), DoprfError> {
+    let PreparedHashes {
+        hashes,
+        hdb_verification_input,
+        execution_stats,
+        exemptions,
+        non_empty_records,
+        report_window_offsets,
+        ..
+    } = prepared;
+
+    let now = get_now();
+    let mut response = match exemptions {
+        Some((ets, et_hashes)) => {
+            hdb_client
+                .query_with_ets(hashes, ets, et_hashes.clone())
+                .await?
+        }
+        None => {
+            hdb_client
+                .query(hashes, hdb_verification_input.clone(), *execution_stats)
+                .await?
         }
     };
+    debug!(
+        "Querying HDB for {:?} done. Took: {:.2?}",
+        hdb_client.region(),
+        now.elapsed()
+    );
 
     // The HDB sets `record` based on how many new-record flags it has encountered, but
     // sufficiently small FASTA records won't produce windows, so the `record`s returned
     // by the HDB need to be fixed up to account for records without windows.
     for hazard in &mut response.results {
-        hazard.record = *usize::try_from(hazard.record)
-            .ok()
-            .and_then(|hdb_record| windows.non_empty_records.get(hdb_record))
+        let hdb_record = usize::try_from(hazard.record).map_err(|_| DoprfError::InvalidRecord)?;
+        if !report_window_offsets {
+            hazard.window_offsets.clear();
+        }
+        hazard.record = *non_empty_records
+            .get(hdb_record)
             .ok_or(DoprfError::InvalidRecord)?;
     }
+    Ok((hdb_client.region(), response))
+}
 
-    Ok(DoprfOutput {
-        n_hashes: windows.count,
-        too_short: false,
-        response,
-    })
+/// Hashes the given sequences with the keyservers, per the DOPRF protocol, and
+/// returns the tagged hashes without ever connecting to an HDB.
+///
+/// This is for callers that run their own HDB. The hash spec comes from the
+/// keyservers rather than the HDB.
+pub async fn hash_only<'a, NLike, SliceN>(
+    config: DoprfConfig<'a, SliceN>,
+) -> Result<PackedRistrettos<TaggedHash>, DoprfError>
+where
+    NLike: ToNucleotideLike + Copy + 'a,
+    SliceN: AsRef<[NLike]>,
+{
+    let nucleotide_total_count = config.nucleotide_total_count()?;
+
+    let ChosenSelectionSubset {
+        keyserver_threshold,
+        active_security_key,
+        keyservers,
+        hdb: _,
+    } = config.server_selector.clone().choose().await?;
+
+    let keyserver_id_set: KeyserverIdSet =
+        keyservers.iter().map(|ks| ks.id).collect::<Vec<_>>().into();
+
+    info!(
+        "{}: hashing only, with keyservers=[{}]",
+        config.request_ctx.id,
+        keyservers
+            .iter()
+            .map(|ks| ks.to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+
+    let mut keyservers_with_versions = Vec::with_capacity(keyservers.len());
+    for keyserver in keyservers {
+        let last_server_version = config
+            .server_version_handler
+            .get_server_version(keyserver.domain.clone())
+            .await?;
+        keyservers_with_versions.push((keyserver, last_server_version));
+    }
+
+    let ks = KeyserverSetClient::open(
+        keyservers_with_versions,
+        ClientConfig {
+            keyserver_apply: None,
+            ..config.client_config()
+        },
+        nucleotide_total_count,
+        keyserver_id_set.clone(),
+    )
+    .await?;
+
+    for client in ks.clients() {
+        config
+            .server_version_handler
+            .set_server_version(client.domain().to_string(), client.server_version())
+            .await;
+    }
+
+    let hash_spec = ks
+        .clients()
+        .next()
+        .expect("server selection always chooses at least one keyserver")
+        .hash_spec()
+        .clone();
+    let windows = DoprfWindows::create(config.sequences.iter(), &hash_spec, config.max_windows)?;
+    if windows.combined_windows.is_empty() {
+        info!("{}: didn't generate any windows", config.request_ctx.id);
+        return Ok(PackedRistrettos::new(vec![]));
+    }
+
+    let hash_total_count = keyserver_hash_count(windows.count)?;
+
+    let (mut querystate, _, _) = make_keyserver_querysets(
+        config.request_ctx,
+        &windows.combined_windows,
+        keyserver_threshold as usize,
+        &active_security_key,
+        config.security_parameter.unwrap_or_default(),
+        &config.effective_prover(),
+        config.progress_sink(),
+    )
+    .await?;
+    if let Some(threshold) = config.parallel_threshold {
+        querystate.set_parallel_threshold(threshold);
+    }
+    querystate.set_canonical_response_order(config.canonical_response_order);
+    let querystate_ristrettos = PackedRistrettos::<Query>::from(&querystate);
+    let keyserver_responses = ks.query(hash_total_count, &querystate_ristrettos).await?;
+
+    if config.capture_failures {
+        incorporate_responses_and_hash_capturing(
+            config.request_ctx,
+            querystate,
+            &keyserver_id_set,
+            keyserver_responses,
+            config.progress_sink(),
+        )
+        .await
+    } else {
+        incorporate_responses_and_hash(
+            config.request_ctx,
+            querystate,
+            &keyserver_id_set,
+            keyserver_responses,
+            config.progress_sink(),
+        )
+        .await
+    }
 }
 
 #[cfg(test)]
@@ -493,6 +1411,7 @@ mod tests {
         ServerEnumerationSource, ServerSelectionConfig, ServerSelectionError,
     };
     use http_client::test_utils::ApiClientCoreMock;
+    use shared_types::hash::HashTypeDescriptor;
     use shared_types::requests::RequestId;
 
     #[tokio::test]
@@ -532,7 +1451,10 @@ mod tests {
                 soft_timeout: None,
                 blocking_timeout: None,
                 soft_extra_keyserver_threshold: None,
+                min_keyservers: None,
                 soft_extra_hdb_threshold: None,
+                bad_server_penalty: None,
+                refresh_failure_backoff: None,
             },
             mock_api_client.clone(),
             selection,
@@ -550,13 +1472,27 @@ mod tests {
             server_selector: selector.clone(),
             request_ctx: &request_ctx,
             certs: certs.clone(),
-            region: Region::All,
+            regions: vec![Region::All],
             debug_info: false,
             sequences: &[dna.as_slice()],
             max_windows: u64::MAX,
-            version_hint: "test".to_owned(),
+            version_hint: "test".into(),
             ets: vec![],
             server_version_handler: &Default::default(),
+            zero_window_policy: ZeroWindowPolicy::Omit,
+            hdb_failure_policy: HdbFailurePolicy::FailClosed,
+            capture_failures: false,
+            report_window_offsets: false,
+            keyserver_rate_limiter: None,
+            max_keyserver_response_bytes: None,
+            max_total_attempts: None,
+            overall_deadline: None,
+            parallel_threshold: None,
+            canonical_response_order: false,
+            security_parameter: None,
+            prover: Default::default(),
+            keyserver_apply: None,
+            progress_sink: None,
         })
         .await
         .unwrap_err();
@@ -573,4 +1509,536 @@ mod tests {
             ServerSelectionError::NoQuorum(_),
         ));
     }
+
+    /// Screen a short sequence with `api_client` standing in for every server.
+    async fn screen_with_limits(
+        api_client: &BaseApiClient,
+        request_ctx: &RequestContext,
+        max_total_attempts: Option<NonZeroUsize>,
+        overall_deadline: Option<Instant>,
+        hdb_failure_policy: HdbFailurePolicy,
+    ) -> Result<DoprfOutput, DoprfError> {
+        let selector = Arc::new(make_test_selector(
+            ServerSelectionConfig {
+                enumeration_source: ServerEnumerationSource::Fixed {
+                    keyserver_domains: vec![],
+                    hdb_domains: vec![],
+                },
+                soft_timeout: None,
+                blocking_timeout: None,
+                soft_extra_keyserver_threshold: None,
+                min_keyservers: None,
+                soft_extra_hdb_threshold: None,
+                bad_server_penalty: None,
+                refresh_failure_backoff: None,
+            },
+            api_client.clone(),
+            make_test_selection(
+                2,
+                &[("seattle.keyserver", 1), ("sf.keyserver", 2)],
+                &["hdb"],
+            ),
+            get_now(),
+        ));
+        let dna = DnaSequence::<Nucleotide>::parse(0, "atcgatcgatcgatcgatcg").unwrap();
+        process(DoprfConfig {
+            api_client,
+            server_selector: selector,
+            request_ctx,
+            certs: Arc::new(ClientCerts::load_test_certs()),
+            regions: vec![Region::All],
+            debug_info: false,
+            sequences: &[dna.as_slice()],
+            max_windows: u64::MAX,
+            version_hint: "test".into(),
+            ets: vec![],
+            server_version_handler: &Default::default(),
+            zero_window_policy: ZeroWindowPolicy::Omit,
+            hdb_failure_policy,
+            capture_failures: false,
+            report_window_offsets: false,
+            keyserver_rate_limiter: None,
+            max_keyserver_response_bytes: None,
+            max_total_attempts,
+            overall_deadline,
+            parallel_threshold: None,
+            canonical_response_order: false,
+            security_parameter: None,
+            prover: Default::default(),
+            keyserver_apply: None,
+            progress_sink: None,
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn each_screen_attempt_records_its_servers() {
+        // Every request is rejected, so the HDB that's reached first is marked
+        // bad, and the next screen swaps it for the other one.
+        let api_client = BaseApiClient::from(ApiClientCoreMock::from(
+            |url: String, _body, _content_type, _headers, _expected_content_type| {
+                async move {
+                    Err(http_client::error::HttpError::RequestError {
+                        ctx: url,
+                        status: Some(418),
+                        retriable: false,
+                        source: "i'm a teapot".into(),
+                    })
+                }
+                .boxed()
+            },
+        ));
+        let selector = Arc::new(make_test_selector(
+            ServerSelectionConfig {
+                enumeration_source: ServerEnumerationSource::Fixed {
+                    keyserver_domains: vec![],
+                    hdb_domains: vec![],
+                },
+                soft_timeout: None,
+                blocking_timeout: None,
+                soft_extra_keyserver_threshold: None,
+                min_keyservers: None,
+                soft_extra_hdb_threshold: None,
+                bad_server_penalty: None,
+                refresh_failure_backoff: None,
+            },
+            api_client.clone(),
+            make_test_selection(
+                2,
+                &[("seattle.keyserver", 1), ("sf.keyserver", 2)],
+                &["hdb1", "hdb2"],
+            ),
+            get_now(),
+        ));
+        let request_ctx = RequestContext::single(RequestId::new_unique());
+        let certs = Arc::new(ClientCerts::load_test_certs());
+        let dna = DnaSequence::<Nucleotide>::parse(0, "atcgatcgatcgatcgatcg").unwrap();
+        for attempt in 1..=2 {
+            process(DoprfConfig {
+                api_client: &api_client,
+                server_selector: selector.clone(),
+                request_ctx: &request_ctx,
+                certs: certs.clone(),
+                regions: vec![Region::All],
+                debug_info: false,
+                sequences: &[dna.as_slice()],
+                max_windows: u64::MAX,
+                version_hint: "test".into(),
+                ets: vec![],
+                server_version_handler: &Default::default(),
+                zero_window_policy: ZeroWindowPolicy::Omit,
+                hdb_failure_policy: HdbFailurePolicy::FailClosed,
+                capture_failures: false,
+                report_window_offsets: false,
+                keyserver_rate_limiter: None,
+                max_keyserver_response_bytes: None,
+                max_total_attempts: None,
+                overall_deadline: None,
+                parallel_threshold: None,
+                canonical_response_order: false,
+                security_parameter: None,
+                prover: Default::default(),
+                keyserver_apply: None,
+                progress_sink: None,
+            })
+            .await
+            .unwrap_err();
+            assert_eq!(request_ctx.attempts.count(), attempt);
+        }
+
+        let attempts = request_ctx.attempts.attempts();
+        for servers in &attempts {
+            let mut keyservers = servers.keyservers.clone();
+            keyservers.sort();
+            assert_eq!(keyservers, ["seattle.keyserver", "sf.keyserver"]);
+        }
+        assert_ne!(attempts[0].hdb, attempts[1].hdb);
+        assert_eq!(
+            request_ctx.attempts.excluded(),
+            vec![attempts[0].hdb.clone()]
+        );
+    }
+
+    #[tokio::test]
+    async fn persistently_failing_screens_give_up_at_their_limits() {
+        // set up every request to fail after a short delay
+        let failing_api_client = |retriable: bool| {
+            BaseApiClient::from(ApiClientCoreMock::from(
+                move |url: String, _body, _content_type, _headers, _expected_content_type| {
+                    async move {
+                        tokio::time::sleep(Duration::from_millis(50)).await;
+                        Err(http_client::error::HttpError::RequestError {
+                            ctx: url,
+                            status: Some(503),
+                            retriable,
+                            source: "unavailable".into(),
+                        })
+                    }
+                    .boxed()
+                },
+            ))
+        };
+
+        // Retrying the whole screen stops once the maximum number of attempts
+        // have been made.
+        let api_client = failing_api_client(false);
+        let request_ctx = RequestContext::single(RequestId::new_unique());
+        let max_total_attempts = NonZeroUsize::new(3);
+        let mut calls = 0;
+        let err = loop {
+            calls += 1;
+            assert!(calls <= 10, "never gave up");
+            let result = screen_with_limits(
+                &api_client,
+                &request_ctx,
+                max_total_attempts,
+                None,
+                HdbFailurePolicy::FailClosed,
+            )
+            .await;
+            match result {
+                Err(err @ DoprfError::DeadlineExceeded { .. }) => break err,
+                result => assert!(result.is_err()),
+            }
+        };
+        assert!(!err.is_retriable());
+        assert_eq!(calls, 4);
+        assert!(matches!(
+            err,
+            DoprfError::DeadlineExceeded {
+                limit: RetryLimit::MaxTotalAttempts(max),
+                ref attempts,
+            } if Some(max) == max_total_attempts
+                && attempts.len() == 3
+                && *attempts == request_ctx.attempts.attempts()
+        ));
+
+        // Retries within a screen are abandoned at the deadline, rather than
+        // running through the whole retry schedule (which takes several seconds).
+        let api_client = failing_api_client(true);
+        let request_ctx = RequestContext::single(RequestId::new_unique());
+        let start = get_now();
+        let deadline = start + Duration::from_millis(300);
+        let err = screen_with_limits(
+            &api_client,
+            &request_ctx,
+            None,
+            Some(deadline),
+            HdbFailurePolicy::FailClosed,
+        )
+        .await
+        .unwrap_err();
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert!(matches!(
+            err,
+            DoprfError::DeadlineExceeded {
+                limit: RetryLimit::OverallDeadline,
+                ref attempts,
+            } if attempts.len() == 1
+        ));
+
+        // Once the deadline has passed, no more attempts are started.
+        let err = screen_with_limits(
+            &api_client,
+            &request_ctx,
+            None,
+            Some(deadline),
+            HdbFailurePolicy::FailClosed,
+        )
+        .await
+        .unwrap_err();
+        assert!(matches!(err, DoprfError::DeadlineExceeded { .. }));
+        assert_eq!(request_ctx.attempts.count(), 1);
+    }
+
+    #[tokio::test]
+    async fn hdb_failures_follow_the_policy() {
+        // set up every request to fail, so opening the HDB session (which
+        // comes before contacting any keyserver) fails
+        let api_client = BaseApiClient::from(ApiClientCoreMock::from(
+            |url: String, _body, _content_type, _headers, _expected_content_type| {
+                async move {
+                    Err(http_client::error::HttpError::RequestError {
+                        ctx: url,
+                        status: Some(500),
+                        retriable: false,
+                        source: "internal server error".into(),
+                    })
+                }
+                .boxed()
+            },
+        ));
+
+        let request_ctx = RequestContext::single(RequestId::new_unique());
+        screen_with_limits(
+            &api_client,
+            &request_ctx,
+            None,
+            None,
+            HdbFailurePolicy::FailClosed,
+        )
+        .await
+        .unwrap_err();
+
+        // Failing open screens nothing, and says why.
+        let request_ctx = RequestContext::single(RequestId::new_unique());
+        let output = screen_with_limits(
+            &api_client,
+            &request_ctx,
+            None,
+            None,
+            HdbFailurePolicy::FailOpen,
+        )
+        .await
+        .unwrap();
+        assert!(output.hdb_failure.is_some());
+        assert_eq!(output.n_hashes, 0);
+        assert!(!output.too_short);
+        assert_eq!(output.responses.len(), 1);
+        assert!(output.responses[&Region::All].results.is_empty());
+    }
+
+    #[tokio::test]
+    async fn hash_only_never_contacts_hdb() {
+        let requested_urls = Arc::new(std::sync::Mutex::new(vec![]));
+        let mock_api_client = BaseApiClient::from(ApiClientCoreMock::from({
+            let requested_urls = requested_urls.clone();
+            move |url: String, _body, _content_type, _headers, _expected_content_type| {
+                requested_urls.lock().unwrap().push(url.clone());
+                async {
+                    Err(http_client::error::HttpError::RequestError {
+                        ctx: url,
+                        status: Some(400),
+                        retriable: false,
+                        source: "not a real keyserver".into(),
+                    })
+                }
+                .boxed()
+            }
+        }));
+
+        let selection = make_test_selection(
+            2,
+            &[("seattle.keyserver", 1), ("sf.keyserver", 2)],
+            &["hdb"],
+        );
+        let selector = Arc::new(make_test_selector(
+            ServerSelectionConfig {
+                enumeration_source: ServerEnumerationSource::Fixed {
+                    keyserver_domains: vec![],
+                    hdb_domains: vec![],
+                },
+                soft_timeout: None,
+                blocking_timeout: None,
+                soft_extra_keyserver_threshold: None,
+                min_keyservers: None,
+                soft_extra_hdb_threshold: None,
+                bad_server_penalty: None,
+                refresh_failure_backoff: None,
+            },
+            mock_api_client.clone(),
+            selection,
+            get_now(),
+        ));
+
+        let request_ctx = RequestContext::single(RequestId::new_unique());
+        let dna = DnaSequence::<Nucleotide>::parse(0, "atcgatcgatcgatcgatcg").unwrap();
+
+        // The mock keyservers reject the session, but only after being contacted.
+        hash_only(DoprfConfig {
+            api_client: &mock_api_client,
+            server_selector: selector,
+            request_ctx: &request_ctx,
+            certs: Arc::new(ClientCerts::load_test_certs()),
+            regions: vec![Region::All],
+            debug_info: false,
+            sequences: &[dna.as_slice()],
+            max_windows: u64::MAX,
+            version_hint: "test".into(),
+            ets: vec![],
+            server_version_handler: &Default::default(),
+            zero_window_policy: ZeroWindowPolicy::Omit,
+            hdb_failure_policy: HdbFailurePolicy::FailClosed,
+            capture_failures: false,
+            report_window_offsets: false,
+            keyserver_rate_limiter: None,
+            max_keyserver_response_bytes: None,
+            max_total_attempts: None,
+            overall_deadline: None,
+            parallel_threshold: None,
+            canonical_response_order: false,
+            security_parameter: None,
+            prover: Default::default(),
+            keyserver_apply: None,
+            progress_sink: None,
+        })
+        .await
+        .unwrap_err();
+
+        let requested_urls = requested_urls.lock().unwrap();
+        assert!(requested_urls.iter().any(|url| url.contains("keyserver")));
+        assert!(requested_urls.iter().all(|url| !url.contains("//hdb")));
+    }
+
+    #[tokio::test]
+    async fn version_hint_updates_apply_to_the_next_screen() {
+        let version_hint = VersionHint::new("v1");
+        let open_bodies = Arc::new(std::sync::Mutex::new(vec![]));
+        let mock_api_client = BaseApiClient::from(ApiClientCoreMock::from({
+            let version_hint = version_hint.clone();
+            let open_bodies = open_bodies.clone();
+            move |url: String,
+                  body: Option<bytes::Bytes>,
+                  _content_type,
+                  _headers,
+                  _expected_content_type| {
+                let body = String::from_utf8_lossy(&body.unwrap_or_default()).into_owned();
+                open_bodies.lock().unwrap().push(body);
+                // Rotate the hint while this screen is in flight.
+                version_hint.set("v2");
+                async {
+                    Err(http_client::error::HttpError::RequestError {
+                        ctx: url,
+                        status: Some(400),
+                        retriable: false,
+                        source: "not a real keyserver".into(),
+                    })
+                }
+                .boxed()
+            }
+        }));
+
+        let request_ctx = RequestContext::single(RequestId::new_unique());
+        let dna = DnaSequence::<Nucleotide>::parse(0, "atcgatcgatcgatcgatcg").unwrap();
+        let screen = || {
+            let selector = Arc::new(make_test_selector(
+                ServerSelectionConfig {
+                    enumeration_source: ServerEnumerationSource::Fixed {
+                        keyserver_domains: vec![],
+                        hdb_domains: vec![],
+                    },
+                    soft_timeout: None,
+                    blocking_timeout: None,
+                    soft_extra_keyserver_threshold: None,
+                    min_keyservers: None,
+                    soft_extra_hdb_threshold: None,
+                    bad_server_penalty: None,
+                    refresh_failure_backoff: None,
+                },
+                mock_api_client.clone(),
+                make_test_selection(
+                    2,
+                    &[("seattle.keyserver", 1), ("sf.keyserver", 2)],
+                    &["hdb"],
+                ),
+                get_now(),
+            ));
+            hash_only(DoprfConfig {
+                api_client: &mock_api_client,
+                server_selector: selector,
+                request_ctx: &request_ctx,
+                certs: Arc::new(ClientCerts::load_test_certs()),
+                regions: vec![Region::All],
+                debug_info: false,
+                sequences: &[dna.as_slice()],
+                max_windows: u64::MAX,
+                version_hint: version_hint.clone(),
+                ets: vec![],
+                server_version_handler: &Default::default(),
+                zero_window_policy: ZeroWindowPolicy::Omit,
+                hdb_failure_policy: HdbFailurePolicy::FailClosed,
+                capture_failures: false,
+                report_window_offsets: false,
+                keyserver_rate_limiter: None,
+                max_keyserver_response_bytes: None,
+                max_total_attempts: None,
+                overall_deadline: None,
+                parallel_threshold: None,
+                canonical_response_order: false,
+                security_parameter: None,
+                prover: Default::default(),
+                keyserver_apply: None,
+                progress_sink: None,
+            })
+        };
+
+        let sent_hints = || -> Vec<bool> {
+            let bodies = std::mem::take(&mut *open_bodies.lock().unwrap());
+            assert!(!bodies.is_empty());
+            bodies
+                .iter()
+                .map(|body| body.contains(r#""version_hint":"v2""#))
+                .collect()
+        };
+
+        // Every keyserver in the first screen gets the hint it started with...
+        screen().await.unwrap_err();
+        assert!(sent_hints().iter().all(|is_v2| !is_v2));
+        // ...and the next screen picks up the rotated one.
+        screen().await.unwrap_err();
+        assert!(sent_hints().iter().all(|is_v2| *is_v2));
+    }
+
+    #[test]
+    fn nucleotide_count_overflow_reports_offending_sequence() {
+        assert_eq!(checked_total_len([3, 4, 5].into_iter()).unwrap(), 12);
+
+        let lens = [u64::MAX - 10, 5, 20, 1];
+        let err = checked_total_len(lens.into_iter()).unwrap_err();
+        assert!(
+            matches!(
+                err,
+                DoprfError::NucleotideCountOverflow {
+                    total_before,
+                    sequence_index: 2,
+                } if total_before == u64::MAX - 5
+            ),
+            "{err:?}"
+        );
+    }
+
+    #[test]
+    fn keyserver_hash_count_is_windows_plus_checksum() {
+        let dna = DnaSequence::<Nucleotide>::parse(0, &"acgt".repeat(20)).unwrap();
+        let spec = HashSpec::unambiguous(vec![HashTypeDescriptor::dna_normal_cech()]);
+        let windows = DoprfWindows::create([dna.as_slice()].iter(), &spec, u64::MAX).unwrap();
+        assert_eq!(windows.count, windows.combined_windows.len() as u64);
+
+        let sent = keyserver_hash_count(windows.count).unwrap();
+        assert_eq!(sent, windows.count + CHECKSUM_HASHES);
+
+        assert!(matches!(
+            keyserver_hash_count(u64::MAX),
+            Err(DoprfError::SequencesTooBig)
+        ));
+    }
+
+    #[test]
+    fn zero_window_records_are_reported_as_not_screened() {
+        let long = DnaSequence::<Nucleotide>::parse(0, &"acgt".repeat(20)).unwrap();
+        let short = DnaSequence::<Nucleotide>::parse(0, "acgtacgt").unwrap();
+        let sequences = [
+            short.as_slice(),
+            long.as_slice(),
+            short.as_slice(),
+            short.as_slice(),
+            long.as_slice(),
+        ];
+        let spec = HashSpec::unambiguous(vec![HashTypeDescriptor::dna_normal_cech()]);
+        let windows = DoprfWindows::create(sequences.iter(), &spec, u64::MAX).unwrap();
+        assert_eq!(windows.non_empty_records, vec![1, 4]);
+
+        let too_short = |record| NotScreened {
+            record,
+            reason: NotScreenedReason::TooShort,
+        };
+        assert_eq!(
+            not_screened(windows.empty_records.iter().copied(), ZeroWindowPolicy::Report),
+            vec![too_short(0), too_short(2), too_short(3)],
+        );
+        assert_eq!(
+            not_screened(windows.empty_records.iter().copied(), ZeroWindowPolicy::Omit),
+            vec![],
+        );
+    }
 }
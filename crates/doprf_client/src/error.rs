@@ -1,17 +1,30 @@
 // Copyright 2021-2024 SecureDNA Stiftung (SecureDNA Foundation) <licensing@securedna.org>
 // SPDX-License-Identifier: MIT OR Apache-2.0
 
+use std::num::NonZeroUsize;
 use std::time::Duration;
 
+use shared_types::requests::{AttemptLog, AttemptServers};
 use thiserror::Error;
 
+use crate::failure_capture::FailureCapture;
 use crate::{server_selection::ServerSelectionError, windows::WindowsError};
-use doprf::prf::{DecodeError, QueryError};
+use doprf::party::{KeyserverId, KeyserverIdSet};
+use doprf::prf::{DecodeError, QueryError, ZeroQuorumError};
 
 #[derive(Debug, Error)]
 pub enum DoprfError {
     #[error("Timed out after {:.3}s", after.as_secs_f64())]
     Timeout { after: Duration },
+    /// The request hit one of the limits on retrying it, see
+    /// [`DoprfConfig::max_total_attempts`](crate::doprf_client::DoprfConfig::max_total_attempts)
+    /// and [`DoprfConfig::overall_deadline`](crate::doprf_client::DoprfConfig::overall_deadline).
+    #[error("Gave up after {} attempt(s): {limit}", attempts.len())]
+    DeadlineExceeded {
+        limit: RetryLimit,
+        /// The servers selected for each attempt, in order.
+        attempts: Vec<AttemptServers>,
+    },
     #[error("Error during server selection: {0}")]
     ServerSelectionError(#[from] ServerSelectionError),
     #[error("Error querying last server version for {domain}: {source}")]
@@ -28,31 +41,97 @@ pub enum DoprfError {
     },
     #[error("Order exceeds maximum size")]
     SequencesTooBig,
+    #[error(
+        "Order exceeds maximum size: nucleotide count overflowed at sequence {sequence_index} \
+         (total before it: {total_before})"
+    )]
+    NucleotideCountOverflow {
+        /// The number of nucleotides in the sequences before `sequence_index`.
+        total_before: u64,
+        /// The index of the sequence that pushed the total past `u64::MAX`.
+        sequence_index: usize,
+    },
     #[error("Error windowing the provided sequences: {0}")]
     WindowsError(#[from] WindowsError),
     #[error("Error while decoding ristretto points: {0}")]
     DecodeError(#[from] DecodeError),
     #[error("Error incorporating queries: {0}")]
     CryptoError(#[from] QueryError),
+    /// A [`Self::CryptoError`] with the inputs needed to reproduce it, see
+    /// [`crate::failure_capture`].
+    #[error("Error incorporating queries: {error}")]
+    CapturedCryptoError {
+        error: QueryError,
+        capture: Box<FailureCapture>,
+    },
+    #[error("Keyserver {id} responded, but isn't in the chosen quorum ({quorum})")]
+    UnexpectedKeyserver {
+        id: KeyserverId,
+        quorum: KeyserverIdSet,
+    },
+    #[error("Invalid keyserver threshold: {0}")]
+    ZeroQuorum(#[from] ZeroQuorumError),
+    #[error("No regions were given to screen under")]
+    NoRegions,
     #[error("Hazard database responded with invalid record number. This is a bug.")]
     InvalidRecord,
+    #[error("Error writing results: {0}")]
+    WriteError(std::io::Error),
+    #[error("Checkpoint was taken while screening different sequences")]
+    CheckpointMismatch,
+    #[error(
+        "Prepared {queries} queries for a request of {hash_total_count} hashes. This is a bug."
+    )]
+    QueryCountMismatch {
+        hash_total_count: u64,
+        queries: usize,
+    },
+    #[error("Error proving queries: {0}")]
+    ProvingError(#[from] doprf::prove::ProofError),
 }
 
 impl DoprfError {
     pub fn is_retriable(&self) -> bool {
         match self {
             Self::Timeout { .. } => true,
+            Self::DeadlineExceeded { .. } => false,
             Self::ServerSelectionError(_) => true,
             Self::GetLastServerVersion { .. } => false,
             Self::HttpError(e) => e.is_retriable(),
             Self::ScepError { .. } => false,
             Self::SequencesTooBig => false,
+            Self::NucleotideCountOverflow { .. } => false,
             Self::WindowsError { .. } => false,
             Self::DecodeError { .. } => false,
             Self::CryptoError { .. } => false,
+            Self::CapturedCryptoError { .. } => false,
+            Self::UnexpectedKeyserver { .. } => false,
+            Self::ZeroQuorum(_) => false,
+            Self::NoRegions => false,
             Self::InvalidRecord => false,
+            Self::WriteError(_) => false,
+            Self::CheckpointMismatch => false,
+            Self::QueryCountMismatch { .. } => false,
+            Self::ProvingError(e) => e.is_retriable(),
         }
     }
+
+    /// Give up on a request because of `limit`, reporting its `attempts` so far.
+    pub(crate) fn deadline_exceeded(limit: RetryLimit, attempts: &AttemptLog) -> Self {
+        Self::DeadlineExceeded {
+            limit,
+            attempts: attempts.attempts(),
+        }
+    }
+}
+
+/// Which limit on retrying a request was hit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+pub enum RetryLimit {
+    #[error("reached the maximum of {0} attempt(s)")]
+    MaxTotalAttempts(NonZeroUsize),
+    #[error("passed the overall deadline")]
+    OverallDeadline,
 }
 
 impl<E: std::error::Error + Send + Sync + 'static> From<scep_client_helpers::Error<E>>
@@ -0,0 +1,269 @@
+// Copyright 2021-2024 SecureDNA Stiftung (SecureDNA Foundation) <licensing@securedna.org>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Self-contained captures of failed screens, for reproducing them offline.
+//!
+//! When [`DoprfConfig::capture_failures`] is set, a [`QueryError`] while
+//! incorporating keyserver responses is returned as
+//! [`DoprfError::CapturedCryptoError`], carrying a [`FailureCapture`] the caller
+//! can serialize and save. [`replay`] later runs the same computation again,
+//! without any network access.
+//!
+//! [`DoprfConfig::capture_failures`]: crate::DoprfConfig::capture_failures
+//! [`QueryError`]: doprf::prf::QueryError
+
+use std::fmt;
+
+use doprf::party::KeyserverId;
+use doprf::prf::{HashPart, QueryStateSet, SerializableQueryStateSet};
+use doprf::tagged::TaggedHash;
+use packed_ristretto::PackedRistrettos;
+use serde::{Deserialize, Serialize};
+use shared_types::requests::{RequestContext, SerializableRequestContext};
+
+use crate::error::DoprfError;
+
+/// Everything needed to replay incorporating keyserver responses.
+///
+/// This includes the blinding factors, so a capture reveals the screened
+/// windows and should be handled like the order itself. Certificates and
+/// exemption tokens are never included.
+#[derive(Serialize, Deserialize)]
+pub struct FailureCapture {
+    pub request_ctx: SerializableRequestContext,
+    /// The query set before any responses were incorporated.
+    pub querystate: SerializableQueryStateSet,
+    pub keyserver_responses: Vec<(KeyserverId, PackedRistrettos<HashPart>)>,
+}
+
+impl FailureCapture {
+    pub fn new(
+        request_ctx: &RequestContext,
+        querystate: &QueryStateSet,
+        keyserver_responses: &[(KeyserverId, PackedRistrettos<HashPart>)],
+    ) -> Self {
+        Self {
+            request_ctx: request_ctx.to_serializable_request_context(),
+            querystate: querystate.to_serializable_set(),
+            keyserver_responses: keyserver_responses.to_vec(),
+        }
+    }
+
+    /// The keyservers responsible for a failed validation, without hashing.
+    ///
+    /// This is the blame set of the captured
+    /// [`QueryError::ValidationFailed`](doprf::prf::QueryError::ValidationFailed),
+    /// and empty if every captured response was valid.
+    pub fn blame(&self) -> Result<Vec<KeyserverId>, DoprfError> {
+        Ok(self
+            .incorporate()?
+            .find_keyservers_with_invalid_contribution())
+    }
+
+    fn incorporate(&self) -> Result<QueryStateSet, DoprfError> {
+        let mut querystate = self.querystate.to_query_state_set()?;
+        for (id, responses) in &self.keyserver_responses {
+            let parts = responses
+                .iter_decoded()
+                .collect::<Result<Vec<HashPart>, _>>()?;
+            querystate.incorporate_response(*id, &parts)?;
+        }
+        Ok(querystate)
+    }
+}
+
+impl fmt::Debug for FailureCapture {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let keyservers: Vec<_> = self.keyserver_responses.iter().map(|(id, _)| id).collect();
+        f.debug_struct("FailureCapture")
+            .field("keyservers", &keyservers)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Incorporate the captured responses and hash, as the original screen did.
+///
+/// A capture of a [`QueryError`](doprf::prf::QueryError) replays to the same
+/// error, as [`DoprfError::CryptoError`].
+pub fn replay(capture: &FailureCapture) -> Result<Vec<TaggedHash>, DoprfError> {
+    Ok(capture.incorporate()?.get_hash_values()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroU32;
+
+    use doprf::active_security::{
+        commitments_from_secret_and_keyshares, ActiveSecurityKey, Commitment,
+    };
+    use doprf::party::KeyserverIdSet;
+    use doprf::prf::{generate_keyshares, DecodeError, KeyShare, QueryError, SecurityParameter};
+    use doprf::prove::{Prover, ProverBackend, ProverPaths};
+    use doprf::tagged::HashTag;
+    use rand::rngs::OsRng;
+    use shared_types::requests::RequestId;
+
+    use super::*;
+    use crate::operations::{incorporate_responses_and_hash_capturing, make_keyserver_querysets};
+    use crate::progress::NoProgress;
+
+    #[tokio::test]
+    async fn captured_failure_replays_to_same_error() {
+        let request_ctx = RequestContext::single(RequestId::new_unique());
+        let active_security_key =
+            ActiveSecurityKey::from_commitments([Commitment::hash_from_bytes_for_tests_only(&[1])]);
+        let windows = [
+            (HashTag::new(true, 0, 0), "acgtacgtacgt"),
+            (HashTag::new(false, 0, 1), "cgtacgtacgta"),
+        ];
+        let (querystate, _, _) = make_keyserver_querysets(
+            &request_ctx,
+            &windows,
+            1,
+            &active_security_key,
+            SecurityParameter::default(),
+            &Prover::new(ProverPaths::default(), ProverBackend::Mock),
+            &NoProgress,
+        )
+        .await
+        .unwrap();
+
+        // A keyserver that echoes the queries back instead of applying its keyshare.
+        let keyserver = KeyserverId::try_from(1u32).unwrap();
+        let echoed: PackedRistrettos<HashPart> = querystate.queries().copied().collect();
+
+        let err = incorporate_responses_and_hash_capturing::<TaggedHash>(
+            &request_ctx,
+            querystate,
+            &KeyserverIdSet::from(vec![keyserver]),
+            vec![(keyserver, echoed)],
+            &NoProgress,
+        )
+        .await
+        .unwrap_err();
+        let DoprfError::CapturedCryptoError {
+            error:
+                QueryError::ValidationFailed {
+                    keyservers: blamed,
+                    discrepancy,
+                },
+            capture,
+        } = err
+        else {
+            panic!("expected a captured validation failure, got {err:?}");
+        };
+        assert_eq!(blamed, vec![keyserver]);
+
+        // The capture survives serialization and fails the same way offline.
+        let capture: FailureCapture =
+            serde_json::from_str(&serde_json::to_string(&capture).unwrap()).unwrap();
+        match replay(&capture) {
+            Err(DoprfError::CryptoError(QueryError::ValidationFailed {
+                keyservers: replay_blamed,
+                discrepancy: replay_discrepancy,
+            })) => {
+                assert_eq!(replay_blamed, blamed);
+                assert_eq!(replay_discrepancy, discrepancy);
+            }
+            other => panic!("expected the replay to fail validation, got {other:?}"),
+        }
+        assert_eq!(capture.blame().unwrap(), blamed);
+    }
+
+    #[tokio::test]
+    async fn blame_reports_only_the_corrupted_keyserver() {
+        let request_ctx = RequestContext::single(RequestId::new_unique());
+        let secret: KeyShare = format!("2a{}", "00".repeat(31)).parse().unwrap();
+        let required = NonZeroU32::new(2).unwrap();
+        let keyshares =
+            generate_keyshares(&secret, required, NonZeroU32::new(3).unwrap(), &mut OsRng).unwrap();
+        let active_security_key = ActiveSecurityKey::from_commitments(
+            commitments_from_secret_and_keyshares(&secret, &keyshares, required).unwrap(),
+        );
+        let windows = [
+            (HashTag::new(true, 0, 0), "acgtacgtacgt"),
+            (HashTag::new(false, 0, 1), "cgtacgtacgta"),
+        ];
+        let (querystate, _, _) = make_keyserver_querysets(
+            &request_ctx,
+            &windows,
+            2,
+            &active_security_key,
+            SecurityParameter::default(),
+            &Prover::new(ProverPaths::default(), ProverBackend::Mock),
+            &NoProgress,
+        )
+        .await
+        .unwrap();
+
+        // Keyserver 1 applies its keyshare, keyserver 2 echoes the queries back.
+        let honest = KeyserverId::try_from(1u32).unwrap();
+        let corrupted = KeyserverId::try_from(2u32).unwrap();
+        let quorum = KeyserverIdSet::from(vec![honest, corrupted]);
+        let coefficient = quorum.langrange_coefficient_for_id(&honest);
+        let applied: PackedRistrettos<HashPart> = querystate
+            .queries()
+            .map(|&q| keyshares[0].apply_query_and_lagrange_coefficient(q, &coefficient))
+            .collect();
+        let echoed: PackedRistrettos<HashPart> = querystate.queries().copied().collect();
+
+        let err = incorporate_responses_and_hash_capturing::<TaggedHash>(
+            &request_ctx,
+            querystate,
+            &quorum,
+            vec![(honest, applied), (corrupted, echoed)],
+            &NoProgress,
+        )
+        .await
+        .unwrap_err();
+        let DoprfError::CapturedCryptoError { capture, .. } = err else {
+            panic!("expected a captured failure, got {err:?}");
+        };
+
+        let capture: FailureCapture =
+            serde_json::from_str(&serde_json::to_string(&capture).unwrap()).unwrap();
+        assert_eq!(capture.blame().unwrap(), vec![corrupted]);
+    }
+
+    #[tokio::test]
+    async fn corrupted_captures_are_errors() {
+        let request_ctx = RequestContext::single(RequestId::new_unique());
+        let active_security_key =
+            ActiveSecurityKey::from_commitments([Commitment::hash_from_bytes_for_tests_only(&[1])]);
+        let windows = [(HashTag::new(true, 0, 0), "acgtacgtacgt")];
+        let (querystate, _, _) = make_keyserver_querysets(
+            &request_ctx,
+            &windows,
+            1,
+            &active_security_key,
+            SecurityParameter::default(),
+            &Prover::new(ProverPaths::default(), ProverBackend::Mock),
+            &NoProgress,
+        )
+        .await
+        .unwrap();
+        let capture =
+            serde_json::to_value(FailureCapture::new(&request_ctx, &querystate, &[])).unwrap();
+        // Not a valid compressed Ristretto point.
+        let invalid_point = serde_json::to_value([0xffu8; 32]).unwrap();
+
+        let mut bad_target = capture.clone();
+        bad_target["querystate"]["randomized_target"]["target"] = invalid_point.clone();
+        // A response captured as already incorporated.
+        let mut bad_response = capture;
+        bad_response["querystate"]["querystates"][0][1]["responses"] =
+            serde_json::json!([[1, invalid_point]]);
+
+        for corrupted in [bad_target, bad_response] {
+            let capture: FailureCapture = serde_json::from_value(corrupted).unwrap();
+            assert!(matches!(
+                capture.blame(),
+                Err(DoprfError::DecodeError(DecodeError::InvalidRistrettoPoint))
+            ));
+            assert!(matches!(
+                replay(&capture),
+                Err(DoprfError::DecodeError(DecodeError::InvalidRistrettoPoint))
+            ));
+        }
+    }
+}
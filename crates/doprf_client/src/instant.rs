@@ -15,6 +15,14 @@ impl Instant {
     }
 }
 
+#[cfg(target_arch = "wasm32")]
+impl Instant {
+    pub fn saturating_duration_since(&self, earlier: Instant) -> std::time::Duration {
+        let millis = (self.millis - earlier.millis).max(0.0);
+        std::time::Duration::from_secs_f64(millis / 1e3)
+    }
+}
+
 #[cfg(target_arch = "wasm32")]
 impl std::ops::Add<std::time::Duration> for Instant {
     type Output = Self;
@@ -1,15 +1,21 @@
 // Copyright 2021-2024 SecureDNA Stiftung (SecureDNA Foundation) <licensing@securedna.org>
 // SPDX-License-Identifier: MIT OR Apache-2.0
 
+pub mod checkpoint;
 pub mod doprf_client;
 pub mod error;
+pub mod failure_capture;
 pub mod instant;
 pub mod operations;
+pub mod preflight;
 pub mod progress;
+pub mod rate_limit;
+pub mod recursive_proof;
 pub mod retry_if; // TODO: how to share this with synthclient?
 pub mod scep_client;
 pub mod server_selection;
 pub mod server_version_handler;
+pub mod to_writer;
 pub mod windows;
 
 pub use crate::doprf_client::*;
@@ -2,16 +2,18 @@
 // SPDX-License-Identifier: MIT OR Apache-2.0
 
 use crate::error::DoprfError;
+use crate::failure_capture::FailureCapture;
 use crate::instant::get_now;
-use crate::progress::report_progress;
+use crate::progress::{report_phase, ProgressPhase, ProgressSink};
 use doprf::active_security::ActiveSecurityKey;
-use doprf::party::KeyserverId;
-use doprf::prf::{HashPart, QueryStateSet, VerificationInput};
+use doprf::party::{KeyserverId, KeyserverIdSet};
+use doprf::prf::{HashPart, QueryError, QueryStateSet, SecurityParameter, VerificationInput};
+use doprf::prove::{prove_querystate, ExecutionStats, Prover};
 use doprf::tagged::{HashTag, TaggedHash};
 use packed_ristretto::{PackableRistretto, PackedRistrettos};
 
 use shared_types::requests::RequestContext;
-use tracing::debug;
+use tracing::{debug, warn};
 
 #[cfg(target_arch = "wasm32")]
 async fn spawn_blocking<F, R>(f: F) -> Result<R, ()>
@@ -48,55 +50,97 @@ pub const CHUNK_SIZE_DEFAULT: usize = 10_000;
 /// correspond to 10,000 sequences each, and the last one to the final chunk of
 /// 2,000 sequences.
 ///
-/// `sequences` cannot be empty, the method will panic if it is.
-pub fn make_keyserver_querysets(
+/// `sequences` cannot be empty, the method will panic if it is. Errors if
+/// `num_required_keyshares` is zero, or if proving with `prover` fails.
+///
+/// Also returns the proofs of the QueryStateSet, and what running the zkVM
+/// programs for them cost. Progress is reported to `progress`.
+pub async fn make_keyserver_querysets(
     request_ctx: &RequestContext,
-    sequences: &[(HashTag, impl AsRef<str> + Sync)],
+    sequences: &[(HashTag, impl AsRef<[u8]> + Sync)],
     num_required_keyshares: usize,
     target: &ActiveSecurityKey,
-) -> (QueryStateSet, Vec<VerificationInput>) {
+    security_parameter: SecurityParameter,
+    prover: &Prover,
+    progress: &dyn ProgressSink,
+) -> Result<(QueryStateSet, Vec<VerificationInput>, ExecutionStats), DoprfError> {
 
     let now = get_now();
 
     assert!(!sequences.is_empty());
 
-    report_progress(request_ctx);
+    report_phase(request_ctx, progress, ProgressPhase::Preparing).await;
 
     // initial querystateset of hashes, blinds keyservers from seeing original sequences
-    let (querystates, verification_inputs)  = QueryStateSet::from_iter(
-        sequences.iter().map(|(t, w)| (*t, w.as_ref().as_bytes())),
+    let querystates = QueryStateSet::build(
+        sequences.iter().map(|(t, w)| (*t, w.as_ref())),
         num_required_keyshares,
         target.clone(),
-    );
+        security_parameter,
+    )?;
+    let (verification_inputs, execution_stats) = prove_querystate(
+        &querystates,
+        sequences.iter().map(|(_, w)| w.as_ref()),
+        target,
+        security_parameter,
+        prover,
+    )?;
 
-    report_progress(request_ctx);
+    report_phase(request_ctx, progress, ProgressPhase::Prepared).await;
 
     let setup_duration = now.elapsed();
     debug!("Setting up done. Took: {:.2?}", setup_duration);
-    (querystates, verification_inputs)
+    Ok((querystates, verification_inputs, execution_stats))
 }
 
 /// Given a QueryStateSet, and a Vec of keyserver responses,
 /// incorporate the responses into the querystate.
 /// Then compute packed Ristretto hashes for the QueryStateSet.
 /// The result is used to query HDB.
+///
+/// Every response must come from a keyserver in `quorum`, the set the queries
+/// were made for, since the Lagrange coefficients depend on it. Progress is
+/// reported to `progress`.
 pub async fn incorporate_responses_and_hash<R>(
     request_ctx: &RequestContext,
     mut querystate: QueryStateSet,
+    quorum: &KeyserverIdSet,
     keyserver_responses: Vec<(KeyserverId, PackedRistrettos<HashPart>)>,
+    progress: &dyn ProgressSink,
 ) -> Result<PackedRistrettos<R>, DoprfError>
 where
     R: From<TaggedHash> + PackableRistretto + 'static,
     <R as PackableRistretto>::Array: Send + 'static,
 {
+    if let Some(&(id, _)) = keyserver_responses
+        .iter()
+        .find(|(id, _)| !quorum.contains(id))
+    {
+        return Err(DoprfError::UnexpectedKeyserver {
+            id,
+            quorum: quorum.clone(),
+        });
+    }
+
     let now = get_now();
-    report_progress(request_ctx);
+    report_phase(request_ctx, progress, ProgressPhase::Incorporating).await;
 
     for (id, ks_pr) in keyserver_responses.into_iter() {
         let parts = ks_pr.iter_decoded().collect::<Result<Vec<HashPart>, _>>()?;
 
-        querystate = spawn_blocking(move || -> Result<QueryStateSet, doprf::prf::QueryError> {
-            querystate.incorporate_response(id, &parts)?;
+        let quorum = quorum.clone();
+        querystate = spawn_blocking(move || -> Result<QueryStateSet, QueryError> {
+            let incorporated = querystate.incorporate_response(id, &parts);
+            if let Err(QueryError::WrongSizeResponse) = incorporated {
+                warn!(
+                    "keyserver {id} sent {} hash parts for {} queries",
+                    parts.len(),
+                    querystate.len()
+                );
+            }
+            incorporated?;
+            // Blame a bad keyserver now, rather than after hashing with every response.
+            querystate.validate_keyserver_contribution(&quorum, id)?;
             Ok(querystate) // hand back querystate for borrow-checking purposes
         })
         .await
@@ -110,17 +154,21 @@ where
     );
 
     let now = get_now();
-    report_progress(request_ctx);
+    report_phase(request_ctx, progress, ProgressPhase::Hashing).await;
+    // If validation fails, this carries the keyservers to blame.
+    let quorum = quorum.clone();
     let hash_values: PackedRistrettos<R> = spawn_blocking(move || {
-        querystate
-            .get_hash_values()
-            .expect("error processing keyserver responses")
-            .into_iter()
-            .map(R::from)
-            .collect()
+        let hashes = querystate.get_hash_values();
+        if let Err(QueryError::MissingKeyserverResponse) = hashes {
+            warn!(
+                "missing responses from keyservers {:?}",
+                querystate.missing_keyservers(&quorum)
+            );
+        }
+        hashes.map(|hashes| hashes.into_iter().map(R::from).collect())
     })
     .await
-    .expect("could not join thread");
+    .expect("could not join thread")?;
 
     let hash_duration = now.elapsed();
     debug!(
@@ -128,6 +176,90 @@ where
         hash_duration
     );
 
-    report_progress(request_ctx);
+    report_phase(request_ctx, progress, ProgressPhase::Hashed).await;
     Ok(hash_values)
 }
+
+/// Like [`incorporate_responses_and_hash`], but a [`QueryError`] is returned as
+/// [`DoprfError::CapturedCryptoError`], along with the inputs needed to replay it.
+pub async fn incorporate_responses_and_hash_capturing<R>(
+    request_ctx: &RequestContext,
+    querystate: QueryStateSet,
+    quorum: &KeyserverIdSet,
+    keyserver_responses: Vec<(KeyserverId, PackedRistrettos<HashPart>)>,
+    progress: &dyn ProgressSink,
+) -> Result<PackedRistrettos<R>, DoprfError>
+where
+    R: From<TaggedHash> + PackableRistretto + 'static,
+    <R as PackableRistretto>::Array: Send + 'static,
+{
+    let capture = FailureCapture::new(request_ctx, &querystate, &keyserver_responses);
+    incorporate_responses_and_hash(
+        request_ctx,
+        querystate,
+        quorum,
+        keyserver_responses,
+        progress,
+    )
+    .await
+    .map_err(|err| match err {
+        DoprfError::CryptoError(error) => DoprfError::CapturedCryptoError {
+            error,
+            capture: Box::new(capture),
+        },
+        err => err,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use doprf::active_security::Commitment;
+    use doprf::prove::{ProverBackend, ProverPaths};
+    use shared_types::requests::RequestId;
+
+    use super::*;
+    use crate::progress::NoProgress;
+
+    #[tokio::test]
+    async fn responses_from_outside_the_quorum_are_rejected() {
+        let request_ctx = RequestContext::single(RequestId::new_unique());
+        let active_security_key =
+            ActiveSecurityKey::from_commitments([Commitment::hash_from_bytes_for_tests_only(&[1])]);
+        let windows = [(HashTag::new(true, 0, 0), "acgtacgtacgt")];
+        let (querystate, _, _) = make_keyserver_querysets(
+            &request_ctx,
+            &windows,
+            1,
+            &active_security_key,
+            SecurityParameter::default(),
+            &Prover::new(ProverPaths::default(), ProverBackend::Mock),
+            &NoProgress,
+        )
+        .await
+        .unwrap();
+
+        let chosen = KeyserverId::try_from(1u32).unwrap();
+        let stray = KeyserverId::try_from(2u32).unwrap();
+        let quorum = KeyserverIdSet::from(vec![chosen]);
+        let response: PackedRistrettos<HashPart> = querystate.queries().copied().collect();
+
+        let err = incorporate_responses_and_hash::<TaggedHash>(
+            &request_ctx,
+            querystate,
+            &quorum,
+            vec![(stray, response)],
+            &NoProgress,
+        )
+        .await
+        .unwrap_err();
+        let DoprfError::UnexpectedKeyserver {
+            id,
+            quorum: reported,
+        } = err
+        else {
+            panic!("expected a stray keyserver to be rejected, got {err:?}");
+        };
+        assert_eq!(id, stray);
+        assert_eq!(reported.to_string(), quorum.to_string());
+    }
+}
@@ -0,0 +1,112 @@
+// Copyright 2021-2024 SecureDNA Stiftung (SecureDNA Foundation) <licensing@securedna.org>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Checking client certs against the selected servers before screening.
+//!
+//! A preflight runs the SCEP open and authenticate steps against each server,
+//! which is where servers validate the client's token chain (including expiry
+//! and revocation), but never sends any DNA or hashes.
+
+use std::fmt;
+
+use doprf::party::KeyserverIdSet;
+use futures::future::join_all;
+use shared_types::synthesis_permission::Region;
+
+use crate::doprf_client::DoprfConfig;
+use crate::error::DoprfError;
+use crate::scep_client::{ClientConfig, HdbClient, KeyserverClient};
+use crate::server_selection::{ChosenSelectionSubset, SelectedHdb, SelectedKeyserver};
+
+/// The outcome of checking our certs against a single server.
+#[derive(Debug)]
+pub struct ServerCertCheck {
+    pub domain: String,
+    pub result: Result<(), DoprfError>,
+}
+
+/// The outcome of [`preflight_certs`], with one entry per server checked.
+#[derive(Debug, Default)]
+pub struct CertPreflightReport {
+    pub checks: Vec<ServerCertCheck>,
+}
+
+impl CertPreflightReport {
+    /// Whether every server accepted our certs.
+    pub fn is_ok(&self) -> bool {
+        self.checks.iter().all(|check| check.result.is_ok())
+    }
+
+    /// The servers that rejected our certs, or couldn't be checked.
+    pub fn failures(&self) -> impl Iterator<Item = &ServerCertCheck> {
+        self.checks.iter().filter(|check| check.result.is_err())
+    }
+}
+
+impl fmt::Display for CertPreflightReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for check in &self.checks {
+            match &check.result {
+                Ok(()) => writeln!(f, "{}: ok", check.domain)?,
+                Err(err) => writeln!(f, "{}: {err}", check.domain)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Select servers as [`process`](crate::process) would, and check that each of
+/// them accepts the configured certs.
+///
+/// Errors only if servers couldn't be selected; per-server failures are in the report.
+pub async fn preflight_certs<S>(
+    config: &DoprfConfig<'_, S>,
+) -> Result<CertPreflightReport, DoprfError> {
+    let ChosenSelectionSubset {
+        keyservers, hdb, ..
+    } = config.server_selector.clone().choose().await?;
+    let region = config.regions.first().copied().unwrap_or_default();
+    Ok(preflight_servers(config.client_config(), keyservers, Some(hdb), region).await)
+}
+
+/// Check that each of the given servers accepts the certs in `config`.
+pub async fn preflight_servers(
+    config: ClientConfig,
+    keyservers: Vec<SelectedKeyserver>,
+    hdb: Option<SelectedHdb>,
+    region: Region,
+) -> CertPreflightReport {
+    let keyserver_id_set: KeyserverIdSet =
+        keyservers.iter().map(|ks| ks.id).collect::<Vec<_>>().into();
+
+    let keyserver_checks = keyservers.into_iter().map(|server| {
+        let config = config.clone();
+        let keyserver_id_set = keyserver_id_set.clone();
+        async move {
+            let domain = server.domain.clone();
+            let result = async {
+                KeyserverClient::open(server, config, 0, None, keyserver_id_set)
+                    .await?
+                    .preflight()
+                    .await
+            }
+            .await;
+            ServerCertCheck { domain, result }
+        }
+    });
+    let mut checks = join_all(keyserver_checks).await;
+
+    if let Some(server) = hdb {
+        let domain = server.domain.clone();
+        let result = async {
+            HdbClient::open(server, config, 0, None, keyserver_id_set, region, false)
+                .await?
+                .preflight()
+                .await
+        }
+        .await;
+        checks.push(ServerCertCheck { domain, result });
+    }
+
+    CertPreflightReport { checks }
+}
@@ -1,8 +1,86 @@
 // Copyright 2021-2024 SecureDNA Stiftung (SecureDNA Foundation) <licensing@securedna.org>
 // SPDX-License-Identifier: MIT OR Apache-2.0
 
+use shared_types::requests::RequestContext;
+
 #[cfg_attr(target_arch = "wasm32", path = "wasm.rs")]
 #[cfg_attr(not(target_arch = "wasm32"), path = "native.rs")]
 pub mod implementation;
 
 pub use self::implementation::*;
+
+/// The points in hashing a batch of windows at which progress is reported,
+/// in the order they're reached.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProgressPhase {
+    /// About to build and prove the keyserver queries.
+    Preparing,
+    /// The queries are built and proven.
+    Prepared,
+    /// About to incorporate the keyservers' responses.
+    Incorporating,
+    /// The responses are incorporated, and the hashes are being computed.
+    Hashing,
+    /// The hashes are ready to send to the HDB.
+    Hashed,
+}
+
+impl ProgressPhase {
+    pub const ALL: [Self; 5] = [
+        Self::Preparing,
+        Self::Prepared,
+        Self::Incorporating,
+        Self::Hashing,
+        Self::Hashed,
+    ];
+}
+
+/// Reaching a [`ProgressPhase`] while hashing a batch of windows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProgressEvent {
+    pub phase: ProgressPhase,
+    /// How many of the batch's phases have been reached, including this one.
+    pub processed: usize,
+    /// How many phases each batch goes through.
+    pub total: usize,
+}
+
+impl From<ProgressPhase> for ProgressEvent {
+    fn from(phase: ProgressPhase) -> Self {
+        Self {
+            phase,
+            processed: phase as usize + 1,
+            total: ProgressPhase::ALL.len(),
+        }
+    }
+}
+
+/// Receives progress as a screen goes, e.g. to forward it to a UI.
+///
+/// The screen waits for each report to finish before going on, so a sink
+/// that can't keep up slows the screen down rather than buffering events.
+#[cfg_attr(target_arch = "wasm32", async_trait::async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait::async_trait)]
+pub trait ProgressSink: Send + Sync {
+    async fn report(&self, event: ProgressEvent);
+}
+
+/// Discards progress, for screens nobody is watching.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoProgress;
+
+#[cfg_attr(target_arch = "wasm32", async_trait::async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait::async_trait)]
+impl ProgressSink for NoProgress {
+    async fn report(&self, _event: ProgressEvent) {}
+}
+
+/// Report reaching `phase` to the platform, see [`report_progress`], and to `sink`.
+pub(crate) async fn report_phase(
+    request_ctx: &RequestContext,
+    sink: &dyn ProgressSink,
+    phase: ProgressPhase,
+) {
+    report_progress(request_ctx);
+    sink.report(phase.into()).await;
+}
@@ -0,0 +1,89 @@
+// Copyright 2021-2024 SecureDNA Stiftung (SecureDNA Foundation) <licensing@securedna.org>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Pacing requests to servers that we've agreed to send at most so many
+//! requests per second.
+
+use std::collections::HashMap;
+use std::num::NonZeroU32;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::instant::{get_now, Instant};
+
+/// Spaces out requests to each domain so that none receives more than the
+/// configured number of requests per second. Bursts are queued up and sent
+/// one interval apart, rather than rejected.
+///
+/// Share one limiter (e.g. in an `Arc`) between every screen that should count
+/// towards the same limits.
+#[derive(Debug)]
+pub struct RateLimiter {
+    interval: Duration,
+    next_slots: Mutex<HashMap<String, Instant>>,
+}
+
+impl RateLimiter {
+    /// Allow at most `qps` requests per second to each domain.
+    pub fn per_second(qps: NonZeroU32) -> Self {
+        Self {
+            interval: Duration::from_secs(1) / qps.get(),
+            next_slots: Mutex::default(),
+        }
+    }
+
+    /// Wait until a request to `domain` may be sent, and claim that slot.
+    pub async fn wait(&self, domain: &str) {
+        let delay = self.claim(domain, get_now());
+        if !delay.is_zero() {
+            futures_timer::Delay::new(delay).await;
+        }
+    }
+
+    /// Claim the next slot for a request to `domain`, returning how long after
+    /// `now` it is.
+    fn claim(&self, domain: &str, now: Instant) -> Duration {
+        let mut next_slots = self.next_slots.lock().unwrap();
+        let slot = match next_slots.get(domain) {
+            Some(&next) if next > now => next,
+            _ => now,
+        };
+        next_slots.insert(domain.to_owned(), slot + self.interval);
+        slot.saturating_duration_since(now)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bursts_are_paced_per_domain() {
+        let limiter = RateLimiter::per_second(NonZeroU32::new(20).unwrap());
+        let interval = Duration::from_millis(50);
+        let start = get_now();
+
+        // A burst, all at once: the first request to each domain isn't held
+        // back by the other domain, but later ones each wait their turn.
+        let burst: Vec<_> = ["ks1", "ks1", "ks2", "ks1", "ks2"]
+            .into_iter()
+            .map(|domain| limiter.claim(domain, start))
+            .collect();
+        assert_eq!(
+            burst,
+            [
+                Duration::ZERO,
+                interval,
+                Duration::ZERO,
+                interval * 2,
+                interval
+            ]
+        );
+
+        // Partway through the queue, the wait is for what's left of it...
+        let later = start + interval * 2 + Duration::from_millis(10);
+        assert_eq!(limiter.claim("ks1", later), Duration::from_millis(40));
+        // ...and once it has drained, requests go out straight away.
+        assert_eq!(limiter.claim("ks2", later), Duration::ZERO);
+    }
+}
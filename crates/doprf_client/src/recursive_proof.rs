@@ -0,0 +1,216 @@
+// Copyright 2021-2024 SecureDNA Stiftung (SecureDNA Foundation) <licensing@securedna.org>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Checking what the recursive verification proof committed to, without the
+//! client that made it.
+//!
+//! The verification program (in `verification_proof/program`) verifies the
+//! hash and checksum proofs, then commits, in order:
+//!
+//! 1. [`VerificationOutcome::Ok`] with the `PackedRistrettos<TaggedHash>` it
+//!    computed, once the proofs it was given have verified and it has
+//!    incorporated the keyserver responses
+//! 2. the verification key digests of the proofs it verified
+//! 3. the SHA-256 digest of each of those proofs' public values
+//!
+//! If the query states or a keyserver response fail to decode, a response can't
+//! be incorporated, or the hashes can't be computed, it commits only the
+//! [`VerificationOutcome`] saying which (see [`VerificationOutcome::compute`]).
+//! [`read_recursive_hashes`] turns that into [`ProofError::GuestFailed`].
+//!
+//! Anyone holding the verification proof's public values (e.g. a regulator
+//! auditing a screen) can rebuild that commitment from the inputs they expect,
+//! and check it with [`verify_recursive_public`]. This only checks what the
+//! proof claims; the proof itself must still be verified against the
+//! verification program's key.
+
+use sha2::{Digest, Sha256};
+use sp1_sdk::SP1PublicValues;
+
+use doprf::prove::{ProofError, PublicValuesReader};
+use doprf::tagged::TaggedHash;
+use packed_ristretto::{PackedRistrettos, VerificationOutcome};
+
+/// The public values the verification program commits when it verifies proofs
+/// with verification key digests `vkeys` and public values `public_values`, and
+/// computes `committed_hashes`.
+pub fn recursive_commitment(
+    vkeys: &[[u32; 8]],
+    public_values: &[Vec<u8>],
+    committed_hashes: &PackedRistrettos<TaggedHash>,
+) -> Result<SP1PublicValues, ProofError> {
+    // The program asserts this before verifying anything.
+    if vkeys.len() != public_values.len() {
+        return Err(ProofError::MismatchedInputs {
+            vkeys: vkeys.len(),
+            public_values: public_values.len(),
+        });
+    }
+    let public_values_digests: Vec<[u8; 32]> = public_values
+        .iter()
+        .map(|public_values| Sha256::digest(public_values).into())
+        .collect();
+
+    let mut commitment = SP1PublicValues::new();
+    commitment.write(&VerificationOutcome::Ok(committed_hashes.clone()));
+    commitment.write(&vkeys.to_vec());
+    commitment.write(&public_values_digests);
+    Ok(commitment)
+}
+
+/// Check that `committed` (the verification proof's public values) are what the
+/// verification program commits for these inputs. See the [module docs](self).
+pub fn verify_recursive_public(
+    vkeys: &[[u32; 8]],
+    public_values: &[Vec<u8>],
+    committed_hashes: &PackedRistrettos<TaggedHash>,
+    committed: &SP1PublicValues,
+) -> Result<(), ProofError> {
+    let expected = recursive_commitment(vkeys, public_values, committed_hashes)?;
+    if expected.as_slice() == committed.as_slice() {
+        Ok(())
+    } else {
+        Err(ProofError::CommitmentMismatch)
+    }
+}
+
+/// Read the hashes the verification program computed from its public values
+/// `committed`, leaving the rest of them to be read. Fails with
+/// [`ProofError::GuestFailed`] if the program committed a failure instead, or
+/// [`ProofError::Serialize`] if the public values are malformed.
+pub fn read_recursive_hashes(
+    committed: &mut PublicValuesReader<'_>,
+) -> Result<PackedRistrettos<TaggedHash>, ProofError> {
+    match committed.read::<VerificationOutcome>()? {
+        VerificationOutcome::Ok(hashes) => Ok(hashes),
+        failure => Err(ProofError::GuestFailed(failure.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use doprf::active_security::{ActiveSecurityKey, Commitment};
+    use doprf::party::KeyserverId;
+    use doprf::prf::{CompletedHashValue, HashPart, QueryStateSet, SecurityParameter};
+    use doprf::tagged::HashTag;
+
+    use super::*;
+
+    fn hashes(seeds: &[&str]) -> PackedRistrettos<TaggedHash> {
+        seeds
+            .iter()
+            .enumerate()
+            .map(|(i, seed)| TaggedHash {
+                tag: HashTag::new(i == 0, 0, i),
+                hash: CompletedHashValue::hash_from_bytes_for_tests_only(seed.as_bytes()),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn committed_hashes_must_match_public_values() {
+        let vkeys = [[1; 8], [2; 8]];
+        let public_values = vec![b"hash proof".to_vec(), b"checksum proof".to_vec()];
+        let committed_hashes = hashes(&["a", "b", "c"]);
+
+        // As the verification program commits them.
+        let mut committed = SP1PublicValues::new();
+        committed.write(&VerificationOutcome::Ok(committed_hashes.clone()));
+        committed.write(&vkeys.to_vec());
+        committed.write(
+            &public_values
+                .iter()
+                .map(|v| <[u8; 32]>::from(Sha256::digest(v)))
+                .collect::<Vec<_>>(),
+        );
+
+        assert!(
+            verify_recursive_public(&vkeys, &public_values, &committed_hashes, &committed).is_ok()
+        );
+
+        let tampered = hashes(&["a", "x", "c"]);
+        assert!(matches!(
+            verify_recursive_public(&vkeys, &public_values, &tampered, &committed),
+            Err(ProofError::CommitmentMismatch)
+        ));
+
+        let other_public_values = vec![b"hash proof".to_vec(), b"other proof".to_vec()];
+        assert!(matches!(
+            verify_recursive_public(&vkeys, &other_public_values, &committed_hashes, &committed),
+            Err(ProofError::CommitmentMismatch)
+        ));
+
+        assert!(matches!(
+            verify_recursive_public(&vkeys[..1], &public_values, &committed_hashes, &committed),
+            Err(ProofError::MismatchedInputs {
+                vkeys: 1,
+                public_values: 2
+            })
+        ));
+    }
+
+    #[test]
+    fn malformed_response_commits_failure_marker() {
+        let key =
+            ActiveSecurityKey::from_commitments([Commitment::hash_from_bytes_for_tests_only(&[1])]);
+        let windows = ["acgtacgtacgt", "cgtacgtacgta"];
+        let querystates = QueryStateSet::build(
+            windows
+                .iter()
+                .enumerate()
+                .map(|(i, w)| (HashTag::new(i == 0, 0, i), w.as_bytes())),
+            1,
+            key,
+            SecurityParameter::default(),
+        )
+        .unwrap()
+        .to_serializable_set();
+        let id = KeyserverId::try_from(1).unwrap();
+
+        // Not a valid compressed Ristretto point, for each query.
+        let malformed = PackedRistrettos::<HashPart>::new(vec![[0xff; 32]; windows.len() + 1]);
+        let outcome = VerificationOutcome::compute(&querystates, &[(id, malformed)]);
+        assert_eq!(outcome, VerificationOutcome::DecodeFailed);
+
+        // As the verification program commits it.
+        let mut committed = SP1PublicValues::new();
+        committed.write(&outcome);
+        let error = read_recursive_hashes(&mut PublicValuesReader::new(&committed)).unwrap_err();
+        assert!(matches!(error, ProofError::GuestFailed(_)));
+
+        // Too few parts to incorporate.
+        let short = PackedRistrettos::<HashPart>::new(vec![]);
+        let outcome = VerificationOutcome::compute(&querystates, &[(id, short)]);
+        assert_eq!(outcome, VerificationOutcome::IncorporateFailed);
+
+        // A failure can't be mistaken for an empty set of hashes.
+        let outcome = VerificationOutcome::compute(&querystates, &[]);
+        assert_eq!(outcome, VerificationOutcome::HashFailed);
+    }
+
+    #[test]
+    fn malformed_public_values_are_errors() {
+        let committed_hashes = hashes(&["a", "b"]);
+        let mut committed = SP1PublicValues::new();
+        committed.write(&VerificationOutcome::Ok(committed_hashes.clone()));
+        committed.write(&vec![[1u32; 8]]);
+        let mut reader = PublicValuesReader::new(&committed);
+        assert_eq!(
+            read_recursive_hashes(&mut reader).unwrap(),
+            committed_hashes
+        );
+        // The rest is left to be read.
+        assert_eq!(reader.read::<Vec<[u32; 8]>>().unwrap(), vec![[1u32; 8]]);
+
+        // Truncated, e.g. by a malicious prover.
+        let truncated = SP1PublicValues::from(&committed.as_slice()[..8]);
+        assert!(matches!(
+            read_recursive_hashes(&mut PublicValuesReader::new(&truncated)),
+            Err(ProofError::Serialize(_))
+        ));
+        assert!(matches!(
+            read_recursive_hashes(&mut PublicValuesReader::new(&SP1PublicValues::new())),
+            Err(ProofError::Serialize(_))
+        ));
+    }
+}
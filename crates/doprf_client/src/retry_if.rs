@@ -3,9 +3,11 @@
 
 use again::RetryPolicy;
 use futures::{Future, FutureExt};
+use shared_types::requests::AttemptLog;
 use std::time::Duration;
 
-use crate::error::DoprfError;
+use crate::error::{DoprfError, RetryLimit};
+use crate::instant::{get_now, Instant};
 
 const PHI_OVER_SQRT_5: f64 = 0.72360679775;
 const PHI: f64 = 1.61803398875;
@@ -55,6 +57,35 @@ where
     }
 }
 
+/// Run the given `DoprfError`-returning future until `deadline` (if any).
+/// If the deadline passes first, including before the future is started, the
+/// future is dropped (along with any retries inside it), and a non-retriable
+/// `DoprfError::DeadlineExceeded` with the request's `attempts` is returned.
+pub async fn with_deadline<F, Value>(
+    deadline: Option<Instant>,
+    attempts: &AttemptLog,
+    future: F,
+) -> Result<Value, DoprfError>
+where
+    F: Future<Output = Result<Value, DoprfError>>,
+{
+    let Some(deadline) = deadline else {
+        return future.await;
+    };
+    let exceeded = || DoprfError::deadline_exceeded(RetryLimit::OverallDeadline, attempts);
+    let remaining = deadline.saturating_duration_since(get_now());
+    if remaining.is_zero() {
+        return Err(exceeded());
+    }
+
+    let mut future = Box::pin(future).fuse();
+    let mut delay = futures_timer::Delay::new(remaining).fuse();
+    futures::select_biased! {
+        res = future => res,
+        _ = delay => Err(exceeded()),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1,6 +1,7 @@
 // Copyright 2021-2024 SecureDNA Stiftung (SecureDNA Foundation) <licensing@securedna.org>
 // SPDX-License-Identifier: MIT OR Apache-2.0
 
+use std::num::NonZeroUsize;
 use std::sync::Arc;
 use std::time::Duration;
 
@@ -8,32 +9,94 @@ use futures::stream::FuturesUnordered;
 use futures::TryStreamExt;
 use shared_types::et::WithOtps;
 
-use crate::error::DoprfError;
+use crate::error::{DoprfError, RetryLimit};
+use crate::rate_limit::RateLimiter;
 use crate::retry_if;
 use crate::server_selection::{bad_flag::ServerBadFlag, SelectedHdb, SelectedKeyserver};
 use certificates::{DatabaseTokenGroup, ExemptionTokenGroup, KeyserverTokenGroup, TokenBundle};
 use doprf::party::{KeyserverId, KeyserverIdSet};
 use doprf::prf::{CompletedHashValue, HashPart, Query, VerificationInput};
+use doprf::prove::ExecutionStats;
 use doprf::tagged::TaggedHash;
-use http_client::BaseApiClient;
+use http_client::{BaseApiClient, HttpError};
 use packed_ristretto::PackedRistrettos;
 use scep::states::OpenedClientState;
 use scep_client_helpers::{ClientCerts, ScepClient};
+use shared_types::hash::HashSpec;
 use shared_types::hdb::HdbScreeningResult;
+use shared_types::requests::AttemptLog;
+use shared_types::server_selection::Capabilities;
 use shared_types::synthesis_permission::Region;
 
 #[derive(Clone)]
 pub struct ClientConfig {
+    /// Transport TLS (e.g. a client identity for mTLS gateways) is configured
+    /// when this is built, with [`BaseApiClient::with_tls`].
     pub api_client: BaseApiClient,
+    /// Certificates for SCEP, which authenticate us to the servers themselves.
     pub certs: Arc<ClientCerts>,
     pub version_hint: String,
     pub debug_info: bool,
+    /// Paces the requests sent to each keyserver, if set.
+    pub keyserver_rate_limiter: Option<Arc<RateLimiter>>,
+    /// Abandon a keyserver's response once it's longer than this, and mark
+    /// the keyserver bad, rather than reading however much it sends.
+    pub max_keyserver_response_bytes: Option<u64>,
+    /// Answer keyserver queries with this instead of the keyservers, for tests.
+    pub keyserver_apply: Option<KeyserverApplyFn>,
+    /// Limits how often requests to servers are retried.
+    pub retry_budget: RetryBudget,
 }
 
+/// What's left of a request's `max_total_attempts` for retrying requests to
+/// servers. Every retry is recorded in the request's attempt log, and counts
+/// towards the limit just like starting a new attempt does.
+#[derive(Debug, Clone, Default)]
+pub struct RetryBudget {
+    attempts: AttemptLog,
+    max_total_attempts: Option<NonZeroUsize>,
+}
+
+impl RetryBudget {
+    pub fn new(attempts: AttemptLog, max_total_attempts: Option<NonZeroUsize>) -> Self {
+        Self {
+            attempts,
+            max_total_attempts,
+        }
+    }
+
+    /// Record a retry, or fail with [`DoprfError::DeadlineExceeded`] if the
+    /// limit has been reached.
+    fn claim_retry(&self) -> Result<(), DoprfError> {
+        if let Some(max_total_attempts) = self.max_total_attempts {
+            if self.attempts.total() >= max_total_attempts.get() {
+                return Err(DoprfError::deadline_exceeded(
+                    RetryLimit::MaxTotalAttempts(max_total_attempts),
+                    &self.attempts,
+                ));
+            }
+        }
+        self.attempts.record_retry();
+        Ok(())
+    }
+}
+
+/// Stands in for the keyservers, so tests can drive a whole screen with
+/// keyservers that misbehave in specific ways. Called with each chosen
+/// keyserver's ID and the queries sent to it, and returns its response.
+pub type KeyserverApplyFn =
+    Arc<dyn Fn(KeyserverId, &[Query]) -> PackedRistrettos<HashPart> + Send + Sync>;
+
 pub struct HdbClient {
     client: ScepClient<DatabaseTokenGroup>,
     server: SelectedHdb,
     pub state: OpenedClientState,
+    // What the session was opened with, for `reopen`
+    config: ClientConfig,
+    nucleotide_total_count: u64,
+    keyserver_id_set: KeyserverIdSet,
+    region: Region,
+    with_exemption: bool,
 }
 
 impl HdbClient {
@@ -48,10 +111,10 @@ impl HdbClient {
         with_exemption: bool,
     ) -> Result<Self, DoprfError> {
         let client = ScepClient::<DatabaseTokenGroup>::new(
-            config.api_client,
+            config.api_client.clone(),
             format!("https://{}", server.domain),
-            config.certs,
-            config.version_hint,
+            config.certs.clone(),
+            config.version_hint.clone(),
         );
 
         let state = retry_with_timeout_and_mark_bad(
@@ -68,6 +131,7 @@ impl HdbClient {
                     .await?)
             },
             &server.bad_flag,
+            &config.retry_budget,
         )
         .await?;
 
@@ -75,14 +139,87 @@ impl HdbClient {
             client,
             server,
             state,
+            config,
+            nucleotide_total_count,
+            keyserver_id_set,
+            region,
+            with_exemption,
+        })
+    }
+
+    /// Open a new session with the same HDB and parameters, but for `region`,
+    /// choosing whether it will be screened with exemption tokens.
+    ///
+    /// The HDB tracks sessions with a cookie, so this replaces `self`'s
+    /// session: only query with `self` before calling this.
+    pub async fn reopen_with_exemption(
+        &self,
+        region: Region,
+        with_exemption: bool,
+    ) -> Result<Self, DoprfError> {
+        Self::open(
+            self.server.clone(),
+            self.config.clone(),
+            self.nucleotide_total_count,
+            Some(self.server_version()),
+            self.keyserver_id_set.clone(),
+            region,
+            with_exemption,
+        )
+        .await
+    }
+
+    /// Open another session with the same HDB and parameters, through
+    /// `api_client`, to screen the same hashes as this one under `region`.
+    ///
+    /// It declares no nucleotides: opening this session has already counted
+    /// them towards the client's daily base pair limit, and screening them
+    /// under another region doesn't synthesize any more. Through an API client
+    /// with its own cookies (see [`BaseApiClient::with_own_cookies`]), both
+    /// sessions can be queried at once; otherwise this replaces `self`'s
+    /// session, as [`Self::reopen_with_exemption`] does.
+    pub async fn open_for_region(
+        &self,
+        api_client: BaseApiClient,
+        region: Region,
+    ) -> Result<Self, DoprfError> {
+        let config = ClientConfig {
+            api_client,
+            ..self.config.clone()
+        };
+        let session = Self::open(
+            self.server.clone(),
+            config,
+            0,
+            Some(self.server_version()),
+            self.keyserver_id_set.clone(),
+            region,
+            self.with_exemption,
+        )
+        .await?;
+        Ok(Self {
+            nucleotide_total_count: self.nucleotide_total_count,
+            ..session
         })
     }
 
+    /// The API client the session was opened through.
+    pub fn api_client(&self) -> &BaseApiClient {
+        &self.config.api_client
+    }
+
+    /// Whether the session was opened to be screened with exemption tokens,
+    /// which [`Self::query_with_ets`] requires.
+    pub fn with_exemption(&self) -> bool {
+        self.with_exemption
+    }
+
     /// Post packed `TaggedHash`es to the HDB, and return the HDB response set
     pub async fn query(
-        self,
+        &self,
         hashes: &PackedRistrettos<TaggedHash>,
         hdb_verification_input: VerificationInput,
+        execution_stats: ExecutionStats,
     ) -> Result<HdbScreeningResult, DoprfError> {
         let hash_total_count = hashes
             .len()
@@ -98,12 +235,14 @@ impl HdbClient {
                     .await?)
             },
             &self.server.bad_flag,
+            &self.config.retry_budget,
         ).await?;
 
         // Step 2: Actual screening query (with verification)
         retry_with_timeout_and_mark_bad(
-            || async { Ok(self.client.screen_and_verify(hashes, hdb_verification_input.clone()).await?) },
+            || async { Ok(self.client.screen_and_verify(hashes, hdb_verification_input.clone(), execution_stats).await?) },
             &self.server.bad_flag,
+            &self.config.retry_budget,
         ).await
 
         // // Step 2: Actual screening query (without verification)
@@ -115,7 +254,7 @@ impl HdbClient {
 
     /// Post packed `CompletedHashValue`s to the HDB, and return the HDB response set
     pub async fn query_with_ets(
-        self,
+        &self,
         hashes: &PackedRistrettos<TaggedHash>,
         ets: &[WithOtps<TokenBundle<ExemptionTokenGroup>>],
         et_hashes: PackedRistrettos<CompletedHashValue>,
@@ -133,12 +272,25 @@ impl HdbClient {
                     .await?)
             },
             &self.server.bad_flag,
+            &self.config.retry_budget,
         )
         .await?;
 
         retry_with_timeout_and_mark_bad(
             || async { Ok(self.client.screen_with_ets(hashes, ets, &et_hashes).await?) },
             &self.server.bad_flag,
+            &self.config.retry_budget,
+        )
+        .await
+    }
+
+    /// Authenticate without sending any hashes, to check that the HDB accepts
+    /// our certs (including against its revocation list).
+    pub async fn preflight(self) -> Result<(), DoprfError> {
+        retry_with_timeout_and_mark_bad(
+            || async { Ok(self.client.authenticate(self.state.clone(), 0).await?) },
+            &self.server.bad_flag,
+            &self.config.retry_budget,
         )
         .await
     }
@@ -150,12 +302,26 @@ impl HdbClient {
     pub fn server_version(&self) -> u64 {
         self.state.server_version
     }
+
+    /// The region this session screens under.
+    pub fn region(&self) -> Region {
+        self.region
+    }
+
+    /// The content types and encodings the HDB advertised when it was
+    /// selected, to negotiate against what this client supports.
+    pub fn capabilities(&self) -> &Capabilities {
+        &self.server.capabilities
+    }
 }
 
 pub struct KeyserverClient {
     client: ScepClient<KeyserverTokenGroup>,
     server: SelectedKeyserver,
     state: OpenedClientState,
+    rate_limiter: Option<Arc<RateLimiter>>,
+    max_response_bytes: Option<u64>,
+    retry_budget: RetryBudget,
 }
 
 impl KeyserverClient {
@@ -173,8 +339,11 @@ impl KeyserverClient {
             config.version_hint,
         );
 
+        let rate_limiter = config.keyserver_rate_limiter;
+        let retry_budget = config.retry_budget;
         let state = retry_with_timeout_and_mark_bad(
             || async {
+                pace(&rate_limiter, &server.domain).await;
                 Ok(client
                     .open(
                         nucleotide_total_count,
@@ -186,6 +355,7 @@ impl KeyserverClient {
                     .await?)
             },
             &server.bad_flag,
+            &retry_budget,
         )
         .await?;
 
@@ -193,29 +363,86 @@ impl KeyserverClient {
             client,
             server,
             state,
+            rate_limiter,
+            max_response_bytes: config.max_keyserver_response_bytes,
+            retry_budget,
         })
     }
 
+    /// Wait for our turn to send a request, if this keyserver is rate limited.
+    async fn pace(&self) {
+        pace(&self.rate_limiter, &self.server.domain).await;
+    }
+
     /// Post packed `Query`s to the given keyserver, and return the response of packed `HashPart`s
     pub async fn query(
         self,
         hash_total_count: u64,
         queries: &PackedRistrettos<Query>,
     ) -> Result<PackedRistrettos<HashPart>, DoprfError> {
+        self.authenticate(hash_total_count).await?;
+        self.keyserve(queries).await
+    }
+
+    /// Authenticate for `hash_total_count` hashes, giving the keyserver the
+    /// chance to reject a request it won't serve before any queries are sent.
+    pub async fn authenticate(&self, hash_total_count: u64) -> Result<(), DoprfError> {
         retry_with_timeout_and_mark_bad(
             || async {
+                self.pace().await;
                 Ok(self
                     .client
                     .authenticate(self.state.clone(), hash_total_count)
                     .await?)
             },
             &self.server.bad_flag,
+            &self.retry_budget,
         )
-        .await?;
+        .await
+    }
+
+    /// Post packed `Query`s to the keyserver, once authenticated, and return
+    /// the response of packed `HashPart`s
+    ///
+    /// A response longer than the configured `max_keyserver_response_bytes`
+    /// isn't retried, but does mark the keyserver bad.
+    pub async fn keyserve(
+        &self,
+        queries: &PackedRistrettos<Query>,
+    ) -> Result<PackedRistrettos<HashPart>, DoprfError> {
+        let result = retry_with_timeout_and_mark_bad(
+            || async {
+                self.pace().await;
+                Ok(self
+                    .client
+                    .keyserve_with_limit(queries, self.max_response_bytes)
+                    .await?)
+            },
+            &self.server.bad_flag,
+            &self.retry_budget,
+        )
+        .await;
+        if let Err(DoprfError::HttpError(HttpError::ResponseTooLarge { .. })) = result {
+            self.server.bad_flag.mark_bad();
+        }
+        result
+    }
 
+    /// The hash spec the keyserver reported when the session was opened.
+    pub fn hash_spec(&self) -> &HashSpec {
+        &self.state.hash_spec
+    }
+
+    /// Authenticate without sending any queries, to check that the keyserver
+    /// accepts our certs (including against its revocation list).
+    pub async fn preflight(self) -> Result<(), DoprfError> {
         retry_with_timeout_and_mark_bad(
-            || async { Ok(self.client.keyserve(queries).await?) },
+            || async {
+                self.pace().await;
+                Ok(self.client.authenticate(self.state.clone(), 0).await?)
+            },
             &self.server.bad_flag,
+            &self.retry_budget,
         )
         .await
     }
@@ -229,18 +456,38 @@ impl KeyserverClient {
     }
 }
 
+/// Wait for our turn to send a request to `domain`, if there's a `rate_limiter`.
+async fn pace(rate_limiter: &Option<Arc<RateLimiter>>, domain: &str) {
+    if let Some(rate_limiter) = rate_limiter {
+        rate_limiter.wait(domain).await;
+    }
+}
+
 pub struct KeyserverSetClient {
     clients: Vec<KeyserverClient>,
+    /// Set instead of `clients` if the [`ClientConfig`] had a `keyserver_apply`.
+    apply: Option<(Vec<KeyserverId>, KeyserverApplyFn)>,
 }
 
 impl KeyserverSetClient {
     /// Open an SCEP session with all keyservers in parallel
+    ///
+    /// If the [`ClientConfig`] had a `keyserver_apply`, no sessions are opened,
+    /// and it answers the queries for each keyserver instead.
     pub async fn open(
         servers: impl IntoIterator<Item = (SelectedKeyserver, Option<u64>)>,
         config: ClientConfig,
         nucleotide_total_count: u64,
         keyserver_id_set: KeyserverIdSet,
     ) -> Result<Self, DoprfError> {
+        if let Some(apply) = config.keyserver_apply {
+            let ids = servers.into_iter().map(|(s, _)| s.id).collect();
+            return Ok(Self {
+                clients: vec![],
+                apply: Some((ids, apply)),
+            });
+        }
+
         let clients = servers
             .into_iter()
             .map(|(s, last_server_version)| {
@@ -255,25 +502,52 @@ impl KeyserverSetClient {
             .collect::<FuturesUnordered<_>>()
             .try_collect()
             .await?;
-        Ok(Self { clients })
+        Ok(Self {
+            clients,
+            apply: None,
+        })
     }
 
     /// Query all keyservers in parallel, returning an error on first failure
+    ///
+    /// If the [`ClientConfig`] had a `keyserver_rate_limiter`, each request
+    /// waits for its turn with that keyserver, so the fan-out is paced, as is
+    /// opening the sessions in [`Self::open`].
+    ///
+    /// Every keyserver authenticates for `hash_total_count` hashes before any
+    /// is sent queries, so that a keyserver that won't serve a request this
+    /// large rejects it before the others have applied their keyshares.
     pub async fn query(
         self,
         hash_total_count: u64,
         queries: &PackedRistrettos<Query>,
     ) -> Result<Vec<(KeyserverId, PackedRistrettos<HashPart>)>, DoprfError> {
+        if queries.len() as u64 != hash_total_count {
+            return Err(DoprfError::QueryCountMismatch {
+                hash_total_count,
+                queries: queries.len(),
+            });
+        }
+
+        if let Some((ids, apply)) = &self.apply {
+            let queries = queries.iter_decoded().collect::<Result<Vec<Query>, _>>()?;
+            return Ok(ids.iter().map(|&id| (id, apply(id, &queries))).collect());
+        }
+
         self.clients
-            .into_iter()
-            .map(|client| {
-                let client_id = client.server.id;
-                async move {
-                    client
-                        .query(hash_total_count, queries)
-                        .await
-                        .map(|hash_parts| (client_id, hash_parts))
-                }
+            .iter()
+            .map(|client| client.authenticate(hash_total_count))
+            .collect::<FuturesUnordered<_>>()
+            .try_collect::<Vec<()>>()
+            .await?;
+
+        self.clients
+            .iter()
+            .map(|client| async move {
+                client
+                    .keyserve(queries)
+                    .await
+                    .map(|hash_parts| (client.server.id, hash_parts))
             })
             .collect::<FuturesUnordered<_>>()
             .try_collect()
@@ -288,9 +562,13 @@ impl KeyserverSetClient {
 /// Helper for hdb and keyserver api clients: retry the given future with our
 /// retry and timeout schedule, and mark the server error flag if we don't get a
 /// response within the given number of retries.
+///
+/// Each retry is taken out of `retry_budget`. Once that runs out, no more
+/// retries are made, and [`DoprfError::DeadlineExceeded`] is returned.
 async fn retry_with_timeout_and_mark_bad<Fut, Val>(
     mut mk_future: impl FnMut() -> Fut,
     server_bad_flag: &ServerBadFlag,
+    retry_budget: &RetryBudget,
 ) -> Result<Val, DoprfError>
 where
     Fut: futures::Future<Output = Result<Val, DoprfError>>,
@@ -303,7 +581,7 @@ where
 
     // we want to return the first error we get, since with SCEP sessions that's most likely
     // the root cause (since SCEP drops the session on error, retrying an SCEP-originated
-    // error leads to a 400 unknown cookie error, which isn't helpful.)
+    // error leads to a 440 unknown cookie error, which isn't helpful.)
     //
     // However, if the first try succeeded, or the first try resulted in a non-retriable error,
     // we should immediately return.
@@ -313,34 +591,44 @@ where
     };
 
     let mut skip_try = true;
+    let mut budget_err = None;
     let mk_future = || {
         if skip_try {
             // account for the first try we already did
             skip_try = false;
-            futures::future::Either::Left(async { Err(true) })
-        } else {
-            let future = mk_future();
-            futures::future::Either::Right(async { future.await.map_err(|e| e.is_retriable()) })
+            return futures::future::Either::Left(futures::future::ready(Err(true)));
+        }
+        if let Err(e) = retry_budget.claim_retry() {
+            budget_err = Some(e);
+            return futures::future::Either::Left(futures::future::ready(Err(false)));
         }
+        let future = mk_future();
+        futures::future::Either::Right(async { future.await.map_err(|e| e.is_retriable()) })
     };
 
-    policy
+    let result = policy
         .retry_if(mk_future, |is_retriable: &bool| *is_retriable)
-        .await
-        .map_err(|_| {
+        .await;
+    result.map_err(|_| match budget_err {
+        Some(budget_err) => budget_err,
+        None => {
             server_bad_flag.mark_bad();
             first_err
-        })
+        }
+    })
 }
 
 #[cfg(test)]
 mod tests {
+    use std::num::NonZeroUsize;
     use std::sync::Arc;
 
     use http_client::HttpError;
+    use shared_types::requests::{AttemptLog, AttemptServers};
 
     use crate::{
-        error::DoprfError, scep_client::retry_with_timeout_and_mark_bad,
+        error::{DoprfError, RetryLimit},
+        scep_client::{retry_with_timeout_and_mark_bad, RetryBudget},
         server_selection::bad_flag::ServerBadFlag,
     };
 
@@ -364,9 +652,13 @@ mod tests {
 
         let server_bad_flag = ServerBadFlag::default();
 
-        let ctx = match retry_with_timeout_and_mark_bad(mk_future, &server_bad_flag)
-            .await
-            .unwrap_err()
+        let ctx = match retry_with_timeout_and_mark_bad(
+            mk_future,
+            &server_bad_flag,
+            &RetryBudget::default(),
+        )
+        .await
+        .unwrap_err()
         {
             DoprfError::HttpError(HttpError::RequestError {
                 ctx,
@@ -382,6 +674,47 @@ mod tests {
         assert_eq!(ctx, "error #1");
     }
 
+    #[tokio::test]
+    async fn test_retry_stops_once_budget_is_spent() {
+        let state = Arc::new(tokio::sync::Mutex::new(0u8));
+
+        let mk_future = || async {
+            *state.lock().await += 1;
+            Result::<(), _>::Err(DoprfError::HttpError(
+                http_client::HttpError::RequestError {
+                    ctx: "".into(),
+                    status: Some(503),
+                    retriable: true,
+                    source: "unavailable".into(),
+                },
+            ))
+        };
+
+        // One attempt has already started, so two retries are left.
+        let attempts = AttemptLog::default();
+        attempts.record(AttemptServers {
+            keyservers: vec![],
+            hdb: "hdb".into(),
+        });
+        let retry_budget = RetryBudget::new(attempts.clone(), NonZeroUsize::new(3));
+        let server_bad_flag = ServerBadFlag::default();
+
+        let err = retry_with_timeout_and_mark_bad(mk_future, &server_bad_flag, &retry_budget)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            DoprfError::DeadlineExceeded {
+                limit: RetryLimit::MaxTotalAttempts(_),
+                ..
+            }
+        ));
+        assert_eq!(*state.lock().await, 3);
+        assert_eq!(attempts.total(), 3);
+        // Running out of retries isn't the server's fault.
+        assert!(!server_bad_flag.is_bad());
+    }
+
     #[tokio::test]
     async fn test_retry_non_retriable_err() {
         let state = Arc::new(tokio::sync::Mutex::new(0u8));
@@ -402,9 +735,13 @@ mod tests {
 
         let server_bad_flag = ServerBadFlag::default();
 
-        let ctx = match retry_with_timeout_and_mark_bad(mk_future, &server_bad_flag)
-            .await
-            .unwrap_err()
+        let ctx = match retry_with_timeout_and_mark_bad(
+            mk_future,
+            &server_bad_flag,
+            &RetryBudget::default(),
+        )
+        .await
+        .unwrap_err()
         {
             DoprfError::HttpError(HttpError::RequestError {
                 ctx,
@@ -432,9 +769,10 @@ mod tests {
 
         let server_bad_flag = ServerBadFlag::default();
 
-        let v = retry_with_timeout_and_mark_bad(mk_future, &server_bad_flag)
-            .await
-            .unwrap();
+        let v =
+            retry_with_timeout_and_mark_bad(mk_future, &server_bad_flag, &RetryBudget::default())
+                .await
+                .unwrap();
 
         // should have only tried once
         assert_eq!(v, 1);
@@ -461,9 +799,10 @@ mod tests {
 
         let server_bad_flag = ServerBadFlag::default();
 
-        let v = retry_with_timeout_and_mark_bad(mk_future, &server_bad_flag)
-            .await
-            .unwrap();
+        let v =
+            retry_with_timeout_and_mark_bad(mk_future, &server_bad_flag, &RetryBudget::default())
+                .await
+                .unwrap();
 
         // should have tried twice
         assert_eq!(v, 2);
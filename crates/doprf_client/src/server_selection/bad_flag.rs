@@ -1,30 +1,158 @@
 // Copyright 2021-2024 SecureDNA Stiftung (SecureDNA Foundation) <licensing@securedna.org>
 // SPDX-License-Identifier: MIT OR Apache-2.0
 
-use std::sync::{atomic, Arc};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::instant::{get_now, Instant};
+
+/// How long a server that errored is excluded from selection.
+///
+/// The first failure excludes the server for `base_cooldown`, and each consecutive failure
+/// doubles that, up to `max_cooldown`. Once the cooldown is over the server is chosen again,
+/// which probes whether it has recovered. Each `decay_period` without a failure forgives one
+/// failure, so a server that recovered starts over from `base_cooldown` when it next fails.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BadServerPenalty {
+    pub base_cooldown: Duration,
+    pub max_cooldown: Duration,
+    pub decay_period: Duration,
+}
+
+impl BadServerPenalty {
+    fn cooldown(&self, failures: u32) -> Duration {
+        let factor = 2u32.saturating_pow(failures.saturating_sub(1));
+        self.base_cooldown
+            .saturating_mul(factor)
+            .min(self.max_cooldown)
+    }
+
+    fn forgiven(&self, since_last_failure: Duration) -> u32 {
+        since_last_failure
+            .as_nanos()
+            .checked_div(self.decay_period.as_nanos())
+            .map_or(u32::MAX, |n| n.try_into().unwrap_or(u32::MAX))
+    }
+}
+
+/// A server's current penalty, as reported in a [`super::SelectionStatus`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServerPenalty {
+    /// Consecutive failures not yet forgiven by [`BadServerPenalty::decay_period`].
+    pub failures: u32,
+    /// How much longer the server is excluded for. `None` if it's excluded until the
+    /// selection is refreshed or an operator marks it good.
+    pub remaining: Option<Duration>,
+}
 
 /// Whether this server has been marked bad due to an error, and shouldn't be used.
-/// Can only be set, to unset, the server must be re-qualified in a new round of server selection.
+///
+/// Without a [`BadServerPenalty`], errors only ever set it; to unset, the server must be
+/// re-qualified in a new round of server selection, or an operator must explicitly mark it good
+/// (see [`ServerBadFlag::mark_good`]). With one, the server is excluded only for its cooldown.
 ///
 /// This flag is internally mutable, inside an `Arc` so that clones preserve the reference to the same flag.
 /// We want internal mutability so that flagging a single server as bad does not require waiting for all *reads*
 /// of the server selection to be finished. We're fine with flagging the servers used by an in-progress request
-/// as bad, since badness is a ratchet.
+/// as bad, since the worst that can happen is that the request fails and is retried.
 #[derive(Default, Debug, Clone)]
-pub struct ServerBadFlag(Arc<atomic::AtomicBool>);
+pub struct ServerBadFlag(Arc<Mutex<BadState>>);
+
+#[derive(Default, Debug)]
+struct BadState {
+    penalty: Option<BadServerPenalty>,
+    /// Marked bad by an operator, which lasts until they mark it good.
+    manual: bool,
+    failures: u32,
+    last_failure: Option<Instant>,
+}
+
+impl BadState {
+    fn failures_at(&self, now: Instant) -> u32 {
+        match (&self.penalty, self.last_failure) {
+            (Some(penalty), Some(last_failure)) => self
+                .failures
+                .saturating_sub(penalty.forgiven(now.saturating_duration_since(last_failure))),
+            _ => self.failures,
+        }
+    }
 
-// we're a bit cavalier here about loading the atomic bool into a regular bool--this should
-// be fine, since the worst that can happen on a slightly out-of-date read is that a request
-// is made to a bad server, which will just fail and cause a retry. it will never cause a bad
-// server to be marked good, since ServerBadFlag can't be unmarked.
+    fn penalty_at(&self, now: Instant) -> Option<ServerPenalty> {
+        let failures = self.failures_at(now);
+        let remaining = match (&self.penalty, self.last_failure) {
+            _ if self.manual => None,
+            (Some(penalty), Some(last_failure)) => {
+                let until = last_failure + penalty.cooldown(self.failures);
+                let remaining = until.saturating_duration_since(now);
+                if remaining.is_zero() && failures == 0 {
+                    return None;
+                }
+                Some(remaining)
+            }
+            (None, Some(_)) => None,
+            (_, None) => return None,
+        };
+        Some(ServerPenalty {
+            failures,
+            remaining,
+        })
+    }
+}
 
 impl ServerBadFlag {
+    /// A flag that excludes the server for a cooldown after each error, rather than until
+    /// the selection is refreshed, if `penalty` is given.
+    pub fn with_penalty(penalty: Option<BadServerPenalty>) -> Self {
+        Self(Arc::new(Mutex::new(BadState {
+            penalty,
+            ..Default::default()
+        })))
+    }
+
+    /// Record that the server errored.
     pub fn mark_bad(&self) {
-        self.0.store(true, atomic::Ordering::SeqCst);
+        self.mark_bad_at(get_now())
+    }
+
+    /// Like [`Self::mark_bad`], with the failure happening at `now`.
+    pub fn mark_bad_at(&self, now: Instant) {
+        let mut state = self.0.lock().unwrap();
+        state.failures = state.failures_at(now).saturating_add(1);
+        state.last_failure = Some(now);
+    }
+
+    /// Manually mark the server bad, e.g. for maintenance. Unlike errors, this never cools
+    /// down, and lasts until the server is marked good.
+    pub fn mark_bad_manually(&self) {
+        self.0.lock().unwrap().manual = true;
+    }
+
+    /// Manually override badness, e.g. once an operator knows the server has recovered.
+    pub fn mark_good(&self) {
+        let mut state = self.0.lock().unwrap();
+        state.manual = false;
+        state.failures = 0;
+        state.last_failure = None;
     }
 
     pub fn is_bad(&self) -> bool {
-        self.0.load(atomic::Ordering::SeqCst)
+        self.is_bad_at(get_now())
+    }
+
+    /// Whether the server is excluded at `now`.
+    pub fn is_bad_at(&self, now: Instant) -> bool {
+        self.penalty_at(now)
+            .is_some_and(|penalty| penalty.remaining != Some(Duration::ZERO))
+    }
+
+    /// The server's current penalty, if it has failed recently or been marked bad.
+    pub fn penalty(&self) -> Option<ServerPenalty> {
+        self.penalty_at(get_now())
+    }
+
+    /// Like [`Self::penalty`], as of `now`.
+    pub fn penalty_at(&self, now: Instant) -> Option<ServerPenalty> {
+        self.0.lock().unwrap().penalty_at(now)
     }
 }
 
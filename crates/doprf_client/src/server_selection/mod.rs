@@ -4,7 +4,7 @@
 use std::{
     collections::{HashMap, HashSet},
     fmt,
-    sync::Arc,
+    sync::{Arc, Mutex},
     time::Duration,
 };
 
@@ -22,7 +22,8 @@ use crate::{
 use doprf::{active_security::ActiveSecurityKey, party::KeyserverId};
 use http_client::BaseApiClient;
 use shared_types::server_selection::{
-    HdbQualificationResponse, KeyserverQualificationResponse, QualificationRequest, Role, Tier,
+    Capabilities, HdbQualificationResponse, KeyserverQualificationResponse, QualificationRequest,
+    Role, Tier,
 };
 
 pub mod bad_flag;
@@ -46,12 +47,23 @@ pub struct ServerSelectionConfig {
     /// If None, a soft refresh will never be triggered based on a lack of good keyservers (a hard refresh will
     /// still be triggered if there aren't enough keyservers to meet quorum.)
     pub soft_extra_keyserver_threshold: Option<u32>,
+    /// Unlike `soft_extra_keyserver_threshold`, a hard minimum on the number of keyservers with at
+    /// least one good replica. A selection with fewer is never used, and refreshing fails with
+    /// [`ServerSelectionError::NoQuorum`] if no generation has this many keyservers. Values below
+    /// the keyserver threshold have no effect. If None, only the threshold is required.
+    pub min_keyservers: Option<u32>,
     /// A soft (background) refresh will be triggered if there are fewer good HDBs in a selection
     /// than this value + 1. For example, if this value was 2, then a soft refresh would be triggered
     /// if there was less than 3 good HDBs.
     /// If None, a soft refresh will never be triggered based on a lack of good keyservers (a hard refresh will
     /// still be triggered if there aren't enough keyservers to meet quorum.)
     pub soft_extra_hdb_threshold: Option<u32>,
+    /// How long a server that errored is excluded for before it's tried again. If None, it's excluded
+    /// until the selection is next refreshed.
+    pub bad_server_penalty: Option<bad_flag::BadServerPenalty>,
+    /// After a refresh fails, how long further refreshes return that same failure instead of
+    /// enumerating servers again. If None, every refresh re-enumerates.
+    pub refresh_failure_backoff: Option<Duration>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -145,8 +157,14 @@ impl ServerSelection {
     /// Choose a subset from the current selection.
     ///
     /// This returns None if not enough good (not marked bad due to returning errors) servers
-    /// are available. In that case, the selection must be refreshed.
-    fn choose(&self) -> Option<ChosenSelectionSubset> {
+    /// are available, including if fewer than `min_keyservers` keyservers are. In that case, the
+    /// selection must be refreshed.
+    fn choose(&self, min_keyservers: Option<u32>) -> Option<ChosenSelectionSubset> {
+        if let Some(min_keyservers) = min_keyservers {
+            if self.available_keyservers() < min_keyservers as usize {
+                return None;
+            }
+        }
         let keyservers = self.choose_n_keyservers()?.into_iter().cloned().collect();
         let hdb = self.choose_hdb()?.clone();
         Some(ChosenSelectionSubset {
@@ -184,6 +202,8 @@ pub struct SelectedKeyserver {
     pub domain: String,
     /// Whether this server has been marked bad, and shouldn't be selected.
     pub bad_flag: bad_flag::ServerBadFlag,
+    /// What this server advertised in its qualification response.
+    pub capabilities: Capabilities,
 }
 
 impl fmt::Display for SelectedKeyserver {
@@ -204,6 +224,8 @@ pub struct SelectedHdb {
     pub domain: String,
     /// Whether this server has been marked bad, and shouldn't be selected.
     pub bad_flag: bad_flag::ServerBadFlag,
+    /// What this server advertised in its qualification response.
+    pub capabilities: Capabilities,
 }
 
 impl fmt::Display for SelectedHdb {
@@ -217,6 +239,50 @@ impl fmt::Display for SelectedHdb {
     }
 }
 
+/// Whether a server is considered usable for new requests.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ServerHealth {
+    Good,
+    Bad,
+}
+
+impl ServerHealth {
+    fn of(bad_flag: &bad_flag::ServerBadFlag) -> Self {
+        if bad_flag.is_bad() {
+            Self::Bad
+        } else {
+            Self::Good
+        }
+    }
+}
+
+/// The state of a single server in a [`SelectionStatus`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServerStatus {
+    /// Domain this server lives on
+    pub domain: String,
+    /// ID of this server, if it's a keyserver
+    pub keyserver_id: Option<KeyserverId>,
+    pub health: ServerHealth,
+    /// Why the server is (or was recently) considered bad, if it is.
+    pub penalty: Option<bad_flag::ServerPenalty>,
+}
+
+/// A snapshot of a [`ServerSelector`]'s current selection, for inspection by operators.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SelectionStatus {
+    /// What db/keyserver generation the selection landed on
+    pub generation: u32,
+    /// Number of keyservers that need to be contacted
+    pub keyserver_threshold: u32,
+    /// All keyservers (including replicas) in the selection, ordered by id
+    pub keyservers: Vec<ServerStatus>,
+    /// All hdbs in the selection
+    pub hdbs: Vec<ServerStatus>,
+    /// How long ago the selection was made
+    pub age: Duration,
+}
+
 #[derive(Debug)]
 pub struct ServerSelector {
     /// Config for generating and refreshing the current selection
@@ -225,6 +291,8 @@ pub struct ServerSelector {
     api_client: BaseApiClient,
     /// The current selection and selection time
     current: refreshable::Refreshable<(Arc<ServerSelection>, Instant)>,
+    /// The most recent refresh failure and when it happened, cleared by a successful refresh
+    last_refresh_failure: Mutex<Option<(Instant, ServerSelectionError)>>,
 }
 
 impl ServerSelector {
@@ -237,6 +305,7 @@ impl ServerSelector {
             config,
             api_client,
             current: refreshable::Refreshable::new((Arc::new(selection), get_now())),
+            last_refresh_failure: Mutex::new(None),
         })
     }
 
@@ -251,14 +320,12 @@ impl ServerSelector {
                     if self.needs_blocking_refresh_for_time(time) {
                         return None;
                     }
-                    let choice = selection.choose()?;
+                    let choice = selection.choose(self.config.min_keyservers)?;
                     Some((choice, selection, time))
                 },
                 || async {
                     info!("starting blocking refresh");
-                    server_selection(&self.config, &self.api_client)
-                        .await
-                        .map(|selection| (Arc::new(selection), get_now()))
+                    self.refresh().await
                 },
             )
             .await?;
@@ -273,14 +340,7 @@ impl ServerSelector {
             {
                 let this = self.clone();
                 tokio::spawn(async move {
-                    let r = this
-                        .current
-                        .background_refresh(|| async {
-                            server_selection(&this.config, &this.api_client)
-                                .await
-                                .map(|selection| (Arc::new(selection), get_now()))
-                        })
-                        .await;
+                    let r = this.current.background_refresh(|| this.refresh()).await;
                     if let Err(e) = r {
                         info!("error during background refresh: {e}");
                     }
@@ -291,6 +351,91 @@ impl ServerSelector {
         Ok(choice)
     }
 
+    /// Inspect the current selection, without triggering a refresh.
+    pub fn status(&self) -> SelectionStatus {
+        let (selection, time) = self.current.current();
+        let mut keyservers: Vec<_> = selection.keyservers.values().flatten().collect();
+        keyservers.sort_by(|a, b| (a.id, &a.domain).cmp(&(b.id, &b.domain)));
+        SelectionStatus {
+            generation: selection.generation,
+            keyserver_threshold: selection.keyserver_threshold,
+            keyservers: keyservers
+                .into_iter()
+                .map(|ks| ServerStatus {
+                    domain: ks.domain.clone(),
+                    keyserver_id: Some(ks.id),
+                    health: ServerHealth::of(&ks.bad_flag),
+                    penalty: ks.bad_flag.penalty(),
+                })
+                .collect(),
+            hdbs: selection
+                .hdbs
+                .iter()
+                .map(|hdb| ServerStatus {
+                    domain: hdb.domain.clone(),
+                    keyserver_id: None,
+                    health: ServerHealth::of(&hdb.bad_flag),
+                    penalty: hdb.bad_flag.penalty(),
+                })
+                .collect(),
+            age: time.elapsed(),
+        }
+    }
+
+    /// Manually mark every server on `domain` in the current selection as good or bad,
+    /// affecting subsequent calls to [`Self::choose`].
+    ///
+    /// The mark only lasts until the selection is next refreshed, at which point servers are
+    /// re-qualified. Returns false if no server in the current selection is on `domain`.
+    pub fn mark_server(&self, domain: &str, health: ServerHealth) -> bool {
+        let (selection, _) = self.current.current();
+        let keyserver_flags = selection
+            .keyservers
+            .values()
+            .flatten()
+            .map(|ks| (&ks.domain, &ks.bad_flag));
+        let hdb_flags = selection
+            .hdbs
+            .iter()
+            .map(|hdb| (&hdb.domain, &hdb.bad_flag));
+
+        let mut found = false;
+        for (_, bad_flag) in keyserver_flags
+            .chain(hdb_flags)
+            .filter(|(d, _)| *d == domain)
+        {
+            match health {
+                ServerHealth::Good => bad_flag.mark_good(),
+                ServerHealth::Bad => bad_flag.mark_bad_manually(),
+            }
+            found = true;
+        }
+        if found {
+            info!("{domain} manually marked {health:?}");
+        }
+        found
+    }
+
+    /// Run the selection algorithm for a new selection, unless a refresh failed within
+    /// `refresh_failure_backoff`, in which case that failure is returned again.
+    async fn refresh(&self) -> Result<(Arc<ServerSelection>, Instant), ServerSelectionError> {
+        if let Some(backoff) = self.config.refresh_failure_backoff {
+            if let Some((failed_at, error)) = &*self.last_refresh_failure.lock().unwrap() {
+                if *failed_at + backoff > get_now() {
+                    info!("server selection: backing off after recent failure: {error}");
+                    return Err(error.clone());
+                }
+            }
+        }
+
+        let result = server_selection(&self.config, &self.api_client).await;
+        *self.last_refresh_failure.lock().unwrap() = result
+            .as_ref()
+            .err()
+            .map(|error| (get_now(), error.clone()));
+        result.map(|selection| (Arc::new(selection), get_now()))
+    }
+
     fn needs_soft_refresh_for_time(&self, last_selection: Instant) -> bool {
         let Some(soft_timeout) = self.config.soft_timeout else {
             return false;
@@ -327,7 +472,7 @@ impl ServerSelector {
     }
 }
 
-#[derive(Debug, thiserror::Error)]
+#[derive(Debug, Clone, thiserror::Error)]
 pub enum ServerSelectionError {
     #[error("failed to qualify enumerated servers: {0}")]
     Qualification(String),
@@ -387,8 +532,13 @@ pub async fn server_selection(
         .collect::<Vec<_>>();
 
     // run the selection algorithm
-    let selection =
-        do_server_selection(keyservers, hdbs).map_err(ServerSelectionError::NoQuorum)?;
+    let selection = do_server_selection(
+        keyservers,
+        hdbs,
+        config.min_keyservers,
+        &config.bad_server_penalty,
+    )
+    .map_err(ServerSelectionError::NoQuorum)?;
     Ok(selection)
 }
 
@@ -480,12 +630,20 @@ async fn qualify_one<D: DeserializeOwned>(
 fn do_server_selection(
     keyservers: Vec<(String, KeyserverQualificationResponse)>,
     hdbs: Vec<(String, HdbQualificationResponse)>,
+    min_keyservers: Option<u32>,
+    bad_server_penalty: &Option<bad_flag::BadServerPenalty>,
 ) -> Result<ServerSelection, Vec<u32>> {
     let known_generations = find_available_generations(&keyservers, &hdbs);
     info!("server selection: found generations {known_generations:?}");
 
     for generation in known_generations.iter().copied() {
-        match try_server_selection_for_generation(generation, &keyservers, &hdbs) {
+        match try_server_selection_for_generation(
+            generation,
+            &keyservers,
+            &hdbs,
+            min_keyservers,
+            bad_server_penalty,
+        ) {
             Ok(selection) => {
                 info!("server selection: found quorum on generation {generation}",);
                 return Ok(selection);
@@ -537,6 +695,12 @@ enum GenerationSelectionError {
         threshold: u32,
         keyserver_count: u32,
     },
+    #[error("for generation {generation}, at least {min_keyservers} keyservers are required, but only {keyserver_count} were found")]
+    NotEnoughKeyserversForMinimum {
+        generation: u32,
+        min_keyservers: u32,
+        keyserver_count: u32,
+    },
     #[error("could not select active security key for generation {generation}, error: {error}, received these values and counts {:?}", active_security_key_occurances)]
     NoValidActiveSecurityKey {
         generation: u32,
@@ -549,7 +713,11 @@ fn try_server_selection_for_generation(
     generation: u32,
     keyservers: &[(String, KeyserverQualificationResponse)],
     hdbs: &[(String, HdbQualificationResponse)],
+    min_keyservers: Option<u32>,
+    bad_server_penalty: &Option<bad_flag::BadServerPenalty>,
 ) -> Result<ServerSelection, GenerationSelectionError> {
+    let new_bad_flag = || bad_flag::ServerBadFlag::with_penalty(bad_server_penalty.clone());
+
     // first, extract the threshold from the responses, making sure all the keyservers agree
     let threshold = {
         let found_thresholds = keyservers
@@ -586,7 +754,8 @@ fn try_server_selection_for_generation(
                     .push(SelectedKeyserver {
                         id: q.id,
                         domain: domain.clone(),
-                        bad_flag: Default::default(),
+                        bad_flag: new_bad_flag(),
+                        capabilities: q.capabilities.clone(),
                     });
                 *active_security_key_occurances
                     .entry(key_info.active_security_key.clone())
@@ -606,6 +775,17 @@ fn try_server_selection_for_generation(
         });
     }
 
+    // and the operator's minimum, if they set one above the threshold
+    if let Some(min_keyservers) = min_keyservers {
+        if selected_keyservers.len() < min_keyservers as usize {
+            return Err(GenerationSelectionError::NotEnoughKeyserversForMinimum {
+                generation,
+                min_keyservers,
+                keyserver_count: selected_keyservers.len().try_into().unwrap(),
+            });
+        }
+    }
+
     let active_security_key =
         select_active_security_key(&active_security_key_occurances, threshold).map_err(|err| {
             GenerationSelectionError::NoValidActiveSecurityKey {
@@ -622,7 +802,8 @@ fn try_server_selection_for_generation(
             if q.supported_generations.contains(&generation) {
                 Some(SelectedHdb {
                     domain: domain.clone(),
-                    bad_flag: Default::default(),
+                    bad_flag: new_bad_flag(),
+                    capabilities: q.capabilities.clone(),
                 })
             } else {
                 None
@@ -729,6 +910,7 @@ pub mod test_utils {
                 id,
                 domain: domain.to_owned(),
                 bad_flag: Default::default(),
+                capabilities: Default::default(),
             });
         }
 
@@ -737,6 +919,7 @@ pub mod test_utils {
             .map(|&domain| SelectedHdb {
                 domain: domain.to_owned(),
                 bad_flag: Default::default(),
+                capabilities: Default::default(),
             })
             .collect();
 
@@ -765,6 +948,7 @@ pub mod test_utils {
             config,
             api_client,
             current: refreshable::Refreshable::new((Arc::new(selection), selection_time)),
+            last_refresh_failure: Mutex::new(None),
         }
     }
 
@@ -860,6 +1044,8 @@ mod tests {
                         ]
                         .into_iter()
                         .collect(),
+                        max_client_version: 0,
+                        capabilities: Default::default(),
                     },
                 ),
                 (
@@ -873,6 +1059,8 @@ mod tests {
                         ]
                         .into_iter()
                         .collect(),
+                        max_client_version: 0,
+                        capabilities: Default::default(),
                     },
                 ),
                 (
@@ -886,6 +1074,8 @@ mod tests {
                         ]
                         .into_iter()
                         .collect(),
+                        max_client_version: 0,
+                        capabilities: Default::default(),
                     },
                 ),
             ],
@@ -893,8 +1083,11 @@ mod tests {
                 "1.db.prod.securedna.org".into(),
                 HdbQualificationResponse {
                     supported_generations: vec![0, 1],
+                    capabilities: Default::default(),
                 },
             )],
+            None,
+            &None,
         )
         .unwrap();
 
@@ -910,6 +1103,7 @@ mod tests {
                             id: KeyserverId::try_from(1).unwrap(),
                             domain: "1.ks.prod.securedna.org".into(),
                             bad_flag: Default::default(),
+                            capabilities: Default::default(),
                         }]
                     ),
                     (
@@ -919,11 +1113,13 @@ mod tests {
                                 id: KeyserverId::try_from(2).unwrap(),
                                 domain: "2.ks.prod.securedna.org".into(),
                                 bad_flag: Default::default(),
+                                capabilities: Default::default(),
                             },
                             SelectedKeyserver {
                                 id: KeyserverId::try_from(2).unwrap(),
                                 domain: "3.ks.prod.securedna.org".into(),
                                 bad_flag: Default::default(),
+                                capabilities: Default::default(),
                             }
                         ]
                     )
@@ -934,6 +1130,7 @@ mod tests {
                 hdbs: vec![SelectedHdb {
                     domain: "1.db.prod.securedna.org".into(),
                     bad_flag: Default::default(),
+                    capabilities: Default::default(),
                 }]
             }
         )
@@ -969,6 +1166,8 @@ mod tests {
                         ]
                         .into_iter()
                         .collect(),
+                        max_client_version: 0,
+                        capabilities: Default::default(),
                     },
                 ),
                 (
@@ -981,6 +1180,8 @@ mod tests {
                         ]
                         .into_iter()
                         .collect(),
+                        max_client_version: 0,
+                        capabilities: Default::default(),
                     },
                 ),
                 (
@@ -993,6 +1194,8 @@ mod tests {
                         ]
                         .into_iter()
                         .collect(),
+                        max_client_version: 0,
+                        capabilities: Default::default(),
                     },
                 ),
             ],
@@ -1000,8 +1203,11 @@ mod tests {
                 "1.db.prod.securedna.org".into(),
                 HdbQualificationResponse {
                     supported_generations: vec![0, 1],
+                    capabilities: Default::default(),
                 },
             )],
+            None,
+            &None,
         )
         .unwrap();
 
@@ -1017,6 +1223,7 @@ mod tests {
                             id: KeyserverId::try_from(1).unwrap(),
                             domain: "1.ks.prod.securedna.org".into(),
                             bad_flag: Default::default(),
+                            capabilities: Default::default(),
                         }]
                     ),
                     (
@@ -1025,6 +1232,7 @@ mod tests {
                             id: KeyserverId::try_from(2).unwrap(),
                             domain: "2.ks.prod.securedna.org".into(),
                             bad_flag: Default::default(),
+                            capabilities: Default::default(),
                         }]
                     ),
                     (
@@ -1033,6 +1241,7 @@ mod tests {
                             id: KeyserverId::try_from(3).unwrap(),
                             domain: "3.ks.prod.securedna.org".into(),
                             bad_flag: Default::default(),
+                            capabilities: Default::default(),
                         }]
                     )
                 ]
@@ -1042,11 +1251,137 @@ mod tests {
                 hdbs: vec![SelectedHdb {
                     domain: "1.db.prod.securedna.org".into(),
                     bad_flag: Default::default(),
+                    capabilities: Default::default(),
                 }]
             }
         )
     }
 
+    #[tokio::test]
+    async fn hard_keyserver_minimum_above_threshold_is_enforced() {
+        let key_info = KeyInfo {
+            quorum: 2,
+            active_security_key: ActiveSecurityKey::from_commitments(vec![
+                dummy_commitment(1),
+                dummy_commitment(2),
+            ]),
+        };
+        // Exactly a threshold of keyservers qualify
+        let keyservers: Vec<_> = (1..=2)
+            .map(|id| {
+                (
+                    format!("{id}.ks.prod.securedna.org"),
+                    KeyserverQualificationResponse {
+                        id: KeyserverId::try_from(id).unwrap(),
+                        generations_and_key_info: [(0, key_info.clone())].into_iter().collect(),
+                        max_client_version: 0,
+                        capabilities: Default::default(),
+                    },
+                )
+            })
+            .collect();
+        let hdbs = vec![(
+            "1.db.prod.securedna.org".to_owned(),
+            HdbQualificationResponse {
+                supported_generations: vec![0],
+                capabilities: Default::default(),
+            },
+        )];
+
+        assert!(do_server_selection(keyservers.clone(), hdbs.clone(), None, &None).is_ok());
+        assert!(do_server_selection(keyservers.clone(), hdbs.clone(), Some(2), &None).is_ok());
+        assert_eq!(
+            do_server_selection(keyservers, hdbs, Some(3), &None),
+            Err(vec![0])
+        );
+
+        // An existing selection that falls short is refreshed, and the refresh can't find quorum
+        let api_client = BaseApiClient::from(http_client::test_utils::ApiClientCoreMock::from(
+            |_url: String, _body, _content_type, _headers, _expected_content_type| {
+                unreachable!("no servers to qualify")
+            },
+        ));
+        let selector = Arc::new(test_utils::make_test_selector(
+            ServerSelectionConfig {
+                enumeration_source: ServerEnumerationSource::Fixed {
+                    keyserver_domains: vec![],
+                    hdb_domains: vec![],
+                },
+                soft_timeout: None,
+                blocking_timeout: None,
+                soft_extra_keyserver_threshold: None,
+                min_keyservers: Some(3),
+                soft_extra_hdb_threshold: None,
+                bad_server_penalty: None,
+                refresh_failure_backoff: None,
+            },
+            api_client,
+            make_test_selection(2, &[("apple", 1), ("pear", 2)], &["hdb"]),
+            get_now(),
+        ));
+        assert!(matches!(
+            selector.choose().await.unwrap_err(),
+            ServerSelectionError::NoQuorum(_),
+        ));
+    }
+
+    #[tokio::test]
+    async fn failed_refreshes_back_off() {
+        use futures::FutureExt;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        for (backoff, expected_qualifications) in [(None, 3), (Some(Duration::from_secs(3600)), 1)]
+        {
+            let qualifications = Arc::new(AtomicUsize::new(0));
+            let api_client =
+                BaseApiClient::from(http_client::test_utils::ApiClientCoreMock::from({
+                    let qualifications = qualifications.clone();
+                    move |url: String, _body, _content_type, _headers, _expected_content_type| {
+                        qualifications.fetch_add(1, Ordering::SeqCst);
+                        async {
+                            Err(http_client::error::HttpError::RequestError {
+                                ctx: url,
+                                status: Some(400),
+                                retriable: false,
+                                source: "rejected".into(),
+                            })
+                        }
+                        .boxed()
+                    }
+                }));
+            let selector = Arc::new(test_utils::make_test_selector(
+                ServerSelectionConfig {
+                    enumeration_source: ServerEnumerationSource::Fixed {
+                        keyserver_domains: vec!["1.ks.prod.securedna.org".to_owned()],
+                        hdb_domains: vec![],
+                    },
+                    soft_timeout: None,
+                    blocking_timeout: None,
+                    soft_extra_keyserver_threshold: None,
+                    min_keyservers: Some(3),
+                    soft_extra_hdb_threshold: None,
+                    bad_server_penalty: None,
+                    refresh_failure_backoff: backoff,
+                },
+                api_client,
+                make_test_selection(2, &[("apple", 1), ("pear", 2)], &["hdb"]),
+                get_now(),
+            ));
+
+            for _ in 0..3 {
+                assert!(matches!(
+                    selector.clone().choose().await.unwrap_err(),
+                    ServerSelectionError::NoQuorum(_),
+                ));
+            }
+            assert_eq!(
+                qualifications.load(Ordering::SeqCst),
+                expected_qualifications,
+                "backoff {backoff:?}"
+            );
+        }
+    }
+
     #[test]
     fn marking_bad_makes_keyserver_choosing_fail() {
         let selection =
@@ -1099,6 +1434,159 @@ mod tests {
         assert!(selection.choose_n_keyservers().is_none()); // all apples bad, fails
     }
 
+    #[tokio::test]
+    async fn manually_marked_servers_are_skipped_until_marked_good() {
+        let api_client = BaseApiClient::from(http_client::test_utils::ApiClientCoreMock::from(
+            |_url: String, _body, _content_type, _headers, _expected_content_type| {
+                unreachable!("selection should not be refreshed")
+            },
+        ));
+        let selection = make_test_selection(1, &[("apple", 1), ("pear", 2)], &["hdb"]);
+        let selector = Arc::new(test_utils::make_test_selector(
+            ServerSelectionConfig {
+                enumeration_source: ServerEnumerationSource::Fixed {
+                    keyserver_domains: vec![],
+                    hdb_domains: vec![],
+                },
+                soft_timeout: None,
+                blocking_timeout: None,
+                soft_extra_keyserver_threshold: None,
+                min_keyservers: None,
+                soft_extra_hdb_threshold: None,
+                bad_server_penalty: None,
+                refresh_failure_backoff: None,
+            },
+            api_client,
+            selection,
+            get_now(),
+        ));
+        let chosen_domain = || async {
+            let chosen = selector.clone().choose().await.unwrap();
+            chosen.keyservers[0].domain.clone()
+        };
+
+        assert!(!selector.mark_server("banana", ServerHealth::Bad));
+
+        assert!(selector.mark_server("apple", ServerHealth::Bad));
+        for _ in 0..10 {
+            assert_eq!(chosen_domain().await, "pear");
+        }
+        let status = selector.status();
+        assert_eq!(
+            status
+                .keyservers
+                .iter()
+                .map(|ks| (ks.domain.as_str(), ks.health))
+                .collect::<Vec<_>>(),
+            vec![("apple", ServerHealth::Bad), ("pear", ServerHealth::Good)]
+        );
+
+        assert!(selector.mark_server("apple", ServerHealth::Good));
+        assert!(selector.mark_server("pear", ServerHealth::Bad));
+        for _ in 0..10 {
+            assert_eq!(chosen_domain().await, "apple");
+        }
+    }
+
+    #[test]
+    fn marked_bad_servers_are_reeligible_after_their_cooldown() {
+        use bad_flag::{BadServerPenalty, ServerBadFlag, ServerPenalty};
+
+        let secs = Duration::from_secs;
+        let penalty = BadServerPenalty {
+            base_cooldown: secs(10),
+            max_cooldown: secs(30),
+            decay_period: secs(100),
+        };
+        let flag = ServerBadFlag::with_penalty(Some(penalty.clone()));
+        let start = get_now();
+
+        flag.mark_bad_at(start);
+        assert!(flag.is_bad_at(start + secs(9)));
+        assert!(!flag.is_bad_at(start + secs(10)));
+
+        // Failing again as soon as it's probed doubles the cooldown, up to the max.
+        flag.mark_bad_at(start + secs(10));
+        assert_eq!(
+            flag.penalty_at(start + secs(15)),
+            Some(ServerPenalty {
+                failures: 2,
+                remaining: Some(secs(15)),
+            })
+        );
+        assert!(!flag.is_bad_at(start + secs(30)));
+        flag.mark_bad_at(start + secs(30));
+        assert!(flag.is_bad_at(start + secs(59)));
+        assert!(!flag.is_bad_at(start + secs(60)));
+
+        // Failures are forgiven over time, so a later failure starts over.
+        let later = start + secs(30) + secs(300);
+        assert_eq!(flag.penalty_at(later), None);
+        flag.mark_bad_at(later);
+        assert!(!flag.is_bad_at(later + secs(10)));
+
+        // Servers in a selection are chosen again once their cooldown elapses.
+        let mut selection = make_test_selection(1, &[("apple", 1), ("pear", 2)], &["hdb"]);
+        for ks in selection.keyservers.values_mut().flatten() {
+            ks.bad_flag = ServerBadFlag::with_penalty(Some(penalty.clone()));
+        }
+        let flag_for = |domain: &str| {
+            selection
+                .keyservers
+                .values()
+                .flatten()
+                .find(|ks| ks.domain == domain)
+                .unwrap()
+                .bad_flag
+                .clone()
+        };
+        let now = get_now();
+        flag_for("apple").mark_bad_at(now);
+        flag_for("pear").mark_bad_at(now.checked_sub(secs(20)).unwrap());
+        for _ in 0..10 {
+            let chosen = selection.choose(None).unwrap();
+            assert_eq!(chosen.keyservers[0].domain, "pear");
+        }
+
+        let selector = test_utils::make_test_selector(
+            ServerSelectionConfig {
+                enumeration_source: ServerEnumerationSource::Fixed {
+                    keyserver_domains: vec![],
+                    hdb_domains: vec![],
+                },
+                soft_timeout: None,
+                blocking_timeout: None,
+                soft_extra_keyserver_threshold: None,
+                min_keyservers: None,
+                soft_extra_hdb_threshold: None,
+                bad_server_penalty: Some(penalty),
+                refresh_failure_backoff: None,
+            },
+            BaseApiClient::from(http_client::test_utils::ApiClientCoreMock::from(
+                |_url: String, _body, _content_type, _headers, _expected_content_type| {
+                    unreachable!("selection should not be refreshed")
+                },
+            )),
+            selection,
+            now,
+        );
+        let status = selector.status();
+        let (apple, pear) = (&status.keyservers[0], &status.keyservers[1]);
+        assert_eq!(apple.health, ServerHealth::Bad);
+        assert!(apple
+            .penalty
+            .as_ref()
+            .is_some_and(|p| p.failures == 1 && p.remaining.is_some_and(|r| r <= secs(10))));
+        assert_eq!(pear.health, ServerHealth::Good);
+        assert_eq!(
+            pear.penalty,
+            Some(ServerPenalty {
+                failures: 1,
+                remaining: Some(Duration::ZERO),
+            })
+        );
+    }
+
     #[test]
     fn badness_preserved_through_clones() {
         let selection =
@@ -45,6 +45,11 @@ impl<T: Clone> Refreshable<T> {
         Ok(())
     }
 
+    /// The value currently in the channel, whether or not a refresh is in progress.
+    pub fn current(&self) -> T {
+        self.rx.borrow().clone()
+    }
+
     /// Helper to wait for an `accept`able value from the channel.
     async fn wait_for<U>(&self, mut accept: impl FnMut(T) -> Option<U>) -> U {
         let mut rx = self.rx.clone();
@@ -85,98 +85,6 @@ async fn test_verification_direct() {
     assert!(true);
 }
 
-// Keep the original test for reference, but commented out so it doesn't run
-/*
-#[test]
-async fn test_verification_flow() {
-    println!("Starting test_verification_flow");
-    
-    // Create a mock API client that logs requests and returns empty responses
-    let mock_api_client = BaseApiClient::from(ApiClientCoreMock::from(
-        |url: String, body: Option<Bytes>, content_type: &'static str, _headers, _expected_content_type| {
-            println!("Mock API request to: {}", url);
-            println!("Content-Type: {}", content_type);
-            
-            if let Some(body) = &body {
-                println!("Body length: {}", body.len());
-                
-                // If this is the screen endpoint, print more details
-                if url.contains("/screen") {
-                    if let Ok(json_str) = std::str::from_utf8(body) {
-                        if let Ok(json_value) = serde_json::from_str::<serde_json::Value>(json_str) {
-                            println!("Request JSON: {}", serde_json::to_string_pretty(&json_value).unwrap_or_default());
-                        }
-                    }
-                }
-            }
-            
-            // Return an empty successful response
-            async {
-                tokio::time::sleep(Duration::from_millis(50)).await;
-                Ok(serde_json::to_vec(&serde_json::json!({
-                    "results": []
-                })).unwrap().into())
-            }
-            .boxed()
-        },
-    ));
-    
-    // Import necessary modules for the test
-    use crate::doprf_client::{DoprfConfig, process};
-    use crate::server_selection::{ServerSelectionConfig, ServerSelection};
-    use crate::server_selection::test_utils::{make_test_selection, make_test_selector};
-    use crate::server_version_handler::LastServerVersionHandler;
-    use scep_client_helpers::ClientCerts;
-    
-    // Create a test configuration
-    let request_ctx = RequestId::new_unique();
-    
-    // Create test server selection
-    let config = ServerSelectionConfig::default();
-    let selection = make_test_selection(1, &[("test-keyserver.example.com", 1)], &["test-hdb.example.com"]);
-    let server_selector = make_test_selector(
-        config,
-        mock_api_client.clone(),
-        selection,
-        std::time::Instant::now(),
-    );
-    
-    // Create a simple DNA sequence for testing
-    let sequence = DnaSequence::from("ATCGATCGATCGATCGATCGATCGATCGATCGATCGATCGATCGATCGATCGATCGATCGATCG");
-    
-    // Create test certificates
-    let certs = Arc::new(make_test_certs().unwrap());
-    
-    // Create the DOPRF configuration
-    let config = DoprfConfig {
-        api_client: &mock_api_client,
-        server_selector: Arc::new(server_selector),
-        request_ctx: &request_ctx,
-        certs,
-        region: shared_types::synthesis_permission::Region::default(),
-        debug_info: true,
-        sequences: &[sequence],
-        max_windows: 1000,
-        version_hint: "test".to_string(),
-        ets: vec![],
-        server_version_handler: &LastServerVersionHandler::default(),
-    };
-    
-    // Process the sequence
-    println!("Calling process");
-    let result = process(config).await;
-    
-    // Check the result
-    match result {
-        Ok(output) => {
-            println!("Process succeeded");
-            println!("n_hashes: {}", output.n_hashes);
-            println!("too_short: {}", output.too_short);
-            println!("response: {:?}", output.response);
-        }
-        Err(e) => {
-            println!("Process failed: {:?}", e);
-        }
-    }
-}
-*/ 
\ No newline at end of file
+// The full verification flow runs end to end in `tests/test_integration.rs`,
+// against in-process servers. With the `mock-prover` feature it needs no SP1:
+// `cargo test -p doprf_client --features mock-prover`.
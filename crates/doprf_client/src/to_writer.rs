@@ -0,0 +1,208 @@
+// Copyright 2021-2024 SecureDNA Stiftung (SecureDNA Foundation) <licensing@securedna.org>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Writing screening results straight to a writer, for callers that only
+//! persist them.
+//!
+//! Instead of collecting every region's [`HdbScreeningResult`] into a
+//! [`DoprfOutput`](crate::doprf_client::DoprfOutput), [`process_to_writer`]
+//! writes each hazard as a line of JSON (a [`HazardLine`]) as soon as its
+//! region's response arrives, and then drops that response. Only the summary is
+//! returned.
+
+use std::collections::HashMap;
+
+use futures::io::{AsyncWrite, AsyncWriteExt};
+use quickdna::ToNucleotideLike;
+use serde::{Deserialize, Serialize};
+use shared_types::hdb::{ConsolidatedHazardResult, HdbScreeningResult};
+use shared_types::synthesis_permission::Region;
+
+use crate::doprf_client::{prepare_hashes, DoprfConfig, NotScreened, Prepared, RegionQueries};
+use crate::error::DoprfError;
+use crate::retry_if::with_deadline;
+
+/// One line of [`process_to_writer`]'s output.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HazardLine {
+    /// The region the hazard was screened under.
+    pub region: Region,
+    pub hazard: ConsolidatedHazardResult,
+}
+
+/// What [`process_to_writer`] screened, once every hazard has been written.
+///
+/// Apart from `regions`, these are as in [`DoprfOutput`].
+///
+/// [`DoprfOutput`]: crate::doprf_client::DoprfOutput
+#[derive(Debug, Default, PartialEq)]
+pub struct WrittenSummary {
+    pub n_hashes: u64,
+    pub overhead_hashes: u64,
+    pub too_short: bool,
+    /// For each configured region, what was written for it.
+    pub regions: HashMap<Region, RegionSummary>,
+    pub not_screened: Vec<NotScreened>,
+    pub hdb_failure: Option<String>,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct RegionSummary {
+    /// The number of [`HazardLine`]s written for this region.
+    pub hazards: usize,
+    /// See [`HdbScreeningResult::provider_reference`].
+    pub provider_reference: Option<String>,
+}
+
+/// Like [`process`](crate::doprf_client::process), but writes each hazard to
+/// `writer` as a JSON line, and returns a summary instead of the results.
+///
+/// Hazards are written in the order the HDB returned them, one region at a
+/// time. Debug info from the HDB isn't written. If an error is returned, the
+/// lines already written may only cover some of the regions.
+pub async fn process_to_writer<'a, NLike, SliceN>(
+    config: DoprfConfig<'a, SliceN>,
+    mut writer: impl AsyncWrite + Unpin,
+) -> Result<WrittenSummary, DoprfError>
+where
+    NLike: ToNucleotideLike + Copy + 'a,
+    SliceN: AsRef<[NLike]>,
+{
+    let overall_deadline = config.overall_deadline;
+    let attempts = config.request_ctx.attempts.clone();
+
+    let mut regions = HashMap::new();
+    let output = match prepare_hashes(config).await? {
+        Prepared::Finished(output) => output,
+        Prepared::Hashed(prepared, hdb_client) => {
+            with_deadline(overall_deadline, &attempts, async {
+                let mut queries = RegionQueries::new(prepared, hdb_client);
+                loop {
+                    match queries.next().await {
+                        Ok(Some((region, response))) => {
+                            let summary = write_hazards(&mut writer, region, response).await?;
+                            regions.insert(region, summary);
+                        }
+                        Ok(None) => return Ok(queries.finish(HashMap::new())),
+                        Err(error) => return queries.fail(HashMap::new(), error),
+                    }
+                }
+            })
+            .await?
+        }
+    };
+    // Outputs finished without querying the HDB still have (empty) responses,
+    // as do regions the HDB never responded for if it failed open.
+    for (region, response) in output.responses {
+        if !regions.contains_key(&region) {
+            regions.insert(region, write_hazards(&mut writer, region, response).await?);
+        }
+    }
+    writer.flush().await.map_err(DoprfError::WriteError)?;
+
+    Ok(WrittenSummary {
+        n_hashes: output.n_hashes,
+        overhead_hashes: output.overhead_hashes,
+        too_short: output.too_short,
+        regions,
+        not_screened: output.not_screened,
+        hdb_failure: output.hdb_failure,
+    })
+}
+
+/// Write each hazard in `response` as a [`HazardLine`].
+async fn write_hazards(
+    writer: &mut (impl AsyncWrite + Unpin),
+    region: Region,
+    response: HdbScreeningResult,
+) -> Result<RegionSummary, DoprfError> {
+    let hazards = response.results.len();
+    for hazard in response.results {
+        let mut line = serde_json::to_vec(&HazardLine { region, hazard })
+            .map_err(|e| DoprfError::WriteError(e.into()))?;
+        line.push(b'\n');
+        writer
+            .write_all(&line)
+            .await
+            .map_err(DoprfError::WriteError)?;
+    }
+    Ok(RegionSummary {
+        hazards,
+        provider_reference: response.provider_reference,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use shared_types::hdb::{HitRegion, Organism};
+    use shared_types::synthesis_permission::SynthesisPermission;
+
+    use super::*;
+
+    fn hazard(record: u64, an: &str) -> ConsolidatedHazardResult {
+        let organism = Organism {
+            name: format!("Organism {an}"),
+            organism_type: pipeline_bridge::OrganismType::Virus,
+            ans: vec![an.into()],
+            tags: vec![],
+        };
+        ConsolidatedHazardResult {
+            record,
+            hit_regions: vec![HitRegion {
+                seq_range_start: 0,
+                seq_range_end: 4,
+            }],
+            synthesis_permission: SynthesisPermission::Denied,
+            most_likely_organism: organism.clone(),
+            organisms: vec![organism],
+            is_dna: true,
+            is_wild_type: None,
+            exempt: false,
+            window_offsets: vec![0],
+        }
+    }
+
+    #[tokio::test]
+    async fn written_lines_parse_back_into_results() {
+        let responses = [
+            (
+                Region::Us,
+                vec![hazard(0, "TST_00000"), hazard(2, "TST_00001")],
+            ),
+            (Region::Prc, vec![hazard(1, "TST_00002")]),
+        ];
+
+        let mut buffer: Vec<u8> = vec![];
+        for (region, results) in &responses {
+            let response = HdbScreeningResult {
+                results: results.clone(),
+                debug_hdb_responses: None,
+                provider_reference: Some(format!("{region:?}")),
+                warnings: vec![],
+            };
+            let summary = write_hazards(&mut buffer, *region, response).await.unwrap();
+            assert_eq!(
+                summary,
+                RegionSummary {
+                    hazards: results.len(),
+                    provider_reference: Some(format!("{region:?}")),
+                }
+            );
+        }
+
+        let lines: Vec<HazardLine> = std::str::from_utf8(&buffer)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        let expected: Vec<HazardLine> = responses
+            .into_iter()
+            .flat_map(|(region, results)| {
+                results
+                    .into_iter()
+                    .map(move |hazard| HazardLine { region, hazard })
+            })
+            .collect();
+        assert_eq!(lines, expected);
+    }
+}
@@ -9,6 +9,7 @@ use quickdna::{NucleotideAmbiguous, NucleotideIter, TranslationTable};
 use shared_types::hash::HashDirection;
 
 use super::expansions::WindowExpansions;
+use super::Window;
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Direction {
@@ -49,7 +50,7 @@ pub struct AaWindows {
 ///
 /// Note that there is no amino acid type yet, so this holds a [`str`].
 #[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
-pub struct AaWindow(String);
+pub struct AaWindow(Window);
 
 impl AaWindows {
     /// Creates a new iterator of DNA windows translated into amino acids.
@@ -90,15 +91,12 @@ impl Iterator for AaWindows {
         let (indexes, nucs) = self.windows.next()?;
         let ncbi1 = TranslationTable::Ncbi1.to_fn();
         let translated = match self.direction {
-            Direction::Forward => nucs.iter().codons().map(ncbi1).collect(),
-            Direction::ReverseComplement => nucs
-                .iter()
-                .reverse_complement()
-                .codons()
-                .map(ncbi1)
-                .collect(),
+            Direction::Forward => Window::from_ascii(nucs.iter().codons().map(ncbi1)),
+            Direction::ReverseComplement => {
+                Window::from_ascii(nucs.iter().reverse_complement().codons().map(ncbi1))
+            }
         };
-        let window = AaWindow(String::from_utf8(translated).unwrap());
+        let window = AaWindow(translated);
         Some((indexes, window))
     }
 
@@ -115,6 +113,12 @@ impl std::ops::Deref for AaWindow {
     }
 }
 
+impl From<AaWindow> for Window {
+    fn from(window: AaWindow) -> Self {
+        window.0
+    }
+}
+
 impl AsRef<str> for AaWindow {
     fn as_ref(&self) -> &str {
         self
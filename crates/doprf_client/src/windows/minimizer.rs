@@ -0,0 +1,66 @@
+// Copyright 2021-2024 SecureDNA Stiftung (SecureDNA Foundation) <licensing@securedna.org>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Minimizer selection over a run of windows, see [`Windowing::Minimizer`].
+//!
+//! [`Windowing::Minimizer`]: shared_types::hash::Windowing::Minimizer
+
+use std::collections::VecDeque;
+use std::num::NonZeroUsize;
+use std::ops::Range;
+
+use super::Window;
+
+/// Select the minimizer windows out of `windows`: for every `w` consecutive
+/// windows, the one with the smallest key is kept (the leftmost one, on ties).
+///
+/// A window's key is the FNV-1a hash of its first `k` letters, followed by the
+/// window itself, so that the choice only depends on window contents.
+pub(super) fn select_minimizers(
+    windows: impl Iterator<Item = (Range<usize>, Window)>,
+    k: NonZeroUsize,
+    w: NonZeroUsize,
+) -> Vec<(Range<usize>, Window)> {
+    let windows: Vec<_> = windows.collect();
+    let keys: Vec<_> = windows
+        .iter()
+        .map(|(_, window)| {
+            let prefix = &window.as_bytes()[..k.get().min(window.len())];
+            (fnv1a(prefix), window.as_str())
+        })
+        .collect();
+
+    // Monotonic queue of candidate indices, with increasing keys.
+    let mut candidates: VecDeque<usize> = VecDeque::new();
+    let mut selected: Vec<usize> = vec![];
+    for i in 0..keys.len() {
+        while candidates.back().is_some_and(|&j| keys[j] > keys[i]) {
+            candidates.pop_back();
+        }
+        candidates.push_back(i);
+        if candidates.front().is_some_and(|&j| j + w.get() <= i) {
+            candidates.pop_front();
+        }
+        // Sequences with fewer than `w` windows still get their minimizer.
+        if i + 1 >= w.get() || i + 1 == keys.len() {
+            let minimizer = candidates[0];
+            if selected.last() != Some(&minimizer) {
+                selected.push(minimizer);
+            }
+        }
+    }
+
+    let mut windows = windows.into_iter().map(Some).collect::<Vec<_>>();
+    selected
+        .into_iter()
+        .filter_map(|i| windows[i].take())
+        .collect()
+}
+
+/// 64-bit FNV-1a, chosen because it's stable across platforms and releases:
+/// the HDB and clients must agree on which windows are minimizers.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3)
+    })
+}
@@ -7,11 +7,15 @@ use std::num::NonZeroUsize;
 
 use doprf::tagged::HashTag;
 use quickdna::{NucleotideAmbiguous, NucleotideLike, ToNucleotideLike};
-use shared_types::hash::{HashDirection, HashSkipType, HashSpec, HashType, HashTypeDescriptor};
+use shared_types::hash::{
+    HashDirection, HashSkipType, HashSpec, HashType, HashTypeDescriptor, Windowing,
+};
+use smallvec::SmallVec;
 
 mod aa;
 mod dna;
 mod expansions;
+mod minimizer;
 
 pub use aa::{AaWindow, AaWindows};
 pub use dna::{DnaWindow, DnaWindows};
@@ -27,6 +31,70 @@ pub enum WindowsError {
     NonShingledHtd(HashTypeDescriptor),
     #[error("too many HTDs")]
     TooManyHtds,
+    #[error("minimizer k and w must be nonzero")]
+    ZeroMinimizerParameter,
+}
+
+/// How many letters a [`Window`] can hold without a heap allocation. This
+/// covers the widths of all the hash types in use (42bp DNA, 20aa).
+const INLINE_WINDOW_LEN: usize = 48;
+
+/// The letters (bases or amino acids) of a single window, as ASCII.
+///
+/// Large orders produce millions of windows, so these are stored inline
+/// rather than each getting its own heap allocation (unless a hash type is
+/// wider than [`INLINE_WINDOW_LEN`]).
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Window(SmallVec<[u8; INLINE_WINDOW_LEN]>);
+
+impl Window {
+    /// `letters` must be ASCII, so the window can be viewed as a [`str`].
+    fn from_ascii(letters: impl IntoIterator<Item = u8>) -> Self {
+        let letters: SmallVec<_> = letters.into_iter().collect();
+        assert!(letters.is_ascii(), "window letters must be ASCII");
+        Self(letters)
+    }
+
+    pub fn as_str(&self) -> &str {
+        std::str::from_utf8(&self.0).expect("window letters are ASCII")
+    }
+
+    /// Whether this window had to be stored on the heap.
+    pub fn spilled(&self) -> bool {
+        self.0.spilled()
+    }
+}
+
+impl std::ops::Deref for Window {
+    type Target = str;
+
+    fn deref(&self) -> &Self::Target {
+        self.as_str()
+    }
+}
+
+impl AsRef<str> for Window {
+    fn as_ref(&self) -> &str {
+        self
+    }
+}
+
+impl AsRef<[u8]> for Window {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl std::fmt::Debug for Window {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_tuple("Window").field(&self.as_str()).finish()
+    }
+}
+
+impl std::fmt::Display for Window {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(self)
+    }
 }
 
 #[derive(Clone)]
@@ -40,6 +108,8 @@ pub struct Windows {
 enum WindowRun {
     Dna(DnaWindows),
     Aa(AaWindows),
+    /// Windows already selected out of another run, e.g. minimizers.
+    Selected(std::vec::IntoIter<(std::ops::Range<usize>, Window)>),
 }
 
 impl Windows {
@@ -61,6 +131,15 @@ impl Windows {
             return Err(WindowsError::TooManyHtds);
         }
 
+        let minimizer = match spec.windowing {
+            Windowing::All => None,
+            Windowing::Minimizer { k, w } => Some(
+                NonZeroUsize::new(k)
+                    .zip(NonZeroUsize::new(w))
+                    .ok_or(WindowsError::ZeroMinimizerParameter)?,
+            ),
+        };
+
         let runs: Result<Vec<_>, _> = spec
             .htdv
             .iter()
@@ -96,6 +175,13 @@ impl Windows {
                     _ => Err(WindowsError::UnsupportedHtd(htd.clone())),
                 }
             })
+            .map(|run| match minimizer {
+                Some((k, w)) => {
+                    let selected = minimizer::select_minimizers(run?, k, w);
+                    Ok(WindowRun::Selected(selected.into_iter()))
+                }
+                None => run,
+            })
             .collect();
 
         Ok(Windows {
@@ -106,9 +192,41 @@ impl Windows {
     }
 }
 
+/// Split `seq` into chunks that can each be windowed on their own, for
+/// screening huge sequences in bounded memory.
+///
+/// Each chunk starts `chunk_target` bases after the previous one, and runs on
+/// for the widest window in `spec` less one, so every window of `seq` lies
+/// entirely within some chunk. A window starting in the first `chunk_target`
+/// bases of a chunk (or anywhere in the last chunk) is in no earlier chunk;
+/// keeping only those gives exactly the windows of `seq`.
+///
+/// This holds for [`Windowing::All`]. Minimizers are selected among
+/// neighbouring windows, so the minimizers picked near a chunk's edges can
+/// differ from those picked when windowing `seq` whole.
+pub fn split_for_windowing<'a, N>(
+    seq: &'a [N],
+    spec: &HashSpec,
+    chunk_target: NonZeroUsize,
+) -> impl Iterator<Item = &'a [N]> + 'a {
+    let overlap = spec.max_width_bp().unwrap_or(1).saturating_sub(1);
+    let chunk_target = chunk_target.get();
+    let mut start = (!seq.is_empty()).then_some(0);
+    std::iter::from_fn(move || {
+        let chunk_start = start?;
+        let chunk_end = seq.len().min(
+            chunk_start
+                .saturating_add(chunk_target)
+                .saturating_add(overlap),
+        );
+        start = (chunk_end < seq.len()).then_some(chunk_start + chunk_target);
+        Some(&seq[chunk_start..chunk_end])
+    })
+}
+
 // Avoiding flatten in order to keep size_hints accurate.
 impl Iterator for Windows {
-    type Item = (HashTag, String);
+    type Item = (HashTag, Window);
 
     fn next(&mut self) -> Option<Self::Item> {
         while let Some(run) = self.runs.last_mut() {
@@ -136,17 +254,17 @@ impl Iterator for Windows {
 }
 
 impl Iterator for WindowRun {
-    type Item = (std::ops::Range<usize>, String);
+    type Item = (std::ops::Range<usize>, Window);
 
     fn next(&mut self) -> Option<Self::Item> {
-        // TODO: don't allocate new strings every time
-        fn dna_to_string(dna: DnaWindow) -> String {
-            dna.iter().map(|&nuc| char::from(nuc)).collect()
+        fn dna_to_window(dna: DnaWindow) -> Window {
+            Window::from_ascii(dna.iter().map(|&nuc| nuc.to_ascii()))
         }
 
         match self {
-            Self::Dna(iter) => iter.next().map(|(i, dna)| (i, dna_to_string(dna))),
-            Self::Aa(iter) => iter.next().map(|(i, aas)| (i, aas.as_ref().to_owned())),
+            Self::Dna(iter) => iter.next().map(|(i, dna)| (i, dna_to_window(dna))),
+            Self::Aa(iter) => iter.next().map(|(i, aas)| (i, aas.into())),
+            Self::Selected(iter) => iter.next(),
         }
     }
 
@@ -154,6 +272,7 @@ impl Iterator for WindowRun {
         match self {
             Self::Dna(iter) => iter.size_hint(),
             Self::Aa(iter) => iter.size_hint(),
+            Self::Selected(iter) => iter.size_hint(),
         }
     }
 }
@@ -162,7 +281,7 @@ impl Iterator for WindowRun {
 mod test {
     use std::collections::HashSet;
 
-    use quickdna::{BaseSequence, DnaSequenceStrict};
+    use quickdna::{BaseSequence, DnaSequenceStrict, Nucleotide};
     use shared_types::hash::{HashDirection, HashType, HashTypeDescriptor};
     use shared_types::{WINDOW_LENGTH_AA, WINDOW_LENGTH_DNA_NORMAL, WINDOW_LENGTH_DNA_RUNT};
 
@@ -184,7 +303,7 @@ mod test {
         ]);
         let windows: HashSet<_> = Windows::from_dna(dna.iter(), &spec)
             .unwrap()
-            .map(|(i, x)| (i.index_in_record(), x))
+            .map(|(i, x)| (i.index_in_record(), x.to_string()))
             .collect();
 
         // First windows
@@ -206,7 +325,7 @@ mod test {
 
         let windows: HashSet<_> = Windows::from_dna(dna.iter(), &spec)
             .unwrap()
-            .map(|(i, x)| (i.index_in_record(), x))
+            .map(|(i, x)| (i.index_in_record(), x.to_string()))
             .collect();
 
         // First windows
@@ -229,7 +348,7 @@ mod test {
 
         let windows: HashSet<_> = Windows::from_dna(dna.iter(), &spec)
             .unwrap()
-            .map(|(i, x)| (i.index_in_record(), x))
+            .map(|(i, x)| (i.index_in_record(), x.to_string()))
             .collect();
 
         // First windows
@@ -297,6 +416,98 @@ mod test {
         assert_eq!(windows.size_hint(), (expected_len, Some(expected_len)));
     }
 
+    #[test]
+    fn windowing_split_chunks_matches_windowing_whole() {
+        let mut state: u32 = 7;
+        let dna: DnaSequenceStrict = (0..500)
+            .map(|_| {
+                state = state.wrapping_mul(1103515245).wrapping_add(12345);
+                char::from(b"ACGT"[(state >> 16) as usize % 4])
+            })
+            .collect::<String>()
+            .parse()
+            .unwrap();
+        let spec = HashSpec::unambiguous(vec![
+            HashTypeDescriptor::dna_normal_cech(),
+            HashTypeDescriptor::dna_runt_cech(),
+            HashTypeDescriptor::aa_fw(),
+            HashTypeDescriptor::aa_rc(),
+        ]);
+        let tagged = |tag: HashTag, offset: usize, window: Window| {
+            let start = offset + tag.index_in_record();
+            (tag.hash_type_index(), start, window.to_string())
+        };
+
+        let whole: Vec<_> = Windows::from_dna(dna.iter(), &spec)
+            .unwrap()
+            .map(|(tag, window)| tagged(tag, 0, window))
+            .collect();
+
+        for chunk_target in [1, 37, 100, 499, 500, 1000] {
+            let chunk_target = NonZeroUsize::new(chunk_target).unwrap();
+            let chunks: Vec<_> = split_for_windowing(dna.as_slice(), &spec, chunk_target).collect();
+            assert!(chunks.iter().all(|chunk| !chunk.is_empty()));
+
+            let mut chunked = vec![];
+            for (i, chunk) in chunks.iter().enumerate() {
+                let is_last = i + 1 == chunks.len();
+                chunked.extend(
+                    Windows::from_dna(chunk.iter(), &spec)
+                        .unwrap()
+                        .filter(|(tag, _)| is_last || tag.index_in_record() < chunk_target.get())
+                        .map(|(tag, window)| tagged(tag, i * chunk_target.get(), window)),
+                );
+            }
+
+            let mut whole = whole.clone();
+            whole.sort();
+            chunked.sort();
+            assert_eq!(chunked, whole, "chunk target {chunk_target}");
+        }
+
+        let empty: &[Nucleotide] = &[];
+        let one = NonZeroUsize::new(1).unwrap();
+        assert_eq!(split_for_windowing(empty, &spec, one).count(), 0);
+    }
+
+    #[test]
+    fn minimizer_windowing_is_sparser_but_still_covers_hazard() {
+        // Pseudorandom, so that minimizers are spread out as they would be in a genome.
+        let mut state: u32 = 42;
+        let dna: String = (0..600)
+            .map(|_| {
+                state = state.wrapping_mul(1103515245).wrapping_add(12345);
+                char::from(b"ACGT"[(state >> 16) as usize % 4])
+            })
+            .collect();
+        let hazard: DnaSequenceStrict = dna[250..350].parse().unwrap();
+        let dna: DnaSequenceStrict = dna.parse().unwrap();
+
+        let all = HashSpec::dna_normal_cech();
+        let minimizer = HashSpec {
+            windowing: Windowing::Minimizer { k: 12, w: 10 },
+            ..HashSpec::dna_normal_cech()
+        };
+        let windows = |dna: &DnaSequenceStrict, spec: &HashSpec| -> HashSet<Window> {
+            Windows::from_dna(dna.iter(), spec)
+                .unwrap()
+                .map(|(_, window)| window)
+                .collect()
+        };
+
+        let all_windows = Windows::from_dna(dna.iter(), &all).unwrap().count();
+        let minimizer_windows = Windows::from_dna(dna.iter(), &minimizer).unwrap();
+        let (len, _) = minimizer_windows.size_hint();
+        assert_eq!(minimizer_windows.count(), len);
+        assert!(len < all_windows / 2, "{len} of {all_windows} windows");
+
+        // The HDB is built with the same windowing, so the hazard's minimizers
+        // are among the sequence's.
+        let hazard_windows = windows(&hazard, &minimizer);
+        assert!(!hazard_windows.is_empty());
+        assert!(hazard_windows.is_subset(&windows(&dna, &minimizer)));
+    }
+
     #[test]
     fn check_bad_htdv() {
         // 64 nucleotides long
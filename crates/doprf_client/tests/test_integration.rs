@@ -8,19 +8,30 @@ use std::net::TcpListener;
 use std::num::NonZeroU32;
 use std::path::PathBuf;
 use std::str::FromStr;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use std::{cell::RefCell, collections::HashMap};
 
+use certificates::{ExemptionTokenGroup, Organism, Sequence, SequenceIdentifier, TokenBundle};
 use futures::{future, pin_mut};
 
-use doprf::party::KeyserverId;
-use doprf::prf::KeyShare;
+use doprf::party::{KeyserverId, KeyserverIdSet};
+use doprf::prf::{KeyShare, Query, QueryError};
 use doprf::shims::{genkey, genkeyshares};
 use doprf::{active_security::Commitment, shims::genactivesecuritykey};
+use doprf_client::error::DoprfError;
+use doprf_client::packed_ristretto::PackedRistrettos;
+use doprf_client::progress::{ProgressEvent, ProgressPhase, ProgressSink};
+use doprf_client::rate_limit::RateLimiter;
+use doprf_client::scep_client::{ClientConfig, KeyserverApplyFn, KeyserverSetClient};
 use doprf_client::server_selection::{
     ServerEnumerationSource, ServerSelectionConfig, ServerSelector,
 };
-use doprf_client::{server_version_handler::LastServerVersionHandler, DoprfConfig};
+use doprf_client::{
+    server_version_handler::LastServerVersionHandler, DoprfConfig, DoprfOutput, DoprfTimings,
+    HdbFailurePolicy, Prepared, ZeroWindowPolicy,
+};
+use hdb::exemption::make_test_et;
 use hdb::shims::genhdb;
 use http_client::{BaseApiClient, HttpsToHttpRewriter};
 use minhttp::mpserver::common::{default_listen_fn, read_no_disk, stub_cfg};
@@ -28,6 +39,7 @@ use minhttp::mpserver::{traits::ValidServerSetup, ExternalWorld, PlaneConfig, Se
 use pipeline_bridge::OrganismType;
 use quickdna::{DnaSequence, Nucleotide};
 use scep_client_helpers::ClientCerts;
+use shared_types::et::WithOtps;
 use shared_types::hdb::{ConsolidatedHazardResult, HitRegion};
 use shared_types::{
     requests::{RequestContext, RequestId},
@@ -142,7 +154,7 @@ async fn test_hdb() {
 
     // 4. Put the strings in the temporary database directory
     let gen_hdb_opts = genhdb::Opts {
-        secret_key: key,
+        secret_key: key.clone(),
         artifacts_dir: artifacts_path,
         database: db_path.clone(),
         command: genhdb::Command::New { force: false },
@@ -164,7 +176,7 @@ async fn test_hdb() {
         None => unreachable!(),
     };
     let keyshare_opts = genkeyshares::Opts {
-        secret_key: key,
+        secret_key: key.clone(),
         keyholders_required: KEYHOLDERS_REQUIRED,
         num_keyholders: NUM_KEYHOLDERS,
     };
@@ -218,8 +230,12 @@ async fn test_hdb() {
         max_heavy_clients: 1,
         disk_parallelism_per_server: 1,
         disk_parallelism_per_request: 1,
+        max_concurrent_proof_verifications: 1,
+        max_queued_proof_verifications: 1,
         hash_spec_path: None,
-        yubico_api_client_id: None,
+        severity_policy_path: None,
+        // Accept any OTP, so ETs can be screened
+        yubico_api_client_id: Some("allow_all".into()),
         yubico_api_secret_key: None,
         scep_json_size_limit: 100_000,
         et_size_limit: 1_000_000,
@@ -231,6 +247,9 @@ async fn test_hdb() {
         keypair_passphrase_file: format!("{certs_dir}/database-token.passphrase").into(),
         allow_insecure_cookie: true,
         event_store_path: ":memory:".into(),
+        shadow_database: None,
+        shadow_sample_rate: 0.0,
+        max_concurrent_shadow_checks: 0,
     };
     let server_config = Arc::new(ServerConfig {
         main: PlaneConfig {
@@ -247,7 +266,8 @@ async fn test_hdb() {
         load_cfg: stub_cfg(move || (*server_config).clone()),
         read_file: read_no_disk,
     };
-    let server = hdbserver::server_setup()
+    // Clients built with the `mock-prover` feature send stand-ins for proofs
+    let server = hdbserver::server_setup_accepting_mock_proofs()
         .to_server_setup()
         .build_with_external_world(external_world);
     servers.push(server);
@@ -260,17 +280,20 @@ async fn test_hdb() {
         let app_cfg = keyserver::Config {
             id: KeyserverId::try_from(k + 1).unwrap(),
             keyholders_required: KEYHOLDERS_REQUIRED.get(),
-            keyshare: shares[k as usize],
+            keyshare: shares[k as usize].clone(),
             max_heavy_clients: 1,
             crypto_parallelism_per_server: None,
             crypto_parallelism_per_request: None,
+            max_hash_total_count: None,
             active_security_key: active_security_key.clone(),
             scep_json_size_limit: 100_000,
+            session_idle_timeout_secs: keyserver::Config::default_session_idle_timeout_secs(),
             manufacturer_roots: format!("{certs_dir}/manufacturer-roots").into(),
             revocation_list: None,
             token_file: keyserver_file_base.with_extension("kt"),
             keypair_file: keyserver_file_base.with_extension("priv"),
             keypair_passphrase_file: keyserver_file_base.with_extension("passphrase"),
+            hash_preview_token_file: None,
             allow_insecure_cookie: true,
             event_store_path: ":memory:".into(),
         };
@@ -336,7 +359,10 @@ async fn test_hdb() {
                     soft_timeout: None,
                     blocking_timeout: None,
                     soft_extra_keyserver_threshold: None,
+                    min_keyservers: None,
                     soft_extra_hdb_threshold: None,
+                    bad_server_penalty: None,
+                    refresh_failure_backoff: None,
                 },
                 api_client.clone(),
             )
@@ -347,7 +373,8 @@ async fn test_hdb() {
         let client_certs = Arc::new(ClientCerts::load_test_certs());
         let server_versions = Arc::new(tokio::sync::Mutex::new(HashMap::<String, u64>::new()));
 
-        let run_query = |records: Vec<String>, region: Region| {
+        // Screen with `process`, or with `prepare_hashes` and `submit_to_hdb` if `two_step`.
+        let screen_with = |records: Vec<String>, regions: Vec<Region>, two_step: bool| {
             let server_selector = &server_selector;
             let request_ctx = &request_ctx;
             let api_client = api_client.clone();
@@ -359,16 +386,16 @@ async fn test_hdb() {
 
             let certs = client_certs.clone();
             async move {
-                let output = doprf_client::process(DoprfConfig {
+                let config = DoprfConfig {
                     api_client: &api_client,
                     server_selector: server_selector.clone(),
                     request_ctx,
                     certs,
-                    region,
+                    regions,
                     debug_info: false,
                     sequences: &sequences[..],
                     max_windows: u64::MAX,
-                    version_hint: "integration_test".to_owned(),
+                    version_hint: "integration_test".into(),
                     ets: vec![],
                     server_version_handler: &LastServerVersionHandler::new(
                         {
@@ -391,26 +418,61 @@ async fn test_hdb() {
                             })
                         },
                     ),
-                })
-                .await
-                .unwrap();
+                    zero_window_policy: ZeroWindowPolicy::Omit,
+                    hdb_failure_policy: HdbFailurePolicy::FailClosed,
+                    capture_failures: false,
+                    report_window_offsets: false,
+                    keyserver_rate_limiter: None,
+                    max_keyserver_response_bytes: None,
+                    max_total_attempts: None,
+                    overall_deadline: None,
+                    parallel_threshold: None,
+                    canonical_response_order: false,
+                    security_parameter: None,
+                    prover: Default::default(),
+                    keyserver_apply: None,
+                    progress_sink: None,
+                };
+                let output = if two_step {
+                    match doprf_client::prepare_hashes(config).await.unwrap() {
+                        Prepared::Finished(output) => output,
+                        Prepared::Hashed(hashes, hdb_client) => {
+                            doprf_client::submit_to_hdb(hashes, hdb_client)
+                                .await
+                                .unwrap()
+                        }
+                    }
+                } else {
+                    doprf_client::process(config).await.unwrap()
+                };
 
-                println!("{:#?}", output.response);
+                println!("{:#?}", output.responses);
 
                 output
-                    .response
-                    .results
+                    .responses
                     .into_iter()
-                    .flat_map(|matched| {
-                        let ConsolidatedHazardResult { hit_regions, .. } = matched.clone();
-                        hit_regions
+                    .map(|(region, response)| {
+                        let hits = response
+                            .results
                             .into_iter()
-                            .map(|hit_region| (hit_region.seq_range_start, matched.clone()))
-                            .collect::<Vec<_>>()
+                            .flat_map(|matched| {
+                                let ConsolidatedHazardResult { hit_regions, .. } = matched.clone();
+                                hit_regions
+                                    .into_iter()
+                                    .map(|hit_region| (hit_region.seq_range_start, matched.clone()))
+                                    .collect::<Vec<_>>()
+                            })
+                            .collect::<Vec<(usize, ConsolidatedHazardResult)>>();
+                        (region, hits)
                     })
-                    .collect::<Vec<(usize, ConsolidatedHazardResult)>>()
+                    .collect::<HashMap<_, _>>()
             }
         };
+        let run_query_with = |records, region, two_step| {
+            let screen = screen_with(records, vec![region], two_step);
+            async move { screen.await.remove(&region).unwrap() }
+        };
+        let run_query = |records, region| run_query_with(records, region, false);
 
         let t_integrationitis = shared_types::hdb::Organism {
             name: "T. Integrationitis".into(),
@@ -447,6 +509,7 @@ async fn test_hdb() {
                         is_dna: true,
                         is_wild_type: None,
                         exempt: false,
+                        window_offsets: vec![],
                     }
                 )]
             );
@@ -476,10 +539,31 @@ async fn test_hdb() {
                     is_dna: true,
                     is_wild_type: None,
                     exempt: false,
+                    window_offsets: vec![],
                 }
             )]
         );
 
+        // One screen under both regions shares the hashing, but gets each region's verdict
+        let by_region = screen_with(
+            vec![
+                HAZ_RUNT.to_owned(),
+                "GACCCCCAATCACCGCCTCATACTTCTTTG".to_owned(),
+            ],
+            vec![Region::Us, Region::Eu],
+            false,
+        )
+        .await;
+        assert_eq!(by_region.len(), 2);
+        let permissions = |region| -> Vec<SynthesisPermission> {
+            by_region[&region]
+                .iter()
+                .map(|(_, hit)| hit.synthesis_permission)
+                .collect()
+        };
+        assert_eq!(permissions(Region::Us), vec![SynthesisPermission::Denied]);
+        assert_eq!(permissions(Region::Eu), vec![SynthesisPermission::Granted]);
+
         assert_eq!(
             run_query(
                 vec![
@@ -505,6 +589,7 @@ async fn test_hdb() {
                         is_dna: false,
                         is_wild_type: Some(true),
                         exempt: false,
+                        window_offsets: vec![],
                     }
                 ),
                 (
@@ -521,6 +606,7 @@ async fn test_hdb() {
                         is_dna: true,
                         is_wild_type: None,
                         exempt: false,
+                        window_offsets: vec![],
                     }
                 ),
                 (
@@ -537,6 +623,7 @@ async fn test_hdb() {
                         is_dna: true,
                         is_wild_type: None,
                         exempt: false,
+                        window_offsets: vec![],
                     }
                 ),
             ]
@@ -575,6 +662,7 @@ async fn test_hdb() {
                         is_dna: true,
                         is_wild_type: None,
                         exempt: false,
+                        window_offsets: vec![],
                     }
                 ),
                 (
@@ -591,6 +679,7 @@ async fn test_hdb() {
                         is_dna: true,
                         is_wild_type: None,
                         exempt: false,
+                        window_offsets: vec![],
                     }
                 ),
                 (
@@ -607,6 +696,7 @@ async fn test_hdb() {
                         is_dna: true,
                         is_wild_type: None,
                         exempt: false,
+                        window_offsets: vec![],
                     }
                 ),
                 (
@@ -623,10 +713,224 @@ async fn test_hdb() {
                         is_dna: false,
                         is_wild_type: Some(true),
                         exempt: false,
+                        window_offsets: vec![],
                     }
                 ),
             ]
         );
+
+        // Hashing and querying the HDB separately gives the same results as `process`
+        let records = vec![
+            "CATTAG".to_owned(),
+            HAZ_NORMAL.to_owned(),
+            HAZ_RUNT.to_owned(),
+            "CATTAG".to_owned(),
+            HAZ_AA_DNA.to_owned(),
+        ];
+        assert_eq!(
+            run_query_with(records.clone(), Region::All, true).await,
+            run_query_with(records, Region::All, false).await
+        );
+        assert_eq!(
+            run_query_with(vec!["CATTAG".to_owned()], Region::All, true).await,
+            vec![]
+        );
+
+        // An ET exempts the same hazards whether `process` hashes its sequences, or
+        // the caller does and submits them with `submit_to_hdb_with_ets`
+        let ets = vec![WithOtps {
+            et: make_test_et(vec![Organism::new(
+                "T. Integrationitis",
+                vec![SequenceIdentifier::Dna(
+                    Sequence::try_new(format!(">exempt\n{HAZ_NORMAL}")).unwrap(),
+                )],
+            )]),
+            requestor_otp: "123456".to_owned(),
+            issuer_otp: None,
+        }];
+        let sequences =
+            [HAZ_NORMAL, HAZ_RUNT].map(|r| DnaSequence::<Nucleotide>::from_str(r).unwrap());
+        let et_sequences = [DnaSequence::<Nucleotide>::from_str(HAZ_NORMAL).unwrap()];
+        let server_version_handler = LastServerVersionHandler::default();
+        let test_config = TestConfig {
+            api_client: &api_client,
+            server_selector: &server_selector,
+            request_ctx: &request_ctx,
+            certs: &client_certs,
+            server_version_handler: &server_version_handler,
+        };
+
+        let via_process = doprf_client::process(test_config.config(&sequences, ets.clone()))
+            .await
+            .unwrap();
+
+        let et_hashes = doprf_client::hash_only(test_config.config(&et_sequences, vec![]))
+            .await
+            .unwrap()
+            .iter_decoded()
+            .map(|tagged| tagged.unwrap().hash)
+            .collect();
+        let Prepared::Hashed(hashes, hdb_client) =
+            doprf_client::prepare_hashes(test_config.config(&sequences, vec![]))
+                .await
+                .unwrap()
+        else {
+            panic!("expected hashes to submit to the HDB");
+        };
+
+        // Hashing without an HDB gives exactly the hashes a screen sends it
+        let hashed_only = doprf_client::hash_only(test_config.config(&sequences, vec![]))
+            .await
+            .unwrap();
+        assert!(!hashed_only.encoded_items().is_empty());
+        assert_eq!(hashed_only.encoded_items(), hashes.hashes.encoded_items());
+
+        let via_submit =
+            doprf_client::submit_to_hdb_with_ets(hashes, ets.clone(), et_hashes, hdb_client)
+                .await
+                .unwrap();
+
+        let results = |output: &DoprfOutput| output.responses[&Region::All].results.clone();
+        assert_eq!(results(&via_submit), results(&via_process));
+        assert!(results(&via_process).iter().any(|hit| hit.exempt));
+
+        // Asked to, a screen reports the offsets of the windows that hit
+        let haz_normal = [DnaSequence::<Nucleotide>::from_str(HAZ_NORMAL).unwrap()];
+        let mut config = test_config.config(&haz_normal, vec![]);
+        config.report_window_offsets = true;
+        let output = doprf_client::process(config).await.unwrap();
+        let offsets: Vec<_> = results(&output)
+            .into_iter()
+            .map(|hit| hit.window_offsets)
+            .collect();
+        assert_eq!(offsets, vec![vec![0, 1]]);
+
+        // Every phase of a screen is timed, and together they fit within it
+        let start = std::time::Instant::now();
+        let output = doprf_client::process(test_config.config(&sequences, vec![]))
+            .await
+            .unwrap();
+        let total = start.elapsed();
+        let DoprfTimings {
+            windowing,
+            keyserver_query,
+            proof_gen,
+            incorporation,
+            hdb_query,
+        } = output.timings;
+        let phases = [
+            windowing,
+            keyserver_query,
+            proof_gen,
+            incorporation,
+            hdb_query,
+        ];
+        assert!(phases.iter().all(|phase| !phase.is_zero()), "{phases:?}");
+        assert!(phases.iter().sum::<Duration>() <= total);
+
+        // A custom sink sees every phase of hashing, in order
+        let sink = Arc::new(CollectingSink::default());
+        let mut config = test_config.config(&sequences, vec![]);
+        config.progress_sink = Some(sink.clone());
+        doprf_client::process(config).await.unwrap();
+        let events = sink.events.lock().unwrap().clone();
+        let phases: Vec<_> = events.iter().map(|e| e.phase).collect();
+        assert_eq!(phases, ProgressPhase::ALL);
+        for (i, event) in events.iter().enumerate() {
+            assert_eq!(
+                (event.processed, event.total),
+                (i + 1, ProgressPhase::ALL.len())
+            );
+        }
+
+        // A keyserver that applies its share with the wrong Lagrange coefficient
+        // is caught by active security, and blamed for it
+        let quorum: KeyserverIdSet = (1..=KEYHOLDERS_REQUIRED.get())
+            .map(|id| KeyserverId::try_from(id).unwrap())
+            .collect();
+        let misbehaving = KeyserverId::try_from(1).unwrap();
+        let impostor = KeyserverId::try_from(2).unwrap();
+        let impostor_shares = shares.clone();
+        let keyserver_apply: KeyserverApplyFn = Arc::new(move |id, queries| {
+            let coefficient_id = if id == misbehaving { impostor } else { id };
+            let coefficient = quorum.langrange_coefficient_for_id(&coefficient_id);
+            impostor_shares[id.as_u32() as usize - 1]
+                .apply_batch(queries, &coefficient)
+                .into_iter()
+                .collect()
+        });
+        let mut config = test_config.config(&sequences, vec![]);
+        config.keyserver_apply = Some(keyserver_apply);
+        match doprf_client::process(config).await {
+            Err(DoprfError::CryptoError(QueryError::ValidationFailed { keyservers, .. })) => {
+                assert_eq!(keyservers, vec![misbehaving]);
+            }
+            other => panic!("expected a validation failure, got {other:?}"),
+        }
+
+        // The hash counts a screen reports add up to what each keyserver was
+        // actually sent, ET hashes included
+        let quorum: KeyserverIdSet = (1..=KEYHOLDERS_REQUIRED.get())
+            .map(|id| KeyserverId::try_from(id).unwrap())
+            .collect();
+        let sent = Arc::new(Mutex::new(HashMap::<KeyserverId, u64>::new()));
+        let counting_apply: KeyserverApplyFn = {
+            let sent = sent.clone();
+            let shares = shares.clone();
+            Arc::new(move |id, queries| {
+                *sent.lock().unwrap().entry(id).or_default() += queries.len() as u64;
+                let coefficient = quorum.langrange_coefficient_for_id(&id);
+                shares[id.as_u32() as usize - 1]
+                    .apply_batch(queries, &coefficient)
+                    .into_iter()
+                    .collect()
+            })
+        };
+        let mut config = test_config.config(&sequences, ets.clone());
+        config.keyserver_apply = Some(counting_apply);
+        let output = doprf_client::process(config).await.unwrap();
+        let sent = sent.lock().unwrap().clone();
+        assert_eq!(sent.len(), KEYHOLDERS_REQUIRED.get() as usize);
+        for &count in sent.values() {
+            assert_eq!(count, output.n_hashes + output.overhead_hashes);
+        }
+        assert!(output.overhead_hashes > doprf_client::CHECKSUM_HASHES);
+
+        // A rate limited keyserver set paces every request to each keyserver:
+        // opening the session, authenticating, and the queries themselves
+        let keyservers = server_selector.clone().choose().await.unwrap().keyservers;
+        let keyserver_id_set: KeyserverIdSet =
+            keyservers.iter().map(|ks| ks.id).collect::<Vec<_>>().into();
+        let qps = NonZeroU32::new(4).unwrap();
+        let client_config = ClientConfig {
+            api_client: api_client.clone(),
+            certs: client_certs.clone(),
+            version_hint: "integration_test".into(),
+            debug_info: false,
+            keyserver_rate_limiter: Some(Arc::new(RateLimiter::per_second(qps))),
+            max_keyserver_response_bytes: None,
+            keyserver_apply: None,
+            retry_budget: Default::default(),
+        };
+        let queries: PackedRistrettos<Query> = ["foo", "bar"]
+            .into_iter()
+            .map(Query::hash_from_string)
+            .collect();
+        let start = std::time::Instant::now();
+        let keyserver_set_client = KeyserverSetClient::open(
+            keyservers.into_iter().map(|ks| (ks, None)),
+            client_config,
+            100,
+            keyserver_id_set,
+        )
+        .await
+        .unwrap();
+        let responses = keyserver_set_client
+            .query(queries.len() as u64, &queries)
+            .await
+            .unwrap();
+        assert_eq!(responses.len(), KEYHOLDERS_REQUIRED.get() as usize);
+        assert!(start.elapsed() >= 2 * (Duration::from_secs(1) / qps.get()));
     };
     pin_mut!(tests);
 
@@ -637,3 +941,62 @@ async fn test_hdb() {
         panic!("servers stopped running before the tests ended");
     }
 }
+
+/// Collects the progress it's sent.
+#[derive(Default)]
+struct CollectingSink {
+    events: Mutex<Vec<ProgressEvent>>,
+}
+
+#[async_trait::async_trait]
+impl ProgressSink for CollectingSink {
+    async fn report(&self, event: ProgressEvent) {
+        self.events.lock().unwrap().push(event);
+    }
+}
+
+/// What screening configs for tests outside `screen_with` share.
+#[derive(Clone, Copy)]
+struct TestConfig<'a> {
+    api_client: &'a BaseApiClient,
+    server_selector: &'a Arc<ServerSelector>,
+    request_ctx: &'a RequestContext,
+    certs: &'a Arc<ClientCerts>,
+    server_version_handler: &'a LastServerVersionHandler,
+}
+
+impl<'a> TestConfig<'a> {
+    fn config(
+        self,
+        sequences: &'a [DnaSequence<Nucleotide>],
+        ets: Vec<WithOtps<TokenBundle<ExemptionTokenGroup>>>,
+    ) -> DoprfConfig<'a, DnaSequence<Nucleotide>> {
+        DoprfConfig {
+            api_client: self.api_client,
+            server_selector: self.server_selector.clone(),
+            request_ctx: self.request_ctx,
+            certs: self.certs.clone(),
+            regions: vec![Region::All],
+            debug_info: false,
+            sequences,
+            max_windows: u64::MAX,
+            version_hint: "integration_test".into(),
+            ets,
+            server_version_handler: self.server_version_handler,
+            zero_window_policy: ZeroWindowPolicy::Omit,
+            hdb_failure_policy: HdbFailurePolicy::FailClosed,
+            capture_failures: false,
+            report_window_offsets: false,
+            keyserver_rate_limiter: None,
+            max_keyserver_response_bytes: None,
+            max_total_attempts: None,
+            overall_deadline: None,
+            parallel_threshold: None,
+            canonical_response_order: false,
+            security_parameter: None,
+            prover: Default::default(),
+            keyserver_apply: None,
+            progress_sink: None,
+        }
+    }
+}
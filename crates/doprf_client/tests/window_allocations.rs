@@ -0,0 +1,119 @@
+// Copyright 2021-2024 SecureDNA Stiftung (SecureDNA Foundation) <licensing@securedna.org>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Windowing a large order shouldn't leave a heap allocation behind for every
+//! window, and storing windows inline shouldn't change what they hash to.
+//!
+//! This is its own test binary since it counts allocations with a global allocator.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::num::NonZeroUsize;
+
+use doprf::prf::Query;
+use doprf_client::windows::{DnaWindows, Window, Windows};
+use quickdna::{BaseSequence, DnaSequenceStrict, NucleotideAmbiguous, NucleotideLike};
+use shared_types::hash::{HashSpec, HashTypeDescriptor};
+
+/// Counts the allocations made by each thread, so tests running in parallel
+/// don't count each other's.
+struct CountingAllocator;
+
+thread_local! {
+    static LIVE_ALLOCATIONS: Cell<isize> = const { Cell::new(0) };
+}
+
+fn count_allocation(delta: isize) {
+    // Ignore allocations made while the thread is being torn down.
+    let _ = LIVE_ALLOCATIONS.try_with(|live| live.set(live.get() + delta));
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        count_allocation(1);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        count_allocation(-1);
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// Run `f`, returning its output and the number of allocations it left live.
+fn live_allocations_after<T>(f: impl FnOnce() -> T) -> (T, isize) {
+    let before = LIVE_ALLOCATIONS.with(Cell::get);
+    let output = f();
+    let after = LIVE_ALLOCATIONS.with(Cell::get);
+    (output, after - before)
+}
+
+/// Pseudorandom DNA, so windows aren't all alike.
+fn pseudorandom_dna(len: usize) -> DnaSequenceStrict {
+    let mut state: u32 = 42;
+    let dna: String = (0..len)
+        .map(|_| {
+            state = state.wrapping_mul(1103515245).wrapping_add(12345);
+            char::from(b"ACGT"[(state >> 16) as usize % 4])
+        })
+        .collect();
+    dna.parse().unwrap()
+}
+
+#[test]
+fn held_windows_dont_each_allocate() {
+    let dna = pseudorandom_dna(20_000);
+    let spec = HashSpec::unambiguous(vec![
+        HashTypeDescriptor::dna_normal_cech(),
+        HashTypeDescriptor::dna_runt_cech(),
+        HashTypeDescriptor::aa_fw(),
+        HashTypeDescriptor::aa_rc(),
+    ]);
+
+    let (windows, live) = live_allocations_after(|| {
+        Windows::from_dna(dna.iter(), &spec)
+            .unwrap()
+            .collect::<Vec<_>>()
+    });
+    assert!(windows.len() > 70_000, "{} windows", windows.len());
+    assert!(windows.iter().all(|(_, window)| !window.spilled()));
+    // Just the `Vec` of windows, rather than one `String` per window.
+    assert!(
+        live < 10,
+        "{live} live allocations for {} windows",
+        windows.len()
+    );
+}
+
+#[test]
+fn inline_windows_hash_like_their_text() {
+    let dna = pseudorandom_dna(2_000);
+    let htd = HashTypeDescriptor::dna_normal_cech();
+    let windows: Vec<Window> = Windows::from_dna(dna.iter(), &HashSpec::unambiguous(vec![htd]))
+        .unwrap()
+        .map(|(_, window)| window)
+        .collect();
+
+    // How windows were turned into text before they were stored inline.
+    let ambiguous: Vec<NucleotideAmbiguous> = dna
+        .iter()
+        .map(|nuc| nuc.to_ascii().try_into().unwrap())
+        .collect();
+    let window_len = NonZeroUsize::new(shared_types::WINDOW_LENGTH_DNA_NORMAL).unwrap();
+    let texts: Vec<String> = DnaWindows::new(ambiguous, window_len, None)
+        .map(|(_, dna)| dna.iter().map(|&nuc| char::from(nuc)).collect())
+        .collect();
+
+    assert_eq!(windows.len(), texts.len());
+    for (window, text) in windows.iter().zip(&texts) {
+        // Queries are hashed from a window's bytes (see `QueryStateSet::build`).
+        let bytes: &[u8] = window.as_ref();
+        assert_eq!(
+            Query::hash_from_bytes_for_tests_only(bytes),
+            Query::hash_from_string(text)
+        );
+    }
+}
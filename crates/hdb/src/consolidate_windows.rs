@@ -35,7 +35,8 @@
 //! shingled, though. So when hitting a bacterium/fungus, we expect the hits to be spaced into tiles.
 //! Thus we must be more lenient when consolidating bacteria/fungi. This is done by allowing a margin
 //! of `window_gap` between hits, which is 1 or 3bp for shingled hits but 30bp~42bp when the hit
-//! is bacterial/fungal.
+//! is bacterial/fungal. With minimizer windowing, only one window out of every `w` is hashed,
+//! so the margin is multiplied by `w`.
 //!
 //! ## an_likelihood
 //!
@@ -48,12 +49,24 @@ use indexmap::IndexMap;
 use crate::{response::HdbOrganism, HdbResponse, Provenance};
 use serde::{Deserialize, Serialize};
 use shared_types::{
-    hash::{HashSpec, HashTypeDescriptor},
+    hash::{HashSpec, HashTypeDescriptor, Windowing},
     hdb as hdb_api,
     synthesis_permission::SynthesisPermission,
 };
 use thiserror::Error;
 
+/// How far past the last window of a hit region the next hit may start and
+/// still be consolidated into it.
+///
+/// Consecutive minimizers can be up to `w` windows apart, so with minimizer
+/// windowing, hits spaced that far still belong to the same region.
+fn consolidation_margin(window_gap: usize, windowing: Windowing) -> usize {
+    match windowing {
+        Windowing::All => window_gap,
+        Windowing::Minimizer { w, .. } => window_gap * w.max(1),
+    }
+}
+
 /// Consolidated Result of DOPRF on contiguous sequences that were contained in the HDB
 #[derive(Debug, PartialEq, Deserialize, Serialize)]
 pub struct ConsolidatedHazardResult {
@@ -62,6 +75,9 @@ pub struct ConsolidatedHazardResult {
     /// Indexes marking the beginning and end of the hit region, as well as the index of the last
     /// window in the range.
     pub hit_regions: Vec<HitRegion>,
+    /// Where each window that hit starts, in the order they were hit. For
+    /// tiled hazards, these needn't cover `hit_regions`.
+    pub window_starts: Vec<usize>,
     /// Aggregated response from the HDB
     pub hdb_response: HdbResponse,
 }
@@ -135,9 +151,7 @@ pub struct HashId {
 impl HashId {
     pub fn new(hash_tag: HashTag, previous_record: Option<u64>) -> Self {
         Self {
-            record: previous_record
-                .map(|r| r + hash_tag.starts_new_record() as u64)
-                .unwrap_or_default(),
+            record: hash_tag.record_index(previous_record),
             index_in_record: hash_tag
                 .index_in_record()
                 .try_into()
@@ -156,7 +170,8 @@ impl HashId {
 /// close enough and all their metadata matches up. Hits are considered close
 /// enough if they are within the `window_gap` of the hazard they hit; this
 /// means hits for tiled (fungal/bacterial) organisms consolidate more easily
-/// than hits for viral organisms.
+/// than hits for viral organisms. With minimizer windowing, only one window
+/// out of every `w` is hashed, so the margin is widened to match.
 ///
 pub fn consolidate_windows(
     hdb_responses: impl Iterator<Item = (HashId, HdbResponse)>,
@@ -207,7 +222,7 @@ pub fn consolidate_windows(
         let last_window_start = seq_position;
 
         if let Some(last) = res.last_mut() {
-            let margin = hdb_response.window_gap;
+            let margin = consolidation_margin(hdb_response.window_gap, hash_spec.windowing);
             let is_contiguous = seq_range_start <= last.hit_region.last_window_start + margin;
 
             if htd == &last.htd
@@ -216,6 +231,7 @@ pub fn consolidate_windows(
                 && hash_id.record == last.record
             {
                 last.hit_region.window_count += 1;
+                last.window_starts.push(seq_position);
 
                 last.hit_region.last_window_start = last_window_start;
                 last.hit_region.seq_range_end = seq_range_end;
@@ -232,6 +248,7 @@ pub fn consolidate_windows(
 
         res.push(ConsolidatedHits {
             record: hash_id.record,
+            window_starts: vec![seq_position],
             hit_region: HitRegion {
                 seq_range_start,
                 seq_range_end,
@@ -246,33 +263,37 @@ pub fn consolidate_windows(
     // Group again by metadata (GroupKey, which is like HdbResponse w/out an_likelihood)
     //
     // The f32 is an_likelihood, which we sum while iterating.
-    let mut meta2hits: IndexMap<GroupKey, (f32, Vec<HitRegion>)> = IndexMap::new();
+    let mut meta2hits: IndexMap<GroupKey, (f32, Vec<HitRegion>, Vec<usize>)> = IndexMap::new();
     for consolidated_hits in res {
         let an_likelihood = consolidated_hits.hdb_response.an_likelihood;
         let group_key = GroupKey::new(consolidated_hits.record, consolidated_hits.hdb_response);
 
-        let (consolidated_an_likelihood, mapped_hit_regions) =
+        let (consolidated_an_likelihood, mapped_hit_regions, window_starts) =
             meta2hits.entry(group_key).or_default();
         mapped_hit_regions.push(consolidated_hits.hit_region);
+        window_starts.extend(consolidated_hits.window_starts);
         *consolidated_an_likelihood += an_likelihood;
     }
 
     let consolidated_hazard_results: Vec<ConsolidatedHazardResult> = meta2hits
         .into_iter()
         .map(
-            |(group_key, (consolidated_an_likelihood, hit_regions))| ConsolidatedHazardResult {
-                record: group_key.record,
-                hit_regions,
-                hdb_response: HdbResponse {
-                    synthesis_permission: group_key.synthesis_permission,
-                    most_likely_organism: group_key.most_likely_organism,
-                    organisms: group_key.organisms,
-                    an_likelihood: consolidated_an_likelihood,
-                    provenance: group_key.provenance,
-                    reverse_screened: group_key.reverse_screened,
-                    window_gap: group_key.window_gap,
-                    exempt: group_key.exempt,
-                },
+            |(group_key, (consolidated_an_likelihood, hit_regions, window_starts))| {
+                ConsolidatedHazardResult {
+                    record: group_key.record,
+                    hit_regions,
+                    window_starts,
+                    hdb_response: HdbResponse {
+                        synthesis_permission: group_key.synthesis_permission,
+                        most_likely_organism: group_key.most_likely_organism,
+                        organisms: group_key.organisms,
+                        an_likelihood: consolidated_an_likelihood,
+                        provenance: group_key.provenance,
+                        reverse_screened: group_key.reverse_screened,
+                        window_gap: group_key.window_gap,
+                        exempt: group_key.exempt,
+                    },
+                }
             },
         )
         .collect();
@@ -285,6 +306,7 @@ pub fn consolidate_windows(
 
 struct ConsolidatedHits {
     record: u64,
+    window_starts: Vec<usize>,
     hdb_response: HdbResponse,
     hit_region: HitRegion,
     htd: HashTypeDescriptor,
@@ -338,6 +360,34 @@ impl Consolidation {
             }
         }
 
+        fn into_hit_regions(hit_regions: &[HitRegion]) -> Vec<hdb_api::HitRegion> {
+            hit_regions
+                .iter()
+                .map(|x| hdb_api::HitRegion {
+                    seq_range_start: x.seq_range_start,
+                    seq_range_end: x.seq_range_end,
+                })
+                .collect()
+        }
+
+        // A granted, unexempted hit is a hazard that simply isn't controlled for
+        // this screen; worth a second look even though it doesn't block synthesis.
+        let warnings = self
+            .results
+            .iter()
+            .filter(|x| {
+                x.hdb_response.synthesis_permission == SynthesisPermission::Granted
+                    && !x.hdb_response.exempt
+                    && !x.hdb_response.reverse_screened
+            })
+            .map(|x| hdb_api::ScreeningWarning {
+                record: x.record,
+                kind: hdb_api::ScreeningWarningKind::GrantedHazardMatch,
+                organism: into_organism(x.hdb_response.most_likely_organism.clone()),
+                hit_regions: into_hit_regions(&x.hit_regions),
+            })
+            .collect();
+
         hdb_api::HdbScreeningResult {
             results: self
                 .results
@@ -348,14 +398,7 @@ impl Consolidation {
                     } else {
                         Some(hdb_api::ConsolidatedHazardResult {
                             record: x.record,
-                            hit_regions: x
-                                .hit_regions
-                                .into_iter()
-                                .map(|x| hdb_api::HitRegion {
-                                    seq_range_start: x.seq_range_start,
-                                    seq_range_end: x.seq_range_end,
-                                })
-                                .collect(),
+                            hit_regions: into_hit_regions(&x.hit_regions),
                             synthesis_permission: x.hdb_response.synthesis_permission,
                             most_likely_organism: into_organism(
                                 x.hdb_response.most_likely_organism,
@@ -369,6 +412,7 @@ impl Consolidation {
                             is_dna: x.hdb_response.provenance.is_dna(),
                             is_wild_type: x.hdb_response.provenance.is_wild_type(),
                             exempt: x.hdb_response.exempt,
+                            window_offsets: x.window_starts,
                         })
                     }
                 })
@@ -408,6 +452,7 @@ impl Consolidation {
                     .collect()
             }),
             provider_reference,
+            warnings,
         }
     }
 }
@@ -426,6 +471,7 @@ mod test {
         let spec = &HashSpec {
             max_expansions_per_window: NonZeroUsize::MIN,
             htdv: vec![HashTypeDescriptor::dna_normal_fw()],
+            windowing: Windowing::All,
         };
 
         let hdb_response = HdbResponse {
@@ -476,6 +522,7 @@ mod test {
                     last_window_start: 0,
                     window_count: 1,
                 }],
+                window_starts: vec![0],
                 hdb_response: hdb_response.clone(),
             }]
         );
@@ -514,6 +561,7 @@ mod test {
                     last_window_start: 1,
                     window_count: 2,
                 }],
+                window_starts: vec![0, 1],
                 hdb_response: HdbResponse {
                     an_likelihood: 2.0,
                     ..hdb_response.clone()
@@ -563,6 +611,7 @@ mod test {
                         window_count: 1,
                     }
                 ],
+                window_starts: vec![0, 2],
                 hdb_response: HdbResponse {
                     an_likelihood: 2.0,
                     ..hdb_response.clone()
@@ -620,6 +669,7 @@ mod test {
                         window_count: 2,
                     }
                 ],
+                window_starts: vec![0, 2, 3],
                 hdb_response: HdbResponse {
                     an_likelihood: 3.0,
                     ..hdb_response
@@ -633,6 +683,7 @@ mod test {
         let spec = &HashSpec {
             max_expansions_per_window: NonZeroUsize::MIN,
             htdv: vec![HashTypeDescriptor::dna_runt_fw()],
+            windowing: Windowing::All,
         };
 
         let hdb_response = HdbResponse {
@@ -694,6 +745,7 @@ mod test {
                     last_window_start: 60,
                     window_count: 3,
                 }],
+                window_starts: vec![0, 30, 60],
                 hdb_response: HdbResponse {
                     an_likelihood: 3.0,
                     ..hdb_response.clone()
@@ -743,6 +795,7 @@ mod test {
                     last_window_start: 59,
                     window_count: 3,
                 }],
+                window_starts: vec![0, 29, 59],
                 hdb_response: HdbResponse {
                     an_likelihood: 3.0,
                     ..hdb_response
@@ -759,6 +812,7 @@ mod test {
                 HashTypeDescriptor::dna_runt_fw(),
                 HashTypeDescriptor::dna_runt_rc(),
             ],
+            windowing: Windowing::All,
         };
 
         let hdb_response = HdbResponse {
@@ -812,6 +866,7 @@ mod test {
                         last_window_start: 0,
                         window_count: 1,
                     },],
+                    window_starts: vec![0],
                     hdb_response: HdbResponse {
                         an_likelihood: 1.0,
                         ..hdb_response.clone()
@@ -825,6 +880,7 @@ mod test {
                         last_window_start: 0,
                         window_count: 1,
                     }],
+                    window_starts: vec![0],
                     hdb_response: HdbResponse {
                         an_likelihood: 1.0,
                         ..hdb_response
@@ -844,6 +900,7 @@ mod test {
                 HashTypeDescriptor::aa0_fw(),
                 HashTypeDescriptor::dna_runt_fw(),
             ],
+            windowing: Windowing::All,
         };
 
         let organism = HdbOrganism {
@@ -1076,6 +1133,7 @@ mod test {
                             window_count: 2,
                         },
                     ],
+                    window_starts: vec![1, 2, 3, 5, 6],
                     hdb_response: HdbResponse {
                         an_likelihood: 5.0,
                         ..hdb_response_hog
@@ -1103,6 +1161,7 @@ mod test {
                             window_count: 2,
                         }
                     ],
+                    window_starts: vec![0, 3, 5, 6],
                     hdb_response: HdbResponse {
                         an_likelihood: 4.0,
                         ..hdb_response_runt
@@ -1124,6 +1183,7 @@ mod test {
                             window_count: 1,
                         }
                     ],
+                    window_starts: vec![0, 3, 6, 12],
                     hdb_response: HdbResponse {
                         an_likelihood: 4.0,
                         ..hdb_response_aa
@@ -1137,6 +1197,7 @@ mod test {
                         last_window_start: 1,
                         window_count: 2
                     }],
+                    window_starts: vec![0, 1],
                     hdb_response: HdbResponse {
                         an_likelihood: 2.0,
                         ..hdb_response_runt_tiled
@@ -1150,6 +1211,7 @@ mod test {
                         last_window_start: 6,
                         window_count: 3
                     }],
+                    window_starts: vec![0, 3, 6],
                     hdb_response: HdbResponse {
                         an_likelihood: 3.0,
                         ..hdb_response_aa_tiled
@@ -1158,4 +1220,133 @@ mod test {
             ]
         );
     }
+
+    #[test]
+    fn test_granted_hit_warns_without_denying() {
+        let spec = &HashSpec {
+            max_expansions_per_window: NonZeroUsize::MIN,
+            htdv: vec![HashTypeDescriptor::dna_normal_fw()],
+            windowing: Windowing::All,
+        };
+
+        let response = |synthesis_permission| HdbResponse {
+            synthesis_permission,
+            most_likely_organism: HdbOrganism {
+                name: "Test Hazard".into(),
+                organism_type: pipeline_bridge::OrganismType::Virus,
+                ans: vec![],
+                tags: vec![],
+            },
+            organisms: vec![],
+            an_likelihood: 1.0,
+            provenance: Provenance::DnaNormal,
+            reverse_screened: false,
+            window_gap: 1,
+            exempt: false,
+        };
+        let hit = |record| HashId {
+            record,
+            index_in_record: 0,
+            hash_type_index: 0,
+        };
+
+        let result = consolidate_windows(
+            [
+                (hit(0), response(SynthesisPermission::Granted)),
+                (hit(1), response(SynthesisPermission::Denied)),
+            ]
+            .into_iter(),
+            spec,
+            false,
+        )
+        .unwrap()
+        .to_hdb_screening_result(None);
+
+        assert_eq!(
+            result.results[0].synthesis_permission,
+            SynthesisPermission::Granted
+        );
+        assert_eq!(
+            result.warnings,
+            vec![hdb_api::ScreeningWarning {
+                record: 0,
+                kind: hdb_api::ScreeningWarningKind::GrantedHazardMatch,
+                organism: hdb_api::Organism {
+                    name: "Test Hazard".into(),
+                    organism_type: pipeline_bridge::OrganismType::Virus,
+                    ans: vec![],
+                    tags: vec![],
+                },
+                hit_regions: vec![hdb_api::HitRegion {
+                    seq_range_start: 0,
+                    seq_range_end: 42,
+                }],
+            }]
+        );
+    }
+
+    #[test]
+    fn test_window_consolidation_minimizer_spaced() {
+        let spec = &HashSpec {
+            max_expansions_per_window: NonZeroUsize::MIN,
+            htdv: vec![HashTypeDescriptor::dna_normal_fw()],
+            windowing: Windowing::Minimizer { k: 8, w: 5 },
+        };
+
+        let hdb_response = HdbResponse {
+            synthesis_permission: SynthesisPermission::Denied,
+            most_likely_organism: HdbOrganism {
+                name: "Test Hazard".into(),
+                organism_type: pipeline_bridge::OrganismType::Virus,
+                ans: vec![],
+                tags: vec![],
+            },
+            organisms: vec![],
+            an_likelihood: 1.0,
+            provenance: Provenance::DnaNormal,
+            reverse_screened: false,
+            window_gap: 1,
+            exempt: false,
+        };
+        let hit = |index_in_record| {
+            (
+                HashId {
+                    record: 0,
+                    index_in_record,
+                    hash_type_index: 0,
+                },
+                hdb_response.clone(),
+            )
+        };
+
+        // Minimizers at most `w` windows apart consolidate into one region,
+        // one further than that starts a new one.
+        assert_eq!(
+            consolidate_windows([0, 3, 8, 14].into_iter().map(hit), spec, false)
+                .unwrap()
+                .results,
+            vec![ConsolidatedHazardResult {
+                record: 0,
+                hit_regions: vec![
+                    HitRegion {
+                        seq_range_start: 0,
+                        seq_range_end: 50,
+                        last_window_start: 8,
+                        window_count: 3,
+                    },
+                    HitRegion {
+                        seq_range_start: 14,
+                        seq_range_end: 56,
+                        last_window_start: 14,
+                        window_count: 1,
+                    }
+                ],
+                window_starts: vec![0, 3, 8, 14],
+                hdb_response: HdbResponse {
+                    an_likelihood: 4.0,
+                    ..hdb_response.clone()
+                }
+            }]
+        );
+    }
 }
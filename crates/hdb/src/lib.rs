@@ -21,6 +21,7 @@ pub use metadata::{Metadata, MetadataDecodeError, Provenance};
 pub use response::{HdbOrganism, HdbResponse, HdbResponseError};
 
 use shared_types::synthesis_permission::{Region, SynthesisPermission};
+use synthesis_permission::{DefaultSeverityPolicy, SeverityPolicy};
 
 /// Parameters passed to the HDB alongside a query.
 #[derive(Debug)]
@@ -33,6 +34,8 @@ pub struct HdbParams<'a> {
 pub struct HdbConfig<'a> {
     pub database: &'a Database,
     pub hlt: &'a HazardLookupTable,
+    /// How hazard tags map to a synthesis permission in each region.
+    pub severity_policy: &'a dyn SeverityPolicy,
 }
 
 /// Run the entire HDB flow, returning `Some(HdbResponse)` if an entry exists, and `None` otherwise.
@@ -49,7 +52,13 @@ pub fn query_hdb(
         .map_err(|e| QueryError::FileRead(e, *query))?;
 
     if let Some(entry) = entry {
-        let response = entry_to_response(entry, params.region, params.exemptions, config.hlt)?;
+        let response = entry_to_response_with_policy(
+            entry,
+            params.region,
+            params.exemptions,
+            config.hlt,
+            config.severity_policy,
+        )?;
         Ok(Some(response))
     } else {
         Ok(None)
@@ -66,12 +75,23 @@ pub fn entry_to_response(
     region: Region,
     exemptions: &Exemptions,
     hlt: &HazardLookupTable,
+) -> Result<HdbResponse, QueryError> {
+    entry_to_response_with_policy(entry, region, exemptions, hlt, &DefaultSeverityPolicy)
+}
+
+/// Like [`entry_to_response`], but mapping hazard tags to a permission with `policy`.
+pub fn entry_to_response_with_policy(
+    entry: Entry,
+    region: Region,
+    exemptions: &Exemptions,
+    hlt: &HazardLookupTable,
+    policy: &dyn SeverityPolicy,
 ) -> Result<HdbResponse, QueryError> {
     let metadata = entry
         .metadata()
         .map_err(|e| QueryError::MetadataFormat(e, entry))?;
 
-    let mut response = HdbResponse::with_hlt(metadata, region, exemptions, hlt)
+    let mut response = HdbResponse::with_hlt_and_policy(metadata, region, exemptions, hlt, policy)
         .map_err(|e| QueryError::HdbResponse(e, metadata))?;
 
     if response.synthesis_permission == SynthesisPermission::Denied
@@ -169,4 +189,56 @@ mod test {
             }
         );
     }
+
+    /// Treats select agents as reportable but not blocking, in every region.
+    struct LenientSelectAgents;
+
+    impl SeverityPolicy for LenientSelectAgents {
+        fn permission(&self, tags: &[Tag], region: Region) -> SynthesisPermission {
+            let tags = tags.iter().copied().filter(|tag| {
+                !matches!(
+                    tag,
+                    Tag::SelectAgentHhs | Tag::SelectAgentUsda | Tag::SelectAgentAphis
+                )
+            });
+            shared_types::synthesis_permission::permission_for_region(tags, region)
+        }
+    }
+
+    #[test]
+    fn severity_policy_decides_permission() {
+        let hlt: HazardLookupTable = serde_json::from_str(TEST_HLT).unwrap();
+        let metadata = Metadata {
+            hlt_index: 198,
+            an_subindex: 0,
+            an_likelihood: half::f16::from_f32(0.5),
+            provenance: Provenance::AAWildType,
+            reverse_screened: false,
+            is_common: false,
+        };
+        let entry = Entry::new(HashPart::from_rp(Default::default()), metadata);
+        let exemptions = Default::default();
+
+        let default = entry_to_response_with_policy(
+            entry,
+            Region::Us,
+            &exemptions,
+            &hlt,
+            &DefaultSeverityPolicy,
+        )
+        .unwrap();
+        let lenient = entry_to_response_with_policy(
+            entry,
+            Region::Us,
+            &exemptions,
+            &hlt,
+            &LenientSelectAgents,
+        )
+        .unwrap();
+
+        assert_eq!(default.synthesis_permission, SynthesisPermission::Denied);
+        assert_eq!(lenient.synthesis_permission, SynthesisPermission::Granted);
+        assert_eq!(default.organisms, lenient.organisms);
+        assert!(!lenient.exempt);
+    }
 }
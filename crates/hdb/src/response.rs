@@ -7,8 +7,9 @@ use pipeline_bridge::{OrganismType, Tag};
 use shared_types::synthesis_permission::{Region, SynthesisPermission};
 
 use crate::{
-    hlt::HltLookupError, synthesis_permission::PermissionResult, tags, Exemptions,
-    HazardLookupTable, HltId, Metadata, Provenance,
+    hlt::HltLookupError,
+    synthesis_permission::{DefaultSeverityPolicy, PermissionResult, SeverityPolicy},
+    tags, Exemptions, HazardLookupTable, HltId, Metadata, Provenance,
 };
 
 /// Note: if modifying this structure, make sure to also modify Hash impl!
@@ -96,6 +97,17 @@ impl HdbResponse {
         region: Region,
         exemptions: &Exemptions,
         hlt: &HazardLookupTable,
+    ) -> Result<Self, HltLookupError> {
+        Self::with_hlt_and_policy(metadata, region, exemptions, hlt, &DefaultSeverityPolicy)
+    }
+
+    /// Like [`HdbResponse::with_hlt`], but mapping tags to a permission with `policy`.
+    pub fn with_hlt_and_policy(
+        metadata: Metadata,
+        region: Region,
+        exemptions: &Exemptions,
+        hlt: &HazardLookupTable,
+        policy: &dyn SeverityPolicy,
     ) -> Result<Self, HltLookupError> {
         let (hlt_entry, likely_hltids) =
             hlt.get_with_subindex(&metadata.hlt_index, &metadata.an_subindex)?;
@@ -127,12 +139,14 @@ impl HdbResponse {
         let tiled = likely_hltids.iter().any(HltId::tiled);
         let window_gap = metadata.provenance.window_gap(tiled);
 
-        let PermissionResult { permission, exempt } = crate::synthesis_permission::get_permission(
-            &organisms,
-            metadata.reverse_screened,
-            region,
-            exemptions,
-        );
+        let PermissionResult { permission, exempt } =
+            crate::synthesis_permission::get_permission_with_policy(
+                &organisms,
+                metadata.reverse_screened,
+                region,
+                exemptions,
+                policy,
+            );
 
         Ok(Self {
             synthesis_permission: permission,
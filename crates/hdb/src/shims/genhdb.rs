@@ -345,7 +345,7 @@ fn hash_file(opts: &Opts, fraglist: &Path, hlt_index: u32, an_subindex: u8) -> a
         let num_sub_batches = BATCH_SIZE / opts.num_threads + 1;
         for chunk in line_iter.chunks(num_sub_batches).into_iter() {
             let chunk = chunk.collect::<Vec<std::io::Result<String>>>();
-            let key = opts.secret_key;
+            let key = opts.secret_key.clone();
             handles.push(std::thread::spawn(move || {
                 hash_chunk(&key, chunk, hlt_index, an_subindex)
             }))
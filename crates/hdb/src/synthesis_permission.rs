@@ -1,9 +1,51 @@
 // Copyright 2021-2024 SecureDNA Stiftung (SecureDNA Foundation) <licensing@securedna.org>
 // SPDX-License-Identifier: MIT OR Apache-2.0
 
+use std::collections::HashMap;
+
 use crate::{Exemptions, HdbOrganism};
 use pipeline_bridge::Tag;
-use shared_types::synthesis_permission::{permission_for_region, Region, SynthesisPermission};
+use serde::Deserialize;
+use shared_types::synthesis_permission::{
+    permission_for_region, tag_by_region_flag_table, Region, SynthesisPermission,
+};
+
+/// Maps a hazard's tags to a [`SynthesisPermission`] in a given region.
+///
+/// Different regulatory regimes can treat the same hazard differently, so the
+/// HDB can be configured with a policy other than [`DefaultSeverityPolicy`].
+pub trait SeverityPolicy: Send + Sync {
+    fn permission(&self, tags: &[Tag], region: Region) -> SynthesisPermission;
+}
+
+/// The built-in region flag table, see [`permission_for_region`].
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DefaultSeverityPolicy;
+
+impl SeverityPolicy for DefaultSeverityPolicy {
+    fn permission(&self, tags: &[Tag], region: Region) -> SynthesisPermission {
+        permission_for_region(tags.iter().copied(), region)
+    }
+}
+
+/// The built-in region flag table, with the permissions for some tags in some
+/// regions overridden. Deserializes from e.g.
+/// `{"SelectAgentHhs": {"Us": "granted", "All": "granted"}}`.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(transparent)]
+pub struct OverrideSeverityPolicy(HashMap<Tag, HashMap<Region, SynthesisPermission>>);
+
+impl SeverityPolicy for OverrideSeverityPolicy {
+    fn permission(&self, tags: &[Tag], region: Region) -> SynthesisPermission {
+        SynthesisPermission::merge(tags.iter().map(|&tag| {
+            self.0
+                .get(&tag)
+                .and_then(|by_region| by_region.get(&region))
+                .copied()
+                .unwrap_or_else(|| tag_by_region_flag_table(tag, region))
+        }))
+    }
+}
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub struct PermissionResult {
@@ -43,6 +85,23 @@ pub fn get_permission<'o>(
     reverse_screened: bool,
     region: Region,
     exemptions: &Exemptions,
+) -> PermissionResult {
+    get_permission_with_policy(
+        organisms,
+        reverse_screened,
+        region,
+        exemptions,
+        &DefaultSeverityPolicy,
+    )
+}
+
+/// Like [`get_permission`], but mapping tags to permissions with `policy`.
+pub fn get_permission_with_policy<'o>(
+    organisms: impl IntoIterator<Item = &'o HdbOrganism>,
+    reverse_screened: bool,
+    region: Region,
+    exemptions: &Exemptions,
+    policy: &dyn SeverityPolicy,
 ) -> PermissionResult {
     use SynthesisPermission::{Denied, Granted};
 
@@ -61,8 +120,8 @@ pub fn get_permission<'o>(
         }
     }
 
-    let actual_permission = permission_for_region(actual_tags, region);
-    let without_el = permission_for_region(all_tags, region);
+    let actual_permission = policy.permission(&actual_tags, region);
+    let without_el = policy.permission(&all_tags, region);
     match (actual_permission, without_el) {
         (Granted, Granted) => PermissionResult::granted(),
         (Granted, Denied) => PermissionResult::exempt(),
@@ -197,4 +256,22 @@ mod tests {
             PermissionResult::exempt(),
         )
     }
+
+    #[test]
+    fn override_policy_replaces_only_overridden_regions() {
+        let policy: OverrideSeverityPolicy =
+            serde_json::from_str(r#"{"SelectAgentAphis": {"Us": "granted"}}"#).unwrap();
+        let permission = |region| {
+            get_permission_with_policy(
+                &[make_organism(&[Tag::SelectAgentAphis])],
+                false,
+                region,
+                &Default::default(),
+                &policy,
+            )
+        };
+
+        assert_eq!(permission(Region::Us), PermissionResult::granted());
+        assert_eq!(permission(Region::All), PermissionResult::denied());
+    }
 }
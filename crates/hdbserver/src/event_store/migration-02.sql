@@ -0,0 +1,13 @@
+-- Copyright 2021-2024 SecureDNA Stiftung (SecureDNA Foundation) <licensing@securedna.org>
+-- SPDX-License-Identifier: MIT OR Apache-2.0
+
+-- This migration adds a table of screen events that will never get a row in
+-- "screen_results", because the request was cancelled or failed after the
+-- event was recorded. Every screen event should eventually have either a
+-- result or an abandonment.
+
+CREATE TABLE screen_abandonments(
+    screen_id INTEGER NOT NULL PRIMARY KEY,
+    timestamp_utc INTEGER NOT NULL,
+    FOREIGN KEY (screen_id) REFERENCES screen_events(screen_id) ON DELETE RESTRICT
+) STRICT;
@@ -21,7 +21,8 @@ use shared_types::{
     et::WithOtps,
     synthesis_permission::{Region, SynthesisPermission},
 };
-use tracing::warn;
+use tokio::sync::oneshot;
+use tracing::{error, warn};
 
 pub async fn open_db(path: impl AsRef<Path>) -> Result<Connection, OpenError> {
     persistence::open_db(
@@ -30,6 +31,7 @@ pub async fn open_db(path: impl AsRef<Path>) -> Result<Connection, OpenError> {
         Migrations::from_iter([
             M::up(include_str!("migration-00.sql")),
             M::up(include_str!("migration-01.sql")),
+            M::up(include_str!("migration-02.sql")),
         ]),
     )
     .await
@@ -153,6 +155,111 @@ pub async fn insert_screen_result(
     Ok(())
 }
 
+pub async fn insert_screen_abandonment(
+    conn: &Connection,
+    screen_event: ScreenEventId,
+) -> Result<(), tokio_rusqlite::Error> {
+    conn.call(move |conn| {
+        conn.execute(
+            r#"
+            INSERT INTO screen_abandonments (screen_id, timestamp_utc)
+            VALUES (?1, ?2);
+            "#,
+            params![screen_event.0, SqlOffsetDateTime::now_utc()],
+        )?;
+        Ok(())
+    })
+    .await?;
+    Ok(())
+}
+
+/// A screen event that is waiting for its result.
+///
+/// The writes behind it run on detached tasks, so they complete even if the
+/// request handler is cancelled. If it's dropped before
+/// [`ScreenRecord::finish`] is called, the event is marked as abandoned.
+pub struct ScreenRecord {
+    conn: Connection,
+    screen_event: Option<ScreenEventId>,
+}
+
+impl ScreenRecord {
+    /// Insert a screen event, as in [`insert_screen_event`].
+    pub async fn begin(
+        conn: &Connection,
+        client_mid: Id,
+        screened_bp: u64,
+        region: Region,
+        ets: Vec<WithOtps<TokenBundle<ExemptionTokenGroup>>>,
+    ) -> Result<Self, tokio_rusqlite::Error> {
+        let (sender, receiver) = oneshot::channel();
+        let task_conn = conn.clone();
+        tokio::spawn(async move {
+            let inserted =
+                insert_screen_event(&task_conn, client_mid, screened_bp, region, &ets).await;
+            if let Err(Ok(screen_event)) = sender.send(inserted) {
+                // Cancelled before the handler could learn the event's ID.
+                abandon(&task_conn, screen_event).await;
+            }
+        });
+        let screen_event = receiver
+            .await
+            .map_err(|e| tokio_rusqlite::Error::Other(e.into()))??;
+        Ok(Self {
+            conn: conn.clone(),
+            screen_event: Some(screen_event),
+        })
+    }
+
+    /// Record the event's result, as in [`insert_screen_result`].
+    pub async fn finish(
+        mut self,
+        synthesis_permission: SynthesisPermission,
+    ) -> Result<(), tokio_rusqlite::Error> {
+        let screen_event = self
+            .screen_event
+            .take()
+            .expect("screen record already finished");
+        let conn = self.conn.clone();
+        tokio::spawn(async move {
+            insert_screen_result(&conn, screen_event, synthesis_permission).await
+        })
+        .await
+        .map_err(|e| tokio_rusqlite::Error::Other(e.into()))?
+    }
+}
+
+impl Drop for ScreenRecord {
+    fn drop(&mut self) {
+        if let Some(screen_event) = self.screen_event.take() {
+            let conn = self.conn.clone();
+            tokio::spawn(async move { abandon(&conn, screen_event).await });
+        }
+    }
+}
+
+async fn abandon(conn: &Connection, screen_event: ScreenEventId) {
+    if let Err(e) = insert_screen_abandonment(conn, screen_event).await {
+        error!("Failed to persist abandonment of screen event {screen_event:?}: {e}");
+    }
+}
+
+/// Screen events that were abandoned before they got a result.
+pub async fn query_abandoned_screen_events(
+    conn: &Connection,
+) -> Result<Vec<ScreenEventId>, tokio_rusqlite::Error> {
+    let ids = conn
+        .call(|conn| {
+            let ids = conn
+                .prepare("SELECT screen_id FROM screen_abandonments ORDER BY screen_id;")?
+                .query_map([], |row| row.get(0).map(ScreenEventId))?
+                .collect::<Result<_, _>>()?;
+            Ok(ids)
+        })
+        .await?;
+    Ok(ids)
+}
+
 pub async fn insert_ratelimit_exceedance(
     conn: &Connection,
     client_mid: Id,
@@ -615,4 +722,66 @@ mod tests {
         assert!(exceedances_per_day.contains(&(date.into(), client_2_id, 1)));
         assert!(exceedances_per_day.contains(&(date_1.into(), client_1_id, 1)));
     }
+
+    #[tokio::test]
+    async fn cancelled_screens_are_marked_abandoned() {
+        let conn = open_db(":memory:").await.unwrap();
+        let [client, _] = make_synth_tokens();
+        let client_id = *client.token.issuance_id();
+        insert_open_event(&conn, &client, 0).await.unwrap();
+
+        // Cancelled while the event is being inserted
+        let begin = ScreenRecord::begin(&conn, client_id, 100, Region::Us, vec![]);
+        tokio::time::timeout(std::time::Duration::ZERO, begin)
+            .await
+            .unwrap_err();
+
+        // Cancelled while screening, after the event was inserted
+        let record = ScreenRecord::begin(&conn, client_id, 200, Region::Us, vec![])
+            .await
+            .unwrap();
+        drop(record);
+
+        // Finished normally
+        let record = ScreenRecord::begin(&conn, client_id, 300, Region::Us, vec![])
+            .await
+            .unwrap();
+        record.finish(SynthesisPermission::Granted).await.unwrap();
+
+        // Abandonments are written by detached tasks, so wait for them
+        let mut abandoned = vec![];
+        for _ in 0..100 {
+            abandoned = query_abandoned_screen_events(&conn).await.unwrap();
+            if abandoned.len() == 2 {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        assert_eq!(abandoned.len(), 2);
+
+        // Every event has exactly one of a result or an abandonment
+        let unresolved: u64 = conn
+            .call(|conn| {
+                let count = conn.query_row(
+                    r#"
+                    SELECT COUNT(*) FROM screen_events e
+                    WHERE (SELECT COUNT(*) FROM screen_results r WHERE r.screen_id = e.screen_id)
+                        + (SELECT COUNT(*) FROM screen_abandonments a WHERE a.screen_id = e.screen_id)
+                        != 1;
+                    "#,
+                    [],
+                    |row| row.get(0),
+                )?;
+                Ok(count)
+            })
+            .await
+            .unwrap();
+        assert_eq!(unresolved, 0);
+        assert_eq!(
+            query_client_screened_bp_in_last_day(&conn, client_id)
+                .await
+                .unwrap(),
+            600
+        );
+    }
 }
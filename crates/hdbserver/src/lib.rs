@@ -3,11 +3,13 @@
 
 pub mod event_store;
 mod opts;
+mod proof_verification;
 mod qualification;
 mod screening;
 mod server;
+mod shadow;
 mod state;
 mod validation;
 
 pub use opts::{Config, Opts};
-pub use server::server_setup;
+pub use server::{server_setup, server_setup_accepting_mock_proofs};
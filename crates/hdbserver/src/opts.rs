@@ -54,6 +54,24 @@ pub struct Config {
     #[serde(default = "Config::default_disk_parallelism_per_request")]
     pub disk_parallelism_per_request: usize,
 
+    #[clap(
+        long,
+        help = "Maximum simultaneous proof verifications, by default half the available cores. Further verifications wait for one to finish",
+        default_value_t = Config::default_max_concurrent_proof_verifications(),
+        env = "SECUREDNA_HDBSERVER_MAX_CONCURRENT_PROOF_VERIFICATIONS"
+    )]
+    #[serde(default = "Config::default_max_concurrent_proof_verifications")]
+    pub max_concurrent_proof_verifications: usize,
+
+    #[clap(
+        long,
+        help = "Maximum proof verifications waiting to run before 503 unavailable is returned",
+        default_value_t = Config::default_max_queued_proof_verifications(),
+        env = "SECUREDNA_HDBSERVER_MAX_QUEUED_PROOF_VERIFICATIONS"
+    )]
+    #[serde(default = "Config::default_max_queued_proof_verifications")]
+    pub max_queued_proof_verifications: usize,
+
     #[clap(
         long,
         help = "Path to a JSON file describing a hash spec",
@@ -61,6 +79,13 @@ pub struct Config {
     )]
     pub hash_spec_path: Option<PathBuf>,
 
+    #[clap(
+        long,
+        help = "Path to a JSON file overriding the built-in synthesis permission of tags per region, e.g. {\"SelectAgentHhs\": {\"Us\": \"granted\"}}",
+        env = "SECUREDNA_HDBSERVER_SEVERITY_POLICY_PATH"
+    )]
+    pub severity_policy_path: Option<PathBuf>,
+
     #[clap(
         long,
         help = "Yubico API client ID. This is a short digit string, used to verify YubiKey OTPs when handling an order with a 2FA-enabled exemption. If set to the string 'allow_all', all YubiKey OTPs are treated as valid.",
@@ -152,6 +177,31 @@ pub struct Config {
     )]
     #[serde(default = "Config::default_event_store_path")]
     pub event_store_path: PathBuf,
+
+    #[clap(
+        long,
+        help = "Secondary database to shadow-screen against, e.g. during an HDB migration. Differences are logged but never change the returned result.",
+        env = "SECUREDNA_HDBSERVER_SHADOW_DATABASE"
+    )]
+    pub shadow_database: Option<PathBuf>,
+
+    #[clap(
+        long,
+        help = "Fraction of screening requests (0.0 to 1.0) that are also run against the shadow database",
+        default_value_t = Config::default_shadow_sample_rate(),
+        env = "SECUREDNA_HDBSERVER_SHADOW_SAMPLE_RATE"
+    )]
+    #[serde(default = "Config::default_shadow_sample_rate")]
+    pub shadow_sample_rate: f64,
+
+    #[clap(
+        long,
+        help = "Maximum number of windows checked against the shadow database at once; sampled windows beyond this aren't checked",
+        default_value_t = Config::default_max_concurrent_shadow_checks(),
+        env = "SECUREDNA_HDBSERVER_MAX_CONCURRENT_SHADOW_CHECKS"
+    )]
+    #[serde(default = "Config::default_max_concurrent_shadow_checks")]
+    pub max_concurrent_shadow_checks: u32,
 }
 
 impl Config {
@@ -167,6 +217,14 @@ impl Config {
         256
     }
 
+    pub fn default_max_concurrent_proof_verifications() -> usize {
+        std::thread::available_parallelism().map_or(1, |n| (n.get() / 2).max(1))
+    }
+
+    pub fn default_max_queued_proof_verifications() -> usize {
+        64
+    }
+
     pub fn default_scep_json_size_limit() -> u64 {
         100000
     }
@@ -178,6 +236,14 @@ impl Config {
     pub fn default_event_store_path() -> PathBuf {
         ":memory:".into()
     }
+
+    pub fn default_shadow_sample_rate() -> f64 {
+        0.1
+    }
+
+    pub fn default_max_concurrent_shadow_checks() -> u32 {
+        256
+    }
 }
 
 pub const DEFAULT_HASH_SPEC: &str = r#"{
@@ -195,7 +261,9 @@ impl RelativeConfig for Config {
     fn relative_to(mut self, base: impl AsRef<Path>) -> Self {
         let base = base.as_ref();
         self.database = base.join(self.database);
+        self.shadow_database = self.shadow_database.map(|p| base.join(p));
         self.hash_spec_path = self.hash_spec_path.map(|p| base.join(p));
+        self.severity_policy_path = self.severity_policy_path.map(|p| base.join(p));
         self.exemption_roots = base.join(self.exemption_roots);
         self.manufacturer_roots = base.join(self.manufacturer_roots);
         self.revocation_list = self.revocation_list.map(|p| base.join(p));
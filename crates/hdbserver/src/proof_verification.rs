@@ -0,0 +1,136 @@
+// Copyright 2021-2024 SecureDNA Stiftung (SecureDNA Foundation) <licensing@securedna.org>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Bounds on concurrent proof verifications.
+//!
+//! Verifying a proof is CPU-heavy, so without a bound a burst of
+//! proof-bearing screens could saturate every core and starve plain screens.
+//! Verifications beyond the limit wait for a permit; once too many are
+//! waiting, further ones are turned away with 503.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use hyper::StatusCode;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use minhttp::response::{self, GenericResponse};
+
+pub struct ProofVerifications {
+    permits: Arc<Semaphore>,
+    /// Verifications currently waiting for a permit.
+    waiting: AtomicUsize,
+    max_waiting: usize,
+}
+
+impl ProofVerifications {
+    pub fn new(max_concurrent: usize, max_waiting: usize) -> Self {
+        Self {
+            permits: Arc::new(Semaphore::new(max_concurrent)),
+            waiting: AtomicUsize::new(0),
+            max_waiting,
+        }
+    }
+
+    /// Wait for a permit to verify a proof, which should be held for the
+    /// duration of the verification.
+    ///
+    /// Returns a 503 response if `max_waiting` verifications are already
+    /// waiting for a permit.
+    pub async fn acquire(&self) -> Result<OwnedSemaphorePermit, GenericResponse> {
+        if let Ok(permit) = self.permits.clone().try_acquire_owned() {
+            return Ok(permit);
+        }
+
+        let waiting = WaitingGuard::enter(&self.waiting, self.max_waiting).ok_or_else(|| {
+            response::text(
+                StatusCode::SERVICE_UNAVAILABLE,
+                "Too many proofs awaiting verification. Try again later.",
+            )
+        })?;
+        let permit = self
+            .permits
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("proof verification semaphore is never closed");
+        drop(waiting);
+        Ok(permit)
+    }
+}
+
+/// Counts a waiting verification, until dropped (including when the waiting
+/// request is cancelled).
+struct WaitingGuard<'a>(&'a AtomicUsize);
+
+impl<'a> WaitingGuard<'a> {
+    fn enter(waiting: &'a AtomicUsize, max_waiting: usize) -> Option<Self> {
+        waiting
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |n| {
+                (n < max_waiting).then_some(n + 1)
+            })
+            .ok()
+            .map(|_| Self(waiting))
+    }
+}
+
+impl Drop for WaitingGuard<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn concurrent_verifications_never_exceed_limit() {
+        let verifications = Arc::new(ProofVerifications::new(2, 100));
+        let active = Arc::new(AtomicUsize::new(0));
+        let max_active = Arc::new(AtomicUsize::new(0));
+
+        let tasks: Vec<_> = (0..20)
+            .map(|_| {
+                let verifications = verifications.clone();
+                let active = active.clone();
+                let max_active = max_active.clone();
+                tokio::spawn(async move {
+                    let _permit = verifications.acquire().await.unwrap();
+                    let now_active = active.fetch_add(1, Ordering::SeqCst) + 1;
+                    max_active.fetch_max(now_active, Ordering::SeqCst);
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                    active.fetch_sub(1, Ordering::SeqCst);
+                })
+            })
+            .collect();
+        for task in tasks {
+            task.await.unwrap();
+        }
+
+        assert_eq!(max_active.load(Ordering::SeqCst), 2);
+        assert_eq!(verifications.waiting.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn verifications_are_rejected_when_queue_is_full() {
+        let verifications = Arc::new(ProofVerifications::new(1, 1));
+        let running = verifications.acquire().await.unwrap();
+
+        let queued = tokio::spawn({
+            let verifications = verifications.clone();
+            async move { verifications.acquire().await.map(drop).is_ok() }
+        });
+        while verifications.waiting.load(Ordering::SeqCst) == 0 {
+            tokio::task::yield_now().await;
+        }
+
+        let rejected = verifications.acquire().await.unwrap_err();
+        assert_eq!(rejected.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        drop(running);
+        assert!(queued.await.unwrap());
+    }
+}
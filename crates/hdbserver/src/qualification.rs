@@ -6,9 +6,13 @@ use hyper::body::{Body, Incoming};
 use hyper::{Request, StatusCode};
 use tracing::warn;
 
+use doprf::tagged::TaggedHash;
 use minhttp::response::{self, ErrResponse, ResponseResult};
-use shared_types::server_selection::{HdbQualificationResponse, QualificationRequest};
+use shared_types::server_selection::{
+    Capabilities, HdbQualificationResponse, QualificationRequest, JSON_CONTENT_TYPE,
+};
 use streamed_ristretto::stream::MessageError;
+use streamed_ristretto::HasContentType;
 
 use crate::state::HdbServerState;
 
@@ -34,7 +38,7 @@ pub async fn qualification(
     let data: QualificationRequest = serde_json::from_slice(&body)
         .map_err(|e| ErrResponse(response::text(StatusCode::BAD_REQUEST, e)))?;
 
-    if data.client_version != 0 {
+    if !data.is_supported_version() {
         return Err(ErrResponse(response::text(
             StatusCode::BAD_REQUEST,
             "bad client version",
@@ -43,6 +47,10 @@ pub async fn qualification(
 
     let response = HdbQualificationResponse {
         supported_generations: vec![0],
+        capabilities: Capabilities {
+            content_types: vec![TaggedHash::CONTENT_TYPE.into(), JSON_CONTENT_TYPE.into()],
+            encodings: vec![],
+        },
     };
 
     let json = serde_json::to_string(&response).map_err(|err| {
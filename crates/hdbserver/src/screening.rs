@@ -1,19 +1,21 @@
 // Copyright 2021-2024 SecureDNA Stiftung (SecureDNA Foundation) <licensing@securedna.org>
 // SPDX-License-Identifier: MIT OR Apache-2.0
 
+use std::convert::Infallible;
 use std::sync::Arc;
 use tracing::debug;
 use anyhow::Context;
 use doprf::prf::{CompletedHashValue, VerificationInput};
-use futures::{StreamExt, TryStreamExt};
-use http_body_util::{BodyExt, Full};
+use doprf::prove::{ExecutionStats, PublicValuesReader};
+use futures::{stream, Stream, StreamExt, TryStreamExt};
+use http_body_util::BodyExt;
 use bytes::Bytes;
 use hyper::body::{Body, Incoming};
 use hyper::{Request, StatusCode};
 use scep::states::{EtState, ServerStateForClient};
 use scep::steps::{server_et_client, server_et_seq_hashes_client};
 use tracing::{error, info, warn};
-use packed_ristretto::PackedRistrettos;
+use packed_ristretto::{PackedRistrettos, VerificationOutcome};
 use sp1_sdk::{
     include_elf, HashableKey, ProverClient, SP1Proof, SP1ProofWithPublicValues, SP1Stdin,
     SP1VerifyingKey,
@@ -22,14 +24,14 @@ use sp1_sdk::{
 use certificates::Issued;
 use doprf::tagged::{HashTag, TaggedHash};
 use hdb::consolidate_windows::{consolidate_windows, HashId};
-use hdb::{Exemptions, HdbConfig, HdbParams};
+use hdb::{Exemptions, HdbConfig, HdbParams, HdbResponse};
 use minhttp::response::{self, GenericResponse};
 use once_cell::sync::Lazy;
 use scep::error::ScepError;
 use scep::types::{ScreenCommon, ScreenWithExemptionParams};
 use shared_types::hdb::HdbScreeningResult;
 use shared_types::requests::RequestId;
-use shared_types::synthesis_permission::SynthesisPermission;
+use shared_types::synthesis_permission::{Region, SynthesisPermission};
 use streamed_ristretto::hyper::{check_content_length, from_request};
 use streamed_ristretto::stream::{check_content_type, ShortErrorMsg, StreamableRistretto, decode, encode};
 use streamed_ristretto::HasContentType;
@@ -87,6 +89,9 @@ pub async fn scep_endpoint_screen_and_verify(
     struct RequestWithVerification {
         ristretto_data: Vec<u8>,
         verification: VerificationInput,
+        // Older clients don't report execution stats
+        #[serde(default)]
+        execution_stats: ExecutionStats,
     }
 
     // Check content type for JSON
@@ -103,11 +108,7 @@ pub async fn scep_endpoint_screen_and_verify(
         .write()
         .await
         .take_session(&cookie)
-        .ok_or_else(|| {
-            scep::error::ScepError::InvalidMessage(anyhow::anyhow!("unknown cookie {cookie}"))
-        })?;
-    let client_mid = client_state.open_request().client_mid();
-    let debug_info = client_state.open_request().debug_info;
+        .ok_or(scep::error::ScepError::UnknownSessionCookie(cookie))?;
 
     // Consume body and deserialize
     let bytes = request
@@ -122,213 +123,115 @@ pub async fn scep_endpoint_screen_and_verify(
         .map_err(|e| scep::error::ScepError::InvalidMessage(e.into()))?;
 
     // Verify the proof
-    let client = ProverClient::new();
-    let VerificationInput { proof, vk } = request_data.verification;
-    client.verify(&proof, &vk).expect("verification failed");
-    println!("HDB verification successful");
-
-    // Build a fake request to mimic the form expected in scep_endpoint_screen, data is moved
-    let fake_request = Request::builder()
-    .header("Content-Type", TaggedHash::CONTENT_TYPE)
-    .body(Full::new(Bytes::from(request_data.ristretto_data)))
-    .expect("failed to build fake request");
-
-    let hash_count_from_content_len =
-        check_content_length(fake_request.body().size_hint().exact(), TaggedHash::SIZE)
-            .context("in screen")
-            .map_err(scep::error::ScepError::InvalidMessage)?;
-
-    let (params, client_state) =
-        scep::steps::server_screen_client(hash_count_from_content_len, client_state)?;
-
-    let ScreenCommon {
-        region,
-        provider_reference,
-    } = params;
-
-    let permit = match hdbs_state.throttle_heavy_requests() {
+    let verification_permit = match hdbs_state.proof_verifications.acquire().await {
         Ok(permit) => permit,
         Err(err_response) => return Ok(err_response),
     };
-
-    let (ets, exemptions) = match client_state.et_state {
-        EtState::NoEt => (vec![], NO_EXEMPTIONS.clone()),
-        EtState::AwaitingEtSize | EtState::PromisedEt { .. } => {
-            return Err(scep::error::Screen::ScreenBeforeEt.into())
-        }
-        EtState::EtNeedsHashes { .. } => {
-            return Err(scep::error::Screen::ScreenBeforeEtHashes.into())
-        }
-        EtState::EtReady { ets, hashes } => {
-            let exemptions = exemptions_after_validation(
-                ets.clone(),
-                hashes.into_iter().collect(),
-                &hdbs_state.validator,
-                &hdbs_state.exemptions_roots,
-                &hdbs_state.scep.revocation_list,
-            )
-            .await
-            .map_err(|e| scep::error::Screen::EtValidation(e.to_string()))?;
-
-            (ets, Arc::new(exemptions))
-        }
-    };
+    // Verifying is CPU-heavy, so keep it off the async workers. The permit moves
+    // into the task so it's held until verification actually finishes.
+    let verifying_state = hdbs_state.clone();
+    let verification = request_data.verification;
+    let ristretto_data = Bytes::from(request_data.ristretto_data);
+    let verified_data = ristretto_data.clone();
+    tokio::task::spawn_blocking(move || {
+        let _permit = verification_permit;
+        verify_proof(
+            &verifying_state.prover_client,
+            verification,
+            &verified_data,
+            verifying_state.accept_mock_proofs,
+        )
+    })
+    .await
+    .map_err(|e| ScepError::InternalError(e.into()))??;
+    debug!("HDB verification successful");
 
     if let Some(metrics) = &hdbs_state.metrics {
-        metrics.requests.inc();
+        let stats = request_data.execution_stats;
+        metrics.hash_program_cycles.inc_by(stats.hash_cycles);
+        metrics
+            .checksum_program_cycles
+            .inc_by(stats.checksum_cycles);
+        metrics
+            .verification_program_cycles
+            .inc_by(stats.verification_cycles);
     }
 
-    let num_hashes = check_content_length(fake_request.body().size_hint().exact(), TaggedHash::SIZE)
-        .context("in screen")
-        .map_err(ScepError::InvalidMessage)?;
-    info!("{request_id}: Processing request of size {num_hashes}");
-
-    let queries = from_request(fake_request)
+    let hash_count = check_content_length(Some(ristretto_data.len() as u64), TaggedHash::SIZE)
         .context("in screen_and_verify")
         .map_err(ScepError::InvalidMessage)?;
+    let queries = decode_queries(ristretto_data);
 
-    struct LogDone(RequestId);
+    run_screen_core(request_id, hdbs_state, client_state, hash_count, queries).await
+}
 
-    impl Drop for LogDone {
-        fn drop(&mut self) {
-            info!("{}: Done.", self.0);
-        }
+/// Verify the client's proof, and that it proves `ristretto_data` are the
+/// hashes the verification program computed. With `accept_mock_proofs`, the
+/// stand-ins that mock provers send instead of proofs are accepted without
+/// verifying, but what they commit is still checked.
+fn verify_proof(
+    client: &ProverClient,
+    verification: VerificationInput,
+    ristretto_data: &[u8],
+    accept_mock_proofs: bool,
+) -> Result<(), scep::error::Screen> {
+    if accept_mock_proofs && verification.is_mock() {
+        warn!("Accepting a mock proof without verifying it");
+    } else {
+        // `verify` picks the routine for the proof's kind, so any mode the client
+        // was configured with is accepted, but only as a wrapped proof
+        let Some(mode) = verification.proof_mode() else {
+            let error = "not a compressed, Groth16 or PLONK proof".to_owned();
+            return Err(scep::error::Screen::ProofVerificationFailed(error));
+        };
+        debug!("Verifying a {mode} proof");
+        client
+            .verify(&verification.proof, &verification.vk)
+            .map_err(|e| scep::error::Screen::ProofVerificationFailed(e.to_string()))?;
     }
+    let mut committed = PublicValuesReader::new(&verification.proof.public_values);
+    check_committed_hashes(&mut committed, ristretto_data)
+}
 
-    let logdone = LogDone(request_id.clone());
-
-    let screen_evt_id = match event_store::insert_screen_event(
-        &hdbs_state.persistence_connection,
-        client_mid,
-        client_state.open_request.nucleotide_total_count,
-        region,
-        &ets,
-    )
-    .await
-    {
-        Ok(id) => Some(id),
-        Err(e) => {
-            error!("Failed to persist screen event for {client_mid}: {e}");
-            None
-        }
+/// Check that the verification program committed [`VerificationOutcome::Ok`]
+/// with exactly the hashes in `ristretto_data`. A proof of the program giving
+/// up on bad keyserver responses verifies just as well as one of it succeeding.
+fn check_committed_hashes(
+    committed: &mut PublicValuesReader<'_>,
+    ristretto_data: &[u8],
+) -> Result<(), scep::error::Screen> {
+    let outcome = committed
+        .read::<VerificationOutcome>()
+        .map_err(|e| scep::error::Screen::ProofVerificationFailed(e.to_string()))?;
+    let VerificationOutcome::Ok(hashes) = outcome else {
+        let error = format!("the verification program failed: {outcome}");
+        return Err(scep::error::Screen::ProofVerificationFailed(error));
     };
-
-    let mut last_record = None;
-    let hdbs_state2 = hdbs_state.clone();
-    let exemptions2 = exemptions.clone();
-    let hdb_responses: Result<Vec<_>, anyhow::Error> = queries
-        .map(move |query| {
-            let _permit = &permit;
-            let _logdone = &logdone;
-
-            let hash_id_and_query = query.map(|query: UnparsedTaggedHash| {
-                let hash_id = HashId::new(query.hash_tag(), last_record);
-                last_record = Some(hash_id.record);
-                (hash_id, query)
-            });
-
-            let hdbs_state2 = hdbs_state2.clone();
-            let exemptions2 = exemptions2.clone();
-            async move {
-                let (hash_id, query) = hash_id_and_query?;
-
-                let permit = hdbs_state2
-                    .hdb_queries
-                    .clone()
-                    .acquire_owned()
-                    .await
-                    .unwrap();
-                // TODO: Maybe use a nursery to prevent orphans, so long as that's not too expensive?
-                let hdbs_state3 = hdbs_state2.clone();
-                let exemptions3 = exemptions2.clone();
-                let resp = tokio::task::spawn_blocking(move || {
-                    let _permit = permit;
-                    let params = HdbParams {
-                        region,
-                        exemptions: &exemptions3,
-                    };
-                    let config = HdbConfig {
-                        database: &hdbs_state3.database,
-                        hlt: &hdbs_state3.hlt,
-                    };
-                    hdb::query_hdb(query.hash_bytes(), &params, &config)
-                })
-                .await
-                .unwrap()?;
-
-                // only incremented if there's no error
-                if let Some(metrics) = &hdbs_state2.metrics {
-                    metrics.hash_counter.inc();
-                }
-
-                Ok(resp.map(|r| (hash_id, r)))
-            }
-        })
-        .buffered(hdbs_state.parallelism_per_request)
-        .filter_map(|res| async { res.transpose() })
-        .try_collect()
-        .await;
-
-    let hdb_responses = match hdb_responses {
-        Ok(r) => r,
-        Err(err) => {
-            warn!("Error while processing HDB records: {err:?}");
-            if let Some(metrics) = &hdbs_state.metrics {
-                metrics.io_errors.inc();
-            }
-            return Ok(response::text(
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "internal server error",
-            ));
-        }
-    };
-
-    let consolidation =
-        consolidate_windows(hdb_responses.into_iter(), &hdbs_state.hash_spec, debug_info)
-            .context("in screen consolidation")
-            .map_err(ScepError::InternalError)?;
-
-    let response: HdbScreeningResult = consolidation.to_hdb_screening_result(provider_reference);
-
-    let merged_permission =
-        SynthesisPermission::merge(response.results.iter().map(|r| r.synthesis_permission));
-    info!(
-        message = "screened",
-        %client_mid,
-        issued_to=client_state.open_request.cert_chain.token.issuer_description(),
-        screened_bp=client_state.open_request.nucleotide_total_count,
-        hash_count=hash_count_from_content_len,
-        %merged_permission,
-    );
-    if let Some(screen_evt_id) = screen_evt_id {
-        if let Err(e) = event_store::insert_screen_result(
-            &hdbs_state.persistence_connection,
-            screen_evt_id,
-            shared_types::synthesis_permission::SynthesisPermission::merge(
-                response.results.iter().map(|r| r.synthesis_permission),
-            ),
-        )
-        .await
-        {
-            error!(
-                "Failed to persist screening result for {}: {e}",
-                client_state.open_request.client_mid()
-            );
-        }
+    if !hashes.encoded_items().iter().flatten().eq(ristretto_data) {
+        let error = "the proven hashes aren't the ones to screen".to_owned();
+        return Err(scep::error::Screen::ProofVerificationFailed(error));
     }
+    Ok(())
+}
 
-    let json = serde_json::to_string(&response)
-        .context("in screen serialization")
-        .map_err(ScepError::InternalError)?;
-
-    Ok(response::json(StatusCode::OK, json))
+/// Decode the hashes to screen from a body that has already been read in full.
+fn decode_queries(
+    data: Bytes,
+) -> impl Stream<Item = Result<UnparsedTaggedHash, impl std::error::Error + Send + Sync + 'static>>
+{
+    decode(stream::iter([Ok::<_, Infallible>(data)]))
 }
 
-pub async fn scep_endpoint_screen(
+pub async fn scep_endpoint_screen<B>(
     request_id: &RequestId,
     hdbs_state: Arc<HdbServerState>,
-    request: Request<Incoming>,
-) -> Result<GenericResponse, scep::error::ScepError<scep::error::Screen>> {
+    request: Request<B>,
+) -> Result<GenericResponse, scep::error::ScepError<scep::error::Screen>>
+where
+    B: Body + Send + Sync + 'static,
+    B::Data: Send + Sync,
+    B::Error: std::error::Error + Send + Sync + 'static,
+{
     check_content_type(request.headers(), TaggedHash::CONTENT_TYPE)
         .context("in screen")
         .map_err(scep::error::ScepError::InvalidMessage)?;
@@ -341,19 +244,34 @@ pub async fn scep_endpoint_screen(
         .write()
         .await
         .take_session(&cookie)
-        .ok_or_else(|| {
-            scep::error::ScepError::InvalidMessage(anyhow::anyhow!("unknown cookie {cookie}"))
-        })?;
+        .ok_or(scep::error::ScepError::UnknownSessionCookie(cookie))?;
+
+    let hash_count = check_content_length(request.body().size_hint().exact(), TaggedHash::SIZE)
+        .context("in screen")
+        .map_err(ScepError::InvalidMessage)?;
+    let queries = from_request(request)
+        .context("in screen")
+        .map_err(ScepError::InvalidMessage)?;
+
+    run_screen_core(request_id, hdbs_state, client_state, hash_count, queries).await
+}
+
+/// Screen `queries`, the `hash_count` hashes sent by the client with session
+/// `client_state`, however the endpoint read them.
+async fn run_screen_core<E>(
+    request_id: &RequestId,
+    hdbs_state: Arc<HdbServerState>,
+    client_state: ServerStateForClient,
+    hash_count: u64,
+    queries: impl Stream<Item = Result<UnparsedTaggedHash, E>>,
+) -> Result<GenericResponse, scep::error::ScepError<scep::error::Screen>>
+where
+    E: std::error::Error + Send + Sync + 'static,
+{
     let client_mid = client_state.open_request().client_mid();
     let debug_info = client_state.open_request().debug_info;
 
-    let hash_count_from_content_len =
-        check_content_length(request.body().size_hint().exact(), TaggedHash::SIZE)
-            .context("in screen")
-            .map_err(scep::error::ScepError::InvalidMessage)?;
-
-    let (params, client_state) =
-        scep::steps::server_screen_client(hash_count_from_content_len, client_state)?;
+    let (params, client_state) = scep::steps::server_screen_client(hash_count, client_state)?;
 
     let ScreenCommon {
         region,
@@ -392,14 +310,7 @@ pub async fn scep_endpoint_screen(
         metrics.requests.inc();
     }
 
-    let num_hashes = check_content_length(request.body().size_hint().exact(), TaggedHash::SIZE)
-        .context("in screen")
-        .map_err(ScepError::InvalidMessage)?;
-    info!("{request_id}: Processing request of size {num_hashes}");
-
-    let queries = from_request(request)
-        .context("in screen")
-        .map_err(ScepError::InvalidMessage)?;
+    info!("{request_id}: Processing request of size {hash_count}");
 
     struct LogDone(RequestId);
 
@@ -411,22 +322,30 @@ pub async fn scep_endpoint_screen(
 
     let logdone = LogDone(request_id.clone());
 
-    let screen_evt_id = match event_store::insert_screen_event(
+    // Dropped without a result (e.g. if this handler is cancelled), the event
+    // is marked as abandoned.
+    let screen_record = match event_store::ScreenRecord::begin(
         &hdbs_state.persistence_connection,
         client_mid,
         client_state.open_request.nucleotide_total_count,
         region,
-        &ets,
+        ets,
     )
     .await
     {
-        Ok(id) => Some(id),
+        Ok(record) => Some(record),
         Err(e) => {
             error!("Failed to persist screen event for {client_mid}: {e}");
             None
         }
     };
 
+    let shadow_sampled = hdbs_state
+        .shadow
+        .as_ref()
+        .is_some_and(|shadow| shadow.should_sample());
+    let shadow_request_id = Arc::new(request_id.clone());
+
     let mut last_record = None;
     let hdbs_state2 = hdbs_state.clone();
     let exemptions2 = exemptions.clone();
@@ -443,8 +362,10 @@ pub async fn scep_endpoint_screen(
 
             let hdbs_state2 = hdbs_state2.clone();
             let exemptions2 = exemptions2.clone();
+            let shadow_request_id = shadow_request_id.clone();
             async move {
                 let (hash_id, query) = hash_id_and_query?;
+                let hash = *query.hash_bytes();
 
                 let permit = hdbs_state2
                     .hdb_queries
@@ -464,12 +385,25 @@ pub async fn scep_endpoint_screen(
                     let config = HdbConfig {
                         database: &hdbs_state3.database,
                         hlt: &hdbs_state3.hlt,
+                        severity_policy: &*hdbs_state3.severity_policy,
                     };
                     hdb::query_hdb(query.hash_bytes(), &params, &config)
                 })
                 .await
                 .unwrap()?;
 
+                if shadow_sampled {
+                    spawn_shadow_check(
+                        &hdbs_state2,
+                        shadow_request_id,
+                        hash_id,
+                        hash,
+                        region,
+                        exemptions2,
+                        resp.clone(),
+                    );
+                }
+
                 // only incremented if there's no error
                 if let Some(metrics) = &hdbs_state2.metrics {
                     metrics.hash_counter.inc();
@@ -511,19 +445,11 @@ pub async fn scep_endpoint_screen(
         %client_mid,
         issued_to=client_state.open_request.cert_chain.token.issuer_description(),
         screened_bp=client_state.open_request.nucleotide_total_count,
-        hash_count=num_hashes,
+        hash_count=hash_count,
         %merged_permission,
     );
-    if let Some(screen_evt_id) = screen_evt_id {
-        if let Err(e) = event_store::insert_screen_result(
-            &hdbs_state.persistence_connection,
-            screen_evt_id,
-            shared_types::synthesis_permission::SynthesisPermission::merge(
-                response.results.iter().map(|r| r.synthesis_permission),
-            ),
-        )
-        .await
-        {
+    if let Some(screen_record) = screen_record {
+        if let Err(e) = screen_record.finish(merged_permission).await {
             error!(
                 "Failed to persist screening result for {}: {e}",
                 client_state.open_request.client_mid()
@@ -538,6 +464,47 @@ pub async fn scep_endpoint_screen(
     Ok(response::json(StatusCode::OK, json))
 }
 
+/// Check a window against the shadow HDB, given the `primary` HDB's response,
+/// on a blocking task of its own: neither the screening response nor the
+/// primary HDB's query permits wait on it. Skipped if the shadow HDB is
+/// already running as many checks as it may.
+fn spawn_shadow_check(
+    hdbs_state: &Arc<HdbServerState>,
+    request_id: Arc<RequestId>,
+    hash_id: HashId,
+    hash: [u8; 32],
+    region: Region,
+    exemptions: Arc<Exemptions>,
+    primary: Option<HdbResponse>,
+) {
+    let Some(shadow) = &hdbs_state.shadow else {
+        return;
+    };
+    let Some(permit) = shadow.try_reserve_check() else {
+        debug!("{request_id}: shadow HDB busy, skipping window");
+        return;
+    };
+    let hdbs_state = hdbs_state.clone();
+    tokio::task::spawn_blocking(move || {
+        let _permit = permit;
+        let Some(shadow) = &hdbs_state.shadow else {
+            return;
+        };
+        let params = HdbParams {
+            region,
+            exemptions: &exemptions,
+        };
+        shadow.check_window(
+            &request_id,
+            hash_id,
+            &hash,
+            &params,
+            &*hdbs_state.severity_policy,
+            primary.as_ref(),
+        );
+    });
+}
+
 pub async fn scep_endpoint_screen_with_exemption(
     _request_id: &RequestId,
     hdbs_state: Arc<HdbServerState>,
@@ -551,9 +518,7 @@ pub async fn scep_endpoint_screen_with_exemption(
         .write()
         .await
         .take_session(&cookie)
-        .ok_or_else(|| {
-            scep::error::ScepError::InvalidMessage(anyhow::anyhow!("unknown cookie {cookie}"))
-        })?;
+        .ok_or(scep::error::ScepError::UnknownSessionCookie(cookie))?;
 
     let bytes = request
         .into_body()
@@ -607,9 +572,7 @@ pub async fn scep_endpoint_exemption(
         .write()
         .await
         .take_session(&cookie)
-        .ok_or_else(|| {
-            scep::error::ScepError::InvalidMessage(anyhow::anyhow!("unknown cookie {cookie}"))
-        })?;
+        .ok_or(scep::error::ScepError::UnknownSessionCookie(cookie))?;
 
     let et_body = request
         .into_body()
@@ -654,9 +617,7 @@ pub async fn scep_endpoint_exemption_seq_hashes(
         .write()
         .await
         .take_session(&cookie)
-        .ok_or_else(|| {
-            scep::error::ScepError::InvalidMessage(anyhow::anyhow!("unknown cookie {cookie}"))
-        })?;
+        .ok_or(scep::error::ScepError::UnknownSessionCookie(cookie))?;
 
     let hashes: Vec<_> = from_request::<_, CompletedHashValue>(request)
         .context("in exemption-seq-hashes")
@@ -683,3 +644,380 @@ pub async fn scep_endpoint_exemption_seq_hashes(
     // Give them the OK to hit /exemption-screen-hashes next.
     Ok(response::json(StatusCode::OK, "{}"))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Write;
+    use std::path::{Path, PathBuf};
+    use std::str::FromStr;
+
+    use certificates::DatabaseTokenGroup;
+    use doprf::party::KeyserverIdSet;
+    use doprf::prf::{KeyShare, Query};
+    use hdb::shims::genhdb;
+    use hdb::synthesis_permission::DefaultSeverityPolicy;
+    use hdb::{Database, HazardLookupTable};
+    use http_body_util::Full;
+    use rand::Rng;
+    use scep::cookie::SessionCookie;
+    use scep::states::ServerStateForAuthenticatedClient;
+    use scep::types::ClientRequestType;
+    use scep_client_helpers::ClientCerts;
+    use scep_server_helpers::server::ServerState;
+    use shared_types::hash::HashSpec;
+    use tokio::sync::Semaphore;
+
+    use crate::proof_verification::ProofVerifications;
+    use crate::shadow::ShadowHdb;
+    use crate::validation::NetworkingValidator;
+
+    const CERTS_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../../test/certs");
+
+    /// Arbitrary canonically-encoded scalar used as the HDB secret key.
+    const SECRET_KEY: &str = "0f1e2d3c4b5a69788796a5b4c3d2e1f00f1e2d3c4b5a69788796a5b4c3d2e100";
+
+    /// 42-mer written to the fixture HDB.
+    const HAZARD: &str = "CGGCTTTTTGGTAGTTAGGCTATTGGTAGGATAGATGTTCGC";
+
+    /// 42-mer that is not in the fixture HDB.
+    const NOT_A_HAZARD: &str = "ACGTACGTACGTACGTACGTACGTACGTACGTACGTACGTAC";
+
+    const ORGANISM_NAME: &str = "T. Fixturitis";
+
+    /// Build an HDB under `dir` containing only `hazard`, returning the database path.
+    fn build_fixture_hdb(dir: &Path, key: &KeyShare, hazard_window: &str) -> PathBuf {
+        let artifacts_dir = dir.join("artifacts");
+        std::fs::create_dir(&artifacts_dir).unwrap();
+        let dna_normal_fraglist = artifacts_dir.join("dna42.fraglist");
+        let dna_runt_fraglist = artifacts_dir.join("dna30.fraglist");
+        let protein_fraglist = artifacts_dir.join("protein.fraglist");
+
+        let hazard = pipeline_bridge::AggregatedHazard {
+            hazard_meta: pipeline_bridge::HazardProperties {
+                path_name: "Testus_Fixturitis".into(),
+                common_name: ORGANISM_NAME.into(),
+                accessions: vec!["TST_00000".into()],
+                tags: vec![
+                    pipeline_bridge::Tag::SelectAgentAphis,
+                    pipeline_bridge::Tag::PRCExportControlPart1,
+                ],
+                tiled: false,
+                organism_type: pipeline_bridge::OrganismType::Virus,
+            },
+            dna_variant_42mers_path: dna_normal_fraglist.clone(),
+            dna_variant_30mers_path: dna_runt_fraglist.clone(),
+            protein_variants_path: protein_fraglist.clone(),
+        };
+        serde_json::to_writer(
+            std::fs::File::create(artifacts_dir.join("aggregated.json")).unwrap(),
+            &vec![hazard],
+        )
+        .unwrap();
+
+        let entry = pipeline_bridge::VariantEntry {
+            variant: hazard_window.into(),
+            provenance: None,
+            log_likelihood: None,
+            reverse_screened: None,
+            is_common: false,
+        };
+        let mut fraglist = std::fs::File::create(&dna_normal_fraglist).unwrap();
+        writeln!(fraglist, "{}", serde_json::to_string(&entry).unwrap()).unwrap();
+        std::fs::File::create(&dna_runt_fraglist).unwrap();
+        std::fs::File::create(&protein_fraglist).unwrap();
+
+        let database = dir.join("hdb");
+        genhdb::main(&genhdb::Opts {
+            secret_key: key.clone(),
+            artifacts_dir,
+            database: database.clone(),
+            command: genhdb::Command::New { force: false },
+            skip_build_info: true,
+            num_threads: 1,
+            sort_only: false,
+            index_mb: 1,
+        })
+        .unwrap();
+        database
+    }
+
+    async fn fixture_state(database: &Path, shadow: Option<ShadowHdb>) -> Arc<HdbServerState> {
+        let passphrase =
+            std::fs::read_to_string(format!("{CERTS_DIR}/database-token.passphrase")).unwrap();
+        let hash_spec: HashSpec = serde_json::from_str(crate::opts::DEFAULT_HASH_SPEC).unwrap();
+
+        Arc::new(HdbServerState {
+            build_timestamp: None,
+            database: Database::open(database).unwrap(),
+            heavy_requests: Arc::new(Semaphore::new(1)),
+            hlt: HazardLookupTable::read(database).unwrap(),
+            metrics: None,
+            hdb_queries: Arc::new(Semaphore::new(1)),
+            proof_verifications: ProofVerifications::new(1, 1),
+            prover_client: Lazy::new(ProverClient::new),
+            parallelism_per_request: 1,
+            hash_spec,
+            validator: NetworkingValidator {
+                yubico_api_client_id: None,
+                yubico_api_secret_key: None,
+            },
+            scep: ServerState {
+                clients: Default::default(),
+                json_size_limit: 100_000,
+                manufacturer_roots: vec![],
+                revocation_list: Default::default(),
+                token_bundle: scep_server_helpers::certs::read_tokenbundle::<DatabaseTokenGroup>(
+                    format!("{CERTS_DIR}/database-token.dt"),
+                )
+                .unwrap(),
+                keypair: scep_server_helpers::certs::read_keypair(
+                    format!("{CERTS_DIR}/database-token.priv"),
+                    passphrase.trim(),
+                )
+                .unwrap(),
+                max_hash_total_count: None,
+                allow_insecure_cookie: true,
+            },
+            et_size_limit: 1_000_000,
+            exemptions_roots: vec![],
+            persistence_path: ":memory:".into(),
+            persistence_connection: event_store::open_db(":memory:").await.unwrap(),
+            shadow,
+            severity_policy: Arc::new(DefaultSeverityPolicy),
+            accept_mock_proofs: false,
+        })
+    }
+
+    /// An authenticated screening session for `windows` (as a single record
+    /// of 42-mers).
+    fn screening_session(
+        cookie: SessionCookie,
+        region: Region,
+        windows: &[&str],
+    ) -> ServerStateForClient {
+        let (open_request, _) = scep::steps::client_initialize(
+            ClientRequestType::Screen(ScreenCommon {
+                region,
+                provider_reference: Some("fixture".into()),
+            }),
+            "screening_fixture_test".into(),
+            ClientCerts::load_test_certs().token,
+            (windows.len() * 42) as u64,
+            None,
+            KeyserverIdSet::from(vec![]),
+            false,
+        );
+
+        ServerStateForClient::Authenticated(ServerStateForAuthenticatedClient {
+            cookie,
+            open_request,
+            server_nonce: rand::thread_rng().gen(),
+            hash_total_count: windows.len() as u64,
+            et_state: EtState::NoEt,
+        })
+    }
+
+    /// The tagged hashes of `windows` (as a single record), as the client sends them.
+    fn screen_body(key: &KeyShare, windows: &[&str]) -> Bytes {
+        let mut body = vec![];
+        for (i, window) in windows.iter().enumerate() {
+            let hash = key.apply(Query::hash_from_string(window));
+            body.extend_from_slice(HashTag::new(i == 0, 0, i).as_bytes());
+            body.extend_from_slice(&<[u8; 32]>::from(hash));
+        }
+        body.into()
+    }
+
+    async fn screening_result(response: GenericResponse) -> HdbScreeningResult {
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    /// Seed an authenticated screening session, then post `windows` (as a single
+    /// record of 42-mers) to the screen endpoint.
+    async fn screen(
+        hdbs_state: Arc<HdbServerState>,
+        key: &KeyShare,
+        region: Region,
+        windows: &[&str],
+    ) -> HdbScreeningResult {
+        let cookie: SessionCookie = rand::thread_rng().gen();
+        hdbs_state
+            .scep
+            .clients
+            .write()
+            .await
+            .add_session(cookie, screening_session(cookie, region, windows))
+            .unwrap();
+
+        let request = Request::post(scep::SCREEN_ENDPOINT)
+            .header(hyper::header::CONTENT_TYPE, TaggedHash::CONTENT_TYPE)
+            .header(hyper::header::COOKIE, format!("SecureDNA={cookie}"))
+            .body(Full::new(screen_body(key, windows)))
+            .unwrap();
+
+        let request_id = RequestId::from_str("screening_fixture_test").unwrap();
+        let response = scep_endpoint_screen(&request_id, hdbs_state, request)
+            .await
+            .unwrap();
+        screening_result(response).await
+    }
+
+    #[tokio::test]
+    async fn screen_fixture_hazard_is_denied() {
+        let dir = tempfile::tempdir().unwrap();
+        let key = KeyShare::from_str(SECRET_KEY).unwrap();
+        let hdbs_state = fixture_state(&build_fixture_hdb(dir.path(), &key, HAZARD), None).await;
+
+        let result = screen(hdbs_state, &key, Region::All, &[HAZARD]).await;
+
+        assert_eq!(result.provider_reference.as_deref(), Some("fixture"));
+        assert_eq!(result.results.len(), 1);
+        let hit = &result.results[0];
+        assert_eq!(hit.record, 0);
+        assert_eq!(hit.synthesis_permission, SynthesisPermission::Denied);
+        assert_eq!(hit.most_likely_organism.name, ORGANISM_NAME);
+        assert!(hit.is_dna);
+        assert!(!hit.exempt);
+        assert_eq!(hit.hit_regions.len(), 1);
+        assert_eq!(hit.hit_regions[0].seq_range_start, 0);
+        assert_eq!(hit.hit_regions[0].seq_range_end, 42);
+    }
+
+    #[tokio::test]
+    async fn screen_fixture_non_hazard_is_granted() {
+        let dir = tempfile::tempdir().unwrap();
+        let key = KeyShare::from_str(SECRET_KEY).unwrap();
+        let hdbs_state = fixture_state(&build_fixture_hdb(dir.path(), &key, HAZARD), None).await;
+
+        let result = screen(hdbs_state, &key, Region::All, &[NOT_A_HAZARD]).await;
+
+        assert_eq!(result.provider_reference.as_deref(), Some("fixture"));
+        assert!(result.results.is_empty());
+        assert!(result.debug_hdb_responses.is_none());
+    }
+
+    #[tokio::test]
+    async fn shadow_discrepancy_is_logged_but_primary_result_is_returned() {
+        let primary_dir = tempfile::tempdir().unwrap();
+        let shadow_dir = tempfile::tempdir().unwrap();
+        let key = KeyShare::from_str(SECRET_KEY).unwrap();
+
+        // The shadow HDB doesn't know about `HAZARD`, so it would grant it.
+        let shadow_db = build_fixture_hdb(shadow_dir.path(), &key, NOT_A_HAZARD);
+        let shadow = ShadowHdb::open(&shadow_db, 1.0, 1).unwrap();
+        let primary_db = build_fixture_hdb(primary_dir.path(), &key, HAZARD);
+        let hdbs_state = fixture_state(&primary_db, Some(shadow)).await;
+
+        let result = screen(hdbs_state.clone(), &key, Region::All, &[HAZARD]).await;
+
+        assert_eq!(result.results.len(), 1);
+        assert_eq!(
+            result.results[0].synthesis_permission,
+            SynthesisPermission::Denied
+        );
+        let shadow = hdbs_state.shadow.as_ref().unwrap();
+        shadow.wait_for_checks().await;
+        assert_eq!(shadow.discrepancies(), 1);
+    }
+
+    #[tokio::test]
+    async fn screen_and_screen_and_verify_share_a_core() {
+        let dir = tempfile::tempdir().unwrap();
+        let key = KeyShare::from_str(SECRET_KEY).unwrap();
+        let hdbs_state = fixture_state(&build_fixture_hdb(dir.path(), &key, HAZARD), None).await;
+        let windows = [NOT_A_HAZARD, HAZARD];
+
+        let streamed = screen(hdbs_state.clone(), &key, Region::All, &windows).await;
+
+        // As screen_and_verify screens the hashes it read from its JSON body,
+        // once the proof has verified.
+        let request_id = RequestId::from_str("screening_fixture_test").unwrap();
+        let response = run_screen_core(
+            &request_id,
+            hdbs_state,
+            screening_session(rand::thread_rng().gen(), Region::All, &windows),
+            windows.len() as u64,
+            decode_queries(screen_body(&key, &windows)),
+        )
+        .await
+        .unwrap();
+        let buffered = screening_result(response).await;
+
+        assert_eq!(streamed.results.len(), 1);
+        assert_eq!(streamed, buffered);
+    }
+
+    /// A stand-in for a verification proof that committed `outcome`.
+    fn committing(outcome: &VerificationOutcome) -> VerificationInput {
+        use doprf::prove::{Program, Prover, ProverBackend};
+        use sp1_sdk::SP1PublicValues;
+
+        let prover = Prover::new(Default::default(), ProverBackend::Mock);
+        let (_pk, vk) = prover.keys(Program::Verification).unwrap();
+        let mut public_values = SP1PublicValues::new();
+        public_values.write(outcome);
+        VerificationInput::mock(vk.clone(), public_values)
+    }
+
+    fn tagged_hashes(seeds: &[&str]) -> PackedRistrettos<TaggedHash> {
+        seeds
+            .iter()
+            .enumerate()
+            .map(|(i, seed)| TaggedHash {
+                tag: HashTag::new(i == 0, 0, i),
+                hash: CompletedHashValue::hash_from_bytes_for_tests_only(seed.as_bytes()),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn mock_proofs_are_accepted_without_verifying() {
+        let hashes = tagged_hashes(&["a", "b"]);
+        let data: Vec<u8> = hashes.encoded_items().concat();
+        // Would fail if it were verified.
+        let verification = committing(&VerificationOutcome::Ok(hashes));
+        let client = ProverClient::mock();
+        verify_proof(&client, verification, &data, true).unwrap();
+    }
+
+    #[test]
+    fn unverifiable_proofs_are_rejected() {
+        let hashes = tagged_hashes(&["a", "b"]);
+        let data: Vec<u8> = hashes.encoded_items().concat();
+        let verification = committing(&VerificationOutcome::Ok(hashes));
+        let client = ProverClient::mock();
+        assert!(matches!(
+            verify_proof(&client, verification, &data, false),
+            Err(scep::error::Screen::ProofVerificationFailed(_))
+        ));
+    }
+
+    #[test]
+    fn proofs_must_commit_the_hashes_to_screen() {
+        let hashes = tagged_hashes(&["a", "b"]);
+        let data: Vec<u8> = hashes.encoded_items().concat();
+        let client = ProverClient::mock();
+
+        // The verification program gave up on the keyserver responses.
+        let verification = committing(&VerificationOutcome::HashFailed);
+        assert!(matches!(
+            verify_proof(&client, verification, &data, true),
+            Err(scep::error::Screen::ProofVerificationFailed(_))
+        ));
+
+        // It computed other hashes than the client sent.
+        let verification = committing(&VerificationOutcome::Ok(tagged_hashes(&["a", "c"])));
+        assert!(matches!(
+            verify_proof(&client, verification, &data, true),
+            Err(scep::error::Screen::ProofVerificationFailed(_))
+        ));
+
+        // It computed more hashes than the client sent.
+        let verification = committing(&VerificationOutcome::Ok(hashes));
+        assert!(verify_proof(&client, verification, &data[..TaggedHash::SIZE], true).is_err());
+    }
+}
@@ -9,12 +9,16 @@ use std::sync::{Arc, Weak};
 
 use anyhow::Context;
 use hyper::body::Incoming;
+use hyper::header::ACCEPT;
 use hyper::{Method, Request, StatusCode};
+use once_cell::sync::Lazy;
 use serde::Deserialize;
+use sp1_sdk::ProverClient;
 use tokio::sync::Semaphore;
 use tracing::{error, info, warn};
 
 use certificates::{DatabaseTokenGroup, Exemption, Issued, Manufacturer};
+use hdb::synthesis_permission::{DefaultSeverityPolicy, OverrideSeverityPolicy, SeverityPolicy};
 use hdb::{Database, HazardLookupTable};
 use minhttp::error::ErrWrapper;
 use minhttp::mpserver::traits::ValidServerSetup;
@@ -24,12 +28,16 @@ use scep_server_helpers::server::ServerState;
 use securedna_versioning::version::get_version;
 use shared_types::hash::HashSpec;
 use shared_types::http::add_cors_headers;
-use shared_types::metrics::{get_metrics_output, HdbMetrics};
+use shared_types::metrics::{
+    get_metrics_output, get_openmetrics_output, HdbMetrics, OPENMETRICS_CONTENT_TYPE,
+};
 use shared_types::requests::RequestId;
 use shared_types::server_versions::HdbVersion;
 
 use crate::event_store;
 use crate::opts::Config;
+use crate::proof_verification::ProofVerifications;
+use crate::shadow::ShadowHdb;
 use crate::state::{BuildTimestamp, HdbServerState};
 use crate::validation::NetworkingValidator;
 
@@ -38,7 +46,18 @@ const SERVER_VERSION: u64 = 1;
 
 pub fn server_setup() -> impl ValidServerSetup<Config, HdbServerState> {
     MultiplaneServer::builder()
-        .with_reconfigure(reconfigure)
+        .with_reconfigure(|cfg, prev_state| reconfigure(cfg, prev_state, false))
+        .with_response(respond)
+        .with_response_to_monitoring(respond_to_monitoring_plane)
+}
+
+/// Like [`server_setup`], but accepts mock proofs without verifying them, so
+/// that tests can screen with [`doprf::prove::ProverBackend::Mock`] against an
+/// in-process server. Never use this for a deployed server: it lets any client
+/// skip proving.
+pub fn server_setup_accepting_mock_proofs() -> impl ValidServerSetup<Config, HdbServerState> {
+    MultiplaneServer::builder()
+        .with_reconfigure(|cfg, prev_state| reconfigure(cfg, prev_state, true))
         .with_response(respond)
         .with_response_to_monitoring(respond_to_monitoring_plane)
 }
@@ -46,6 +65,7 @@ pub fn server_setup() -> impl ValidServerSetup<Config, HdbServerState> {
 async fn reconfigure(
     server_cfg: ServerConfig<Config>,
     prev_state: Weak<HdbServerState>,
+    accept_mock_proofs: bool,
 ) -> Result<Arc<HdbServerState>, ErrWrapper> {
     let app_cfg = server_cfg.main.custom;
     let prev_state = Weak::upgrade(&prev_state);
@@ -65,6 +85,19 @@ async fn reconfigure(
     let hlt = HazardLookupTable::read(&app_cfg.database).context("failed to open HLT")?;
     info!("HLT is ready!");
 
+    let shadow = match &app_cfg.shadow_database {
+        Some(path) => {
+            let shadow = ShadowHdb::open(
+                path,
+                app_cfg.shadow_sample_rate,
+                app_cfg.max_concurrent_shadow_checks,
+            )?;
+            info!("Shadow database is opened!");
+            Some(shadow)
+        }
+        None => None,
+    };
+
     let exemptions_roots =
         scep_server_helpers::certs::read_certificates::<Exemption>(app_cfg.exemption_roots)
             .context("reading exemption root certs")?
@@ -103,6 +136,10 @@ async fn reconfigure(
 
     let heavy_requests = Arc::new(Semaphore::new(app_cfg.max_heavy_clients));
     let hdb_queries = Arc::new(Semaphore::new(app_cfg.disk_parallelism_per_server));
+    let proof_verifications = ProofVerifications::new(
+        app_cfg.max_concurrent_proof_verifications,
+        app_cfg.max_queued_proof_verifications,
+    );
 
     // Once metrics are enabled, they can't be disabled.
     // (at least, I don't yet know enough about our metrics code to be sure that's sensible)
@@ -125,6 +162,17 @@ async fn reconfigure(
         serde_json::from_str(&hash_spec_json_string).context("failed to decode hash spec json")?;
     hash_spec.validate().context("hash spec is invalid")?;
 
+    let severity_policy: Arc<dyn SeverityPolicy> = match &app_cfg.severity_policy_path {
+        Some(path) => {
+            let json = std::fs::read_to_string(path)
+                .with_context(|| format!("failed to open severity policy file: {path:?}"))?;
+            let policy: OverrideSeverityPolicy =
+                serde_json::from_str(&json).context("failed to decode severity policy json")?;
+            Arc::new(policy)
+        }
+        None => Arc::new(DefaultSeverityPolicy),
+    };
+
     let validator = NetworkingValidator {
         yubico_api_client_id: app_cfg.yubico_api_client_id,
         yubico_api_secret_key: app_cfg.yubico_api_secret_key,
@@ -153,6 +201,8 @@ async fn reconfigure(
         hlt,
         metrics: metrics.clone(),
         hdb_queries,
+        proof_verifications,
+        prover_client: Lazy::new(ProverClient::new),
         parallelism_per_request: app_cfg.disk_parallelism_per_request,
         hash_spec,
         validator,
@@ -163,12 +213,16 @@ async fn reconfigure(
             revocation_list,
             token_bundle,
             keypair,
+            max_hash_total_count: None,
             allow_insecure_cookie: app_cfg.allow_insecure_cookie,
         },
         et_size_limit: app_cfg.et_size_limit,
         exemptions_roots,
         persistence_path: app_cfg.event_store_path,
         persistence_connection,
+        shadow,
+        severity_policy,
+        accept_mock_proofs,
     }))
 }
 
@@ -397,13 +451,28 @@ async fn respond_to_monitoring_plane(
     request: Request<Incoming>,
 ) -> GenericResponse {
     match (request.method(), request.uri().path()) {
-        (&Method::GET, "/metrics") => query_server_metrics(),
+        (&Method::GET, "/metrics") => query_server_metrics(&request),
         _ => response::text(StatusCode::NOT_FOUND, "404 not found"),
     }
 }
 
-fn query_server_metrics() -> GenericResponse {
-    response::text(StatusCode::OK, get_metrics_output())
+/// Serves OpenMetrics to scrapers that ask for it, and the Prometheus text
+/// format otherwise.
+fn query_server_metrics(request: &Request<Incoming>) -> GenericResponse {
+    let wants_openmetrics = request.headers().get_all(ACCEPT).iter().any(|accept| {
+        accept
+            .to_str()
+            .is_ok_and(|accept| accept.contains("application/openmetrics-text"))
+    });
+    if wants_openmetrics {
+        response::full(
+            StatusCode::OK,
+            OPENMETRICS_CONTENT_TYPE,
+            get_openmetrics_output(),
+        )
+    } else {
+        response::text(StatusCode::OK, get_metrics_output())
+    }
 }
 
 async fn scep_endpoint_open(
@@ -498,7 +567,11 @@ mod test {
             max_heavy_clients: Config::default_max_heavy_clients(),
             disk_parallelism_per_server: Config::default_disk_parallelism_per_server(),
             disk_parallelism_per_request: Config::default_disk_parallelism_per_request(),
+            max_concurrent_proof_verifications: Config::default_max_concurrent_proof_verifications(
+            ),
+            max_queued_proof_verifications: Config::default_max_queued_proof_verifications(),
             hash_spec_path: None,
+            severity_policy_path: None,
             yubico_api_client_id: None,
             yubico_api_secret_key: None,
             scep_json_size_limit: Config::default_scep_json_size_limit(),
@@ -511,6 +584,9 @@ mod test {
             keypair_passphrase_file: "test/certs/database-token.passphrase".into(),
             allow_insecure_cookie: true,
             event_store_path: Config::default_event_store_path(),
+            shadow_database: None,
+            shadow_sample_rate: Config::default_shadow_sample_rate(),
+            max_concurrent_shadow_checks: Config::default_max_concurrent_shadow_checks(),
         };
         let server_config = ServerConfig {
             main: PlaneConfig {
@@ -0,0 +1,131 @@
+// Copyright 2021-2024 SecureDNA Stiftung (SecureDNA Foundation) <licensing@securedna.org>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Shadow screening against a secondary HDB.
+//!
+//! During an HDB migration, a sampled fraction of screens is also run against
+//! the shadow database, and any per-window difference in [`SynthesisPermission`]
+//! is logged. The shadow never affects the result returned to the client,
+//! nor holds it up: its checks run on tasks of their own, and are skipped
+//! while too many are already running.
+
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use anyhow::Context;
+use rand::Rng;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tracing::warn;
+
+use hdb::consolidate_windows::HashId;
+use hdb::synthesis_permission::SeverityPolicy;
+use hdb::{Database, HazardLookupTable, HdbConfig, HdbParams, HdbResponse};
+use shared_types::requests::RequestId;
+use shared_types::synthesis_permission::SynthesisPermission;
+
+pub struct ShadowHdb {
+    pub database: Database,
+    pub hlt: HazardLookupTable,
+    /// Fraction of requests (0.0 to 1.0) that are shadow-screened.
+    pub sample_rate: f64,
+    discrepancies: AtomicU64,
+    checks: Arc<Semaphore>,
+    max_concurrent_checks: u32,
+}
+
+impl ShadowHdb {
+    pub fn new(
+        database: Database,
+        hlt: HazardLookupTable,
+        sample_rate: f64,
+        max_concurrent_checks: u32,
+    ) -> Self {
+        Self {
+            database,
+            hlt,
+            sample_rate,
+            discrepancies: AtomicU64::new(0),
+            checks: Arc::new(Semaphore::new(max_concurrent_checks as usize)),
+            max_concurrent_checks,
+        }
+    }
+
+    pub fn open(path: &Path, sample_rate: f64, max_concurrent_checks: u32) -> anyhow::Result<Self> {
+        anyhow::ensure!(
+            (0.0..=1.0).contains(&sample_rate),
+            "shadow sample rate must be between 0 and 1, got {sample_rate}"
+        );
+        let database = Database::open(path)
+            .with_context(|| format!("failed to open shadow database: {path:?}"))?;
+        let hlt = HazardLookupTable::read(path).context("failed to open shadow HLT")?;
+        Ok(Self::new(database, hlt, sample_rate, max_concurrent_checks))
+    }
+
+    /// Decide whether the current request should be shadow-screened.
+    pub fn should_sample(&self) -> bool {
+        rand::thread_rng().gen_bool(self.sample_rate)
+    }
+
+    /// Reserve a slot to run [`Self::check_window`] in, or `None` if
+    /// `max_concurrent_checks` are already running and the check should be
+    /// skipped.
+    pub fn try_reserve_check(&self) -> Option<OwnedSemaphorePermit> {
+        self.checks.clone().try_acquire_owned().ok()
+    }
+
+    /// Wait until every reserved check has finished.
+    pub async fn wait_for_checks(&self) {
+        let _all = self.checks.acquire_many(self.max_concurrent_checks).await;
+    }
+
+    /// Number of windows whose permission differed between the primary and shadow HDBs.
+    pub fn discrepancies(&self) -> u64 {
+        self.discrepancies.load(Ordering::Relaxed)
+    }
+
+    /// Query the shadow HDB for `hash` and log if its permission differs from `primary`.
+    ///
+    /// Errors from the shadow HDB are logged and otherwise ignored.
+    pub fn check_window(
+        &self,
+        request_id: &RequestId,
+        hash_id: HashId,
+        hash: &[u8; 32],
+        params: &HdbParams,
+        severity_policy: &dyn SeverityPolicy,
+        primary: Option<&HdbResponse>,
+    ) {
+        let config = HdbConfig {
+            database: &self.database,
+            hlt: &self.hlt,
+            severity_policy,
+        };
+        let shadow = match hdb::query_hdb(hash, params, &config) {
+            Ok(shadow) => shadow,
+            Err(err) => {
+                warn!("{request_id}: shadow HDB query failed: {err}");
+                return;
+            }
+        };
+
+        let primary_permission = permission(primary);
+        let shadow_permission = permission(shadow.as_ref());
+        if primary_permission != shadow_permission {
+            self.discrepancies.fetch_add(1, Ordering::Relaxed);
+            warn!(
+                message = "shadow screen discrepancy",
+                %request_id,
+                record = hash_id.record,
+                index_in_record = hash_id.index_in_record,
+                %primary_permission,
+                %shadow_permission,
+            );
+        }
+    }
+}
+
+/// A window that isn't in the HDB is implicitly granted.
+fn permission(response: Option<&HdbResponse>) -> SynthesisPermission {
+    response.map_or(SynthesisPermission::Granted, |r| r.synthesis_permission)
+}
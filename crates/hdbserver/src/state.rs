@@ -5,9 +5,12 @@ use std::path::PathBuf;
 use std::sync::Arc;
 
 use hyper::StatusCode;
+use once_cell::sync::Lazy;
+use sp1_sdk::ProverClient;
 use tokio::sync::{OwnedSemaphorePermit, Semaphore};
 
 use certificates::{DatabaseTokenGroup, PublicKey};
+use hdb::synthesis_permission::SeverityPolicy;
 use hdb::{Database, HazardLookupTable};
 use minhttp::response::{self, GenericResponse};
 use scep_server_helpers::server::ServerState;
@@ -15,6 +18,8 @@ use shared_types::hash::HashSpec;
 use shared_types::metrics::HdbMetrics;
 
 use crate::event_store::Connection;
+use crate::proof_verification::ProofVerifications;
+use crate::shadow::ShadowHdb;
 use crate::validation::NetworkingValidator;
 
 #[derive(Clone)]
@@ -27,6 +32,10 @@ pub struct HdbServerState {
     pub hlt: HazardLookupTable,
     pub metrics: Option<Arc<HdbMetrics>>,
     pub hdb_queries: Arc<Semaphore>,
+    pub proof_verifications: ProofVerifications,
+    /// Verifies clients' proofs. Creating one is expensive, so it's created
+    /// for the first proof and then reused.
+    pub prover_client: Lazy<ProverClient>,
     pub parallelism_per_request: usize,
     pub hash_spec: HashSpec,
     #[allow(dead_code)]
@@ -36,6 +45,14 @@ pub struct HdbServerState {
     pub exemptions_roots: Vec<PublicKey>,
     pub persistence_path: PathBuf,
     pub persistence_connection: Connection,
+    pub shadow: Option<ShadowHdb>,
+    /// Maps hazards to a synthesis permission per region.
+    pub severity_policy: Arc<dyn SeverityPolicy>,
+    /// Accept the stand-ins mock provers send instead of proofs, without
+    /// verifying them. Never read from config: only servers built with
+    /// [`crate::server_setup_accepting_mock_proofs`], by in-process test
+    /// harnesses, set it.
+    pub accept_mock_proofs: bool,
 }
 
 impl HdbServerState {
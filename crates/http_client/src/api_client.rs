@@ -11,7 +11,8 @@ use crate::api_client_core::{ApiClientCore, ApiClientCoreImpl};
 use crate::error::HttpError;
 use packed_ristretto::{PackableRistretto, PackedRistrettos};
 use shared_types::requests::RequestId;
-use streamed_ristretto::{stream::check_content_length, HasContentType};
+use streamed_ristretto::stream::{body_checksum, check_content_length, CHECKSUM_HEADER};
+use streamed_ristretto::HasContentType;
 
 /// Helper for querying internal servers (HDB and keyservers)
 #[derive(Clone)]
@@ -37,13 +38,33 @@ impl BaseApiClient {
         ApiClientCoreImpl::new(request_id).into()
     }
 
+    /// Construct a new ApiClient for the given RequestId, connecting with the
+    /// given transport TLS settings (e.g. a client identity for mTLS gateways).
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn with_tls(
+        request_id: RequestId,
+        tls: crate::tls::TlsConfig,
+    ) -> Result<Self, crate::tls::TlsConfigError> {
+        Ok(ApiClientCoreImpl::with_tls(request_id, tls)?.into())
+    }
+
     /// Construct a new ApiClient for use with external APIs: it won't set any headers
     /// or handle API keys.
     pub fn new_external() -> Self {
         ApiClientCoreImpl::new_external().into()
     }
 
+    /// A client that makes requests the same way, but keeps cookies of its own,
+    /// so it can hold a SCEP session with a server alongside this one's. `None`
+    /// if that isn't possible, e.g. in a browser.
+    pub fn with_own_cookies(&self) -> Option<Self> {
+        let core = self.core.with_own_cookies()?;
+        Some(Self { core })
+    }
+
     /// Post ristrettos, get JSON. Returns error for >=400 status.
+    ///
+    /// The body's checksum is sent along so the server can detect corruption in transit.
     pub async fn ristretto_json_post<I, O>(
         &self,
         url: &str,
@@ -54,13 +75,19 @@ impl BaseApiClient {
         O: serde::de::DeserializeOwned,
         for<'a> &'a I::Array: IntoIterator<Item = &'a u8>,
     {
-        let body: Vec<_> = packed_ristrettos
-            .iter_encoded()
-            .flatten()
-            .copied()
-            .collect();
+        let mut body = Vec::with_capacity(PackedRistrettos::<I>::expected_byte_len(
+            packed_ristrettos.len(),
+        ));
+        body.extend(packed_ristrettos.iter_encoded().flatten().copied());
+        let headers = [(CHECKSUM_HEADER.to_string(), body_checksum(&body))];
         let bytes = self
-            .raw_post(url, body.into(), I::CONTENT_TYPE, &[], "application/json")
+            .raw_post(
+                url,
+                body.into(),
+                I::CONTENT_TYPE,
+                &headers,
+                "application/json",
+            )
             .await?;
 
         serde_json::from_slice(&bytes).map_err(|e| {
@@ -131,15 +158,48 @@ impl BaseApiClient {
         O: PackableRistretto + HasContentType + 'static,
         for<'a> &'a I::Array: IntoIterator<Item = &'a u8>,
     {
-        let body: Vec<_> = packed_ristrettos
-            .iter_encoded()
-            .flatten()
-            .copied()
-            .collect();
+        self.ristretto_ristretto_post_with_limit(url, packed_ristrettos, headers, None)
+            .await
+    }
 
-        let bytes = self
-            .raw_post(url, body.into(), I::CONTENT_TYPE, headers, O::CONTENT_TYPE)
-            .await?;
+    /// Post ristrettos, get ristrettos (with custom headers). Returns error for >=400 status,
+    /// or [`HttpError::ResponseTooLarge`] if `max_response_bytes` is given and the response is
+    /// longer than that.
+    pub async fn ristretto_ristretto_post_with_limit<I, O>(
+        &self,
+        url: &str,
+        packed_ristrettos: &PackedRistrettos<I>,
+        headers: &[(String, String)],
+        max_response_bytes: Option<u64>,
+    ) -> Result<PackedRistrettos<O>, HttpError>
+    where
+        I: PackableRistretto + HasContentType,
+        O: PackableRistretto + HasContentType + 'static,
+        for<'a> &'a I::Array: IntoIterator<Item = &'a u8>,
+    {
+        let mut body = Vec::with_capacity(PackedRistrettos::<I>::expected_byte_len(
+            packed_ristrettos.len(),
+        ));
+        body.extend(packed_ristrettos.iter_encoded().flatten().copied());
+
+        let bytes = match max_response_bytes {
+            None => {
+                self.raw_post(url, body.into(), I::CONTENT_TYPE, headers, O::CONTENT_TYPE)
+                    .await?
+            }
+            Some(max_response_bytes) => {
+                self.core
+                    .raw_request_limited(
+                        url,
+                        Some(body.into()),
+                        I::CONTENT_TYPE,
+                        headers,
+                        O::CONTENT_TYPE,
+                        max_response_bytes,
+                    )
+                    .await?
+            }
+        };
 
         let content_len = bytes.len().try_into().ok();
         check_content_length(content_len, O::SIZE).map_err(|e| HttpError::DecodeError {
@@ -245,6 +305,34 @@ impl ApiClientCore for HttpsToHttpRewriter {
             .raw_request(&new_url, body, content_type, headers, expected_content_type)
             .await
     }
+
+    async fn raw_request_limited(
+        &self,
+        url: &str,
+        body: Option<Bytes>,
+        content_type: &'static str,
+        headers: &[(String, String)],
+        expected_content_type: &'static str,
+        max_response_bytes: u64,
+    ) -> Result<bytes::Bytes, HttpError> {
+        let new_url = url.replace("https://", "http://");
+        info!("api_client::HttpsToHttpRewriter: rewrote {url} to {new_url} for local testing",);
+        self.inner
+            .raw_request_limited(
+                &new_url,
+                body,
+                content_type,
+                headers,
+                expected_content_type,
+                max_response_bytes,
+            )
+            .await
+    }
+
+    fn with_own_cookies(&self) -> Option<Arc<dyn ApiClientCore + Send + Sync>> {
+        let inner = self.inner.with_own_cookies()?;
+        Some(Arc::new(Self { inner }))
+    }
 }
 
 impl HttpsToHttpRewriter {
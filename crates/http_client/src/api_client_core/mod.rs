@@ -7,6 +7,8 @@ pub mod status_code;
 #[cfg_attr(not(target_arch = "wasm32"), path = "reqwest.rs")]
 pub mod implementation;
 
+use std::sync::Arc;
+
 use bytes::Bytes;
 
 pub use self::implementation::ApiClientCore as ApiClientCoreImpl;
@@ -23,6 +25,48 @@ pub trait ApiClientCore {
         headers: &[(String, String)],
         expected_content_type: &'static str,
     ) -> Result<bytes::Bytes, HttpError>;
+
+    /// Like [`Self::raw_request`], but failing with [`HttpError::ResponseTooLarge`] if the
+    /// response body is longer than `max_response_bytes`. By default the whole body is read
+    /// before it's checked; implementations that can should stop reading as soon as it's over.
+    async fn raw_request_limited(
+        &self,
+        url: &str,
+        body: Option<Bytes>,
+        content_type: &'static str,
+        headers: &[(String, String)],
+        expected_content_type: &'static str,
+        max_response_bytes: u64,
+    ) -> Result<bytes::Bytes, HttpError> {
+        let bytes = self
+            .raw_request(url, body, content_type, headers, expected_content_type)
+            .await?;
+        check_response_len(url, bytes.len() as u64, max_response_bytes)?;
+        Ok(bytes)
+    }
+
+    /// A core that makes requests the same way, but keeps cookies of its own,
+    /// so it can hold a session with a server alongside this one's. `None` if
+    /// it can't, e.g. in a browser, which keeps one set of cookies per site.
+    fn with_own_cookies(&self) -> Option<Arc<dyn ApiClientCore + Send + Sync>> {
+        None
+    }
+}
+
+/// Error if a response of `len` bytes from `url` is over `max_response_bytes`.
+pub(crate) fn check_response_len(
+    url: &str,
+    len: u64,
+    max_response_bytes: u64,
+) -> Result<(), HttpError> {
+    if len > max_response_bytes {
+        Err(HttpError::ResponseTooLarge {
+            ctx: format!("requesting {url}"),
+            limit: max_response_bytes,
+        })
+    } else {
+        Ok(())
+    }
 }
 
 #[cfg_attr(target_arch = "wasm32", async_trait::async_trait(?Send))]
@@ -39,6 +83,38 @@ impl ApiClientCore for ApiClientCoreImpl {
         self.raw_request(url, body, content_type, headers, expected_content_type)
             .await
     }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    async fn raw_request_limited(
+        &self,
+        url: &str,
+        body: Option<Bytes>,
+        content_type: &'static str,
+        headers: &[(String, String)],
+        expected_content_type: &'static str,
+        max_response_bytes: u64,
+    ) -> Result<bytes::Bytes, HttpError> {
+        self.raw_request_with_limit(
+            url,
+            body,
+            content_type,
+            headers,
+            expected_content_type,
+            Some(max_response_bytes),
+        )
+        .await
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn with_own_cookies(&self) -> Option<Arc<dyn ApiClientCore + Send + Sync>> {
+        match self.with_own_cookie_store() {
+            Ok(core) => Some(Arc::new(core)),
+            Err(error) => {
+                tracing::warn!("couldn't build a client with its own cookies: {error}");
+                None
+            }
+        }
+    }
 }
 
 pub mod test_utils {
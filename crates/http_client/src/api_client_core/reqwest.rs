@@ -1,20 +1,73 @@
 // Copyright 2021-2024 SecureDNA Stiftung (SecureDNA Foundation) <licensing@securedna.org>
 // SPDX-License-Identifier: MIT OR Apache-2.0
 
-use bytes::Bytes;
+use std::sync::Arc;
+
+use bytes::{Bytes, BytesMut};
 use tracing::debug;
 
+use super::check_response_len;
 use crate::error::HttpError;
+use crate::tls::{TlsConfig, TlsConfigError};
 use shared_types::requests::RequestId;
 use streamed_ristretto::reqwest::check_content_type;
 
 #[derive(Debug, Clone)]
 pub struct ApiClientCore {
     client: reqwest::Client, // cheaply cloneable (Arc<...> internally), see docs
+    tls: Option<Arc<TlsConfig>>,
+    // What the client was built for, for `with_own_cookies`; `None` if external
+    request_id: Option<RequestId>,
 }
 
 impl ApiClientCore {
     pub fn new(request_id: RequestId) -> Self {
+        // this only fails if the system config is messed up, isn't recoverable
+        let client = Self::builder(request_id.clone()).build().unwrap();
+
+        Self {
+            client,
+            tls: None,
+            request_id: Some(request_id),
+        }
+    }
+
+    /// Like [`Self::new`], but with the given transport TLS settings.
+    pub fn with_tls(request_id: RequestId, tls: TlsConfig) -> Result<Self, TlsConfigError> {
+        let client = tls
+            .apply(Self::builder(request_id.clone()))?
+            .build()
+            .map_err(|e| TlsConfigError::Build(e.into()))?;
+
+        Ok(Self {
+            client,
+            tls: Some(Arc::new(tls)),
+            request_id: Some(request_id),
+        })
+    }
+
+    /// A new client built like this one, but with an empty cookie store of its own.
+    pub fn with_own_cookie_store(&self) -> Result<Self, TlsConfigError> {
+        let builder = match &self.request_id {
+            Some(request_id) => Self::builder(request_id.clone()),
+            None => reqwest::ClientBuilder::new().cookie_store(true),
+        };
+        let builder = match &self.tls {
+            Some(tls) => tls.apply(builder)?,
+            None => builder,
+        };
+        let client = builder
+            .build()
+            .map_err(|e| TlsConfigError::Build(e.into()))?;
+
+        Ok(Self {
+            client,
+            tls: self.tls.clone(),
+            request_id: self.request_id.clone(),
+        })
+    }
+
+    fn builder(request_id: RequestId) -> reqwest::ClientBuilder {
         let mut default_headers = reqwest::header::HeaderMap::with_capacity(3);
         default_headers.insert(
             "X-Request-ID",
@@ -23,13 +76,9 @@ impl ApiClientCore {
             ),
         );
 
-        let client = reqwest::ClientBuilder::new()
+        reqwest::ClientBuilder::new()
             .default_headers(default_headers)
             .cookie_store(true)
-            .build()
-            .unwrap(); // this only fails if the system config is messed up, isn't recoverable
-
-        Self { client }
     }
 
     /// Construct a new ApiClientCore with no default headers / assumptions, for use with external APIs
@@ -39,6 +88,8 @@ impl ApiClientCore {
                 .cookie_store(true)
                 .build()
                 .unwrap(), // See `Self::new()`, can't fail in normal circumstances
+            tls: None,
+            request_id: None,
         }
     }
 
@@ -52,6 +103,31 @@ impl ApiClientCore {
         header_iter: &[(String, String)],
         expected_content_type: &'static str,
     ) -> Result<bytes::Bytes, HttpError> {
+        self.raw_request_with_limit(
+            url,
+            body,
+            content_type,
+            header_iter,
+            expected_content_type,
+            None,
+        )
+        .await
+    }
+
+    /// Like [`Self::raw_request`], but if `max_response_bytes` is given, stop reading the
+    /// response as soon as it's known to be longer than that.
+    pub(crate) async fn raw_request_with_limit(
+        &self,
+        url: &str,
+        body: Option<Bytes>,
+        content_type: &'static str,
+        header_iter: &[(String, String)],
+        expected_content_type: &'static str,
+        max_response_bytes: Option<u64>,
+    ) -> Result<bytes::Bytes, HttpError> {
+        let overridden_url = self.tls.as_ref().and_then(|tls| tls.override_url(url));
+        let url = overridden_url.as_deref().unwrap_or(url);
+
         let mut rb = match body {
             Some(b) => self
                 .client
@@ -81,15 +157,28 @@ impl ApiClientCore {
 
         let status = response.status();
         let content_type_err = check_content_type(response.headers(), expected_content_type);
-        let bytes = response
-            .bytes()
-            .await
-            .map_err(|e| HttpError::RequestError {
-                ctx: format!("requesting {url}"),
-                status: Some(status.as_u16()),
-                retriable,
-                source: e.into(),
-            })?;
+        let read_err = |e: reqwest::Error| HttpError::RequestError {
+            ctx: format!("requesting {url}"),
+            status: Some(status.as_u16()),
+            retriable,
+            source: e.into(),
+        };
+        let bytes = match max_response_bytes {
+            None => response.bytes().await.map_err(read_err)?,
+            Some(limit) => {
+                if let Some(len) = response.content_length() {
+                    check_response_len(url, len, limit)?;
+                }
+                // The declared length may be missing or wrong, so count as we go too.
+                let mut response = response;
+                let mut buf = BytesMut::new();
+                while let Some(chunk) = response.chunk().await.map_err(read_err)? {
+                    check_response_len(url, (buf.len() + chunk.len()) as u64, limit)?;
+                    buf.extend_from_slice(&chunk);
+                }
+                buf.freeze()
+            }
+        };
 
         if status.is_client_error() || status.is_server_error() {
             Err(HttpError::RequestError {
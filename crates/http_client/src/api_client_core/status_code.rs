@@ -27,7 +27,8 @@ pub fn is_retriable(code: u16) -> bool {
         504 => true,
 
         // Don't retry when we get 400 (Bad Request), 401 (Unauthorized), 404 (Not Found), 501 (Not
-        // Implemented)...
+        // Implemented), 440 (Login Time-out, nonstandard: our SCEP session expired, and retries
+        // would reuse the same session cookie)...
         _ => false,
     }
 }
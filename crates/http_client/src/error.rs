@@ -25,6 +25,9 @@ pub enum HttpError {
     ProtocolError { error: String },
     #[error("in js: {error}")]
     JsError { error: String },
+    /// The server sent a larger response than the caller allowed, so it was abandoned.
+    #[error("while {ctx}: response exceeded the limit of {limit} bytes")]
+    ResponseTooLarge { ctx: String, limit: u64 },
 }
 
 impl HttpError {
@@ -34,7 +37,8 @@ impl HttpError {
             HttpError::DecodeError { .. }
             | HttpError::EncodeError { .. }
             | HttpError::ProtocolError { .. }
-            | HttpError::JsError { .. } => false,
+            | HttpError::JsError { .. }
+            | HttpError::ResponseTooLarge { .. } => false,
         }
     }
 }
@@ -4,6 +4,8 @@
 pub mod api_client;
 pub mod api_client_core;
 pub mod error;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod tls;
 
 pub use api_client::{BaseApiClient, HttpsToHttpRewriter};
 pub use api_client_core::test_utils;
@@ -0,0 +1,227 @@
+// Copyright 2021-2024 SecureDNA Stiftung (SecureDNA Foundation) <licensing@securedna.org>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Transport TLS settings for reaching internal servers, e.g. from behind a
+//! gateway that requires mutual TLS.
+//!
+//! This is separate from (and in addition to) the certificates used by SCEP,
+//! which authenticate the client to the servers themselves.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+
+/// A client certificate chain and its private key, to present to servers that
+/// ask for one.
+#[derive(Clone)]
+pub struct ClientIdentity {
+    /// PEM-encoded certificate chain, leaf first.
+    pub cert_chain_pem: Vec<u8>,
+    /// PEM-encoded PKCS#8 private key.
+    pub key_pem: Vec<u8>,
+}
+
+impl fmt::Debug for ClientIdentity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ClientIdentity").finish_non_exhaustive()
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct TlsConfig {
+    /// Presented to servers that ask for a client certificate.
+    pub identity: Option<ClientIdentity>,
+    /// PEM-encoded CA certificates to trust. These replace the system's roots
+    /// rather than adding to them, so servers are pinned to these CAs.
+    pub ca_bundle_pem: Option<Vec<u8>>,
+    /// Names to use for SNI and certificate verification instead of a URL's
+    /// host, keyed by that host (without port). Requests still connect to the
+    /// addresses of the URL's host.
+    pub sni_overrides: HashMap<String, String>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum TlsConfigError {
+    #[error("invalid client identity: {0}")]
+    InvalidIdentity(Box<dyn std::error::Error + Send + Sync + 'static>),
+    #[error("invalid CA bundle: {0}")]
+    InvalidCaBundle(Box<dyn std::error::Error + Send + Sync + 'static>),
+    #[error("building TLS transport: {0}")]
+    Build(Box<dyn std::error::Error + Send + Sync + 'static>),
+}
+
+/// The parts of a TLS transport that a [`TlsConfig`] sets up. Implemented
+/// for [`reqwest::ClientBuilder`]; tests can implement it to see what a
+/// config would present.
+pub trait TlsTransport: Sized {
+    fn with_identity(self, identity: &ClientIdentity) -> Result<Self, TlsConfigError>;
+    fn with_ca_bundle(self, pem: &[u8]) -> Result<Self, TlsConfigError>;
+    fn with_sni_overrides(self, overrides: &HashMap<String, String>) -> Self;
+}
+
+impl TlsConfig {
+    /// Set up `transport` with these settings.
+    pub fn apply<T: TlsTransport>(&self, mut transport: T) -> Result<T, TlsConfigError> {
+        if let Some(identity) = &self.identity {
+            transport = transport.with_identity(identity)?;
+        }
+        if let Some(pem) = &self.ca_bundle_pem {
+            transport = transport.with_ca_bundle(pem)?;
+        }
+        if !self.sni_overrides.is_empty() {
+            transport = transport.with_sni_overrides(&self.sni_overrides);
+        }
+        Ok(transport)
+    }
+
+    /// `url` with its host replaced by the SNI override for it, if there is one.
+    pub(crate) fn override_url(&self, url: &str) -> Option<String> {
+        let mut url = reqwest::Url::parse(url).ok()?;
+        let sni = self.sni_overrides.get(url.host_str()?)?;
+        url.set_host(Some(sni)).ok()?;
+        Some(url.into())
+    }
+}
+
+impl TlsTransport for reqwest::ClientBuilder {
+    fn with_identity(self, identity: &ClientIdentity) -> Result<Self, TlsConfigError> {
+        let identity =
+            reqwest::Identity::from_pkcs8_pem(&identity.cert_chain_pem, &identity.key_pem)
+                .map_err(|e| TlsConfigError::InvalidIdentity(e.into()))?;
+        Ok(self.identity(identity))
+    }
+
+    fn with_ca_bundle(self, pem: &[u8]) -> Result<Self, TlsConfigError> {
+        let certs = reqwest::Certificate::from_pem_bundle(pem)
+            .map_err(|e| TlsConfigError::InvalidCaBundle(e.into()))?;
+        if certs.is_empty() {
+            return Err(TlsConfigError::InvalidCaBundle(
+                "no certificates in bundle".into(),
+            ));
+        }
+        Ok(certs
+            .into_iter()
+            .fold(self.tls_built_in_root_certs(false), |builder, cert| {
+                builder.add_root_certificate(cert)
+            }))
+    }
+
+    fn with_sni_overrides(self, overrides: &HashMap<String, String>) -> Self {
+        let real_hosts = overrides
+            .iter()
+            .map(|(host, sni)| (sni.clone(), host.clone()))
+            .collect();
+        self.dns_resolver(std::sync::Arc::new(SniResolver { real_hosts }))
+    }
+}
+
+/// Resolves SNI override names to the addresses of the hosts they stand in
+/// for, so requests rewritten by [`TlsConfig::override_url`] still reach the
+/// original host.
+struct SniResolver {
+    /// Override name → the host it replaced.
+    real_hosts: HashMap<String, String>,
+}
+
+impl Resolve for SniResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let host = self
+            .real_hosts
+            .get(name.as_str())
+            .map_or(name.as_str(), String::as_str)
+            .to_owned();
+        Box::pin(async move {
+            // reqwest replaces the port with the one from the URL.
+            let addrs: Vec<_> = tokio::net::lookup_host((host, 0)).await?.collect();
+            Ok::<Addrs, Box<dyn std::error::Error + Send + Sync>>(Box::new(addrs.into_iter()))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Stands in for a TLS transport, recording what it would present during
+    /// a handshake.
+    #[derive(Default)]
+    struct MockTransport {
+        presented_cert_chain: Option<Vec<u8>>,
+        trusted_roots: Option<Vec<u8>>,
+        sni_overrides: HashMap<String, String>,
+    }
+
+    impl TlsTransport for MockTransport {
+        fn with_identity(mut self, identity: &ClientIdentity) -> Result<Self, TlsConfigError> {
+            self.presented_cert_chain = Some(identity.cert_chain_pem.clone());
+            Ok(self)
+        }
+
+        fn with_ca_bundle(mut self, pem: &[u8]) -> Result<Self, TlsConfigError> {
+            self.trusted_roots = Some(pem.to_vec());
+            Ok(self)
+        }
+
+        fn with_sni_overrides(mut self, overrides: &HashMap<String, String>) -> Self {
+            self.sni_overrides = overrides.clone();
+            self
+        }
+    }
+
+    #[test]
+    fn configured_identity_is_presented() {
+        let config = TlsConfig {
+            identity: Some(ClientIdentity {
+                cert_chain_pem: b"client chain".to_vec(),
+                key_pem: b"client key".to_vec(),
+            }),
+            ca_bundle_pem: Some(b"gateway ca".to_vec()),
+            sni_overrides: HashMap::from([("ks1.internal".into(), "ks1.gateway".into())]),
+        };
+        let transport = config.apply(MockTransport::default()).unwrap();
+        assert_eq!(
+            transport.presented_cert_chain.as_deref(),
+            Some(&b"client chain"[..])
+        );
+        assert_eq!(transport.trusted_roots.as_deref(), Some(&b"gateway ca"[..]));
+        assert_eq!(transport.sni_overrides, config.sni_overrides);
+
+        let transport = TlsConfig::default()
+            .apply(MockTransport::default())
+            .unwrap();
+        assert_eq!(transport.presented_cert_chain, None);
+        assert_eq!(transport.trusted_roots, None);
+    }
+
+    #[test]
+    fn urls_are_rewritten_to_sni_overrides() {
+        let config = TlsConfig {
+            sni_overrides: HashMap::from([("ks1.internal".into(), "ks1.gateway".into())]),
+            ..Default::default()
+        };
+        assert_eq!(
+            config
+                .override_url("https://ks1.internal:8443/qry")
+                .as_deref(),
+            Some("https://ks1.gateway:8443/qry")
+        );
+        assert_eq!(config.override_url("https://ks2.internal/qry"), None);
+    }
+
+    #[test]
+    fn reqwest_rejects_invalid_tls_settings() {
+        let identity = ClientIdentity {
+            cert_chain_pem: b"not a certificate".to_vec(),
+            key_pem: b"not a key".to_vec(),
+        };
+        assert!(matches!(
+            reqwest::ClientBuilder::new().with_identity(&identity),
+            Err(TlsConfigError::InvalidIdentity(_))
+        ));
+        assert!(matches!(
+            reqwest::ClientBuilder::new().with_ca_bundle(b"not a bundle"),
+            Err(TlsConfigError::InvalidCaBundle(_))
+        ));
+    }
+}
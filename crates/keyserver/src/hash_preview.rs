@@ -0,0 +1,141 @@
+// Copyright 2021-2024 SecureDNA Stiftung (SecureDNA Foundation) <licensing@securedna.org>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! An operator-only endpoint for checking that a keyserver applies its keyshare
+//! as expected, without going through a full screen.
+//!
+//! Since it applies the real keyshare to arbitrary queries, the endpoint is
+//! disabled unless a token is configured, and requests must present that token
+//! as an `Authorization: Bearer` header.
+
+use std::str::FromStr;
+
+use http_body_util::BodyExt;
+use hyper::body::{Body, Incoming};
+use hyper::header::AUTHORIZATION;
+use hyper::{Request, StatusCode};
+use subtle::ConstantTimeEq;
+
+use doprf::prf::{HashPart, KeyShare, Query};
+use minhttp::response::{self, ErrResponse, ResponseResult};
+use streamed_ristretto::stream::MessageError;
+
+/// A hex-encoded query, with room for a trailing newline.
+const MAX_BODY_SIZE: u64 = 2 * 32 + 2;
+
+/// Apply the keyshare to the single hex-encoded [`Query`] in the request body,
+/// responding with the hex-encoded [`HashPart`]. No Lagrange coefficient is
+/// applied, so operators can compare against their own test share.
+pub async fn hash_preview(
+    keyshare: &KeyShare,
+    token: Option<&str>,
+    request: Request<Incoming>,
+) -> ResponseResult {
+    authorize(token, &request)?;
+
+    let body = match request.body().size_hint().exact() {
+        Some(size) if size <= MAX_BODY_SIZE => request
+            .into_body()
+            .collect()
+            .await
+            .map_err(|e| ErrResponse(response::text(StatusCode::INTERNAL_SERVER_ERROR, e)))?
+            .to_bytes(),
+        size => {
+            return Err(ErrResponse(response::text(
+                StatusCode::BAD_REQUEST,
+                MessageError::InvalidContentLength(size),
+            )))
+        }
+    };
+
+    let hash_part = preview(keyshare, &body)?;
+    Ok(response::text(StatusCode::OK, hash_part))
+}
+
+fn authorize<B>(token: Option<&str>, request: &Request<B>) -> Result<(), ErrResponse> {
+    let Some(token) = token else {
+        return Err(ErrResponse(response::text(
+            StatusCode::NOT_FOUND,
+            "404 not found",
+        )));
+    };
+    let presented = request
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .unwrap_or_default();
+    if bool::from(presented.as_bytes().ct_eq(token.as_bytes())) {
+        Ok(())
+    } else {
+        Err(ErrResponse(response::text(
+            StatusCode::UNAUTHORIZED,
+            "missing or invalid hash preview token",
+        )))
+    }
+}
+
+fn preview(keyshare: &KeyShare, body: &[u8]) -> Result<HashPart, ErrResponse> {
+    let query = std::str::from_utf8(body)
+        .map_err(|e| ErrResponse(response::text(StatusCode::BAD_REQUEST, e)))
+        .and_then(|body| {
+            Query::from_str(body.trim())
+                .map_err(|e| ErrResponse(response::text(StatusCode::BAD_REQUEST, e)))
+        })?;
+    Ok(keyshare.apply(query))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn preview_matches_local_apply() {
+        let keyshare =
+            KeyShare::from_str("0f00000000000000000000000000000000000000000000000000000000000000")
+                .unwrap();
+        let query = Query::hash_from_string("acgtacgtacgtacgtacgtacgtacgtacgtacgtacgtacgt");
+
+        let body = format!("{query}\n");
+        let Ok(hash_part) = preview(&keyshare, body.as_bytes()) else {
+            panic!("a valid query should be previewed");
+        };
+        assert_eq!(hash_part.as_bytes(), keyshare.apply(query).as_bytes());
+
+        assert!(preview(&keyshare, b"not a query").is_err());
+        assert!(preview(&keyshare, &[0xff; 64]).is_err());
+    }
+
+    #[test]
+    fn preview_requires_configured_token() {
+        let request = |authorization: Option<&str>| {
+            let mut builder = Request::post("/hash-preview");
+            if let Some(authorization) = authorization {
+                builder = builder.header(AUTHORIZATION, authorization);
+            }
+            builder.body(()).unwrap()
+        };
+        let status = |result: Result<(), ErrResponse>| result.err().map(|e| e.0.status());
+
+        assert_eq!(
+            status(authorize(None, &request(Some("Bearer secret")))),
+            Some(StatusCode::NOT_FOUND)
+        );
+        assert_eq!(
+            status(authorize(Some("secret"), &request(None))),
+            Some(StatusCode::UNAUTHORIZED)
+        );
+        assert_eq!(
+            status(authorize(Some("secret"), &request(Some("Bearer wrong")))),
+            Some(StatusCode::UNAUTHORIZED)
+        );
+        assert_eq!(
+            status(authorize(Some("secret"), &request(Some("secret")))),
+            Some(StatusCode::UNAUTHORIZED)
+        );
+        assert_eq!(
+            status(authorize(Some("secret"), &request(Some("Bearer secret")))),
+            None
+        );
+    }
+}
@@ -150,9 +150,7 @@ pub async fn scep_endpoint_keyserve(
         .write()
         .await
         .take_session(&cookie)
-        .ok_or_else(|| {
-            scep::error::ScepError::InvalidMessage(anyhow::anyhow!("unknown cookie {cookie}"))
-        })?;
+        .ok_or(scep::error::ScepError::UnknownSessionCookie(cookie))?;
     let client_mid = client_state.client_mid();
     let nucleotide_total_count = client_state.open_request().nucleotide_total_count;
 
@@ -177,13 +175,14 @@ pub async fn scep_endpoint_keyserve(
     }
 
     let server_state2 = server_state.clone();
-    let keyshare = server_state.keyshare;
     let lagrange_coeff = keyserver_id_set.langrange_coefficient_for_id(&server_state.keyserver_id);
     let encrypt_query = move |query| {
         if let Some(metrics) = &server_state2.metrics {
             metrics.hash_counter.inc();
         }
-        keyshare.apply_query_and_lagrange_coefficient(query, &lagrange_coeff)
+        server_state2
+            .keyshare
+            .apply_query_and_lagrange_coefficient(query, &lagrange_coeff)
     };
 
     let chunks = map_ristretto_stream(
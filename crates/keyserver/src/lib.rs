@@ -2,6 +2,7 @@
 // SPDX-License-Identifier: MIT OR Apache-2.0
 
 pub mod event_store;
+mod hash_preview;
 mod keyserve;
 mod opts;
 mod qualification;
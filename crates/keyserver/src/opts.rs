@@ -78,6 +78,13 @@ pub struct Config {
     )]
     pub crypto_parallelism_per_request: Option<usize>,
 
+    #[clap(
+        long,
+        help = "Maximum number of hashes a client may request in one session. Larger requests are rejected before any hashes are sent",
+        env = "SECUREDNA_KEYSERVER_MAX_HASH_TOTAL_COUNT"
+    )]
+    pub max_hash_total_count: Option<u64>,
+
     #[clap(
         long,
         help = "Size limit for JSON request bodies in SCEP",
@@ -87,6 +94,15 @@ pub struct Config {
     #[serde(default = "Config::default_scep_json_size_limit")]
     pub scep_json_size_limit: u64,
 
+    #[clap(
+        long,
+        help = "Seconds a SCEP session may sit idle between handshake steps before it is evicted",
+        env = "SECUREDNA_KEYSERVER_SESSION_IDLE_TIMEOUT_SECS",
+        default_value_t = Config::default_session_idle_timeout_secs(),
+    )]
+    #[serde(default = "Config::default_session_idle_timeout_secs")]
+    pub session_idle_timeout_secs: u64,
+
     #[clap(
         long,
         help = "Directory containing manufacturer root certs for SCEP client cert verification",
@@ -122,6 +138,13 @@ pub struct Config {
     )]
     pub keypair_passphrase_file: PathBuf,
 
+    #[clap(
+        long,
+        help = "File containing a bearer token that enables the monitoring plane's /hash-preview endpoint. The endpoint is disabled when unset",
+        env = "SECUREDNA_KEYSERVER_HASH_PREVIEW_TOKEN_FILE"
+    )]
+    pub hash_preview_token_file: Option<PathBuf>,
+
     #[clap(
         long,
         help = "Do not set the `secure` flag on session cookies, allowing them to be transported over http://. This is useful for local testing.",
@@ -151,6 +174,10 @@ impl Config {
         100000
     }
 
+    pub fn default_session_idle_timeout_secs() -> u64 {
+        300
+    }
+
     pub fn default_event_store_path() -> PathBuf {
         ":memory:".into()
     }
@@ -164,6 +191,7 @@ impl RelativeConfig for Config {
         self.token_file = base.join(self.token_file);
         self.keypair_file = base.join(self.keypair_file);
         self.keypair_passphrase_file = base.join(self.keypair_passphrase_file);
+        self.hash_preview_token_file = self.hash_preview_token_file.map(|p| base.join(p));
         if self.event_store_path != Path::new(":memory:") {
             self.event_store_path = base.join(self.event_store_path);
         }
@@ -6,11 +6,16 @@ use hyper::body::{Body, Incoming};
 use hyper::{Request, StatusCode};
 use tracing::warn;
 
+use doprf::party::KeyserverId;
+use doprf::prf::{HashPart, Query};
 use minhttp::response::{self, ErrResponse, ResponseResult};
-use shared_types::server_selection::{KeyserverQualificationResponse, QualificationRequest};
+use shared_types::server_selection::{
+    Capabilities, KeyserverQualificationResponse, QualificationRequest, CURRENT_CLIENT_VERSION,
+};
 use streamed_ristretto::stream::MessageError;
+use streamed_ristretto::HasContentType;
 
-use crate::state::KeyserverState;
+use crate::state::{GenerationKeyInfo, KeyserverState};
 
 pub async fn qualification(
     ks_state: &KeyserverState,
@@ -34,17 +39,8 @@ pub async fn qualification(
     let data: QualificationRequest = serde_json::from_slice(&body)
         .map_err(|e| ErrResponse(response::text(StatusCode::BAD_REQUEST, e)))?;
 
-    if data.client_version != 0 {
-        return Err(ErrResponse(response::text(
-            StatusCode::BAD_REQUEST,
-            "bad client version",
-        )));
-    }
-
-    let response = KeyserverQualificationResponse {
-        id: ks_state.keyserver_id,
-        generations_and_key_info: ks_state.generations_key_info.0.clone(),
-    };
+    let response =
+        qualification_response(&data, ks_state.keyserver_id, &ks_state.generations_key_info)?;
 
     let json = serde_json::to_string(&response).map_err(|err| {
         warn!("failed to serialize qualification response: {err}");
@@ -55,3 +51,77 @@ pub async fn qualification(
     })?;
     Ok(response::json(StatusCode::OK, json))
 }
+
+/// Every supported client version gets the same response: version 0 clients
+/// just don't know about the fields added since.
+fn qualification_response(
+    request: &QualificationRequest,
+    id: KeyserverId,
+    generations_key_info: &GenerationKeyInfo,
+) -> Result<KeyserverQualificationResponse, ErrResponse> {
+    if !request.is_supported_version() {
+        return Err(ErrResponse(response::text(
+            StatusCode::BAD_REQUEST,
+            "bad client version",
+        )));
+    }
+
+    Ok(KeyserverQualificationResponse {
+        id,
+        generations_and_key_info: generations_key_info.0.clone(),
+        max_client_version: CURRENT_CLIENT_VERSION,
+        capabilities: Capabilities {
+            content_types: vec![Query::CONTENT_TYPE.into(), HashPart::CONTENT_TYPE.into()],
+            encodings: vec![],
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use doprf::active_security::{ActiveSecurityKey, Commitment};
+    use serde::Deserialize;
+    use shared_types::server_selection::KeyInfo;
+
+    use super::*;
+
+    /// The response as version 0 clients know it.
+    #[derive(Deserialize)]
+    struct LegacyKeyserverQualificationResponse {
+        id: KeyserverId,
+        generations_and_key_info: HashMap<u32, KeyInfo>,
+    }
+
+    #[test]
+    fn version_0_clients_can_parse_the_response() {
+        let id = KeyserverId::try_from(1).unwrap();
+        let key_info = KeyInfo {
+            quorum: 1,
+            active_security_key: ActiveSecurityKey::from_commitments([
+                Commitment::hash_from_bytes_for_tests_only(&[1]),
+            ]),
+        };
+        let generations_key_info = GenerationKeyInfo(HashMap::from([(0, key_info)]));
+
+        let request = QualificationRequest { client_version: 0 };
+        let Ok(response) = qualification_response(&request, id, &generations_key_info) else {
+            panic!("version 0 should still be supported");
+        };
+        let json = serde_json::to_value(&response).unwrap();
+        assert_eq!(json["max_client_version"], CURRENT_CLIENT_VERSION);
+
+        let legacy: LegacyKeyserverQualificationResponse = serde_json::from_value(json).unwrap();
+        assert_eq!(legacy.id, id);
+        assert_eq!(
+            legacy.generations_and_key_info.keys().collect::<Vec<_>>(),
+            vec![&0]
+        );
+
+        let request = QualificationRequest {
+            client_version: CURRENT_CLIENT_VERSION + 1,
+        };
+        assert!(qualification_response(&request, id, &generations_key_info).is_err());
+    }
+}
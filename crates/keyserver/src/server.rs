@@ -7,11 +7,12 @@ use std::future::Future;
 use std::net::SocketAddr;
 use std::num::NonZeroUsize;
 use std::sync::{Arc, Weak};
+use std::time::Duration;
 
 use anyhow::Context;
 use hyper::body::Incoming;
 use hyper::{Method, Request, StatusCode};
-use tokio::sync::Semaphore;
+use tokio::sync::{RwLock, Semaphore};
 use tracing::{error, info};
 
 use certificates::{Issued, KeyserverTokenGroup, Manufacturer};
@@ -19,9 +20,10 @@ use doprf::active_security::ActiveSecurityKey;
 use minhttp::error::ErrWrapper;
 use minhttp::mpserver::{traits::ValidServerSetup, MultiplaneServer, ServerConfig};
 use minhttp::response::{self, ErrResponse, GenericResponse};
+use scep::states::ServerSessions;
 use scep_server_helpers::server::ServerState;
 use securedna_versioning::version::get_version;
-use shared_types::hash::HashSpec;
+use shared_types::hash::{HashSpec, Windowing};
 use shared_types::http::add_cors_headers;
 use shared_types::server_selection::KeyInfo;
 use shared_types::server_versions::KeyserverVersion;
@@ -104,6 +106,19 @@ async fn reconfigure(
     let keypair = scep_server_helpers::certs::read_keypair(app_cfg.keypair_file, passphrase.trim())
         .context("reading keyserver keypair")?;
 
+    let hash_preview_token = match &app_cfg.hash_preview_token_file {
+        Some(path) => {
+            let token = fs::read_to_string(path)
+                .with_context(|| format!("reading hash preview token file: {path:?}"))?;
+            let token = token.trim();
+            if token.is_empty() {
+                return Err(anyhow::anyhow!("hash preview token file is empty: {path:?}").into());
+            }
+            Some(token.to_owned())
+        }
+        None => None,
+    };
+
     let heavy_requests = Arc::new(Semaphore::new(app_cfg.max_heavy_clients));
     let processing_chunks = Arc::new(Semaphore::new(crypto_parallelism_per_server));
 
@@ -156,14 +171,18 @@ async fn reconfigure(
         processing_chunks,
         parallelism_per_request,
         scep: ServerState {
-            clients: Default::default(),
+            clients: RwLock::new(ServerSessions::with_idle_timeout(Duration::from_secs(
+                app_cfg.session_idle_timeout_secs,
+            ))),
             json_size_limit: app_cfg.scep_json_size_limit,
             manufacturer_roots,
             revocation_list,
             token_bundle,
             keypair,
+            max_hash_total_count: app_cfg.max_hash_total_count,
             allow_insecure_cookie: app_cfg.allow_insecure_cookie,
         },
+        hash_preview_token,
         persistence_path: app_cfg.event_store_path,
         persistence_connection,
     }))
@@ -304,12 +323,14 @@ async fn scep_endpoint_open(
     server_state: &KeyserverState,
     request: Request<Incoming>,
 ) -> Result<GenericResponse, scep::error::ScepError<scep::error::ServerPrevalidation>> {
+    server_state.evict_idle_sessions().await;
     scep_server_helpers::server::scep_endpoint_open(
         &server_state.scep,
         SERVER_VERSION,
         &HashSpec {
             max_expansions_per_window: NonZeroUsize::MIN,
             htdv: vec![],
+            windowing: Windowing::All,
         },
         |client_mid| async move {
             match event_store::last_protocol_version_for_client(
@@ -378,12 +399,23 @@ async fn scep_endpoint_authenticate(
 }
 
 async fn respond_to_monitoring_plane(
-    _ks_state: Arc<KeyserverState>,
+    ks_state: Arc<KeyserverState>,
     _peer: SocketAddr,
     request: Request<Incoming>,
 ) -> GenericResponse {
     match (request.method(), request.uri().path()) {
         (&Method::GET, "/metrics") => query_server_metrics(),
+        (&Method::POST, "/hash-preview") => {
+            handle_err(
+                &ks_state.metrics,
+                crate::hash_preview::hash_preview(
+                    &ks_state.keyshare,
+                    ks_state.hash_preview_token.as_deref(),
+                    request,
+                ),
+            )
+            .await
+        }
         _ => response::text(StatusCode::NOT_FOUND, "404 not found"),
     }
 }
@@ -7,6 +7,7 @@ use std::sync::Arc;
 
 use hyper::StatusCode;
 use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tracing::debug;
 
 use certificates::KeyserverTokenGroup;
 use doprf::party::KeyserverId;
@@ -32,6 +33,7 @@ pub struct KeyserverState {
     pub processing_chunks: Arc<Semaphore>,
     pub parallelism_per_request: usize,
     pub scep: ServerState<KeyserverTokenGroup>,
+    pub hash_preview_token: Option<String>,
     pub persistence_path: PathBuf,
     pub persistence_connection: Connection,
 }
@@ -48,4 +50,14 @@ impl KeyserverState {
                 )
             })
     }
+
+    /// Drop SCEP sessions that have sat idle past the configured timeout, so
+    /// that abandoned handshakes don't accumulate. Clients returning with an
+    /// evicted session are told it expired.
+    pub async fn evict_idle_sessions(&self) {
+        let evicted = self.scep.clients.write().await.evict_idle();
+        if evicted > 0 {
+            debug!("evicted {evicted} idle SCEP sessions");
+        }
+    }
 }
@@ -1,9 +1,11 @@
 // Copyright 2021-2024 SecureDNA Stiftung (SecureDNA Foundation) <licensing@securedna.org>
 // SPDX-License-Identifier: MIT OR Apache-2.0
 
+use std::fmt;
 use std::marker::PhantomData;
 
-use doprf::prf::{Query, QueryStateSet};
+use doprf::prf::{CompletedHashValue, DecodeError, Query, QueryStateSet};
+use doprf::tagged::{HashTag, TaggedHash};
 
 use crate::{error::DeserializeError, packable::PackableRistretto};
 
@@ -143,6 +145,28 @@ impl<T: PackableRistretto> PackedRistrettos<T> {
             .map(|encoded| (*encoded).clone().try_into())
     }
 
+    /// Describe each element, for diagnostics. Elements that fail to decode
+    /// are described with their decode error rather than ending the dump.
+    pub fn describe(&self) -> Vec<ElementDescription>
+    where
+        <T as TryFrom<T::Array>>::Error: fmt::Display,
+    {
+        self.iter_encoded()
+            .zip(self.iter_decoded())
+            .enumerate()
+            .map(|(index, (encoded, decoded))| ElementDescription {
+                index,
+                hex: encoded
+                    .as_ref()
+                    .iter()
+                    .map(|byte| format!("{byte:02x}"))
+                    .collect(),
+                tag: T::tag(encoded),
+                error: decoded.err().map(|error| error.to_string()),
+            })
+            .collect()
+    }
+
     /// Returns the number of *elements* in `self` (not the byte length)
     pub fn len(&self) -> usize {
         self.items.len()
@@ -151,7 +175,14 @@ impl<T: PackableRistretto> PackedRistrettos<T> {
     /// Returns the length in bytes of `self` (`self.len() * 32 + 13`)
     pub fn len_bytes(&self) -> usize {
         // header + checksum is an extra 13 bytes
-        self.len() * T::SIZE + 13
+        Self::expected_byte_len(self.len()) + 13
+    }
+
+    /// Returns the length in bytes of `count` encoded items, without the header
+    /// and checksum. This is the `Content-Length` of an upload body, and matches
+    /// `StreamableRistretto::content_length_for` on the server side.
+    pub fn expected_byte_len(count: usize) -> usize {
+        count * T::SIZE
     }
 
     /// Returns whether `self` is empty
@@ -192,6 +223,69 @@ impl<T: PackableRistretto, A: Into<T::Array>> FromIterator<A> for PackedRistrett
     }
 }
 
+/// One element of a [`PackedRistrettos`], as described by
+/// [`PackedRistrettos::describe`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ElementDescription {
+    pub index: usize,
+    /// The encoded element, as lowercase hex.
+    pub hex: String,
+    /// The element's tag, for tagged types like [`TaggedHash`].
+    pub tag: Option<HashTag>,
+    /// Why the element failed to decode, if it did.
+    pub error: Option<String>,
+}
+
+impl fmt::Display for ElementDescription {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:>6}: {}", self.index, self.hex)?;
+        if let Some(tag) = &self.tag {
+            write!(
+                f,
+                " (starts_new_record={}, hash_type_index={}, index_in_record={}",
+                tag.starts_new_record(),
+                tag.hash_type_index(),
+                tag.index_in_record()
+            )?;
+            if tag.has_reserved_bits() {
+                write!(f, ", reserved bits set")?;
+            }
+            write!(f, ")")?;
+        }
+        if let Some(error) = &self.error {
+            write!(f, " invalid: {error}")?;
+        }
+        Ok(())
+    }
+}
+
+impl PackedRistrettos<TaggedHash> {
+    /// Pack `hashes`, checking that each has a valid tag (with no reserved bits
+    /// set) and a valid Ristretto point.
+    ///
+    /// Use the infallible `From<Vec<TaggedHash>>` for hashes that are known to
+    /// be valid, such as those just computed by a `QueryStateSet`.
+    pub fn try_from_tagged(hashes: Vec<TaggedHash>) -> Result<Self, DecodeError> {
+        hashes
+            .into_iter()
+            .map(|hash| {
+                if hash.tag.has_reserved_bits() {
+                    return Err(DecodeError::InvalidHashTag);
+                }
+                CompletedHashValue::try_from(hash.hash.as_bytes())?;
+                Ok(hash.into())
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map(Self::new)
+    }
+}
+
+impl From<Vec<TaggedHash>> for PackedRistrettos<TaggedHash> {
+    fn from(hashes: Vec<TaggedHash>) -> Self {
+        hashes.into_iter().collect()
+    }
+}
+
 impl From<&QueryStateSet> for PackedRistrettos<Query> {
     fn from(value: &QueryStateSet) -> Self {
         value.queries().collect()
@@ -214,6 +308,7 @@ impl From<&QueryStateSet> for PackedRistrettos<Query> {
 mod tests {
     use super::*;
 
+    use doprf::tagged::HashTag;
     use quickcheck::{quickcheck, Arbitrary, Gen};
 
     fn assert_roundtrips<T: PackableRistretto + std::cmp::PartialEq + std::fmt::Debug>(
@@ -376,4 +471,86 @@ mod tests {
             PackedRistrettos::<Dummy>::deserialize(&ser).is_err()
         }
     }
+
+    fn tagged(tag: HashTag, seed: &[u8]) -> TaggedHash {
+        TaggedHash {
+            tag,
+            hash: CompletedHashValue::hash_from_bytes_for_tests_only(seed),
+        }
+    }
+
+    #[test]
+    fn try_from_tagged_packs_valid_hashes() {
+        let hashes = vec![
+            tagged(HashTag::new(true, 0, 0), b"a"),
+            tagged(HashTag::new(false, 1, 1), b"b"),
+        ];
+        let packed = PackedRistrettos::try_from_tagged(hashes.clone()).unwrap();
+
+        assert_eq!(packed, PackedRistrettos::from(hashes.clone()));
+        let decoded: Vec<_> = packed.iter_decoded().map(Result::unwrap).collect();
+        assert_eq!(decoded, hashes);
+    }
+
+    #[test]
+    fn try_from_tagged_rejects_reserved_tag_bits() {
+        let hashes = vec![
+            tagged(HashTag::new(true, 0, 0), b"a"),
+            tagged(HashTag::from_bytes([0xff, 0, 0, 1]), b"b"),
+        ];
+        assert!(matches!(
+            PackedRistrettos::try_from_tagged(hashes),
+            Err(DecodeError::InvalidHashTag)
+        ));
+    }
+
+    #[test]
+    fn describe_reports_invalid_elements_without_failing() {
+        let valid = <[u8; 36]>::from(tagged(HashTag::new(true, 2, 5), b"a"));
+        let mut invalid = [0xff; 36];
+        invalid[..4].copy_from_slice(HashTag::new(false, 2, 6).as_bytes());
+        let packed = PackedRistrettos::<TaggedHash>::new(vec![valid, invalid]);
+
+        let described = packed.describe();
+        assert_eq!(described.len(), 2);
+
+        assert_eq!(described[0].index, 0);
+        assert_eq!(described[0].hex.len(), 72);
+        assert!(described[0].hex.starts_with("12000005"));
+        assert_eq!(described[0].tag, Some(HashTag::new(true, 2, 5)));
+        assert_eq!(described[0].error, None);
+
+        assert_eq!(described[1].index, 1);
+        assert_eq!(described[1].hex, format!("02000006{}", "ff".repeat(32)));
+        assert_eq!(described[1].tag, Some(HashTag::new(false, 2, 6)));
+        assert!(described[1].error.is_some());
+        assert!(described[1].to_string().contains("invalid"));
+
+        let untagged = PackedRistrettos::<Dummy>::new(vec![[0xab; 32]]).describe();
+        assert_eq!(untagged[0].tag, None);
+        assert_eq!(untagged[0].error, None);
+    }
+
+    #[test]
+    fn client_and_server_agree_on_content_length() {
+        use doprf::prf::Query;
+        use doprf::tagged::TaggedHash;
+        use streamed_ristretto::stream::{check_content_length, StreamableRistretto};
+
+        for count in [0, 1, 2, 7, 1000, 123_456] {
+            let client = PackedRistrettos::<Query>::expected_byte_len(count) as u64;
+            assert_eq!(Some(client), Query::content_length_for(count as u64));
+            assert_eq!(
+                check_content_length(Some(client), <Query as StreamableRistretto>::SIZE),
+                Ok(count as u64)
+            );
+
+            let client = PackedRistrettos::<TaggedHash>::expected_byte_len(count) as u64;
+            assert_eq!(Some(client), TaggedHash::content_length_for(count as u64));
+            assert_eq!(
+                check_content_length(Some(client), <TaggedHash as StreamableRistretto>::SIZE),
+                Ok(count as u64)
+            );
+        }
+    }
 }
@@ -7,7 +7,9 @@
 pub mod datatype;
 pub mod error;
 pub mod packable;
+pub mod verification;
 
-pub use datatype::PackedRistrettos;
+pub use datatype::{ElementDescription, PackedRistrettos};
 pub use error::DeserializeError;
 pub use packable::PackableRistretto;
+pub use verification::VerificationOutcome;
@@ -4,7 +4,7 @@
 use doprf::{
     active_security::Commitment,
     prf::{CompletedHashValue, HashPart, Query},
-    tagged::TaggedHash,
+    tagged::{HashTag, TaggedHash},
 };
 
 /// Trait for "Ristretto-like" types—types that can be converted to and from
@@ -16,6 +16,12 @@ pub trait PackableRistretto: TryFrom<Self::Array> + Into<Self::Array> + Send + S
     type Array: Clone + std::fmt::Debug + PartialEq + AsRef<[u8]> + for<'a> TryFrom<&'a [u8]>;
     const SIZE: usize = std::mem::size_of::<Self::Array>();
     const MAGIC: [u8; 4];
+
+    /// Read the tag out of an encoded item, for types that carry one. This
+    /// doesn't require the rest of the item to decode.
+    fn tag(_encoded: &Self::Array) -> Option<HashTag> {
+        None
+    }
 }
 
 impl PackableRistretto for Query {
@@ -41,4 +47,8 @@ impl PackableRistretto for Commitment {
 impl PackableRistretto for TaggedHash {
     type Array = [u8; 36];
     const MAGIC: [u8; 4] = *b"THSH";
+
+    fn tag(encoded: &Self::Array) -> Option<HashTag> {
+        Some(HashTag::from_bytes(encoded[..4].try_into().unwrap()))
+    }
 }
@@ -0,0 +1,62 @@
+// Copyright 2021-2024 SecureDNA Stiftung (SecureDNA Foundation) <licensing@securedna.org>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use std::fmt;
+
+use doprf::party::KeyserverId;
+use doprf::prf::{HashPart, SerializableQueryStateSet};
+use doprf::tagged::TaggedHash;
+use serde::{Deserialize, Serialize};
+
+use crate::PackedRistrettos;
+
+/// What the verification program (in `verification_proof/program`) commits
+/// first: the hashes it computed from the keyserver responses, or the step
+/// at which it gave up.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum VerificationOutcome {
+    Ok(PackedRistrettos<TaggedHash>),
+    /// The query states or a keyserver response failed to decode.
+    DecodeFailed,
+    /// A keyserver response couldn't be incorporated.
+    IncorporateFailed,
+    /// The hashes couldn't be computed from the incorporated responses.
+    HashFailed,
+}
+
+impl VerificationOutcome {
+    /// What the verification program computes once the proofs it was given
+    /// have verified: `querystates` with `keyserver_responses` incorporated,
+    /// hashed, or the first step that failed.
+    pub fn compute(
+        querystates: &SerializableQueryStateSet,
+        keyserver_responses: &[(KeyserverId, PackedRistrettos<HashPart>)],
+    ) -> Self {
+        let Ok(mut querystates) = querystates.to_query_state_set() else {
+            return Self::DecodeFailed;
+        };
+        for (id, response) in keyserver_responses {
+            let Ok(parts) = response.iter_decoded().collect::<Result<Vec<_>, _>>() else {
+                return Self::DecodeFailed;
+            };
+            if querystates.incorporate_response(*id, &parts).is_err() {
+                return Self::IncorporateFailed;
+            }
+        }
+        match querystates.get_hash_values() {
+            Ok(hashes) => Self::Ok(hashes.into_iter().collect()),
+            Err(_) => Self::HashFailed,
+        }
+    }
+}
+
+impl fmt::Display for VerificationOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Ok(hashes) => write!(f, "computed {} hashes", hashes.len()),
+            Self::DecodeFailed => write!(f, "couldn't decode the query states or responses"),
+            Self::IncorporateFailed => write!(f, "couldn't incorporate the keyserver responses"),
+            Self::HashFailed => write!(f, "couldn't compute the hashes"),
+        }
+    }
+}
@@ -1,6 +1,7 @@
 // Copyright 2021-2024 SecureDNA Stiftung (SecureDNA Foundation) <licensing@securedna.org>
 // SPDX-License-Identifier: MIT OR Apache-2.0
 
+use crate::cookie::SessionCookie;
 use crate::types::ClientRequestType;
 use certificates::{Exemption, Manufacturer, SignatureVerificationError, TokenBundleError};
 use doprf::party::KeyserverId;
@@ -14,6 +15,14 @@ pub enum ScepError<Inner: std::error::Error> {
     InternalError(anyhow::Error),
     #[error("invalid request or response: {0}")]
     InvalidMessage(anyhow::Error),
+    #[error("missing session cookie")]
+    MissingSessionCookie,
+    #[error("malformed session cookie: {0}")]
+    MalformedSessionCookie(anyhow::Error),
+    /// The cookie is well-formed, but doesn't belong to a current session, most
+    /// likely because it expired. The client should open a new session.
+    #[error("unknown or expired session cookie {0}")]
+    UnknownSessionCookie(SessionCookie),
     #[error("server is overloaded. try again later")]
     Overloaded,
     #[error("exceeded client daily limit of {limit_bp}bp")]
@@ -64,6 +73,11 @@ pub enum ServerAuthentication {
         hash_total_count: u64,
         nucleotide_total_count: u64,
     },
+    #[error("provided hash_total_count {hash_total_count} exceeds this server's limit of {max_hash_total_count}")]
+    HtcTooLarge {
+        hash_total_count: u64,
+        max_hash_total_count: u64,
+    },
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -90,6 +104,8 @@ pub enum Screen {
     ScreenBeforeEtHashes,
     #[error("exemption token validation error: {0}")]
     EtValidation(String),
+    #[error("proof verification failed: {0}")]
+    ProofVerificationFailed(String),
 }
 
 #[derive(Debug, thiserror::Error)]
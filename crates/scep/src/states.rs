@@ -2,6 +2,7 @@
 // SPDX-License-Identifier: MIT OR Apache-2.0
 
 use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
 
 use crate::cookie::SessionCookie;
 use crate::nonce::ServerNonce;
@@ -26,11 +27,40 @@ pub struct OpenedClientState {
 
 /// Small wrapper for handling client session logic.
 #[derive(Debug)]
-pub struct ServerSessions<State>(HashMap<SessionCookie, State>);
+pub struct ServerSessions<State> {
+    sessions: HashMap<SessionCookie, IdleSession<State>>,
+    /// How long a session may sit between steps before it's evicted, if ever.
+    idle_timeout: Option<Duration>,
+}
+
+#[derive(Debug)]
+struct IdleSession<State> {
+    state: State,
+    /// When the session was last (re)added, i.e. when its last step finished.
+    idle_since: Instant,
+}
+
+impl<State> IdleSession<State> {
+    fn is_expired(&self, idle_timeout: Option<Duration>) -> bool {
+        idle_timeout.is_some_and(|timeout| self.idle_since.elapsed() > timeout)
+    }
+}
 
 impl<State> ServerSessions<State> {
     pub fn new() -> Self {
-        Self(HashMap::new())
+        Self {
+            sessions: HashMap::new(),
+            idle_timeout: None,
+        }
+    }
+
+    /// Sessions that sit idle between steps for longer than `idle_timeout` are
+    /// treated as unknown, and dropped by [`Self::evict_idle`].
+    pub fn with_idle_timeout(idle_timeout: Duration) -> Self {
+        Self {
+            sessions: HashMap::new(),
+            idle_timeout: Some(idle_timeout),
+        }
     }
 
     /// Add a new client session, if one does not already exist for this cookie.
@@ -40,10 +70,13 @@ impl<State> ServerSessions<State> {
     pub fn add_session(&mut self, cookie: SessionCookie, state: State) -> Result<(), &State> {
         use std::collections::hash_map::Entry;
 
-        match self.0.entry(cookie) {
-            Entry::Occupied(_) => Err(self.0.get(&cookie).unwrap()),
+        match self.sessions.entry(cookie) {
+            Entry::Occupied(_) => Err(&self.sessions.get(&cookie).unwrap().state),
             Entry::Vacant(v) => {
-                v.insert(state);
+                v.insert(IdleSession {
+                    state,
+                    idle_since: Instant::now(),
+                });
                 Ok(())
             }
         }
@@ -51,8 +84,22 @@ impl<State> ServerSessions<State> {
 
     /// Take a session from the session map, leaving that space empty so that another request
     /// can't be made with it until the step is finished and the session is readded.
+    ///
+    /// Sessions idle past the idle timeout are dropped instead of returned.
     pub fn take_session(&mut self, cookie: &SessionCookie) -> Option<State> {
-        self.0.remove(cookie)
+        self.sessions
+            .remove(cookie)
+            .filter(|session| !session.is_expired(self.idle_timeout))
+            .map(|session| session.state)
+    }
+
+    /// Drop every session idle past the idle timeout, returning how many were dropped.
+    pub fn evict_idle(&mut self) -> usize {
+        let before = self.sessions.len();
+        let idle_timeout = self.idle_timeout;
+        self.sessions
+            .retain(|_, session| !session.is_expired(idle_timeout));
+        before - self.sessions.len()
     }
 }
 
@@ -158,4 +205,32 @@ mod tests {
         assert_eq!(sessions.take_session(&cookie).unwrap(), 1);
         assert_eq!(sessions.take_session(&cookie), None);
     }
+
+    #[test]
+    fn server_sessions_idle_past_timeout_are_evicted() {
+        let idle: SessionCookie = rand::thread_rng().gen();
+        let mut sessions = ServerSessions::<u8>::with_idle_timeout(Duration::from_millis(10));
+        sessions.add_session(idle, 1).unwrap();
+        std::thread::sleep(Duration::from_millis(20));
+
+        let fresh: SessionCookie = rand::thread_rng().gen();
+        sessions.add_session(fresh, 2).unwrap();
+        assert_eq!(sessions.evict_idle(), 1);
+        assert_eq!(sessions.take_session(&fresh), Some(2));
+
+        // a client returning after eviction is told its session expired
+        let err = sessions
+            .take_session(&idle)
+            .ok_or(crate::error::ScepError::<crate::error::Keyserve>::UnknownSessionCookie(idle))
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!("unknown or expired session cookie {idle}")
+        );
+
+        // sessions past the timeout are rejected even before they're evicted
+        sessions.add_session(idle, 1).unwrap();
+        std::thread::sleep(Duration::from_millis(20));
+        assert_eq!(sessions.take_session(&idle), None);
+    }
 }
@@ -339,6 +339,7 @@ pub async fn server_authenticate_client<
     server_version: u64,
     issuer_pks: &[PublicKey],
     revocation_list: &RevocationList,
+    max_hash_total_count: Option<u64>,
     get_client_screened_last_day: GetScreenedLastDay,
     record_rate_limit_exceedance: RecordExceedance,
 ) -> Result<ServerStateForClient, ScepError<error::ServerAuthentication>>
@@ -406,6 +407,17 @@ where
         .into());
     }
 
+    // reject oversized requests now, before the client sends any hashes
+    if let Some(max_hash_total_count) = max_hash_total_count {
+        if authenticate_request.hash_total_count > max_hash_total_count {
+            return Err(error::ServerAuthentication::HtcTooLarge {
+                hash_total_count: authenticate_request.hash_total_count,
+                max_hash_total_count,
+            }
+            .into());
+        }
+    }
+
     // check rate limits
     let client_mid = client_state.open_request.client_mid();
     let limit_bp = client_state
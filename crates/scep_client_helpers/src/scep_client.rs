@@ -13,6 +13,7 @@ use doprf::prf::CompletedHashValue;
 use doprf::{
     party::{KeyserverId, KeyserverIdSet},
     prf::{HashPart, Query, VerificationInput},
+    prove::ExecutionStats,
     tagged::TaggedHash,
 };
 use http_client::{BaseApiClient, HttpError};
@@ -187,11 +188,23 @@ impl ScepClient<KeyserverTokenGroup> {
     pub async fn keyserve(
         &self,
         queries: &PackedRistrettos<Query>,
+    ) -> Result<PackedRistrettos<HashPart>, HttpError> {
+        self.keyserve_with_limit(queries, None).await
+    }
+
+    /// Like [`Self::keyserve`], but abandoning the response with
+    /// [`HttpError::ResponseTooLarge`] once it's longer than `max_response_bytes`.
+    pub async fn keyserve_with_limit(
+        &self,
+        queries: &PackedRistrettos<Query>,
+        max_response_bytes: Option<u64>,
     ) -> Result<PackedRistrettos<HashPart>, HttpError> {
         self.api_client
-            .ristretto_ristretto_post(
+            .ristretto_ristretto_post_with_limit(
                 &format!("{}{}", self.domain, scep::KEYSERVE_ENDPOINT),
                 queries,
+                &[],
+                max_response_bytes,
             )
             .await
     }
@@ -236,15 +249,19 @@ impl ScepClient<DatabaseTokenGroup> {
             .await
     }
 
+    /// Like [`Self::screen`], but with the proof that the hashes were computed
+    /// honestly, and what running the zkVM programs for it cost.
     pub async fn screen_and_verify(
         &self,
         hashes: &PackedRistrettos<TaggedHash>,
         hdb_verification_input: VerificationInput,
+        execution_stats: ExecutionStats,
     ) -> Result<HdbScreeningResult, HttpError> {
         #[derive(serde::Serialize)]
         struct RequestWithVerification {
             ristretto_data: Vec<u8>,
             verification: VerificationInput,
+            execution_stats: ExecutionStats,
         }
 
         let ristretto_data: Vec<u8> = hashes
@@ -256,6 +273,7 @@ impl ScepClient<DatabaseTokenGroup> {
         let request = RequestWithVerification {
             ristretto_data: ristretto_data,
             verification: hdb_verification_input,
+            execution_stats,
         };
 
         self.api_client
@@ -93,6 +93,7 @@ fn screen_hash(hash: &TaggedHash, exemptions: &Exemptions) -> Option<Consolidate
         is_dna: true,
         is_wild_type: None,
         exempt,
+        window_offsets: vec![],
     })
 }
 
@@ -104,5 +105,6 @@ pub fn mock_screen(hashes: &[TaggedHash], exemptions: &Exemptions) -> HdbScreeni
             .collect(),
         debug_hdb_responses: None,
         provider_reference: None,
+        warnings: vec![],
     }
 }
@@ -5,6 +5,7 @@ use std::fmt::Debug;
 use std::future::Future;
 use std::net::{IpAddr, SocketAddr};
 use std::sync::{Arc, RwLock};
+use std::time::Duration;
 
 use anyhow::Context;
 use bytes::{Buf, Bytes, BytesMut};
@@ -49,6 +50,10 @@ pub struct Opts<T: TokenGroup> {
     pub server_keypair: KeyPair,
     pub keyserve_fn: Arc<dyn Fn(Query) -> HashPart + Send + Sync + 'static>,
     pub hash_spec: HashSpec,
+    pub max_hash_total_count: Option<u64>,
+    /// How long sessions may sit idle between steps, like the keyserver's
+    /// `session_idle_timeout_secs`. Sessions never expire if `None`.
+    pub session_idle_timeout: Option<Duration>,
 }
 
 struct ServerState<T: TokenGroup> {
@@ -147,8 +152,12 @@ where
     T::AssociatedRole: Debug + Send + Sync,
     T::ChainType: Debug + Send + Sync,
 {
+    let sessions = match opts.session_idle_timeout {
+        Some(idle_timeout) => ServerSessions::with_idle_timeout(idle_timeout),
+        None => ServerSessions::new(),
+    };
     let server_state = Arc::new(ServerState {
-        clients: RwLock::new(ServerSessions::new()),
+        clients: RwLock::new(sessions),
         opts,
     });
 
@@ -278,6 +287,8 @@ where
     T::AssociatedRole: Clone + Debug,
     T::ChainType: Clone + Debug,
 {
+    server_state.clients.write().unwrap().evict_idle();
+
     let body = scep_server_helpers::request::check_and_extract_json_body(100_000, request).await?;
     let open_request = scep_server_helpers::request::parse_json_body(&body)?;
 
@@ -335,9 +346,7 @@ async fn endpoint_authenticate<T: TokenGroup>(
         .write()
         .unwrap()
         .take_session(&cookie)
-        .ok_or_else(|| {
-            scep::error::ScepError::InvalidMessage(anyhow::anyhow!("unknown cookie {cookie}"))
-        })?;
+        .ok_or(scep::error::ScepError::UnknownSessionCookie(cookie))?;
 
     let client_state = scep::steps::server_authenticate_client(
         authenticate_request,
@@ -345,6 +354,7 @@ async fn endpoint_authenticate<T: TokenGroup>(
         SERVER_VERSION,
         &server_state.opts.issuer_pks,
         &server_state.opts.revocation_list,
+        server_state.opts.max_hash_total_count,
         |_| async { Ok(0) },
         |_, _| async {},
     )
@@ -382,9 +392,7 @@ async fn endpoint_keyserve<T: TokenGroup>(
         .write()
         .unwrap()
         .take_session(&cookie)
-        .ok_or_else(|| {
-            scep::error::ScepError::InvalidMessage(anyhow::anyhow!("unknown cookie {cookie}"))
-        })?;
+        .ok_or(scep::error::ScepError::UnknownSessionCookie(cookie))?;
 
     let hash_count_from_content_len =
         check_content_length(request.body().size_hint().exact(), HASH_SIZE)
@@ -425,9 +433,7 @@ async fn endpoint_screen<T: TokenGroup>(
         .write()
         .unwrap()
         .take_session(&cookie)
-        .ok_or_else(|| {
-            scep::error::ScepError::InvalidMessage(anyhow::anyhow!("unknown cookie {cookie}"))
-        })?;
+        .ok_or(scep::error::ScepError::UnknownSessionCookie(cookie))?;
 
     let hash_count_from_content_len =
         check_content_length(request.body().size_hint().exact(), TaggedHash::SIZE)
@@ -468,9 +474,7 @@ async fn endpoint_screen_with_exemption<T: TokenGroup>(
         .write()
         .unwrap()
         .take_session(&cookie)
-        .ok_or_else(|| {
-            scep::error::ScepError::InvalidMessage(anyhow::anyhow!("unknown cookie {cookie}"))
-        })?;
+        .ok_or(scep::error::ScepError::UnknownSessionCookie(cookie))?;
 
     let bytes = request
         .into_body()
@@ -515,9 +519,7 @@ async fn endpoint_exemption<T: TokenGroup>(
         .write()
         .unwrap()
         .take_session(&cookie)
-        .ok_or_else(|| {
-            scep::error::ScepError::InvalidMessage(anyhow::anyhow!("unknown cookie {cookie}"))
-        })?;
+        .ok_or(scep::error::ScepError::UnknownSessionCookie(cookie))?;
 
     let et_body = request
         .into_body()
@@ -562,9 +564,7 @@ async fn endpoint_exemption_seq_hashes<T: TokenGroup>(
         .write()
         .unwrap()
         .take_session(&cookie)
-        .ok_or_else(|| {
-            scep::error::ScepError::InvalidMessage(anyhow::anyhow!("unknown cookie {cookie}"))
-        })?;
+        .ok_or(scep::error::ScepError::UnknownSessionCookie(cookie))?;
 
     let hashes: Vec<_> = from_request::<_, CompletedHashValue>(request)
         .context("in exemption-seq-hashes")
@@ -22,6 +22,8 @@ pub async fn not_json() {
             server_keypair: certs.keyserver_keypair,
             keyserve_fn: Arc::new(|_| unreachable!()),
             hash_spec: HashSpec::dna_normal_cech(),
+            max_hash_total_count: None,
+            session_idle_timeout: None,
         },
         async {},
     )
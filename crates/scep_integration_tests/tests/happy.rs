@@ -49,6 +49,8 @@ async fn test_scenario(scenario: Scenario) {
             server_keypair: certs.keyserver_keypair,
             keyserve_fn: Arc::new(rehash_query),
             hash_spec: HashSpec::dna_normal_cech(),
+            max_hash_total_count: None,
+            session_idle_timeout: None,
         },
         async {},
     )
@@ -62,6 +64,8 @@ async fn test_scenario(scenario: Scenario) {
             server_keypair: certs.database_keypair,
             keyserve_fn: Arc::new(rehash_query),
             hash_spec: HashSpec::dna_normal_cech(),
+            max_hash_total_count: None,
+            session_idle_timeout: None,
         },
         async {},
     )
@@ -208,6 +212,7 @@ pub async fn smoketest() {
             results: vec![],
             debug_hdb_responses: None,
             provider_reference: None,
+            warnings: vec![],
         },
     })
     .await
@@ -228,9 +233,11 @@ pub async fn test_hazard_denied() {
                 is_dna: true,
                 is_wild_type: None,
                 exempt: false,
+                window_offsets: vec![],
             }],
             debug_hdb_responses: None,
             provider_reference: None,
+            warnings: vec![],
         },
     })
     .await
@@ -252,9 +259,11 @@ pub async fn test_hazard_exempt_organism() {
                 is_dna: true,
                 is_wild_type: None,
                 exempt: true,
+                window_offsets: vec![],
             }],
             debug_hdb_responses: None,
             provider_reference: None,
+            warnings: vec![],
         },
     })
     .await
@@ -277,9 +286,11 @@ pub async fn test_hazard_exempt_hash() {
                 is_dna: true,
                 is_wild_type: None,
                 exempt: true,
+                window_offsets: vec![],
             }],
             debug_hdb_responses: None,
             provider_reference: None,
+            warnings: vec![],
         },
     })
     .await;
@@ -0,0 +1,85 @@
+// Copyright 2021-2024 SecureDNA Stiftung (SecureDNA Foundation) <licensing@securedna.org>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use std::sync::Arc;
+
+use certificates::KeyserverTokenGroup;
+use doprf::party::KeyserverId;
+use scep_client_helpers::ClientCerts;
+use scep_integration_tests::make_certs::{make_certs, MakeCertsOptions};
+use scep_integration_tests::server::{Opts, TestServer};
+use shared_types::{hash::HashSpec, requests::RequestId};
+
+#[tracing_test::traced_test]
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+pub async fn oversized_request_is_rejected_at_authentication() {
+    let certs = make_certs(Default::default());
+    let issuer_pks = vec![
+        certs.infra_root_keypair.public_key(),
+        certs.manu_root_keypair.public_key(),
+    ];
+    let server = TestServer::spawn(
+        Opts {
+            issuer_pks: issuer_pks.clone(),
+            revocation_list: Default::default(),
+            server_cert_chain: certs.keyserver_tokenbundle,
+            server_keypair: certs.keyserver_keypair,
+            // the request must be rejected before any query is applied
+            keyserve_fn: Arc::new(|_| unreachable!()),
+            hash_spec: HashSpec::dna_normal_cech(),
+            max_hash_total_count: Some(10),
+            session_idle_timeout: None,
+        },
+        async {},
+    )
+    .await;
+    let server_port = server.port();
+
+    let http_client = http_client::BaseApiClient::new(RequestId::new_unique());
+    let keyserver_client = scep_client_helpers::ScepClient::<KeyserverTokenGroup>::new(
+        http_client,
+        format!("http://localhost:{server_port}"),
+        Arc::new(ClientCerts::with_custom_roots(
+            issuer_pks,
+            certs.synth_tokenbundle,
+            certs.synth_keypair,
+        )),
+        "hash_total_count_limit_test".to_owned(),
+    );
+
+    let open = || async {
+        keyserver_client
+            .open(
+                1,
+                None,
+                vec![
+                    KeyserverId::try_from(1).unwrap(),
+                    KeyserverId::try_from(2).unwrap(),
+                    KeyserverId::try_from(3).unwrap(),
+                ]
+                .into(),
+                MakeCertsOptions::default().keyserver_id,
+                false,
+            )
+            .await
+            .unwrap()
+    };
+
+    let err = keyserver_client
+        .authenticate(open().await, 11)
+        .await
+        .unwrap_err();
+    assert!(
+        err.to_string()
+            .contains("provided hash_total_count 11 exceeds this server's limit of 10"),
+        "unexpected error: {err}"
+    );
+
+    // exactly at the limit is fine
+    keyserver_client
+        .authenticate(open().await, 10)
+        .await
+        .unwrap();
+
+    server.stop().await;
+}
@@ -0,0 +1,88 @@
+// Copyright 2021-2024 SecureDNA Stiftung (SecureDNA Foundation) <licensing@securedna.org>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use certificates::KeyserverTokenGroup;
+use doprf::party::KeyserverId;
+use scep::states::OpenedClientState;
+use scep_client_helpers::{ClientCerts, ScepClient};
+use scep_integration_tests::make_certs::{make_certs, MakeCertsOptions};
+use scep_integration_tests::server::{Opts, TestServer};
+use shared_types::{hash::HashSpec, requests::RequestId};
+
+async fn open(client: &ScepClient<KeyserverTokenGroup>) -> OpenedClientState {
+    client
+        .open(
+            1,
+            None,
+            vec![
+                KeyserverId::try_from(1).unwrap(),
+                KeyserverId::try_from(2).unwrap(),
+                KeyserverId::try_from(3).unwrap(),
+            ]
+            .into(),
+            MakeCertsOptions::default().keyserver_id,
+            false,
+        )
+        .await
+        .unwrap()
+}
+
+#[tracing_test::traced_test]
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+pub async fn evicted_session_is_rejected() {
+    let certs = make_certs(Default::default());
+    let issuer_pks = vec![
+        certs.infra_root_keypair.public_key(),
+        certs.manu_root_keypair.public_key(),
+    ];
+    let server = TestServer::spawn(
+        Opts {
+            issuer_pks: issuer_pks.clone(),
+            revocation_list: Default::default(),
+            server_cert_chain: certs.keyserver_tokenbundle,
+            server_keypair: certs.keyserver_keypair,
+            keyserve_fn: Arc::new(|_| unreachable!()),
+            hash_spec: HashSpec::dna_normal_cech(),
+            max_hash_total_count: None,
+            // every session is idle past the timeout by the time its next step arrives
+            session_idle_timeout: Some(Duration::ZERO),
+        },
+        async {},
+    )
+    .await;
+    let server_port = server.port();
+
+    // each client has its own cookie jar, so holds its own session
+    let client_certs = Arc::new(ClientCerts::with_custom_roots(
+        issuer_pks,
+        certs.synth_tokenbundle,
+        certs.synth_keypair,
+    ));
+    let make_client = || {
+        ScepClient::<KeyserverTokenGroup>::new(
+            http_client::BaseApiClient::new(RequestId::new_unique()),
+            format!("http://localhost:{server_port}"),
+            client_certs.clone(),
+            "idle_session_test".to_owned(),
+        )
+    };
+
+    let idle_client = make_client();
+    let opened = open(&idle_client).await;
+
+    // another client opening a session evicts the first one, which has gone idle
+    let other_client = make_client();
+    open(&other_client).await;
+
+    let err = idle_client.authenticate(opened, 1).await.unwrap_err();
+    assert!(
+        err.to_string()
+            .contains("unknown or expired session cookie"),
+        "unexpected error: {err}"
+    );
+
+    server.stop().await;
+}
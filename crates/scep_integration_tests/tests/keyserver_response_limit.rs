@@ -0,0 +1,151 @@
+// Copyright 2021-2024 SecureDNA Stiftung (SecureDNA Foundation) <licensing@securedna.org>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use std::sync::Arc;
+
+use bytes::{Bytes, BytesMut};
+
+use doprf::party::KeyserverId;
+use doprf::prf::Query;
+use doprf_client::error::DoprfError;
+use doprf_client::packed_ristretto::PackedRistrettos;
+use doprf_client::scep_client::{ClientConfig, KeyserverSetClient};
+use doprf_client::server_selection::SelectedKeyserver;
+use http_client::api_client_core::{ApiClientCore, ApiClientCoreImpl};
+use http_client::{BaseApiClient, HttpError, HttpsToHttpRewriter};
+use scep_client_helpers::ClientCerts;
+use scep_integration_tests::make_certs::{make_certs, MakeCertsOptions};
+use scep_integration_tests::mock_screening::{mock_hazard_query, rehash_query};
+use scep_integration_tests::server::{Opts, TestServer};
+use shared_types::hash::HashSpec;
+use shared_types::requests::RequestId;
+
+/// Stands in for a misbehaving keyserver on `port`, which sends an extra hash
+/// part after each keyserve response.
+struct PadKeyserveResponses {
+    inner: ApiClientCoreImpl,
+    port: u16,
+}
+
+#[async_trait::async_trait]
+impl ApiClientCore for PadKeyserveResponses {
+    async fn raw_request(
+        &self,
+        url: &str,
+        body: Option<Bytes>,
+        content_type: &'static str,
+        headers: &[(String, String)],
+        expected_content_type: &'static str,
+    ) -> Result<Bytes, HttpError> {
+        let bytes = ApiClientCore::raw_request(
+            &self.inner,
+            url,
+            body,
+            content_type,
+            headers,
+            expected_content_type,
+        )
+        .await?;
+        let port = self.port;
+        if url.ends_with(&format!(":{port}{}", scep::KEYSERVE_ENDPOINT)) {
+            let mut padded = BytesMut::from(&bytes[..]);
+            padded.extend_from_slice(&[0; 32]);
+            Ok(padded.freeze())
+        } else {
+            Ok(bytes)
+        }
+    }
+}
+
+#[tracing_test::traced_test]
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+pub async fn oversized_keyserver_response_is_aborted_and_blamed() {
+    let certs = make_certs(Default::default());
+    let issuer_pks = vec![
+        certs.infra_root_keypair.public_key(),
+        certs.manu_root_keypair.public_key(),
+    ];
+
+    let spawn_keyserver = || {
+        TestServer::spawn(
+            Opts {
+                issuer_pks: issuer_pks.clone(),
+                revocation_list: Default::default(),
+                server_cert_chain: certs.keyserver_tokenbundle.clone(),
+                server_keypair: certs.keyserver_keypair.clone(),
+                keyserve_fn: Arc::new(rehash_query),
+                hash_spec: HashSpec::dna_normal_cech(),
+                max_hash_total_count: None,
+                session_idle_timeout: None,
+            },
+            async {},
+        )
+    };
+    let honest = spawn_keyserver().await;
+    let oversized = spawn_keyserver().await;
+
+    let queries = PackedRistrettos::<Query>::new(vec![<[u8; 32]>::from(mock_hazard_query()); 4]);
+    let hash_total_count = queries.len() as u64;
+
+    let api_client = HttpsToHttpRewriter::inject(BaseApiClient::from(PadKeyserveResponses {
+        inner: ApiClientCoreImpl::new(RequestId::new_unique()),
+        port: oversized.port(),
+    }));
+    let config = ClientConfig {
+        api_client,
+        certs: Arc::new(ClientCerts::with_custom_roots(
+            issuer_pks.clone(),
+            certs.synth_tokenbundle.clone(),
+            certs.synth_keypair.clone(),
+        )),
+        version_hint: "response_limit_test_client".to_owned(),
+        debug_info: false,
+        keyserver_rate_limiter: None,
+        // Exactly enough for one hash part per query.
+        max_keyserver_response_bytes: Some(32 * hash_total_count),
+        keyserver_apply: None,
+        retry_budget: Default::default(),
+    };
+
+    let keyserver = |port: u16| SelectedKeyserver {
+        id: MakeCertsOptions::default().keyserver_id,
+        domain: format!("localhost:{port}"),
+        bad_flag: Default::default(),
+        capabilities: Default::default(),
+    };
+    let honest_keyserver = keyserver(honest.port());
+    let oversized_keyserver = keyserver(oversized.port());
+
+    let clients = KeyserverSetClient::open(
+        [
+            (honest_keyserver.clone(), None),
+            (oversized_keyserver.clone(), None),
+        ],
+        config,
+        hash_total_count,
+        vec![
+            KeyserverId::try_from(1).unwrap(),
+            KeyserverId::try_from(2).unwrap(),
+            KeyserverId::try_from(3).unwrap(),
+        ]
+        .into(),
+    )
+    .await
+    .unwrap();
+
+    let err = clients.query(hash_total_count, &queries).await.unwrap_err();
+    assert!(
+        matches!(
+            err,
+            DoprfError::HttpError(HttpError::ResponseTooLarge { limit, .. })
+                if limit == 32 * hash_total_count
+        ),
+        "expected ResponseTooLarge, got {err:?}"
+    );
+
+    assert!(oversized_keyserver.bad_flag.is_bad());
+    assert!(!honest_keyserver.bad_flag.is_bad());
+
+    honest.stop().await;
+    oversized.stop().await;
+}
@@ -8,12 +8,16 @@ use tracing::info;
 use certificates::revocation::RevocationList;
 use certificates::KeyserverTokenGroup;
 use doprf::party::KeyserverId;
-use http_client::HttpError;
+use doprf_client::preflight::preflight_servers;
+use doprf_client::scep_client::ClientConfig;
+use doprf_client::server_selection::SelectedKeyserver;
+use http_client::{HttpError, HttpsToHttpRewriter};
 use scep_client_helpers::ClientCerts;
 use scep_integration_tests::make_certs::{make_certs, MakeCertsOptions};
 use scep_integration_tests::server::{Opts, TestServer};
 use shared_types::hash::HashSpec;
 use shared_types::requests::RequestId;
+use shared_types::synthesis_permission::Region;
 
 #[tracing_test::traced_test]
 #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
@@ -34,6 +38,8 @@ pub async fn revoked_client_cert() {
             server_keypair: certs.keyserver_keypair,
             keyserve_fn: Arc::new(|_| unreachable!()),
             hash_spec: HashSpec::dna_normal_cech(),
+            max_hash_total_count: None,
+            session_idle_timeout: None,
         },
         async {},
     )
@@ -95,3 +101,67 @@ pub async fn revoked_client_cert() {
 
     server.stop().await;
 }
+
+#[tracing_test::traced_test]
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+pub async fn preflight_reports_revoked_client_cert() {
+    let certs = make_certs(Default::default());
+    let issuer_pks = vec![
+        certs.infra_root_keypair.public_key(),
+        certs.manu_root_keypair.public_key(),
+    ];
+    let revocation_list =
+        RevocationList::default().with_public_key(certs.synth_keypair.public_key());
+
+    let server = TestServer::spawn(
+        Opts {
+            issuer_pks: issuer_pks.clone(),
+            revocation_list,
+            server_cert_chain: certs.keyserver_tokenbundle,
+            server_keypair: certs.keyserver_keypair,
+            // Preflight must not send any queries.
+            keyserve_fn: Arc::new(|_| unreachable!()),
+            hash_spec: HashSpec::dna_normal_cech(),
+            max_hash_total_count: None,
+            session_idle_timeout: None,
+        },
+        async {},
+    )
+    .await;
+    let server_port = server.port();
+
+    let api_client =
+        HttpsToHttpRewriter::inject(http_client::BaseApiClient::new(RequestId::new_unique()));
+    let config = ClientConfig {
+        api_client,
+        certs: Arc::new(ClientCerts::with_custom_roots(
+            issuer_pks,
+            certs.synth_tokenbundle,
+            certs.synth_keypair,
+        )),
+        version_hint: "preflight_test_client".to_owned(),
+        debug_info: false,
+        keyserver_rate_limiter: None,
+        max_keyserver_response_bytes: None,
+        keyserver_apply: None,
+        retry_budget: Default::default(),
+    };
+    let keyserver = SelectedKeyserver {
+        id: MakeCertsOptions::default().keyserver_id,
+        domain: format!("localhost:{server_port}"),
+        bad_flag: Default::default(),
+        capabilities: Default::default(),
+    };
+
+    let report = preflight_servers(config, vec![keyserver], None, Region::All).await;
+
+    assert!(!report.is_ok());
+    let failures: Vec<_> = report.failures().collect();
+    assert_eq!(failures.len(), 1);
+    assert_eq!(failures[0].domain, format!("localhost:{server_port}"));
+    assert!(report
+        .to_string()
+        .contains("is not valid due to revocation"));
+
+    server.stop().await;
+}
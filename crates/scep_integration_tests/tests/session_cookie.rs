@@ -0,0 +1,80 @@
+// Copyright 2021-2024 SecureDNA Stiftung (SecureDNA Foundation) <licensing@securedna.org>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use std::sync::Arc;
+
+use doprf::tagged::TaggedHash;
+use doprf_client::packed_ristretto::PackedRistrettos;
+use scep_integration_tests::server::{Opts, TestServer};
+use shared_types::hash::HashSpec;
+
+/// Screen with the given `Cookie` header (if any), returning the error status
+/// and whether it's retriable.
+async fn screen_with_cookie(server_port: u16, cookie: Option<&str>) -> (u16, bool) {
+    let http_client = http_client::BaseApiClient::new_external();
+    let headers: Vec<_> = cookie
+        .map(|cookie| ("Cookie".to_owned(), cookie.to_owned()))
+        .into_iter()
+        .collect();
+
+    let resp_err = http_client
+        .ristretto_ristretto_post_with_headers::<TaggedHash, TaggedHash>(
+            &format!("http://localhost:{server_port}{}", scep::SCREEN_ENDPOINT),
+            &PackedRistrettos::new(vec![]),
+            &headers,
+        )
+        .await
+        .unwrap_err();
+
+    let http_client::error::HttpError::RequestError {
+        status, retriable, ..
+    } = resp_err
+    else {
+        panic!("Expected RequestError, got {resp_err:?}");
+    };
+    (status.unwrap(), retriable)
+}
+
+#[tracing_test::traced_test]
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+pub async fn session_cookie_failures_are_distinguished() {
+    let certs = scep_integration_tests::make_certs::make_certs(Default::default());
+    let issuer_pks = vec![
+        certs.infra_root_keypair.public_key(),
+        certs.manu_root_keypair.public_key(),
+    ];
+    let server = TestServer::spawn(
+        Opts {
+            issuer_pks,
+            revocation_list: Default::default(),
+            server_cert_chain: certs.database_tokenbundle,
+            server_keypair: certs.database_keypair,
+            keyserve_fn: Arc::new(|_| unreachable!()),
+            hash_spec: HashSpec::dna_normal_cech(),
+            max_hash_total_count: None,
+            session_idle_timeout: None,
+        },
+        async {},
+    )
+    .await;
+    let server_port = server.port();
+
+    // missing: the client needs to authenticate
+    assert_eq!(screen_with_cookie(server_port, None).await, (401, false));
+
+    // malformed: a client bug, retrying won't help
+    assert_eq!(
+        screen_with_cookie(server_port, Some("SecureDNA=not-hex")).await,
+        (400, false)
+    );
+
+    // unknown (e.g. expired): retrying with the same cookie won't help, the
+    // client has to open a new session
+    let unknown = format!("SecureDNA={}", "00".repeat(32));
+    assert_eq!(
+        screen_with_cookie(server_port, Some(&unknown)).await,
+        (440, false)
+    );
+
+    server.stop().await;
+}
@@ -41,6 +41,8 @@ pub async fn wrong_client_cert() {
             server_keypair: certs.keyserver_keypair,
             keyserve_fn: Arc::new(|_| unreachable!()),
             hash_spec: HashSpec::dna_normal_cech(),
+            max_hash_total_count: None,
+            session_idle_timeout: None,
         },
         async {},
     )
@@ -121,6 +123,8 @@ pub async fn wrong_server_cert() {
             server_keypair: certs.keyserver_keypair,
             keyserve_fn: Arc::new(|_| unreachable!()),
             hash_spec: HashSpec::dna_normal_cech(),
+            max_hash_total_count: None,
+            session_idle_timeout: None,
         },
         async {},
     )
@@ -33,6 +33,8 @@ pub async fn wrong_keyserver_id() {
             server_keypair: certs.keyserver_keypair,
             keyserve_fn: Arc::new(|_| unreachable!()),
             hash_spec: HashSpec::dna_normal_cech(),
+            max_hash_total_count: None,
+            session_idle_timeout: None,
         },
         async {},
     )
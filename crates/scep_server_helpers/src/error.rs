@@ -29,6 +29,18 @@ where
             response::text(StatusCode::INTERNAL_SERVER_ERROR, "internal server error")
         }
         ScepError::InvalidMessage(e) => response::text(StatusCode::BAD_REQUEST, e),
+        ScepError::MissingSessionCookie => {
+            response::text(StatusCode::UNAUTHORIZED, "missing session cookie")
+        }
+        ScepError::MalformedSessionCookie(e) => response::text(
+            StatusCode::BAD_REQUEST,
+            format!("malformed session cookie: {e}"),
+        ),
+        ScepError::UnknownSessionCookie(_) => response::text(
+            // 440 Login Time-out (nonstandard): the session expired or never existed.
+            StatusCode::from_u16(440).unwrap(),
+            "unknown or expired session cookie",
+        ),
         ScepError::Overloaded => response::text(
             StatusCode::SERVICE_UNAVAILABLE,
             "server is overloaded. try again later.",
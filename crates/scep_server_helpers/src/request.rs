@@ -60,30 +60,33 @@ where
         .map_err(ScepError::InvalidMessage)
 }
 
-/// Try to get the SCEP session cookie, returning `ScepError::InvalidMessage` if
-/// there are none, multiple, or the format is incorrect.
+/// Try to get the SCEP session cookie, returning `ScepError::MissingSessionCookie`
+/// if there are no cookies, or `ScepError::MalformedSessionCookie` if there are
+/// multiple session cookies or the format is incorrect.
 pub fn get_session_cookie<E>(
     headers: &HeaderMap<HeaderValue>,
 ) -> Result<SessionCookie, ScepError<E>>
 where
     E: std::error::Error,
 {
-    let mut cookies = headers
-        .get_all(hyper::header::COOKIE)
-        .into_iter()
-        .filter_map(|cookie| {
-            let cookie = cookie.to_str().ok()?;
-            let cookie = cookie::Cookie::parse(cookie).ok()?;
-            let cookie: SessionCookie = cookie.value().parse().ok()?;
-            Some(cookie)
-        });
+    let cookie_headers = headers.get_all(hyper::header::COOKIE);
+    if cookie_headers.iter().next().is_none() {
+        return Err(ScepError::MissingSessionCookie);
+    }
+
+    let mut cookies = cookie_headers.iter().filter_map(|cookie| {
+        let cookie = cookie.to_str().ok()?;
+        let cookie = cookie::Cookie::parse(cookie).ok()?;
+        let cookie: SessionCookie = cookie.value().parse().ok()?;
+        Some(cookie)
+    });
 
     let cookie = cookies.next().ok_or_else(|| {
-        ScepError::InvalidMessage(anyhow::anyhow!("missing or invalid session cookie"))
+        ScepError::MalformedSessionCookie(anyhow::anyhow!("no valid session cookie"))
     })?;
 
     if cookies.next().is_some() {
-        Err(ScepError::InvalidMessage(anyhow::anyhow!(
+        Err(ScepError::MalformedSessionCookie(anyhow::anyhow!(
             "multiple valid session cookies"
         )))
     } else {
@@ -22,6 +22,9 @@ pub struct ServerState<T: TokenGroup> {
     pub revocation_list: RevocationList,
     pub token_bundle: TokenBundle<T>,
     pub keypair: KeyPair,
+    /// The largest `hash_total_count` a client may authenticate with, if any.
+    /// Larger requests are rejected before the client sends its hashes.
+    pub max_hash_total_count: Option<u64>,
     /// Do not set the `secure` flag on session cookies, so they can be transported over http://
     /// Useful for local testing.
     pub allow_insecure_cookie: bool,
@@ -135,9 +138,7 @@ where
         .write()
         .await
         .take_session(&cookie)
-        .ok_or_else(|| {
-            scep::error::ScepError::InvalidMessage(anyhow::anyhow!("unknown cookie {cookie}"))
-        })?;
+        .ok_or(scep::error::ScepError::UnknownSessionCookie(cookie))?;
 
     let client_state = scep::steps::server_authenticate_client(
         authenticate_request,
@@ -145,6 +146,7 @@ where
         server_version,
         &server_state.manufacturer_roots,
         &server_state.revocation_list,
+        server_state.max_hash_total_count,
         get_client_screened_last_day,
         record_rate_limit_exceedance,
     )
@@ -311,17 +311,43 @@ impl HashTypeDescriptor {
     }
 }
 
+/// Describes which of the shingled windows of a sequence are hashed.
+///
+/// The HDB must be built with the same windowing that clients use to screen
+/// against it, or hazards will be missed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Windowing {
+    /// Every window is hashed.
+    #[default]
+    #[serde(rename = "all")]
+    All,
+    /// Only minimizer windows are hashed: out of every `w` consecutive windows,
+    /// the one whose first `k` letters have the smallest hash is kept.
+    ///
+    /// Since the selection only depends on window contents, a stretch of `w`
+    /// consecutive windows shared by a query and a hazard always has a hashed
+    /// window in common. This reduces the number of windows to roughly
+    /// `2 / (w + 1)` of the total, at the cost of missing hazard matches that
+    /// are shorter than `w` consecutive windows.
+    #[serde(rename = "minimizer")]
+    Minimizer { k: usize, w: usize },
+}
+
 /// A specification of which hashes to make and how to make them.
 #[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
 pub struct HashSpec {
     pub max_expansions_per_window: NonZeroUsize,
     pub htdv: Vec<HashTypeDescriptor>,
+    #[serde(default)]
+    pub windowing: Windowing,
 }
 
 #[derive(Debug, Error)]
 pub enum HashSpecValidationError {
     #[error("too many hash types (max {})", HashSpec::MAX_HASH_TYPES)]
     TooManyHashTypes,
+    #[error("minimizer k and w must be nonzero")]
+    ZeroMinimizerParameter,
 }
 
 impl HashSpec {
@@ -333,6 +359,7 @@ impl HashSpec {
         Self {
             max_expansions_per_window: NonZeroUsize::MIN,
             htdv,
+            windowing: Windowing::All,
         }
     }
 
@@ -365,6 +392,7 @@ impl HashSpec {
                     HashTypeDescriptor::aa_rc(),
                 ]
             },
+            windowing: Windowing::All,
         }
     }
 
@@ -372,12 +400,21 @@ impl HashSpec {
         if self.htdv.len() > Self::MAX_HASH_TYPES {
             return Err(HashSpecValidationError::TooManyHashTypes);
         }
+        if let Windowing::Minimizer { k: 0, .. } | Windowing::Minimizer { w: 0, .. } =
+            self.windowing
+        {
+            return Err(HashSpecValidationError::ZeroMinimizerParameter);
+        }
         Ok(())
     }
 
     pub fn min_width_bp(&self) -> Option<usize> {
         self.htdv.iter().map(|h| h.width_bp()).min()
     }
+
+    pub fn max_width_bp(&self) -> Option<usize> {
+        self.htdv.iter().map(|h| h.width_bp()).max()
+    }
 }
 
 #[cfg(test)]
@@ -13,6 +13,26 @@ pub struct HdbScreeningResult {
     pub results: Vec<ConsolidatedHazardResult>,
     pub debug_hdb_responses: Option<Vec<DebugSeqHdbResponse>>,
     pub provider_reference: Option<String>,
+    /// Advisories that don't affect any `synthesis_permission`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<ScreeningWarning>,
+}
+
+/// A soft signal about a record, suggesting manual review.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct ScreeningWarning {
+    pub record: u64,
+    pub kind: ScreeningWarningKind,
+    pub organism: Organism,
+    pub hit_regions: Vec<HitRegion>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScreeningWarningKind {
+    /// The record matched a known hazard, but one that isn't controlled in the
+    /// screened region, so synthesis was granted anyway.
+    GrantedHazardMatch,
 }
 
 /// Consolidated Result of DOPRF on contiguous sequences that were contained in the HDB
@@ -28,6 +48,10 @@ pub struct ConsolidatedHazardResult {
     pub is_dna: bool,
     pub is_wild_type: Option<bool>,
     pub exempt: bool,
+    /// Offsets (in the original sequence) of the windows that hit, as reported
+    /// by the HDB. Clients drop these unless asked to report window offsets.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub window_offsets: Vec<usize>,
 }
 
 // An organism definition
@@ -23,7 +23,7 @@ const BASE_CORS_HEADERS: [(&str, &str); 3] = [
     ("access-control-allow-methods", "POST, GET, PATCH, OPTIONS"),
     (
         "access-control-allow-headers",
-        "Accept, Accept-Encoding, Content-Type, Origin, X-Request-Id, X-Real-Ip, Host, Forwarded, X-Forwarded-For, X-Forwarded-Proto, X-Forwarded-Protocol, X-Url-Scheme, X-Forwarded-Ssl, Front-End-Https",
+        "Accept, Accept-Encoding, Content-Type, Origin, X-Request-Id, X-Ristretto-Checksum, X-Real-Ip, Host, Forwarded, X-Forwarded-For, X-Forwarded-Proto, X-Forwarded-Protocol, X-Url-Scheme, X-Forwarded-Ssl, Front-End-Https",
     ),
     ("access-control-allow-credentials", "true"),
 ];
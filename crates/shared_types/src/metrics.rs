@@ -1,7 +1,10 @@
 // Copyright 2021-2024 SecureDNA Stiftung (SecureDNA Foundation) <licensing@securedna.org>
 // SPDX-License-Identifier: MIT OR Apache-2.0
 
+use std::fmt::Write;
+
 use prometheus::core::{AtomicI64, GenericGauge};
+use prometheus::proto::{MetricFamily, MetricType};
 use prometheus::{register_int_counter, register_int_gauge, Encoder, TextEncoder};
 use prometheus::{IntCounter, IntGauge};
 
@@ -33,6 +36,18 @@ static HDB_IO_ERRORS_NAME: &str = "hdb_io_errors";
 static HDB_IO_ERRORS_DESCRIPTION: &str =
     "Total number of I/O errors (disk read errors, malformed entries, etc.) since last start";
 
+static HASH_PROGRAM_CYCLES_NAME: &str = "hash_program_cycles";
+static HASH_PROGRAM_CYCLES_DESCRIPTION: &str =
+    "Total zkVM cycles clients reported for the hash program since last start";
+
+static CHECKSUM_PROGRAM_CYCLES_NAME: &str = "checksum_program_cycles";
+static CHECKSUM_PROGRAM_CYCLES_DESCRIPTION: &str =
+    "Total zkVM cycles clients reported for the checksum program since last start";
+
+static VERIFICATION_PROGRAM_CYCLES_NAME: &str = "verification_program_cycles";
+static VERIFICATION_PROGRAM_CYCLES_DESCRIPTION: &str =
+    "Total zkVM cycles clients reported for the verification program since last start";
+
 pub struct SynthClientMetrics {
     pub hash_counter: IntCounter,
     pub bp_counter: IntCounter,
@@ -81,6 +96,9 @@ pub struct HdbMetrics {
     pub requests: IntCounter,
     pub io_errors: IntCounter,
     pub bad_requests: IntCounter,
+    pub hash_program_cycles: IntCounter,
+    pub checksum_program_cycles: IntCounter,
+    pub verification_program_cycles: IntCounter,
 }
 
 impl HdbMetrics {
@@ -100,6 +118,21 @@ impl HdbMetrics {
                 .unwrap(),
             bad_requests: register_int_counter!(BAD_REQUESTS_NAME, BAD_REQUESTS_DESCRIPTION)
                 .unwrap(),
+            hash_program_cycles: register_int_counter!(
+                HASH_PROGRAM_CYCLES_NAME,
+                HASH_PROGRAM_CYCLES_DESCRIPTION
+            )
+            .unwrap(),
+            checksum_program_cycles: register_int_counter!(
+                CHECKSUM_PROGRAM_CYCLES_NAME,
+                CHECKSUM_PROGRAM_CYCLES_DESCRIPTION
+            )
+            .unwrap(),
+            verification_program_cycles: register_int_counter!(
+                VERIFICATION_PROGRAM_CYCLES_NAME,
+                VERIFICATION_PROGRAM_CYCLES_DESCRIPTION
+            )
+            .unwrap(),
         }
     }
 
@@ -189,3 +222,138 @@ pub fn get_metrics_output() -> String {
 
     String::from_utf8(buffer.clone()).unwrap()
 }
+
+/// Content type of [`get_openmetrics_output`].
+pub const OPENMETRICS_CONTENT_TYPE: &str =
+    "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+/// Generates an OpenMetrics text representation of all existing metrics
+pub fn get_openmetrics_output() -> String {
+    encode_openmetrics(&prometheus::gather())
+}
+
+/// Encode counters and gauges (the only metric types we register) in the
+/// OpenMetrics text format.
+fn encode_openmetrics(metric_families: &[MetricFamily]) -> String {
+    let mut out = String::new();
+    for family in metric_families {
+        let name = family.get_name();
+        let metric_type = family.get_field_type();
+        let (type_name, sample_name) = match metric_type {
+            // OpenMetrics counter samples carry a `_total` suffix the family name doesn't
+            MetricType::COUNTER => ("counter", format!("{name}_total")),
+            MetricType::GAUGE => ("gauge", name.to_owned()),
+            MetricType::UNTYPED => ("unknown", name.to_owned()),
+            MetricType::SUMMARY | MetricType::HISTOGRAM => continue,
+        };
+
+        writeln!(out, "# HELP {name} {}", escape(family.get_help())).unwrap();
+        writeln!(out, "# TYPE {name} {type_name}").unwrap();
+        for metric in family.get_metric() {
+            let labels: Vec<_> = metric
+                .get_label()
+                .iter()
+                .map(|l| format!("{}=\"{}\"", l.get_name(), escape(l.get_value())))
+                .collect();
+            let labels = if labels.is_empty() {
+                String::new()
+            } else {
+                format!("{{{}}}", labels.join(","))
+            };
+            let value = match metric_type {
+                MetricType::COUNTER => metric.get_counter().get_value(),
+                MetricType::GAUGE => metric.get_gauge().get_value(),
+                _ => metric.get_untyped().get_value(),
+            };
+            writeln!(out, "{sample_name}{labels} {value}").unwrap();
+        }
+    }
+    out.push_str("# EOF\n");
+    out
+}
+
+/// Escape a HELP text or label value.
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Checks the parts of the OpenMetrics text format our encoder uses:
+    /// each family's HELP and TYPE precede its samples, sample names match
+    /// the family (with `_total` for counters), and the exposition ends with
+    /// `# EOF`. Returns the sample values by name.
+    fn parse_openmetrics(text: &str) -> Vec<(String, f64)> {
+        let body = text.strip_suffix("# EOF\n").expect("missing # EOF");
+        assert!(!body.contains("# EOF"), "# EOF must come last");
+
+        let mut samples = vec![];
+        let mut family: Option<(String, String)> = None;
+        let mut lines = body.lines();
+        while let Some(line) = lines.next() {
+            if let Some(help) = line.strip_prefix("# HELP ") {
+                let name = help.split(' ').next().unwrap().to_owned();
+                let type_line = lines.next().expect("HELP without TYPE");
+                let type_name = type_line
+                    .strip_prefix(&format!("# TYPE {name} "))
+                    .expect("TYPE must follow HELP for the same family");
+                assert!(["counter", "gauge", "unknown"].contains(&type_name));
+                family = Some((name, type_name.to_owned()));
+                continue;
+            }
+            let (name, type_name) = family.as_ref().expect("sample before any family");
+            let (sample_name, value) = line.rsplit_once(' ').expect("sample without value");
+            let sample_name = sample_name.split('{').next().unwrap();
+            let expected_name = match type_name.as_str() {
+                "counter" => format!("{name}_total"),
+                _ => name.clone(),
+            };
+            assert_eq!(sample_name, expected_name);
+            samples.push((sample_name.to_owned(), value.parse().unwrap()));
+        }
+        samples
+    }
+
+    #[test]
+    fn hdb_metrics_are_valid_openmetrics() {
+        let metrics = HdbMetrics::new();
+        metrics.requests.inc();
+        metrics.hash_counter.inc_by(3);
+
+        let samples = parse_openmetrics(&get_openmetrics_output());
+        let value = |name: &str| {
+            samples
+                .iter()
+                .find(|(sample, _)| sample == name)
+                .unwrap_or_else(|| panic!("missing {name}"))
+                .1
+        };
+        assert_eq!(value("total_requests_total"), 1.0);
+        assert_eq!(value("total_hashes_processed_total"), 3.0);
+        assert_eq!(value("hdb_io_errors_total"), 0.0);
+        assert_eq!(value("bad_requests_total"), 0.0);
+        assert_eq!(value("currently_processing_client_requests"), 0.0);
+    }
+
+    #[test]
+    fn openmetrics_escapes_help_and_labels() {
+        let registry = prometheus::Registry::new();
+        let opts = prometheus::Opts::new("widgets", "A \"quoted\" \\ help\ntext")
+            .const_label("kind", "a\"b");
+        let counter = IntCounter::with_opts(opts).unwrap();
+        registry.register(Box::new(counter.clone())).unwrap();
+        counter.inc();
+
+        assert_eq!(
+            encode_openmetrics(&registry.gather()),
+            "# HELP widgets A \\\"quoted\\\" \\\\ help\\ntext\n\
+             # TYPE widgets counter\n\
+             widgets_total{kind=\"a\\\"b\"} 1\n\
+             # EOF\n"
+        );
+    }
+}
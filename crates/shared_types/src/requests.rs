@@ -3,6 +3,7 @@
 
 use std::fmt;
 use std::str::FromStr;
+use std::sync::{Arc, Mutex};
 use serde::{Deserialize, Serialize};
 
 use uuid::Uuid;
@@ -63,7 +64,8 @@ impl SerializableRequestContext {
     pub fn to_request_context(self) -> RequestContext {
         RequestContext {
             id: RequestId::from_bytes(self.id.as_slice()).expect("couldn't generate RequestId"),
-            total_records: self.total_records
+            total_records: self.total_records,
+            attempts: AttemptLog::default(),
         }
     }
 }
@@ -78,6 +80,10 @@ pub struct RequestContext {
     /// If the synthesis request was a FASTA file consisting of multiple
     /// records, this is total number of records in the file.
     pub total_records: usize,
+
+    /// The servers tried so far while handling this request. Shared between
+    /// clones, so retries of the same request append to the same log.
+    pub attempts: AttemptLog,
 }
 
 impl RequestContext {
@@ -85,6 +91,7 @@ impl RequestContext {
         RequestContext {
             id,
             total_records: 1,
+            attempts: AttemptLog::default(),
         }
     }
 
@@ -105,3 +112,86 @@ impl fmt::Display for RequestContext {
         }
     }
 }
+
+/// The servers selected for one attempt at handling a request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AttemptServers {
+    pub keyservers: Vec<String>,
+    pub hdb: String,
+}
+
+impl AttemptServers {
+    fn contains(&self, domain: &str) -> bool {
+        self.hdb == domain || self.keyservers.iter().any(|ks| ks == domain)
+    }
+}
+
+impl fmt::Display for AttemptServers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "keyservers=[{}], hdb={}",
+            self.keyservers.join(", "),
+            self.hdb
+        )
+    }
+}
+
+/// A record of each attempt made at handling a request, in order, and of how
+/// many times requests to servers were retried within those attempts.
+#[derive(Debug, Clone, Default)]
+pub struct AttemptLog(Arc<Mutex<Attempts>>);
+
+#[derive(Debug, Default)]
+struct Attempts {
+    servers: Vec<AttemptServers>,
+    retries: usize,
+}
+
+impl AttemptLog {
+    /// Record the start of a new attempt using `servers`.
+    pub fn record(&self, servers: AttemptServers) {
+        self.0.lock().unwrap().servers.push(servers);
+    }
+
+    /// Record retrying a request to a server within the current attempt.
+    pub fn record_retry(&self) {
+        self.0.lock().unwrap().retries += 1;
+    }
+
+    /// The number of attempts made so far.
+    pub fn count(&self) -> usize {
+        self.0.lock().unwrap().servers.len()
+    }
+
+    /// The number of attempts made so far, plus the number of retries made
+    /// within them.
+    pub fn total(&self) -> usize {
+        let attempts = self.0.lock().unwrap();
+        attempts.servers.len() + attempts.retries
+    }
+
+    /// The servers selected for each attempt so far.
+    pub fn attempts(&self) -> Vec<AttemptServers> {
+        self.0.lock().unwrap().servers.clone()
+    }
+
+    /// Servers that were tried in an earlier attempt but not in the latest one,
+    /// in the order they were first tried.
+    pub fn excluded(&self) -> Vec<String> {
+        let attempts = self.0.lock().unwrap();
+        let Some((latest, earlier)) = attempts.servers.split_last() else {
+            return vec![];
+        };
+        let mut excluded: Vec<String> = vec![];
+        for domain in earlier
+            .iter()
+            .flat_map(|servers| servers.keyservers.iter().chain([&servers.hdb]))
+        {
+            if !latest.contains(domain) && !excluded.contains(domain) {
+                excluded.push(domain.clone());
+            }
+        }
+        excluded
+    }
+}
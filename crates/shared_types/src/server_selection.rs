@@ -70,6 +70,20 @@ pub struct QualificationRequest {
     // TODO: client cert chain
 }
 
+/// The newest qualification `client_version` servers understand.
+///
+/// Version 0 is the baseline. Later versions only ever add fields to the
+/// responses, so a version 0 client can still parse what a newer server sends.
+pub const CURRENT_CLIENT_VERSION: u32 = 1;
+
+impl QualificationRequest {
+    /// Whether servers answer this request: any version from the baseline 0 up
+    /// to [`CURRENT_CLIENT_VERSION`].
+    pub fn is_supported_version(&self) -> bool {
+        self.client_version <= CURRENT_CLIENT_VERSION
+    }
+}
+
 /// Details on the key that forms the basis of the distributed keyshares
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct KeyInfo {
@@ -87,10 +101,104 @@ pub struct KeyserverQualificationResponse {
     /// Keyed by which generation numbers this keyserver supports, with values for the quorum (N of N-of-M)
     /// and active security key required for that generation according to this keyserver.
     pub generations_and_key_info: HashMap<u32, KeyInfo>,
+    /// The newest `client_version` this keyserver understands. Keyservers
+    /// predating version 1 don't send it, in which case it's 0.
+    #[serde(default)]
+    pub max_client_version: u32,
+    /// What this keyserver can read and write, beyond what every keyserver can.
+    #[serde(default)]
+    pub capabilities: Capabilities,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HdbQualificationResponse {
     /// Which generation numbers this HDB supports (usually one, but sometimes more)
     pub supported_generations: Vec<u32>,
+    /// What this HDB can read and write, beyond what every HDB can.
+    #[serde(default)]
+    pub capabilities: Capabilities,
+}
+
+pub const JSON_CONTENT_TYPE: &str = "application/json";
+pub const CBOR_CONTENT_TYPE: &str = "application/cbor";
+
+/// The content types and encodings a server advertises in its qualification
+/// response.
+///
+/// Servers predating this advertise nothing, so clients should always be
+/// prepared to fall back to the content types they used to hardcode.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Capabilities {
+    /// Supported `Content-Type`s for request and response bodies.
+    pub content_types: Vec<String>,
+    /// Supported `Content-Encoding`s, not counting `identity`.
+    pub encodings: Vec<String>,
+}
+
+impl Capabilities {
+    /// The first of `client_supported` (most preferred first) that the server
+    /// supports, or `fallback` if there isn't one.
+    pub fn negotiate_content_type<'a>(
+        &self,
+        client_supported: &[&'a str],
+        fallback: &'a str,
+    ) -> &'a str {
+        negotiate(&self.content_types, client_supported).unwrap_or(fallback)
+    }
+
+    /// The first of `client_supported` (most preferred first) that the server
+    /// supports, or `None` to send bodies unencoded.
+    pub fn negotiate_encoding<'a>(&self, client_supported: &[&'a str]) -> Option<&'a str> {
+        negotiate(&self.encodings, client_supported)
+    }
+}
+
+fn negotiate<'a>(server_supported: &[String], client_supported: &[&'a str]) -> Option<&'a str> {
+    client_supported
+        .iter()
+        .copied()
+        .find(|client| server_supported.iter().any(|server| server == client))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn negotiates_cbor_only_when_advertised() {
+        let client_supported = [CBOR_CONTENT_TYPE, JSON_CONTENT_TYPE];
+
+        let legacy = Capabilities::default();
+        assert_eq!(
+            legacy.negotiate_content_type(&client_supported, JSON_CONTENT_TYPE),
+            JSON_CONTENT_TYPE
+        );
+
+        let json_only = Capabilities {
+            content_types: vec![JSON_CONTENT_TYPE.into()],
+            encodings: vec![],
+        };
+        assert_eq!(
+            json_only.negotiate_content_type(&client_supported, JSON_CONTENT_TYPE),
+            JSON_CONTENT_TYPE
+        );
+
+        let with_cbor = Capabilities {
+            content_types: vec![JSON_CONTENT_TYPE.into(), CBOR_CONTENT_TYPE.into()],
+            encodings: vec!["gzip".into()],
+        };
+        assert_eq!(
+            with_cbor.negotiate_content_type(&client_supported, JSON_CONTENT_TYPE),
+            CBOR_CONTENT_TYPE
+        );
+        assert_eq!(with_cbor.negotiate_encoding(&["gzip"]), Some("gzip"));
+        assert_eq!(json_only.negotiate_encoding(&["gzip"]), None);
+    }
+
+    #[test]
+    fn responses_without_capabilities_parse() {
+        let response: HdbQualificationResponse =
+            serde_json::from_str(r#"{"supported_generations":[0]}"#).unwrap();
+        assert_eq!(response.capabilities, Capabilities::default());
+    }
 }
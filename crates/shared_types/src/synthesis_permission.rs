@@ -7,7 +7,7 @@ use pipeline_bridge::Tag;
 
 /// Region jurisdictions for handling requests. Controls e.g. what rules to use for setting
 /// the `synthesis_permission` bit to `denied`.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum Region {
     /// United States
     Us,
@@ -0,0 +1,199 @@
+// Copyright 2021-2024 SecureDNA Stiftung (SecureDNA Foundation) <licensing@securedna.org>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Deterministic streamed ristretto bodies, for checking other implementations
+//! (e.g. non-Rust clients) against known bytes.
+//!
+//! # Layouts
+//!
+//! A fixture body is its items' encodings back to back, with no framing, exactly as they'd be
+//! sent over HTTP with the type's [`HasContentType::CONTENT_TYPE`]:
+//!
+//! - [`Query`], [`HashPart`] and [`CompletedHashValue`] items are 32-byte compressed ristretto
+//!   points. Item `i` is the point hashed (with SHA3-512) from the ASCII bytes `"{label}:{i}"`,
+//!   where the label is `query`, `hash-part` or `completed-hash` respectively.
+//! - [`TaggedHash`] items are a 4-byte big-endian tag followed by a 32-byte compressed
+//!   ristretto point. In the tag, bit 28 marks the start of a new record, bits 24-27 hold the
+//!   hash type index, and bits 0-23 hold the window's index in its record. Item `i` has the
+//!   tag for window `i` of a single record with hash type 0 (so only item 0 starts a record),
+//!   and the point hashed from `"tagged-hash:{i}"`.
+
+use std::io::Cursor;
+
+use doprf::prf::{CompletedHashValue, HashPart, Query};
+use doprf::tagged::{HashTag, TaggedHash};
+use thiserror::Error;
+
+use crate::stream::{decode, StreamableRistretto};
+use crate::HasContentType;
+
+/// A type with deterministic fixture items.
+pub trait FixtureItem: StreamableRistretto + Clone {
+    /// Item `i` of this type's fixtures, as described in the [module docs](self).
+    fn fixture_item(i: usize) -> Self;
+}
+
+impl FixtureItem for Query {
+    fn fixture_item(i: usize) -> Self {
+        Query::hash_from_bytes_for_tests_only(format!("query:{i}").as_bytes())
+    }
+}
+
+impl FixtureItem for HashPart {
+    fn fixture_item(i: usize) -> Self {
+        HashPart::hash_from_bytes_for_tests_only(format!("hash-part:{i}").as_bytes())
+    }
+}
+
+impl FixtureItem for CompletedHashValue {
+    fn fixture_item(i: usize) -> Self {
+        CompletedHashValue::hash_from_bytes_for_tests_only(format!("completed-hash:{i}").as_bytes())
+    }
+}
+
+impl FixtureItem for TaggedHash {
+    fn fixture_item(i: usize) -> Self {
+        TaggedHash {
+            tag: HashTag::new(i == 0, 0, i),
+            hash: CompletedHashValue::hash_from_bytes_for_tests_only(
+                format!("tagged-hash:{i}").as_bytes(),
+            ),
+        }
+    }
+}
+
+/// A generated body, along with the items it encodes.
+pub struct Fixture<R> {
+    pub content_type: &'static str,
+    pub items: Vec<R>,
+    pub body: Vec<u8>,
+}
+
+/// Generate the fixture holding the first `count` [`FixtureItem`]s of type `R`.
+pub fn generate<R: FixtureItem>(count: usize) -> Fixture<R> {
+    let items: Vec<R> = (0..count).map(R::fixture_item).collect();
+    let body = items
+        .iter()
+        .flat_map(|item| encoded(item).as_ref().to_vec())
+        .collect();
+    Fixture {
+        content_type: R::CONTENT_TYPE,
+        items,
+        body,
+    }
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum FixtureError {
+    #[error("couldn't decode item {index}: {error}")]
+    Decode { index: usize, error: String },
+    #[error("item {index} doesn't match the fixture")]
+    Mismatch { index: usize },
+    #[error("expected {expected} items, got {actual}")]
+    WrongCount { expected: usize, actual: usize },
+}
+
+/// Decode `body` as streamed `R`s and check that it holds the first `count`
+/// fixture items, returning the decoded items.
+pub fn validate<R: FixtureItem>(body: &[u8], count: usize) -> Result<Vec<R>, FixtureError> {
+    let chunks = futures::stream::iter([Ok::<_, ()>(Cursor::new(body))]);
+    let decoded: Vec<R> = futures::executor::block_on_stream(Box::pin(decode::<_, R>(chunks)))
+        .enumerate()
+        .map(|(index, item)| {
+            item.map_err(|error| FixtureError::Decode {
+                index,
+                error: format!("{error:?}"),
+            })
+        })
+        .collect::<Result<_, _>>()?;
+
+    if decoded.len() != count {
+        return Err(FixtureError::WrongCount {
+            expected: count,
+            actual: decoded.len(),
+        });
+    }
+    for (index, item) in decoded.iter().enumerate() {
+        if encoded(item).as_ref() != encoded(&R::fixture_item(index)).as_ref() {
+            return Err(FixtureError::Mismatch { index });
+        }
+    }
+    Ok(decoded)
+}
+
+fn encoded<R: StreamableRistretto + Clone>(item: &R) -> R::Array {
+    item.clone().into()
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::StreamExt;
+
+    use super::*;
+    use crate::stream::encode;
+
+    const COUNT: usize = 5;
+
+    fn check_round_trip<R: FixtureItem>() {
+        let fixture = generate::<R>(COUNT);
+        assert_eq!(fixture.content_type, R::CONTENT_TYPE);
+        assert_eq!(fixture.body.len(), COUNT * R::SIZE);
+
+        let decoded = validate::<R>(&fixture.body, COUNT).unwrap();
+        for (decoded, item) in decoded.iter().zip(&fixture.items) {
+            assert_eq!(encoded(decoded).as_ref(), encoded(item).as_ref());
+        }
+
+        // Re-encoding what was decoded gives back the same bytes.
+        let items =
+            futures::stream::iter(decoded.into_iter().map(Ok::<_, std::convert::Infallible>));
+        let reencoded: Vec<u8> = futures::executor::block_on_stream(encode(items, 64).boxed())
+            .flat_map(|chunk| chunk.unwrap())
+            .collect();
+        assert_eq!(reencoded, fixture.body);
+    }
+
+    #[test]
+    fn fixtures_decode_to_their_items_and_round_trip() {
+        check_round_trip::<Query>();
+        check_round_trip::<HashPart>();
+        check_round_trip::<CompletedHashValue>();
+        check_round_trip::<TaggedHash>();
+    }
+
+    #[test]
+    fn tagged_hash_fixture_layout() {
+        let fixture = generate::<TaggedHash>(2);
+        let (first, second) = fixture.body.split_at(TaggedHash::SIZE);
+        assert_eq!(first[..4], [0x10, 0, 0, 0]);
+        assert_eq!(second[..4], [0, 0, 0, 1]);
+        assert_eq!(
+            first[4..],
+            CompletedHashValue::hash_from_bytes_for_tests_only(b"tagged-hash:0").as_bytes()[..]
+        );
+    }
+
+    #[test]
+    fn validate_rejects_other_bodies() {
+        let mut body = generate::<Query>(COUNT).body;
+        assert_eq!(
+            validate::<Query>(&body, COUNT + 1),
+            Err(FixtureError::WrongCount {
+                expected: COUNT + 1,
+                actual: COUNT
+            })
+        );
+
+        body.swap(0, Query::SIZE);
+        assert!(matches!(
+            validate::<Query>(&body, COUNT),
+            Err(FixtureError::Decode { index: 0, .. } | FixtureError::Mismatch { index: 0 })
+        ));
+
+        let body = generate::<Query>(COUNT).body;
+        assert!(matches!(
+            validate::<Query>(&body[1..], COUNT),
+            Err(FixtureError::Decode { .. })
+        ));
+    }
+}
@@ -14,11 +14,14 @@ use hyper::body::{Body, Frame};
 use hyper::{Request, Response};
 use pin_project::pin_project;
 
+use crate::stream::{
+    check_checksum_header, check_content_type, decode, decode_checksummed, encode, ConversionError,
+    Decoder,
+};
 pub use crate::stream::{
     check_content_length, HasShortErrorMsg, MessageError, RistrettoError, StreamableRistretto,
-    HASH_SIZE,
+    CHECKSUM_HEADER, HASH_SIZE,
 };
-use crate::stream::{check_content_type, decode, encode, ConversionError};
 use crate::HasContentType;
 
 /// Convert a [`Request`] into a fallible stream of `R`
@@ -26,6 +29,9 @@ use crate::HasContentType;
 /// Note that this checks that `Content-Type` matches
 /// [`R::CONTENT_TYPE`](HasContentType::CONTENT_TYPE), but does not check the `Content-Length`;
 /// see [`check_content_length`] for that.
+///
+/// If the request has a [`CHECKSUM_HEADER`], the body is checked against it, and the stream
+/// ends with [`RistrettoError::ChecksumMismatch`] if it doesn't match.
 pub fn from_request<B, R>(
     request: Request<B>,
 ) -> Result<impl Stream<Item = Result<R, RistrettoError<B::Error, ConversionError<R>>>>, MessageError>
@@ -35,7 +41,13 @@ where
     R: StreamableRistretto + Send + Sync,
 {
     check_content_type(request.headers(), R::CONTENT_TYPE)?;
-    Ok(decode(BodyStream(request.into_body())))
+    let checksum = check_checksum_header(request.headers())?;
+    let body = BodyStream(request.into_body());
+    let decoder: Decoder<_, R> = match checksum {
+        Some(expected) => decode_checksummed(body, expected),
+        None => decode(body),
+    };
+    Ok(decoder)
 }
 
 #[pin_project]
@@ -113,7 +125,10 @@ mod tests {
     use http_body_util::BodyExt;
     use hyper::Request;
 
-    use crate::stream::ShortErrorMsg;
+    use doprf::prf::CompletedHashValue;
+    use doprf::tagged::{HashTag, TaggedHash};
+
+    use crate::stream::{body_checksum, ShortErrorMsg};
     use crate::HasContentType;
 
     #[derive(Debug, PartialEq)]
@@ -194,6 +209,76 @@ mod tests {
         );
     }
 
+    fn tagged_hash_body() -> Vec<u8> {
+        [b"first".as_slice(), b"second".as_slice()]
+            .into_iter()
+            .enumerate()
+            .flat_map(|(i, seed)| {
+                let hash = TaggedHash {
+                    tag: HashTag::new(i == 0, 0, i),
+                    hash: CompletedHashValue::hash_from_bytes_for_tests_only(seed),
+                };
+                <[u8; TaggedHash::SIZE]>::from(hash)
+            })
+            .collect()
+    }
+
+    fn checksummed_request(body: Vec<u8>, checksum: &str) -> Request<http_body_util::Full<Bytes>> {
+        Request::post("/")
+            .header("Content-Type", TaggedHash::CONTENT_TYPE)
+            .header(&CHECKSUM_HEADER, checksum)
+            .body(http_body_util::Full::new(body.into()))
+            .unwrap()
+    }
+
+    #[test]
+    fn from_request_accepts_matching_checksum() {
+        let body = tagged_hash_body();
+        let request = checksummed_request(body.clone(), &body_checksum(&body));
+        let hashes: Result<Vec<TaggedHash>, _> =
+            stream_to_iter(from_request(request).unwrap().boxed()).collect();
+        let expected: Vec<TaggedHash> = body
+            .chunks_exact(TaggedHash::SIZE)
+            .map(|c| {
+                <[u8; TaggedHash::SIZE]>::try_from(c)
+                    .unwrap()
+                    .try_into()
+                    .unwrap()
+            })
+            .collect();
+        assert_eq!(hashes.unwrap(), expected);
+    }
+
+    #[test]
+    fn from_request_reports_flipped_byte_as_checksum_mismatch() {
+        let body = tagged_hash_body();
+        let checksum = body_checksum(&body);
+        let mut corrupted = body;
+        // In the second hash's tag, which still decodes as a valid `TaggedHash`,
+        // so only the checksum can catch it.
+        corrupted[TaggedHash::SIZE + 3] ^= 1;
+
+        let request = checksummed_request(corrupted, &checksum);
+        let hashes: Result<Vec<TaggedHash>, _> =
+            stream_to_iter(from_request(request).unwrap().boxed()).collect();
+        assert!(matches!(
+            hashes,
+            Err(RistrettoError::ChecksumMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn from_request_rejects_malformed_checksum() {
+        let request = checksummed_request(tagged_hash_body(), "not a checksum");
+        let Err(err) = from_request::<_, TaggedHash>(request) else {
+            panic!("from_request() failed to catch malformed checksum");
+        };
+        assert_eq!(
+            err,
+            MessageError::InvalidChecksumHeader(HeaderValue::from_static("not a checksum"))
+        );
+    }
+
     #[test]
     fn smoke_test_to_response() {
         let ristrettos: [Result<_, Infallible>; 2] = [
@@ -29,12 +29,22 @@
 //!
 //! The message body is just a contiguous array of compressed (32 byte) ristretto points.
 //!
+//! Senders may additionally set an `X-Ristretto-Checksum` header holding the hex-encoded
+//! BLAKE3 hash of the whole body (see [`stream::body_checksum`]). Receivers that find the header
+//! verify it once the body has been fully read and produce
+//! [`RistrettoError::ChecksumMismatch`](stream::RistrettoError::ChecksumMismatch) at the end of
+//! the stream if it doesn't match. This can't be a trailing part of the body without changing
+//! how `Content-Length` maps to a ristretto count, and HTTP trailers are out (see below).
+//!
 //! Mid-stream errors may be indicated by breaking the connection, and optionally replacing a
 //! 32-byte ristretto point with a 32-byte error message beginning and ending with a `0xFF`
 //! byte (the `0xFF` bytes prevent errors from being mistaken for valid ristrettos, because
 //! compressed ristrettos always have bits 7 and 248 cleared). Currently, such error messages
 //! are only intended for human consumption, to aid in debugging.
 //!
+//! The `fixtures` module (behind the feature of the same name) generates deterministic bodies
+//! with documented layouts, for testing other implementations of this format.
+//!
 //! # Design Tidbits
 //!
 //! Streaming is HTTP-compatible! In particular,
@@ -61,6 +71,9 @@ mod contenttype;
 pub mod stream;
 pub mod util;
 
+#[cfg(feature = "fixtures")]
+pub mod fixtures;
+
 #[cfg(feature = "hyper")]
 pub mod hyper;
 
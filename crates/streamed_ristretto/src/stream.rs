@@ -12,11 +12,11 @@ use std::task::{ready, Context, Poll};
 use bytes::{Buf, Bytes};
 use doprf::tagged::TaggedHash;
 use futures::{Stream, TryStream};
-use http::header::{HeaderMap, HeaderValue, CONTENT_TYPE};
+use http::header::{HeaderMap, HeaderName, HeaderValue, CONTENT_TYPE};
 use pin_project::pin_project;
 use thiserror::Error;
 
-use doprf::prf::{CompletedHashValue, Query};
+use doprf::prf::{CompletedHashValue, HashPart, Query};
 
 use crate::util;
 use crate::HasContentType;
@@ -33,6 +33,9 @@ pub(crate) const DEFAULT_CONTENT_TYPE_STR: &str = "application/octet-stream";
 pub(crate) static DEFAULT_CONTENT_TYPE: HeaderValue =
     HeaderValue::from_static(DEFAULT_CONTENT_TYPE_STR);
 
+/// Optional header holding the hex-encoded BLAKE3 hash of the message body
+pub static CHECKSUM_HEADER: HeaderName = HeaderName::from_static("x-ristretto-checksum");
+
 /// Common requirements for a type to be transfered via streaming ristretto format
 pub trait StreamableRistretto:
     HasContentType
@@ -55,6 +58,12 @@ pub trait StreamableRistretto:
     /// place the 30-byte error message in the middle of the 32-byte location
     /// where the encoded Ristretto hash would go, surrounded by 0xFF bytes.
     fn fit_error(error: &ShortErrorMsg) -> Self::Array;
+
+    /// The `Content-Length` of a body holding `count` encoded items, or `None` on
+    /// overflow. This is the inverse of [`check_content_length`].
+    fn content_length_for(count: u64) -> Option<u64> {
+        content_length_for(count, Self::SIZE)
+    }
 }
 
 impl StreamableRistretto for Query {
@@ -68,6 +77,15 @@ impl StreamableRistretto for Query {
     }
 }
 
+impl StreamableRistretto for HashPart {
+    type Array = [u8; HASH_SIZE];
+    type ConversionError = <Self::Array as TryInto<Self>>::Error;
+
+    fn fit_error(error: &ShortErrorMsg) -> Self::Array {
+        Query::fit_error(error)
+    }
+}
+
 impl StreamableRistretto for CompletedHashValue {
     type Array = [u8; HASH_SIZE];
     type ConversionError = <Self::Array as TryInto<Self>>::Error;
@@ -109,6 +127,8 @@ pub enum MessageError {
     },
     #[error("invalid content length: {0:?}")]
     InvalidContentLength(Option<u64>),
+    #[error("invalid X-Ristretto-Checksum header: {0:?}")]
+    InvalidChecksumHeader(HeaderValue),
 }
 
 /// Streamed ristretto errors occuring mid-stream
@@ -123,6 +143,12 @@ pub enum RistrettoError<SE = std::convert::Infallible, CE: Debug = std::convert:
     /// Unable to interpret a chunk of bytes as a ristretto
     #[error("data {data:?} cannot be converted to ristretto because {error:?}")]
     Conversion { data: Bytes, error: CE },
+    /// The body didn't match the checksum given in [`CHECKSUM_HEADER`]
+    #[error("body checksum {actual} does not match expected {expected}")]
+    ChecksumMismatch {
+        expected: blake3::Hash,
+        actual: blake3::Hash,
+    },
 }
 
 impl<SE, CE: Debug> HasShortErrorMsg for RistrettoError<SE, CE> {
@@ -131,6 +157,7 @@ impl<SE, CE: Debug> HasShortErrorMsg for RistrettoError<SE, CE> {
             Self::Stream(_) => *b"Error reading ristrettos.\0\0\0\0\0",
             Self::Incomplete { .. } => *b"Ristretto was incomplete.\0\0\0\0\0",
             Self::Conversion { .. } => *b"Ristretto was invalid.\0\0\0\0\0\0\0\0",
+            Self::ChecksumMismatch { .. } => *b"Ristretto checksum mismatch.\0\0",
         }
     }
 }
@@ -170,13 +197,46 @@ pub fn check_content_length(
     hash_size: usize,
 ) -> Result<u64, MessageError> {
     match content_length {
-        Some(content_length) if content_length % (hash_size as u64) == 0 => {
-            Ok(content_length / (hash_size as u64))
+        Some(content_length) => {
+            let count = content_length / (hash_size as u64);
+            if content_length_for(count, hash_size) == Some(content_length) {
+                Ok(count)
+            } else {
+                Err(MessageError::InvalidContentLength(Some(content_length)))
+            }
         }
-        _ => Err(MessageError::InvalidContentLength(content_length)),
+        None => Err(MessageError::InvalidContentLength(None)),
     }
 }
 
+fn content_length_for(count: u64, hash_size: usize) -> Option<u64> {
+    count.checked_mul(hash_size as u64)
+}
+
+/// Computes the [`CHECKSUM_HEADER`] value for a message body
+pub fn body_checksum(body: &[u8]) -> String {
+    blake3::hash(body).to_hex().to_string()
+}
+
+/// Returns the checksum given in [`CHECKSUM_HEADER`], if any
+///
+/// Errors out if the header is repeated or isn't a hex-encoded BLAKE3 hash.
+pub fn check_checksum_header(headers: &HeaderMap) -> Result<Option<blake3::Hash>, MessageError> {
+    let mut values = headers.get_all(&CHECKSUM_HEADER).iter();
+    let Some(value) = values.next() else {
+        return Ok(None);
+    };
+    if let Some(extra) = values.next() {
+        return Err(MessageError::InvalidChecksumHeader(extra.clone()));
+    }
+    value
+        .to_str()
+        .ok()
+        .and_then(|hex| blake3::Hash::from_hex(hex).ok())
+        .map(Some)
+        .ok_or_else(|| MessageError::InvalidChecksumHeader(value.clone()))
+}
+
 /// Adapt a fallible [`Buf`] stream into a fallible `R` stream.
 ///
 /// The `stream` argument is expected to provide a series of [`Buf`]s that are joined together
@@ -200,10 +260,28 @@ where
     Decoder {
         inner: util::chunked(stream, R::SIZE),
         chunk: Bytes::new(),
+        checksum: None,
         ristretto_type: PhantomData,
     }
 }
 
+/// Like [`decode`], but also verifies that the stream's bytes hash to `expected`.
+///
+/// The checksum can only be verified once `stream` is exhausted, so the `R`s are still yielded
+/// as they arrive; on a mismatch, [`RistrettoError::ChecksumMismatch`] is produced as the last
+/// item. Consumers must therefore drain the stream before acting on its contents.
+pub fn decode_checksummed<S, R>(stream: S, expected: blake3::Hash) -> Decoder<S, R>
+where
+    S: TryStream,
+    S::Ok: Buf,
+    R: StreamableRistretto,
+{
+    Decoder {
+        checksum: Some((blake3::Hasher::new(), expected)),
+        ..decode(stream)
+    }
+}
+
 /// Adapts a fallible [`Buf`] stream into a fallible `R` stream.
 ///
 /// Constructed with [`decode`]; see its docs.
@@ -216,6 +294,7 @@ where
     #[pin]
     inner: util::Chunked<S>,
     chunk: Bytes,
+    checksum: Option<(blake3::Hasher, blake3::Hash)>,
     ristretto_type: PhantomData<R>,
 }
 
@@ -233,11 +312,21 @@ where
             *this.chunk = match ready!(this.inner.as_mut().poll_next(cx)) {
                 Some(Ok(chunk)) => chunk,
                 Some(Err(err)) => return Poll::Ready(Some(Err(RistrettoError::Stream(err)))),
-                None => return Poll::Ready(None),
+                None => {
+                    let mismatch = this.checksum.take().and_then(|(hasher, expected)| {
+                        let actual = hasher.finalize();
+                        (actual != expected)
+                            .then_some(RistrettoError::ChecksumMismatch { expected, actual })
+                    });
+                    return Poll::Ready(mismatch.map(Err));
+                }
             };
         }
 
         let next = this.chunk.split_to(this.chunk.len().min(R::SIZE)); // short if err occurs
+        if let Some((hasher, _)) = this.checksum {
+            hasher.update(&next);
+        }
         let ristretto = <R::Array>::try_from(&*next)
             .map_err(|_| RistrettoError::Incomplete { data: next.clone() })
             .and_then(|hash| {
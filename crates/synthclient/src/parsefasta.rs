@@ -18,9 +18,11 @@ use crate::{
     },
     retry_if::retry_if,
 };
+use doprf::prove::Prover;
 use doprf_client::{
     error::DoprfError, server_selection::ServerSelector,
     server_version_handler::LastServerVersionHandler, windows::WindowsError, DoprfConfig,
+    HdbFailurePolicy, ZeroWindowPolicy,
 };
 use http_client::{BaseApiClient, HttpsToHttpRewriter};
 use quickdna::{
@@ -80,6 +82,7 @@ pub struct CheckerConfiguration<'a> {
     /// Exemption tokens.
     pub ets: Vec<WithOtps<TokenBundle<ExemptionTokenGroup>>>,
     pub server_version_handler: LastServerVersionHandler,
+    pub prover: Arc<Prover>,
 }
 
 pub struct LimitConfiguration<'a> {
@@ -267,6 +270,7 @@ pub async fn check_parsed_fasta<T: NucleotideLike>(
     let request_ctx = RequestContext {
         id: request_id.clone(),
         total_records: fasta_file.records.len(),
+        attempts: Default::default(),
     };
     let records = fasta_file.records;
 
@@ -291,20 +295,35 @@ pub async fn check_parsed_fasta<T: NucleotideLike>(
         .unwrap_or(u64::MAX);
 
     let sequences: Vec<_> = records.iter().map(|record| &record.contents).collect();
-    let output = retry_if(
+    let region: synthesis_permission::Region = config.region.into();
+    let mut output = retry_if(
         || {
             doprf_client::process(DoprfConfig {
                 api_client: &api_client,
                 server_selector: config.server_selector.clone(),
                 request_ctx: &request_ctx,
                 certs: config.certs.clone(),
-                region: config.region.into(),
+                regions: vec![region],
                 debug_info: config.include_debug_info,
                 sequences: &sequences,
                 max_windows,
-                version_hint: config.synthclient_version_hint.to_owned(),
+                version_hint: config.synthclient_version_hint.into(),
                 ets: config.ets.clone(),
                 server_version_handler: &config.server_version_handler,
+                zero_window_policy: ZeroWindowPolicy::Omit,
+                hdb_failure_policy: HdbFailurePolicy::FailClosed,
+                capture_failures: false,
+                report_window_offsets: false,
+                keyserver_rate_limiter: None,
+                max_keyserver_response_bytes: None,
+                max_total_attempts: None,
+                overall_deadline: None,
+                parallel_threshold: None,
+                canonical_response_order: false,
+                security_parameter: None,
+                prover: config.prover.clone(),
+                keyserver_apply: None,
+                progress_sink: None,
             })
         },
         |err: &DoprfError| {
@@ -319,25 +338,33 @@ pub async fn check_parsed_fasta<T: NucleotideLike>(
     )
     .await
     .map_err(|e| {
-        info!("{request_ctx}: internal DOPRF error: {e}");
+        info!(
+            "{request_ctx}: internal DOPRF error after {} attempt(s): {e}",
+            request_ctx.attempts.count()
+        );
         e
     })?;
 
+    let attempts = request_ctx.attempts.count();
+    if attempts > 1 {
+        info!(
+            "{request_ctx}: succeeded on attempt {attempts} after excluding [{}]",
+            request_ctx.attempts.excluded().join(", ")
+        );
+    }
+
+    let response = output.responses.remove(&region).unwrap_or_default();
+
     let synthesis_permission = synthesis_permission::SynthesisPermission::merge(
-        output
-            .response
-            .results
-            .iter()
-            .map(|h| h.synthesis_permission),
+        response.results.iter().map(|h| h.synthesis_permission),
     );
 
-    let debug_grouped_hits = output
-        .response
+    let debug_grouped_hits = response
         .debug_hdb_responses
         .map(|debug_resp| group_debug_hits(debug_resp, &records))
         .transpose()?;
 
-    let hits_by_record = group_hits(output.response.results, &records)?;
+    let hits_by_record = group_hits(response.results, &records)?;
 
     if let Some(m) = &config.metrics {
         m.hash_counter.inc_by(output.n_hashes);
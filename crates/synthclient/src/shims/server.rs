@@ -113,6 +113,9 @@ async fn reconfigure(
         Arc::new(connection)
     };
 
+    // Set up once here, and shared by every screen.
+    let prover = Arc::new(app_cfg.prover.prover());
+
     Ok(Arc::new(SynthClientState {
         app_cfg,
         is_serving_https: server_cfg.main.tls_config.is_some(),
@@ -123,6 +126,7 @@ async fn reconfigure(
         certs,
         synthclient_version,
         persistence_connection,
+        prover,
     }))
 }
 
@@ -380,6 +384,7 @@ async fn screen(
         synthclient_version_hint: &state.synthclient_version,
         ets,
         server_version_handler,
+        prover: Arc::clone(&state.prover),
     };
 
     let api_response = check_fasta::<NucleotideAmbiguous>(&request_id, sequence, &config).await?;
@@ -37,7 +37,10 @@ pub async fn initialize_server_selector(
                     soft_timeout: Some(soft_timeout.into()),
                     blocking_timeout: Some(blocking_timeout.into()),
                     soft_extra_keyserver_threshold,
+                    min_keyservers: None,
                     soft_extra_hdb_threshold,
+                    bad_server_penalty: None,
+                    refresh_failure_backoff: None,
                 },
                 {
                     let client =
@@ -16,6 +16,7 @@ use url::Url;
 use crate::parsefasta::{CurrentSystemLoadTracker, LimitConfiguration};
 use crate::rate_limiter::{RateLimiter, SystemTimeHourProvider};
 use crate::shims::event_store::Connection;
+use doprf::prove::{Elf, ProofMode, Prover, ProverBackend, ProverPaths};
 use doprf_client::server_selection::{ServerEnumerationSource, ServerSelector};
 use minhttp::mpserver::{cli::ServerConfigSource, traits::RelativeConfig};
 use scep_client_helpers::ClientCerts;
@@ -47,6 +48,10 @@ pub struct Config {
     #[serde(flatten)]
     pub certs: CertificateArgs,
 
+    #[command(flatten)]
+    #[serde(flatten)]
+    pub prover: ProverArgs,
+
     #[clap(
         long,
         help = "Memory limit in bytes",
@@ -158,6 +163,7 @@ impl RelativeConfig for Config {
     fn relative_to(mut self, base: impl AsRef<Path>) -> Self {
         let base = base.as_ref();
         self.certs = self.certs.relative_to(base);
+        self.prover = self.prover.relative_to(base);
         if self.event_store_path != Path::new(":memory:") {
             self.event_store_path = base.join(self.event_store_path);
         }
@@ -375,6 +381,121 @@ impl RelativeConfig for CertificateArgs {
     }
 }
 
+#[derive(Args, Clone, Debug, Deserialize)]
+pub struct ProverArgs {
+    #[clap(
+        long,
+        help = "Directory where proofs are saved and loaded from.",
+        env = "SECUREDNA_SYNTHCLIENT_PROOF_OUTPUT_DIR",
+        default_value_os_t = ProverArgs::default_proof_output_dir(),
+    )]
+    #[serde(default = "ProverArgs::default_proof_output_dir")]
+    pub proof_output_dir: PathBuf,
+
+    #[clap(
+        long,
+        help = "Path to the hash proof's zkVM program, instead of the one built into synthclient.",
+        env = "SECUREDNA_SYNTHCLIENT_HASH_ELF"
+    )]
+    pub hash_elf: Option<PathBuf>,
+
+    #[clap(
+        long,
+        help = "Path to the checksum proof's zkVM program, instead of the one built into synthclient.",
+        env = "SECUREDNA_SYNTHCLIENT_CHECKSUM_ELF"
+    )]
+    pub checksum_elf: Option<PathBuf>,
+
+    #[clap(
+        long,
+        help = "Path to the verification proof's zkVM program, instead of the one built into synthclient.",
+        env = "SECUREDNA_SYNTHCLIENT_VERIFICATION_ELF"
+    )]
+    pub verification_elf: Option<PathBuf>,
+
+    #[clap(
+        long,
+        help = "Prove with the SP1 Prover Network at this RPC URL, instead of on this machine.",
+        env = "SECUREDNA_SYNTHCLIENT_PROVER_NETWORK_RPC_URL",
+        requires = "prover_network_api_key"
+    )]
+    pub prover_network_rpc_url: Option<String>,
+
+    #[clap(
+        long,
+        help = "API key for the SP1 Prover Network.",
+        env = "SECUREDNA_SYNTHCLIENT_PROVER_NETWORK_API_KEY",
+        hide_env_values = true,
+        requires = "prover_network_rpc_url"
+    )]
+    pub prover_network_api_key: Option<String>,
+
+    #[clap(
+        long,
+        help = "Don't prove at all, but stand in dummy proofs. Only for testing: the HDB won't accept them.",
+        env = "SECUREDNA_SYNTHCLIENT_MOCK_PROVER",
+        conflicts_with = "prover_network_rpc_url"
+    )]
+    #[serde(default)]
+    pub mock_prover: bool,
+
+    #[clap(
+        long,
+        help = "Kind of verification proof to send the HDB: compressed, groth16 or plonk. The SNARK kinds are much smaller, but slower to generate.",
+        env = "SECUREDNA_SYNTHCLIENT_PROOF_MODE",
+        default_value_t = ProofMode::default()
+    )]
+    #[serde(default)]
+    pub proof_mode: ProofMode,
+}
+
+impl ProverArgs {
+    fn default_proof_output_dir() -> PathBuf {
+        ProverPaths::default().output_dir
+    }
+
+    pub fn prover_paths(&self) -> ProverPaths {
+        let default = ProverPaths::default();
+        let elf = |path: &Option<PathBuf>, default| path.clone().map(Elf::Path).unwrap_or(default);
+        ProverPaths {
+            hash_elf: elf(&self.hash_elf, default.hash_elf),
+            checksum_elf: elf(&self.checksum_elf, default.checksum_elf),
+            verification_elf: elf(&self.verification_elf, default.verification_elf),
+            output_dir: self.proof_output_dir.clone(),
+        }
+    }
+
+    pub fn prover_backend(&self) -> ProverBackend {
+        match (&self.prover_network_rpc_url, &self.prover_network_api_key) {
+            _ if self.mock_prover => ProverBackend::Mock,
+            (Some(rpc_url), Some(api_key)) => ProverBackend::Network {
+                rpc_url: rpc_url.clone(),
+                api_key: api_key.clone(),
+            },
+            _ => ProverBackend::Local,
+        }
+    }
+
+    pub fn prover(&self) -> Prover {
+        Prover::new(self.prover_paths(), self.prover_backend()).with_proof_mode(self.proof_mode)
+    }
+}
+
+impl RelativeConfig for ProverArgs {
+    fn relative_to(mut self, base: impl AsRef<Path>) -> Self {
+        let base = base.as_ref();
+        self.proof_output_dir = base.join(self.proof_output_dir);
+        for elf in [
+            &mut self.hash_elf,
+            &mut self.checksum_elf,
+            &mut self.verification_elf,
+        ] {
+            *elf = elf.take().map(|path| base.join(path));
+        }
+        self
+    }
+}
+
 fn deserialize_via_parse<'de, D, T>(deserializer: D) -> Result<T, D::Error>
 where
     D: Deserializer<'de>,
@@ -395,6 +516,7 @@ pub struct SynthClientState {
     pub certs: Arc<ClientCerts>,
     /// version string returned from /version and passed to doprf_client to identify us
     pub synthclient_version: String,
+    pub prover: Arc<Prover>,
     pub persistence_connection: Arc<Connection>,
 }
 
@@ -155,7 +155,10 @@ pub async fn screen(sequence: JsValue, config: IScreenConfig) -> Result<IApiResp
                         soft_timeout: None,
                         blocking_timeout: None,
                         soft_extra_keyserver_threshold: None,
+                        min_keyservers: None,
                         soft_extra_hdb_threshold: None,
+                        bad_server_penalty: None,
+                        refresh_failure_backoff: None,
                     },
                     api_client.clone(),
                 )
@@ -199,6 +202,7 @@ pub async fn screen(sequence: JsValue, config: IScreenConfig) -> Result<IApiResp
         synthclient_version_hint: &format!("wasm_bindings {version}"),
         ets: vec![], // TODO: support using ET for wasm screening?
         server_version_handler: Default::default(), // don't check server versions in wasm
+        prover: Default::default(),
     };
 
     let result = match sequence.as_string() {
@@ -5,25 +5,21 @@
 #![no_main]
 sp1_zkvm::entrypoint!(main);
 
-use sha3::Sha3_512;
 use curve25519_dalek::ristretto::RistrettoPoint;
 use curve25519_dalek::scalar::Scalar;
 use alloy_sol_types::SolType;
 use fibonacci_lib::{fibonacci, PublicValuesStruct};
-use doprf::prf::Query;
+use doprf::prf::{hash_to_curve, DefaultHashToCurve, Query};
 
 pub fn main() {
-    // Read in each byte array from the input until we reach the sentinel value.
-    loop {
+    // Read the number of windows, then exactly that many (bytes, blinding factor) pairs.
+    // Windows may be empty, so the count (not an empty window) marks the end of the input.
+    let count = sp1_zkvm::io::read::<u64>();
+    for _ in 0..count {
         let bytes = sp1_zkvm::io::read::<Vec<u8>>();
 
-        // Check for the sentinel, break the loop.
-        if bytes.is_empty() {
-            break;
-        }
-
         // Hash the byte array directly to a RistrettoPoint.
-        let hashed_point = RistrettoPoint::hash_from_bytes::<Sha3_512>(&bytes);
+        let hashed_point = hash_to_curve::<DefaultHashToCurve>(&bytes);
 
         // Read the serialized blinding factor from the input
         let blinding_factor_bytes = sp1_zkvm::io::read::<[u8; 32]>();
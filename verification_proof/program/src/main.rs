@@ -11,8 +11,8 @@ use sha2::Sha256;
 use alloy_sol_types::SolType;
 use doprf::prf::{SerializableQueryStateSet, HashPart};
 use doprf::party::KeyserverId;
-use doprf::tagged::TaggedHash;
 use packed_ristretto::datatype::PackedRistrettos;
+use packed_ristretto::VerificationOutcome;
 use shared_types::requests::SerializableRequestContext;
 
 pub fn main() -> () {
@@ -30,46 +30,29 @@ pub fn main() -> () {
         sp1_zkvm::lib::verify::verify_sp1_proof(vkey, &public_values_digest.into());
     }
 
-    // Indicate that the proofs verified
-    sp1_zkvm::io::commit::<bool>(&true);
-
-    // read the serializeed QueryStateSet and deserialized
+    // read the serialized QueryStateSet, and the keyserver responses to incorporate into it
     let serialize_querystate = sp1_zkvm::io::read::<SerializableQueryStateSet>();
-    let mut querystate = serialize_querystate.to_query_state_set();
-
     let keyserver_responses = sp1_zkvm::io::read::<Vec<(KeyserverId, PackedRistrettos<HashPart>)>>();
-    let request_ctx = sp1_zkvm::io::read::<SerializableRequestContext>().to_request_context();
-
-    // Replicating incorporate_responses_and_hash in crates/doprf_client/src/operations.rs
-    for (id, ks_pr) in keyserver_responses.into_iter() {
-        // Handle error return type to avoid '?'
-        let parts = match ks_pr.iter_decoded().collect::<Result<Vec<HashPart>, _>>() {
-            Ok(p) => p,
-            Err(e) => {
-                println!("Error decoding parts: {:?}", e);
-                return;
-            }
-        };
-        
-        // removes thread spawning
-        if let Err(e) = querystate.incorporate_response(id, &parts) {
-            println!("Error incorporating response: {:?}", e);
-            return;
-        }
+    let _request_ctx = sp1_zkvm::io::read::<SerializableRequestContext>().to_request_context();
+
+    // Replicating incorporate_responses_and_hash in crates/doprf_client/src/operations.rs,
+    // without thread spawning
+    let outcome = VerificationOutcome::compute(&serialize_querystate, &keyserver_responses);
+    // Commit the final hash for comparison, marking that the proofs verified and the
+    // responses were incorporated. On any error, the failing step is committed
+    // instead, and nothing after it (see doprf_client::recursive_proof)
+    sp1_zkvm::io::commit::<VerificationOutcome>(&outcome);
+    if !matches!(outcome, VerificationOutcome::Ok(_)) {
+        return;
     }
 
-    // Computes final hash, subsequently calls randomized_target.validate_responses() where checksym is verified
-    // Removes thread spawning
-    let hash_values = match querystate.get_hash_values() {
-        Ok(values) => values,
-        Err(e) => {
-            println!("Error getting hash values: {:?}", e);
-            return;
-        }
-    };
-    let packed_hashes: PackedRistrettos<TaggedHash> = hash_values.into_iter().collect();
-
-    // Commit the final hash for comparison
-    sp1_zkvm::io::commit::<PackedRistrettos<TaggedHash>>(&packed_hashes);
+    // Commit which proofs were verified, so they're bound by this proof's public values
+    // (see doprf_client::recursive_proof)
+    let public_values_digests: Vec<[u8; 32]> = public_values
+        .iter()
+        .map(|public_values| Sha256::digest(public_values).into())
+        .collect();
+    sp1_zkvm::io::commit::<Vec<[u32; 8]>>(&vkeys);
+    sp1_zkvm::io::commit::<Vec<[u8; 32]>>(&public_values_digests);
 }
     